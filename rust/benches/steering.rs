@@ -0,0 +1,29 @@
+use boids::gameworld::NeighbourSearch;
+use boids::headless::HeadlessWorld;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const DELTA: f32 = 1. / 60.;
+
+fn ticks(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ticks");
+    group.sample_size(10);
+    // One element per iteration, so the reported throughput is ticks/second
+    group.throughput(Throughput::Elements(1));
+
+    for count in &[100, 1_000, 10_000] {
+        for (name, search) in &[
+            ("brute_force", NeighbourSearch::BruteForce),
+            ("spatial_index", NeighbourSearch::SpatialIndex),
+        ] {
+            group.bench_with_input(BenchmarkId::new(*name, count), count, |b, count| {
+                let mut world = HeadlessWorld::new(*count, *search);
+                b.iter(|| world.tick(DELTA));
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, ticks);
+criterion_main!(benches);
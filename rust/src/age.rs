@@ -0,0 +1,136 @@
+use legion::systems::ParallelRunnable;
+use legion::*;
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::boids::{Boid, Pos, Velocity};
+use crate::energy::Energy;
+use crate::gameworld::{Delta, Viewport};
+use crate::lifetime::edge_spawn;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+/// Seconds since the boid hatched, and what its age does to it right now.
+/// The multipliers go from the juvenile ones to 1 as it grows up.
+#[derive(Debug, Clone, Copy)]
+pub struct Age {
+    pub seconds: f32,
+    pub scale: f32,
+    pub speed: f32,
+    pub cohesion: f32,
+}
+
+impl Age {
+    pub fn newborn() -> Self {
+        Self {
+            seconds: 0.,
+            scale: 1.,
+            speed: 1.,
+            cohesion: 1.,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Boids are juveniles for `juvenile` seconds, smaller, slower and sticking
+/// closer to the flock by the `juvenile_` multipliers, growing into adults
+/// over that time. With a `max_age` they die of old age and hatch again at an
+/// edge of the screen.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct AgePhases {
+    pub enabled: bool,
+    pub juvenile: f32,
+    pub juvenile_scale: f32,
+    pub juvenile_speed: f32,
+    pub juvenile_cohesion: f32,
+    // Zero for never
+    pub max_age: f32,
+}
+
+impl Default for AgePhases {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            juvenile: 20.,
+            juvenile_scale: 0.6,
+            juvenile_speed: 0.75,
+            juvenile_cohesion: 1.5,
+            max_age: 0.,
+        }
+    }
+}
+
+impl AgePhases {
+    pub fn sanitized(self) -> Self {
+        Self {
+            juvenile: self.juvenile.max(0.),
+            juvenile_scale: self.juvenile_scale.max(0.),
+            juvenile_speed: self.juvenile_speed.max(0.),
+            juvenile_cohesion: self.juvenile_cohesion.max(0.),
+            max_age: self.max_age.max(0.),
+            ..self
+        }
+    }
+
+    // 0 when hatched to 1 when grown up
+    fn maturity(&self, seconds: f32) -> f32 {
+        if self.juvenile <= 0. {
+            return 1.;
+        }
+        let t = (seconds / self.juvenile).min(1.);
+        t * t * (3. - 2. * t)
+    }
+}
+
+fn grown(juvenile: f32, maturity: f32) -> f32 {
+    juvenile + (1. - juvenile) * maturity
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn grow_boids() -> impl ParallelRunnable {
+    SystemBuilder::new("grow boids")
+        .read_resource::<Delta>()
+        .read_resource::<Viewport>()
+        .read_resource::<AgePhases>()
+        .with_query(
+            <(&mut Age, &mut Pos, &mut Velocity, Option<&mut Energy>)>::query()
+                .filter(component::<Boid>()),
+        )
+        .build(|_, world, resources, query| {
+            let (delta, viewport, phases) = resources;
+            let mut rng = thread_rng();
+
+            for (age, pos, vel, energy) in query.iter_mut(world) {
+                age.seconds += delta.0;
+                if !phases.enabled {
+                    *age = Age {
+                        seconds: age.seconds,
+                        ..Age::newborn()
+                    };
+                    continue;
+                }
+
+                if phases.max_age > 0. && age.seconds >= phases.max_age {
+                    let (spawn, velocity) = edge_spawn(viewport, &mut rng);
+                    pos.0 = spawn;
+                    vel.0 = velocity;
+                    age.seconds = 0.;
+                    if let Some(energy) = energy {
+                        *energy = Energy::full();
+                    }
+                }
+
+                let maturity = phases.maturity(age.seconds);
+                age.scale = grown(phases.juvenile_scale, maturity);
+                age.speed = grown(phases.juvenile_speed, maturity);
+                age.cohesion = grown(phases.juvenile_cohesion, maturity);
+            }
+        })
+}
@@ -0,0 +1,162 @@
+use gdnative::core_types::Vector2;
+use legion::systems::ParallelRunnable;
+use legion::*;
+use serde::Serialize;
+
+use crate::boids::{COHESION_RADIUS, MAX_SPEED};
+use crate::error::Result;
+use crate::gameworld::Delta;
+use crate::spatial::FlockIndex;
+
+const SPEED_BINS: usize = 40;
+const NEAREST_BINS: usize = 40;
+const POLARIZATION_BINS: usize = 20;
+
+// -----------------------------------------------------------------------------
+//     - Histogram -
+// -----------------------------------------------------------------------------
+
+/// Counts of values in equal bins from `min` to `max`. Values outside the
+/// range land in the first or last bin.
+#[derive(Debug, Clone, Serialize)]
+pub struct Histogram {
+    pub min: f32,
+    pub max: f32,
+    pub bin_width: f32,
+    pub counts: Vec<u64>,
+}
+
+impl Histogram {
+    pub fn new(min: f32, max: f32, bins: usize) -> Self {
+        Self {
+            min,
+            max,
+            bin_width: (max - min) / bins as f32,
+            counts: vec![0; bins],
+        }
+    }
+
+    pub fn add(&mut self, value: f32) {
+        if !value.is_finite() {
+            return;
+        }
+        let bin = ((value - self.min) / self.bin_width).max(0.) as usize;
+        let last = self.counts.len() - 1;
+        self.counts[bin.min(last)] += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.counts.iter_mut().for_each(|count| *count = 0);
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Distributions over the whole run, for `dump_analysis`. Unlike `Telemetry`
+/// nothing is kept per sample except the polarization, so it can run for
+/// hours.
+#[derive(Debug, Serialize)]
+pub struct Analysis {
+    // Ticks between samples, zero stops sampling
+    #[serde(skip)]
+    pub interval: usize,
+    #[serde(skip)]
+    ticks: usize,
+    // Seconds since the start, or since the last clear
+    pub time: f32,
+    pub samples: usize,
+    pub speed: Histogram,
+    // Boids with nobody inside the cohesion radius are counted in `isolated`
+    pub nearest_neighbour: Histogram,
+    pub isolated: u64,
+    // Length of the mean heading, 1 when every boid flies the same way
+    pub polarization: Histogram,
+    // (time, polarization) of every sample
+    pub polarization_over_time: Vec<(f32, f32)>,
+}
+
+impl Default for Analysis {
+    fn default() -> Self {
+        Self {
+            interval: 10,
+            ticks: 0,
+            time: 0.,
+            samples: 0,
+            speed: Histogram::new(0., MAX_SPEED * 2., SPEED_BINS),
+            nearest_neighbour: Histogram::new(0., COHESION_RADIUS, NEAREST_BINS),
+            isolated: 0,
+            polarization: Histogram::new(0., 1., POLARIZATION_BINS),
+            polarization_over_time: Vec::new(),
+        }
+    }
+}
+
+impl Analysis {
+    pub fn clear(&mut self) {
+        self.ticks = 0;
+        self.time = 0.;
+        self.samples = 0;
+        self.speed.clear();
+        self.nearest_neighbour.clear();
+        self.isolated = 0;
+        self.polarization.clear();
+        self.polarization_over_time.clear();
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    fn sample(&mut self, index: &FlockIndex) {
+        let boids = index.positions.len();
+        if boids == 0 {
+            return;
+        }
+
+        let mut heading = Vector2::ZERO;
+        for vel in &index.velocities {
+            let speed = vel.length();
+            self.speed.add(speed);
+            if speed > 0. {
+                heading += *vel / speed;
+            }
+        }
+        let polarization = heading.length() / boids as f32;
+        self.polarization.add(polarization);
+        self.polarization_over_time.push((self.time, polarization));
+
+        for boid in 0..boids {
+            match index.nearest_distance(boid, COHESION_RADIUS) {
+                Some(distance) => self.nearest_neighbour.add(distance),
+                None => self.isolated += 1,
+            }
+        }
+
+        self.samples += 1;
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn analyse() -> impl ParallelRunnable {
+    SystemBuilder::new("analyse")
+        .read_resource::<Delta>()
+        .read_resource::<FlockIndex>()
+        .write_resource::<Analysis>()
+        .build(|_, _, resources, _| {
+            let (delta, index, analysis) = resources;
+            analysis.time += delta.0;
+
+            if analysis.interval == 0 {
+                return;
+            }
+
+            if analysis.ticks % analysis.interval == 0 {
+                analysis.sample(index);
+            }
+            analysis.ticks += 1;
+        })
+}
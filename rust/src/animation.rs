@@ -0,0 +1,65 @@
+use gdnative::api::AnimatedSprite;
+use gdnative::object::{Ref, TRef};
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::boids::{Acceleration, Velocity, MAX_SPEED};
+use crate::node_commands::{NodeCommand, NodeCommands};
+use crate::traits::Traits;
+
+pub const GLIDE: &str = "glide";
+pub const FLAP: &str = "flap";
+
+// Boids flap when steering harder than this, or when slower than
+// `GLIDE_SPEED` of their max speed
+const FLAP_ACCELERATION: f32 = 200.;
+const GLIDE_SPEED: f32 = 0.6;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+/// An `AnimatedSprite` in the boid scene with both a `glide` and a `flap`
+/// animation. Boids without one are left alone.
+pub struct BoidAnimation(pub Ref<AnimatedSprite>);
+
+impl BoidAnimation {
+    pub unsafe fn new(sprite: TRef<AnimatedSprite>) -> Option<Self> {
+        let frames = sprite.sprite_frames()?;
+        let frames = frames.assume_safe();
+        if frames.has_animation(GLIDE) && frames.has_animation(FLAP) {
+            Some(Self(sprite.claim()))
+        } else {
+            None
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn animate() -> impl ParallelRunnable {
+    SystemBuilder::new("animate")
+        .write_resource::<NodeCommands>()
+        .with_query(
+            <(Entity, &Velocity, &Acceleration, Option<&Traits>)>::query()
+                .filter(component::<BoidAnimation>()),
+        )
+        .build(|_, world, commands, query| {
+            for (&entity, vel, acc, traits) in query.iter(world) {
+                let max_speed = traits.map(|traits| traits.max_speed).unwrap_or(MAX_SPEED);
+                let speed = vel.0.length() / max_speed.max(1.);
+
+                let flapping = acc.0.length() > FLAP_ACCELERATION || speed < GLIDE_SPEED;
+                let (name, speed_scale) = if flapping {
+                    // Beat faster the harder the boid is working
+                    let effort = (acc.0.length() / FLAP_ACCELERATION).min(2.);
+                    (FLAP, 1. + effort * 0.5)
+                } else {
+                    (GLIDE, speed.min(1.))
+                };
+
+                commands.push(entity, NodeCommand::Play(name, speed_scale));
+            }
+        })
+}
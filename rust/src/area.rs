@@ -0,0 +1,128 @@
+use std::collections::HashSet;
+
+use gdnative::api::{CircleShape2D, CollisionShape2D, Node2D, RectangleShape2D};
+use gdnative::core_types::{Rect2, Vector2};
+use gdnative::object::{Ref, TRef};
+use legion::systems::{ParallelRunnable, Runnable};
+use legion::*;
+
+use crate::boids::{live_node, BoidId, Pos};
+use crate::zone::ZoneShape;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+/// A gameplay area (goal, checkpoint, trap) registered with `register_area`.
+/// Its shapes are the rectangle and circle `CollisionShape2D` children of the
+/// node, in world coordinates.
+pub struct Area {
+    pub name: String,
+    pub shapes: Vec<ZoneShape>,
+    // Boids that were inside on the last check
+    pub inside: HashSet<Entity>,
+}
+
+impl Area {
+    fn contains(&self, pos: Vector2) -> bool {
+        self.shapes.iter().any(|shape| shape.contains(pos))
+    }
+}
+
+// The node an `Area` follows
+pub struct AreaNode(pub Ref<Node2D>);
+
+impl AreaNode {
+    pub unsafe fn node(&self) -> Option<TRef<Node2D>> {
+        live_node(&self.0)
+    }
+
+    pub unsafe fn is_alive(&self) -> bool {
+        self.node().is_some()
+    }
+}
+
+// The enabled rectangle and circle `CollisionShape2D` children of `node`, in
+// world coordinates. Rotation is ignored, rects stay axis aligned.
+pub unsafe fn collision_shapes(node: &Node2D) -> Vec<ZoneShape> {
+    (0..node.get_child_count())
+        .filter_map(|i| node.get_child(i))
+        .filter_map(|child| child.assume_safe().cast::<CollisionShape2D>())
+        .filter(|collision| !collision.is_disabled())
+        .filter_map(|collision| {
+            let shape = collision.shape()?;
+            let shape = shape.assume_safe();
+            let center = collision.global_position();
+            let scale = collision.global_scale();
+
+            if let Some(rect) = shape.cast::<RectangleShape2D>() {
+                let extents = rect.extents();
+                let extents = Vector2::new(extents.x * scale.x.abs(), extents.y * scale.y.abs());
+                return Some(ZoneShape::Rect(Rect2::new(center - extents, extents * 2.)));
+            }
+
+            shape
+                .cast::<CircleShape2D>()
+                .map(|circle| ZoneShape::Circle {
+                    center,
+                    radius: circle.radius() as f32 * scale.x.abs().max(scale.y.abs()),
+                })
+        })
+        .collect()
+}
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Boids that entered an area in the last tick, by area name. Drained by the
+/// `GameWorld`, which turns them into `boid_entered_area` signals.
+#[derive(Debug, Default)]
+pub struct AreasEntered(pub Vec<(String, BoidId)>);
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+// Areas move with their nodes, and are dropped once the node is freed
+pub fn sync_areas() -> impl Runnable {
+    SystemBuilder::new("sync areas")
+        .with_query(<(Entity, &AreaNode, &mut Area)>::query())
+        .build(|cmd, world, _, query| {
+            for (&entity, node, area) in query.iter_mut(world) {
+                match unsafe { node.node() } {
+                    Some(node) => area.shapes = unsafe { collision_shapes(&node) },
+                    None => cmd.remove(entity),
+                }
+            }
+        })
+}
+
+pub fn detect_areas() -> impl ParallelRunnable {
+    SystemBuilder::new("detect areas")
+        .write_resource::<AreasEntered>()
+        .with_query(<&mut Area>::query())
+        .with_query(<(Entity, &Pos, &BoidId)>::query())
+        .build(|_, world, entered, queries| {
+            let (areas, boids) = queries;
+            let boids = boids
+                .iter(world)
+                .map(|(&entity, pos, id)| (entity, pos.0, *id))
+                .collect::<Vec<_>>();
+
+            for area in areas.iter_mut(world) {
+                let mut inside = HashSet::new();
+                for (entity, pos, id) in &boids {
+                    if !area.contains(*pos) {
+                        continue;
+                    }
+
+                    if !area.inside.contains(entity) {
+                        entered.0.push((area.name.clone(), *id));
+                    }
+                    inside.insert(*entity);
+                }
+                area.inside = inside;
+            }
+        })
+}
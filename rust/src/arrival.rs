@@ -0,0 +1,131 @@
+use std::collections::HashSet;
+
+use gdnative::core_types::Vector2;
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::boids::{Acceleration, Boid, BoidId, Impulse, Pos, Velocity};
+use crate::ecology::PopulationChanges;
+use crate::gameworld::{BoundaryMode, Viewport};
+use crate::pursuit::TargetTracks;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+/// A boid that reached a target with landing on. It sits where it arrived
+/// until landing is turned off.
+#[derive(Debug, Clone, Copy)]
+pub struct Landed;
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// A boid reaches a target when it comes within `radius` of it. With
+/// `despawn` it's then freed, otherwise with `landing` it stops there.
+#[derive(Debug, Clone)]
+pub struct TargetArrival {
+    pub radius: f32,
+    pub landing: bool,
+    pub despawn: bool,
+    // Boids within the radius last tick, so each arrival only counts once
+    inside: HashSet<Entity>,
+}
+
+impl Default for TargetArrival {
+    fn default() -> Self {
+        Self {
+            radius: 24.,
+            landing: false,
+            despawn: false,
+            inside: HashSet::new(),
+        }
+    }
+}
+
+/// Boids that reached a target this tick. Drained by the `GameWorld`, which
+/// turns them into `boid_reached_target` signals.
+#[derive(Debug, Default)]
+pub struct TargetsReached(pub Vec<BoidId>);
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+// Runs after the boids have moved, so it sees where they ended up
+pub fn detect_arrivals() -> impl ParallelRunnable {
+    SystemBuilder::new("detect arrivals")
+        .read_resource::<BoundaryMode>()
+        .read_resource::<Viewport>()
+        .write_resource::<TargetArrival>()
+        .write_resource::<TargetsReached>()
+        .write_resource::<PopulationChanges>()
+        .read_resource::<TargetTracks>()
+        .with_query(<(Entity, &Pos, &BoidId, Option<&Landed>)>::query().filter(component::<Boid>()))
+        .build(|cmd, world, resources, boids| {
+            let (boundary, viewport, arrival, reached, changes, tracks) = resources;
+            if arrival.radius <= 0. {
+                arrival.inside.clear();
+                return;
+            }
+
+            let targets = tracks.positions().collect::<Vec<_>>();
+            let radius = arrival.radius * arrival.radius;
+            let near = |pos: Vector2| {
+                targets
+                    .iter()
+                    .any(|target| boundary.delta(viewport, pos, *target).length_squared() <= radius)
+            };
+
+            let mut inside = HashSet::new();
+            for (&entity, pos, id, landed) in boids.iter(world) {
+                if !near(pos.0) {
+                    continue;
+                }
+                inside.insert(entity);
+                if arrival.inside.contains(&entity) {
+                    continue;
+                }
+
+                reached.0.push(*id);
+                if arrival.despawn {
+                    if !changes.deaths.contains(&entity) {
+                        changes.deaths.push(entity);
+                    }
+                } else if arrival.landing && landed.is_none() {
+                    cmd.add_component(entity, Landed);
+                }
+            }
+            arrival.inside = inside;
+        })
+}
+
+// Between the forces and the move, so landed boids stay put, impulses
+// included. Turning landing off lets them all take off again.
+pub fn hold_landed() -> impl ParallelRunnable {
+    SystemBuilder::new("hold landed")
+        .read_resource::<TargetArrival>()
+        .with_query(
+            <(
+                Entity,
+                &mut Acceleration,
+                &mut Velocity,
+                Option<&mut Impulse>,
+            )>::query()
+            .filter(component::<Landed>()),
+        )
+        .build(|cmd, world, arrival, query| {
+            for (&entity, acc, vel, impulse) in query.iter_mut(world) {
+                if !arrival.landing {
+                    cmd.remove_component::<Landed>(entity);
+                    continue;
+                }
+                acc.0 = Vector2::ZERO;
+                vel.0 = Vector2::ZERO;
+                if let Some(impulse) = impulse {
+                    impulse.0 = Vector2::ZERO;
+                }
+            }
+        })
+}
@@ -0,0 +1,85 @@
+use std::f32::consts::PI;
+
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::boids::{COHESION_RADIUS, MAX_SPEED};
+use crate::metrics::FlockStats;
+use crate::scatter::Scatter;
+use crate::spatial::FlockIndex;
+
+// Pitch of the flock sound when hovering and at full speed
+const MIN_PITCH: f32 = 0.8;
+const MAX_PITCH: f32 = 1.6;
+// Volume of a loose and a dense flock, in decibels
+const QUIET_DB: f32 = -30.;
+const LOUD_DB: f32 = 0.;
+// Without any boids
+const SILENT_DB: f32 = -80.;
+// Boids per cohesion radius sized circle of the flock bounds that count as a
+// dense flock
+const LOUD_DENSITY: f32 = 6.;
+// How much of the way to the new pitch and volume they move each step
+const SOUND_SMOOTHING: f32 = 0.05;
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// What the player attached with `attach_audio` should sound like, going by
+/// the speed of the flock and how tightly packed it is. `whoosh` is set when
+/// a scatter starts, for the player attached with `attach_whoosh`.
+#[derive(Debug, Clone, Copy)]
+pub struct FlockSound {
+    pub pitch: f32,
+    pub volume_db: f32,
+    pub whoosh: bool,
+}
+
+impl Default for FlockSound {
+    fn default() -> Self {
+        Self {
+            pitch: 1.,
+            volume_db: SILENT_DB,
+            whoosh: false,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+// Runs before `scatter`, while new scatters are still at zero elapsed
+pub fn flock_sound() -> impl ParallelRunnable {
+    SystemBuilder::new("flock sound")
+        .read_resource::<FlockIndex>()
+        .read_resource::<FlockStats>()
+        .write_resource::<FlockSound>()
+        .with_query(<&Scatter>::query())
+        .build(|_, world, resources, scatters| {
+            let (index, stats, sound) = resources;
+
+            if scatters.iter(world).any(|scatter| scatter.elapsed == 0.) {
+                sound.whoosh = true;
+            }
+
+            let boids = index.velocities.len();
+            let (pitch, volume_db) = if boids == 0 {
+                (1., SILENT_DB)
+            } else {
+                let speed =
+                    index.velocities.iter().map(|vel| vel.length()).sum::<f32>() / boids as f32;
+                let pitch = MIN_PITCH + (MAX_PITCH - MIN_PITCH) * (speed / MAX_SPEED).min(1.);
+
+                let area = stats.bounds.size.x * stats.bounds.size.y;
+                let circle = PI * COHESION_RADIUS * COHESION_RADIUS;
+                let density = boids as f32 * circle / area.max(circle);
+                let loudness = (density / LOUD_DENSITY).min(1.);
+                (pitch, QUIET_DB + (LOUD_DB - QUIET_DB) * loudness)
+            };
+
+            sound.pitch += (pitch - sound.pitch) * SOUND_SMOOTHING;
+            sound.volume_db += (volume_db - sound.volume_db) * SOUND_SMOOTHING;
+        })
+}
@@ -0,0 +1,111 @@
+use std::f32::consts::PI;
+
+use gdnative::core_types::Vector2;
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::age::Age;
+use crate::boids::{Boid, Velocity};
+use crate::gameworld::Delta;
+use crate::lod::{Lod, LodLevel};
+use crate::node_commands::{NodeCommand, NodeCommands};
+
+// Steepest bank, in radians
+const MAX_BANK: f32 = 1.2;
+// How much of the measured turn rate goes into the smoothed one each step
+const TURN_SMOOTHING: f32 = 0.2;
+// Bank changes smaller than this aren't sent to the node
+const BANK_EPSILON: f32 = 0.01;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+/// How fast a boid is turning, for the banking visual. Banking squashes the
+/// sprite across its heading, as if the wings were tilted.
+#[derive(Debug, Clone, Copy)]
+pub struct Bank {
+    // Heading on the last step
+    pub heading: Option<f32>,
+    // Radians per second, positive turning clockwise on screen
+    pub turn_rate: f32,
+    // The scale of the node when spawned
+    pub scale: Vector2,
+    // Bank angle and growth from the `Age` last sent to the node
+    pub applied: f32,
+    pub applied_growth: f32,
+}
+
+impl Bank {
+    pub fn new(scale: Vector2) -> Self {
+        Self {
+            heading: None,
+            turn_rate: 0.,
+            scale,
+            applied: 0.,
+            applied_growth: 1.,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Radians of bank per radian per second of turning. Zero turns banking off.
+pub struct BankFactor(pub f32);
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn track_turn_rate() -> impl ParallelRunnable {
+    SystemBuilder::new("track turn rate")
+        .read_resource::<Delta>()
+        .with_query(<(&Velocity, &mut Bank)>::query())
+        .build(|_, world, delta, query| {
+            for (vel, bank) in query.iter_mut(world) {
+                if vel.0.length_squared() == 0. {
+                    continue;
+                }
+
+                let heading = vel.0.y.atan2(vel.0.x);
+                if let Some(previous) = bank.heading.filter(|_| delta.0 > 0.) {
+                    let turn = (heading - previous + PI).rem_euclid(2. * PI) - PI;
+                    let rate = turn / delta.0;
+                    bank.turn_rate += (rate - bank.turn_rate) * TURN_SMOOTHING;
+                }
+                bank.heading = Some(heading);
+            }
+        })
+}
+
+// Far away boids are left as they are
+pub fn bank() -> impl ParallelRunnable {
+    SystemBuilder::new("bank")
+        .read_resource::<BankFactor>()
+        .write_resource::<NodeCommands>()
+        .with_query(
+            <(Entity, &mut Bank, Option<&Lod>, Option<&Age>)>::query().filter(component::<Boid>()),
+        )
+        .build(|_, world, resources, query| {
+            let (factor, commands) = resources;
+            for (&entity, bank, lod, age) in query.iter_mut(world) {
+                if lod.map(|lod| lod.level == LodLevel::Far).unwrap_or(false) {
+                    continue;
+                }
+
+                let angle = (bank.turn_rate * factor.0).max(-MAX_BANK).min(MAX_BANK);
+                let growth = age.map(|age| age.scale).unwrap_or(1.);
+                if (angle - bank.applied).abs() < BANK_EPSILON
+                    && (growth - bank.applied_growth).abs() < BANK_EPSILON
+                {
+                    continue;
+                }
+
+                let scale = Vector2::new(bank.scale.x, bank.scale.y * angle.cos()) * growth;
+                commands.push(entity, NodeCommand::SetScale(scale));
+                bank.applied = angle;
+                bank.applied_growth = growth;
+            }
+        })
+}
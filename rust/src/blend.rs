@@ -0,0 +1,93 @@
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::gameworld::{Delta, ShouldFlee, ShouldSeek};
+use crate::steering::math::smoothstep;
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Weights from 0 to 1 that seek and flee are applied with, following
+/// `ShouldSeek` and `ShouldFlee` over `duration` seconds so toggling them
+/// fades the force in and out. A `duration` of zero switches at once.
+#[derive(Debug, Clone, Copy)]
+pub struct BehaviorBlend {
+    pub duration: f32,
+    pub seek: f32,
+    pub flee: f32,
+}
+
+impl Default for BehaviorBlend {
+    fn default() -> Self {
+        Self {
+            duration: 0.5,
+            seek: 0.,
+            flee: 0.,
+        }
+    }
+}
+
+// Smoothstepped so the force eases in and out rather than ramping linearly
+pub fn eased(weight: f32) -> f32 {
+    smoothstep(0., 1., weight)
+}
+
+fn approach(weight: f32, on: bool, step: f32) -> f32 {
+    let target = if on { 1. } else { 0. };
+    if step.is_infinite() || step >= 1. {
+        return target;
+    }
+    if weight < target {
+        (weight + step).min(target)
+    } else {
+        (weight - step).max(target)
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn blend_behavior_weights() -> impl ParallelRunnable {
+    SystemBuilder::new("blend behavior weights")
+        .read_resource::<Delta>()
+        .read_resource::<ShouldSeek>()
+        .read_resource::<ShouldFlee>()
+        .write_resource::<BehaviorBlend>()
+        .build(|_, _, resources, _| {
+            let (delta, seek, flee, blend) = resources;
+            let step = if blend.duration > 0. {
+                delta.0 / blend.duration
+            } else {
+                std::f32::INFINITY
+            };
+            blend.seek = approach(blend.seek, seek.0, step);
+            blend.flee = approach(blend.flee, flee.0, step);
+        })
+}
+
+// -----------------------------------------------------------------------------
+//     - Tests -
+// -----------------------------------------------------------------------------
+
+#[cfg(feature = "godot_test")]
+pub mod tests {
+    use super::*;
+    use crate::assert_gd;
+
+    pub fn approach_steps_to_target() -> bool {
+        // Steps towards the target from either side without overshooting
+        assert_gd!((approach(0., true, 0.25) - 0.25).abs() < 1e-6);
+        assert_gd!((approach(1., false, 0.25) - 0.75).abs() < 1e-6);
+        assert_gd!(approach(0.9, true, 0.25) == 1.);
+        assert_gd!(approach(0.1, false, 0.25) == 0.);
+
+        // Already there stays there
+        assert_gd!(approach(1., true, 0.25) == 1.);
+        assert_gd!(approach(0., false, 0.25) == 0.);
+
+        // A zero duration switches at once
+        assert_gd!(approach(0.5, true, std::f32::INFINITY) == 1.);
+        assert_gd!(approach(0.5, false, 1.) == 0.)
+    }
+}
@@ -1,285 +1,1263 @@
-use gdnative::{Sprite, Vector2};
-use legion::prelude::*;
-use legion::systems::schedule::Builder;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 
+use gdnative::api::{Node, Node2D};
+use gdnative::core_types::Vector2;
+use gdnative::object::memory::ManuallyManaged;
+use gdnative::object::{GodotObject, Ref, SubClass, TRef};
+use legion::systems::ParallelRunnable;
+use legion::*;
+use rand::prelude::*;
+use rand::rngs::SmallRng;
+
+use crate::age::{grow_boids, Age};
+use crate::analysis::analyse;
+use crate::animation::animate;
+use crate::area::{detect_areas, sync_areas};
+use crate::arrival::{detect_arrivals, hold_landed};
+use crate::audio::flock_sound;
+use crate::bank::{bank, track_turn_rate};
+use crate::blend::{blend_behavior_weights, eased, BehaviorBlend};
+use crate::capture::capture_splits;
+use crate::collision::resolve_collisions;
+use crate::color_mode::color_tint;
+#[cfg(debug_assertions)]
+use crate::debug::check_finite;
+use crate::density::accumulate_density;
+use crate::density_control::{control_density, DensityControl};
+use crate::ecology::{ecology, Nourishment};
+use crate::emitter::{emit_boids, sync_emitters};
+use crate::energy::{stamina, Energy, EXHAUSTED_SPEED_FACTOR, EXHAUSTED_STEERING};
+use crate::error::{BoidsError, Result};
+use crate::flock_state::classify_flock_state;
+use crate::flocks::{detect_flocks, flock_tint, FlockId};
+use crate::flow::flow;
+use crate::forage::forage;
+use crate::formation::assign_formation_slots;
 use crate::gameworld::{
-    AlignmentMul, CohesionMul, Delta, SeparationMul, ShouldFlee, ShouldSeek, Target, Viewport,
+    AlignmentMul, BoundaryMode, CohesionMaxForce, CohesionMul, ColliderHits, Delta, MaxTurnRate,
+    NearestCount, NeighbourMode, NeighbourSearch, NeighbourStaleness, NextBoidId, PerceptionRadii,
+    PredictionHorizon, Predictive, SeparationMul, SteeringInterval, Viewport, ZonalBands,
+    WRAP_MARGIN,
+};
+use crate::gpu::{GpuNeighbours, GpuResults};
+use crate::group::{expire_group_goals, GroupGoal, SplitHeading};
+use crate::home::{Home, HomeAnchor};
+use crate::leader::follow_leaders;
+use crate::lifetime::{age_boids, Lifetime, LifetimeRange};
+use crate::lod::{assign_lod, Lod, LodView};
+use crate::marker::follow_markers;
+use crate::metrics::{flock_stats, telemetry};
+use crate::migration::advance_migration;
+use crate::mood::{update_moods, Mood, MoodState};
+use crate::node_commands::{apply_node_commands, NodeCommand, NodeCommands};
+use crate::noise::{PerceptionNoise, PerceptionRng};
+use crate::patrol::advance_patrol;
+use crate::perch::{perch, sync_perches};
+use crate::point_force::{point_forces, sync_point_forces};
+use crate::pressure::{pressure, pressure_tint, Pressure};
+use crate::pursuit::{intercept, TargetTracks};
+use crate::quality::QualityGovernor;
+use crate::replay::record_trajectory;
+use crate::roles::{role_tint, wander, Role, RoleRatios, Wander};
+use crate::scatter::scatter;
+use crate::schedule::run_schedule;
+use crate::sink::{drain_sinks, sync_sinks};
+use crate::spatial::{FlockIndex, NearestCounts, NeighbourRule};
+use crate::species::{food_chain, Species};
+use crate::stages::{Stage, StagedSchedule};
+use crate::stamp::{play_stamps, record_stamp};
+use crate::steering::{
+    from_math, math, run_behaviors, to_math, ForceChannel, Neighbourhood, SteeringBehavior,
+    SteeringBoid,
 };
+use crate::timestep::{store_previous_positions, FixedTimestep, PreviousPos};
+use crate::traits::{TraitRanges, Traits};
+use crate::walls::{avoid_walls, contain_in_walls};
+use crate::zone::{resolve_zones, ActiveZone};
 
 // -----------------------------------------------------------------------------
 //     - Components -
 // -----------------------------------------------------------------------------
-pub struct Boid(pub Sprite);
+// The root node of the boid scene, see `BoidScene`
+pub struct Boid(pub Ref<Node2D>);
+
+impl Boid {
+    // `None` once gameplay code has freed (or queued to free) the node, after
+    // which it must not be touched. Main thread only.
+    pub unsafe fn node(&self) -> Option<TRef<Node2D>> {
+        live_node(&self.0)
+    }
+
+    pub unsafe fn is_alive(&self) -> bool {
+        self.node().is_some()
+    }
+}
+
+// Anything boids can seek or flee from
+pub struct Target(pub Ref<Node2D>);
+
+impl Target {
+    pub unsafe fn node(&self) -> Option<TRef<Node2D>> {
+        live_node(&self.0)
+    }
+}
+
+// What every node a component follows goes through before it's touched, the
+// `Ref` can outlive the node it points to
+pub unsafe fn live_node<T>(node: &Ref<T>) -> Option<TRef<T>>
+where
+    T: GodotObject<Memory = ManuallyManaged> + SubClass<Node>,
+{
+    node.assume_safe_if_sane()
+        .filter(|node| !node.upcast::<Node>().is_queued_for_deletion())
+}
 
-unsafe impl Send for Boid {}
-unsafe impl Sync for Boid {}
+// Assigned in spawn order, stays the same for the boid's whole life
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BoidId(pub u64);
 
 pub struct Velocity(pub Vector2);
 pub struct Acceleration(pub Vector2);
 pub struct Pos(pub Vector2);
 
+// Half the size of the ship sprite
+pub const BOID_RADIUS: f32 = 16.;
+
+pub struct Radius(pub f32);
+
+impl Radius {
+    pub fn from_scale(scale: Vector2) -> Self {
+        Self(BOID_RADIUS * scale.x.abs().max(scale.y.abs()))
+    }
+}
+
+// Slot relative to the nearest target, in the target's rotating frame
+pub struct EscortOffset(pub Vector2);
+
+// Velocity change from gameplay scripts (explosions, gusts), added straight
+// to the velocity by the next `move_boids` so it doesn't depend on the step
+// size. It lands after the speed cap, see `step_velocity`.
+pub struct Impulse(pub Vector2);
+
+/// Every boid close enough to matter to any of the flocking rules, found in
+/// one search and reused for `NeighbourStaleness` ticks. Boids that move into
+/// range in the meantime go unnoticed until the next search.
+#[derive(Debug, Default)]
+pub struct Neighbours {
+    pub entities: Vec<Entity>,
+    // Ticks left before searching again
+    pub expires: usize,
+}
+
 pub struct Forces {
-    cohesion: Vector2,
-    separation: Vector2,
-    alignment: Vector2,
-    seek: Vector2,
-    flee: Vector2,
+    pub cohesion: Vector2,
+    pub separation: Vector2,
+    pub alignment: Vector2,
+    pub seek: Vector2,
+    pub flee: Vector2,
+    // Sum of the registered `SteeringBehaviors`
+    pub behaviors: Vector2,
+    pub escort: Vector2,
+    pub avoid: Vector2,
+    pub follow: Vector2,
+    pub flow: Vector2,
+    pub scatter: Vector2,
+    pub food_chain: Vector2,
+    pub forage: Vector2,
+    pub point: Vector2,
+    pub wander: Vector2,
+    pub wall: Vector2,
 }
 
 impl Forces {
     pub fn zero() -> Self {
         Self {
-            cohesion: Vector2::zero(),
-            separation: Vector2::zero(),
-            alignment: Vector2::zero(),
-            seek: Vector2::zero(),
-            flee: Vector2::zero(),
+            cohesion: Vector2::ZERO,
+            separation: Vector2::ZERO,
+            alignment: Vector2::ZERO,
+            seek: Vector2::ZERO,
+            flee: Vector2::ZERO,
+            behaviors: Vector2::ZERO,
+            escort: Vector2::ZERO,
+            avoid: Vector2::ZERO,
+            follow: Vector2::ZERO,
+            flow: Vector2::ZERO,
+            scatter: Vector2::ZERO,
+            food_chain: Vector2::ZERO,
+            forage: Vector2::ZERO,
+            point: Vector2::ZERO,
+            wander: Vector2::ZERO,
+            wall: Vector2::ZERO,
         }
     }
 
     fn reset(&mut self) {
         *self = Self::zero();
     }
+
+    // Every force with its name, for reporting
+    pub fn named(&self) -> [(&'static str, Vector2); 16] {
+        [
+            ("cohesion", self.cohesion),
+            ("separation", self.separation),
+            ("alignment", self.alignment),
+            ("seek", self.seek),
+            ("flee", self.flee),
+            ("behaviors", self.behaviors),
+            ("escort", self.escort),
+            ("avoid", self.avoid),
+            ("follow", self.follow),
+            ("flow", self.flow),
+            ("scatter", self.scatter),
+            ("food_chain", self.food_chain),
+            ("forage", self.forage),
+            ("point", self.point),
+            ("wander", self.wander),
+            ("wall", self.wall),
+        ]
+    }
+
+    // Everything but the neighbour rules, for boids skipping a steering pass
+    fn reset_unsteered(&mut self) {
+        *self = Self {
+            cohesion: self.cohesion,
+            separation: self.separation,
+            alignment: self.alignment,
+            ..Self::zero()
+        };
+    }
 }
 
-const MAX_SPEED: f32 = 500.;
+pub const MAX_SPEED: f32 = 500.;
+pub const COHESION_RADIUS: f32 = 200.;
+pub const SEPARATION_RADIUS: f32 = 100.;
+pub const ALIGNMENT_RADIUS: f32 = 100.;
+pub const MOUSE_RADIUS: f32 = 300.;
+
+// The forces are tuned as velocity changes per tick at this many ticks a
+// second. Scaling them by it and the delta makes them accelerations, so the
+// flock steers the same at any tick rate or time scale.
+const FORCE_SCALE: f32 = 60.;
+
+// Speed above the max, from impulses, fades by this rate per second instead of
+// snapping back to the cap
+const OVERSPEED_FALLOFF: f32 = 4.;
+
+// How far ahead (past their own radius) boids look for colliders
+pub const AVOID_DISTANCE: f32 = 120.;
+
+// Escorts slow down inside this distance of their slot
+const ESCORT_ARRIVE_RADIUS: f32 = 100.;
+// How much of the regular flocking still applies to an escort
+const ESCORT_FLOCKING: f32 = 0.25;
 
 // -----------------------------------------------------------------------------
 //     - Systems -
 // -----------------------------------------------------------------------------
 
-fn cohesion() -> Box<dyn Runnable> {
-    SystemBuilder::new("cohesion")
-        .with_query(<(Read<Pos>, Write<Forces>)>::query())
-        .build_thread_local(|_, world, _, query| {
-            let all_positions = query
-                .iter_mut(world)
-                .map(|(pos, _)| pos.0)
-                .collect::<Vec<_>>();
-            let neighbour_distance = 200f32;
+fn build_index() -> impl ParallelRunnable {
+    SystemBuilder::new("build index")
+        .read_resource::<NeighbourSearch>()
+        .read_resource::<NeighbourMode>()
+        .read_resource::<NearestCount>()
+        .read_resource::<NearestCounts>()
+        .read_resource::<BoundaryMode>()
+        .read_resource::<Viewport>()
+        .write_resource::<FlockIndex>()
+        .with_query(<(Entity, &Pos, &Velocity, &Radius)>::query())
+        .build(|_, world, resources, query| {
+            let (search, mode, nearest_count, rule_counts, boundary, viewport, index) = resources;
+            let boids = query
+                .iter(world)
+                .map(|(&entity, pos, vel, radius)| (entity, pos.0, vel.0, radius.0));
+            index.rebuild(**search, boids);
 
-            for (pos, mut force) in query.iter_mut(world) {
-                let mut count = 0;
+            match **mode {
+                NeighbourMode::Metric => index.set_nearest(None, NearestCounts::default()),
+                NeighbourMode::Topological => {
+                    let shared = nearest_count.0;
+                    index.set_nearest(Some(shared), rule_counts.or(shared))
+                }
+            }
 
-                for other_pos in &all_positions {
-                    let distance = (*other_pos - pos.0).length();
+            match **boundary {
+                BoundaryMode::Wrap => index.set_wrap(Some(**viewport)),
+                BoundaryMode::Open | BoundaryMode::Walls => index.set_wrap(None),
+            }
+        })
+}
 
-                    if distance < neighbour_distance {
-                        count += 1;
-                        force.cohesion += *other_pos;
-                    }
+fn cache_neighbours() -> impl ParallelRunnable {
+    SystemBuilder::new("cache neighbours")
+        .read_resource::<FlockIndex>()
+        .read_resource::<PerceptionRadii>()
+        .read_resource::<NeighbourStaleness>()
+        .read_resource::<SteeringInterval>()
+        .read_resource::<QualityGovernor>()
+        .read_resource::<ZonalBands>()
+        .with_query(<(
+            &Pos,
+            &Radius,
+            Option<&Traits>,
+            Option<&BoidId>,
+            Option<&Lod>,
+            &mut Neighbours,
+        )>::query())
+        .build(|_, world, resources, query| {
+            let (index, radii, staleness, steering, governor, bands) = resources;
+            let quality = governor.current();
+            for (pos, radius, traits, id, lod, neighbours) in query.iter_mut(world) {
+                if !steering.due(id) {
+                    continue;
+                }
+
+                if neighbours.expires > 0 {
+                    neighbours.expires -= 1;
+                    continue;
                 }
 
-                if count > 0 {
-                    force.cohesion /= count as f32;
-                    force.cohesion -= pos.0;
+                // Far enough for the widest of the rules, see `SeparationBehavior`
+                let perception = traits.map(|traits| traits.perception).unwrap_or(1.);
+                let reach = if bands.enabled {
+                    (bands.attraction + bands.falloff / 2.) * perception
+                        + radius.0
+                        + index.max_radius()
+                } else {
+                    (radii.cohesion * perception)
+                        .max(radii.alignment * perception)
+                        .max(radii.separation * perception + radius.0 + index.max_radius())
+                } * quality.reach;
+
+                let mut within = index.within(pos.0, reach);
+                if let Some(max) = quality.max_neighbours {
+                    // One more for the boid itself
+                    if within.len() > max + 1 {
+                        let distance_sq =
+                            |other: &usize| index.delta(pos.0, *other).length_squared();
+                        within.select_nth_unstable_by(max + 1, |a, b| {
+                            distance_sq(a)
+                                .partial_cmp(&distance_sq(b))
+                                .unwrap_or(Ordering::Equal)
+                        });
+                        within.truncate(max + 1);
+                    }
                 }
+                neighbours.entities = within
+                    .into_iter()
+                    .map(|other| index.entities[other])
+                    .collect();
+                // Boids far from the camera make do with older neighbours
+                let lod = lod.map(|lod| lod.level.staleness()).unwrap_or(1);
+                neighbours.expires = staleness.0.max(1) * lod * quality.staleness - 1;
             }
         })
 }
 
-fn separation() -> Box<dyn Runnable> {
-    SystemBuilder::new("separation")
-        .with_query(<(Read<Pos>, Write<Forces>)>::query())
-        .build_thread_local(|cmd, world, resources, query| {
-            let all_positions = query
-                .iter_mut(world)
-                .map(|(pos, _)| pos.0)
-                .collect::<Vec<_>>();
-            let neighbour_distance = 100f32;
+// From the boid's cached neighbours when it has them
+pub fn find_neighbours(
+    index: &FlockIndex,
+    cached: Option<&Neighbours>,
+    pos: Vector2,
+    radius: f32,
+    nearest: Option<usize>,
+) -> Vec<usize> {
+    match cached {
+        Some(cached) => index.neighbours_among(pos, radius, &cached.entities, nearest),
+        None => index.neighbours(pos, radius, nearest),
+    }
+}
 
-            for (pos, mut force) in query.iter_mut(world) {
-                let mut count = 0;
+// What the neighbour rules copy out of the resources before each tick
+#[derive(Default)]
+struct RuleSettings {
+    radii: PerceptionRadii,
+    noise: PerceptionNoise,
+    bands: ZonalBands,
+}
 
-                for other_pos in &all_positions {
-                    let distance = (*other_pos - pos.0).length();
+impl RuleSettings {
+    fn read(resources: &Resources) -> Self {
+        Self {
+            radii: resources
+                .get::<PerceptionRadii>()
+                .map(|radii| *radii)
+                .unwrap_or_default(),
+            noise: resources
+                .get::<PerceptionNoise>()
+                .map(|noise| *noise)
+                .unwrap_or_default(),
+            bands: resources
+                .get::<ZonalBands>()
+                .map(|bands| *bands)
+                .unwrap_or_default(),
+        }
+    }
+}
 
-                    if distance < neighbour_distance {
-                        count += 1;
-                        force.separation += pos.0 - *other_pos;
-                    }
-                }
+// One rule's share of the last GPU pass
+fn gpu_results(
+    resources: &Resources,
+    rule: impl Fn(&GpuNeighbours) -> Option<Vector2>,
+) -> HashMap<Entity, Option<Vector2>> {
+    resources
+        .get::<GpuResults>()
+        .map(|gpu| {
+            gpu.0
+                .iter()
+                .map(|(entity, results)| (*entity, rule(results)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Towards the centre of the neighbours, or of the attraction band with
+// `ZonalBands` on
+#[derive(Default)]
+pub struct CohesionBehavior {
+    settings: RuleSettings,
+    max_force: f32,
+    gpu: HashMap<Entity, Option<Vector2>>,
+}
+
+impl SteeringBehavior for CohesionBehavior {
+    fn name(&self) -> &str {
+        "cohesion"
+    }
+
+    fn channel(&self) -> ForceChannel {
+        ForceChannel::Cohesion
+    }
+
+    fn prepare(&mut self, _: &World, resources: &Resources) {
+        self.settings = RuleSettings::read(resources);
+        self.max_force = resources
+            .get::<CohesionMaxForce>()
+            .map(|max| max.0)
+            .unwrap_or(0.);
+        self.gpu = gpu_results(resources, |gpu| gpu.cohesion);
+    }
 
-                if count > 0 {
-                    force.separation /= count as f32;
+    fn compute(
+        &mut self,
+        boid: &SteeringBoid,
+        neighbours: &Neighbourhood,
+        _: &Resources,
+    ) -> Vector2 {
+        let (noise, bands) = (self.settings.noise, self.settings.bands);
+        let index = neighbours.index;
+        let nearest = index.rule_nearest(NeighbourRule::Cohesion);
+        let traits = boid.traits;
+        let vel = to_math(boid.vel);
+
+        let steer = match self.gpu.get(&boid.entity) {
+            Some(offset) => offset.and_then(|offset| {
+                math::seek(to_math(offset), vel, traits.max_speed, self.max_force)
+            }),
+            None if bands.enabled => {
+                let (inner, outer) = (bands.orientation, bands.attraction);
+                let reach = (outer + bands.falloff / 2.) * traits.perception;
+                let mut offsets = Vec::new();
+                let mut weights = Vec::new();
+                for other in neighbours.find(boid.pos, reach, nearest) {
+                    let offset = index.delta(boid.pos, other);
+                    let distance = offset.length() / traits.perception;
+                    weights.push(math::band(distance, inner, outer, bands.falloff));
+                    offsets.push(to_math(offset + neighbours.jitter(noise.position)));
                 }
+                math::weighted_mean(&offsets, &weights).and_then(|to_centroid| {
+                    math::seek(to_centroid, vel, traits.max_speed, self.max_force)
+                })
             }
-        })
+            None => {
+                let radius = self.settings.radii.cohesion * traits.perception;
+                let offsets = neighbours
+                    .find(boid.pos, radius, nearest)
+                    .into_iter()
+                    .map(|other| {
+                        to_math(index.delta(boid.pos, other) + neighbours.jitter(noise.position))
+                    })
+                    .collect::<Vec<_>>();
+                math::cohesion(&offsets, vel, traits.max_speed, self.max_force)
+            }
+        };
+
+        steer.map(from_math).unwrap_or(Vector2::ZERO)
+    }
 }
 
-fn alignment() -> Box<dyn Runnable> {
-    SystemBuilder::new("alignment")
-        .with_query(<(Read<Pos>, Read<Velocity>, Write<Forces>)>::query())
-        .build_thread_local(|cmd, world, resources, query| {
-            let all_positions = query
-                .iter_mut(world)
-                .map(|(pos, vel, _)| (pos.0, vel.0))
-                .collect::<Vec<_>>();
-            let neighbour_distance = 100f32;
+// Away from the neighbours that are too close, measured edge to edge
+#[derive(Default)]
+pub struct SeparationBehavior {
+    settings: RuleSettings,
+    gpu: HashMap<Entity, Option<Vector2>>,
+}
 
-            for (pos, vel, mut force) in query.iter_mut(world) {
-                let mut count = 0;
+impl SteeringBehavior for SeparationBehavior {
+    fn name(&self) -> &str {
+        "separation"
+    }
 
-                for (other_pos, other_vel) in &all_positions {
-                    let distance = (*other_pos - pos.0).length();
+    fn channel(&self) -> ForceChannel {
+        ForceChannel::Separation
+    }
 
-                    if distance < neighbour_distance {
-                        count += 1;
-                        force.alignment += *other_vel;
-                    }
-                }
+    fn prepare(&mut self, _: &World, resources: &Resources) {
+        self.settings = RuleSettings::read(resources);
+        self.gpu = gpu_results(resources, |gpu| gpu.separation);
+    }
 
-                if count > 0 {
-                    force.alignment /= count as f32;
-                }
+    fn compute(
+        &mut self,
+        boid: &SteeringBoid,
+        neighbours: &Neighbourhood,
+        _: &Resources,
+    ) -> Vector2 {
+        if let Some(separation) = self.gpu.get(&boid.entity) {
+            return separation.unwrap_or(Vector2::ZERO);
+        }
+
+        let (noise, bands) = (self.settings.noise, self.settings.bands);
+        let index = neighbours.index;
+        let nearest = index.rule_nearest(NeighbourRule::Separation);
+        let (pos, radius, perception) = (boid.pos, boid.radius, boid.traits.perception);
+
+        if bands.enabled {
+            // Edge to edge like below
+            let outer = bands.repulsion;
+            let reach = (outer + bands.falloff / 2.) * perception + radius + index.max_radius();
+            let mut offsets = Vec::new();
+            let mut weights = Vec::new();
+            for other in neighbours.find(pos, reach, nearest) {
+                let gap = index.gap(pos, radius, other) / perception;
+                weights.push(math::band(gap, 0., outer, bands.falloff));
+                let to_other = index.delta(pos, other) + neighbours.jitter(noise.position);
+                offsets.push(to_math(to_other));
             }
+            return math::weighted_mean(&offsets, &weights)
+                .map(|offset| from_math(math::scale(offset, -1.)))
+                .unwrap_or(Vector2::ZERO);
+        }
+        let separation_radius = self.settings.radii.separation * perception;
+
+        // Big neighbours can be in range from further away, so look far
+        // enough out and then measure edge to edge
+        let reach = separation_radius + radius + index.max_radius();
+        let offsets = neighbours
+            .find(pos, reach, nearest)
+            .into_iter()
+            .filter(|other| index.gap(pos, radius, *other) < separation_radius)
+            .map(|other| to_math(index.delta(pos, other) + neighbours.jitter(noise.position)))
+            .collect::<Vec<_>>();
+        from_math(math::separation(&offsets))
+    }
+}
+
+// Matching the neighbours' velocities, or those in the orientation band with
+// `ZonalBands` on
+#[derive(Default)]
+pub struct AlignmentBehavior {
+    settings: RuleSettings,
+    gpu: HashMap<Entity, Option<Vector2>>,
+}
+
+impl SteeringBehavior for AlignmentBehavior {
+    fn name(&self) -> &str {
+        "alignment"
+    }
+
+    fn channel(&self) -> ForceChannel {
+        ForceChannel::Alignment
+    }
+
+    fn prepare(&mut self, _: &World, resources: &Resources) {
+        self.settings = RuleSettings::read(resources);
+        self.gpu = gpu_results(resources, |gpu| gpu.alignment);
+    }
+
+    fn compute(
+        &mut self,
+        boid: &SteeringBoid,
+        neighbours: &Neighbourhood,
+        _: &Resources,
+    ) -> Vector2 {
+        if let Some(alignment) = self.gpu.get(&boid.entity) {
+            return alignment.unwrap_or(Vector2::ZERO);
+        }
+
+        let (noise, bands) = (self.settings.noise, self.settings.bands);
+        let index = neighbours.index;
+        let nearest = index.rule_nearest(NeighbourRule::Alignment);
+        let (pos, perception) = (boid.pos, boid.traits.perception);
+
+        if bands.enabled {
+            let (inner, outer) = (bands.repulsion, bands.orientation);
+            let reach = (outer + bands.falloff / 2.) * perception;
+            let mut velocities = Vec::new();
+            let mut weights = Vec::new();
+            for other in neighbours.find(pos, reach, nearest) {
+                let distance = index.delta(pos, other).length() / perception;
+                weights.push(math::band(distance, inner, outer, bands.falloff));
+                velocities.push(to_math(
+                    index.velocities[other] + neighbours.jitter(noise.velocity),
+                ));
+            }
+            return from_math(math::weighted_mean(&velocities, &weights).unwrap_or(math::ZERO));
+        }
+
+        let radius = self.settings.radii.alignment * perception;
+        let velocities = neighbours
+            .find(pos, radius, nearest)
+            .into_iter()
+            .map(|other| to_math(index.velocities[other] + neighbours.jitter(noise.velocity)))
+            .collect::<Vec<_>>();
+        from_math(math::alignment(&velocities))
+    }
+}
+
+// Vector from `pos` to the closest of the targets, plus that target's velocity
+fn nearest_target(
+    boundary: BoundaryMode,
+    viewport: &Viewport,
+    pos: Vector2,
+    targets: &[(Vector2, Vector2)],
+) -> Option<(Vector2, Vector2)> {
+    targets
+        .iter()
+        .map(|(target, velocity)| (boundary.delta(viewport, pos, *target), *velocity))
+        .min_by(|(a, _), (b, _)| {
+            a.length_squared()
+                .partial_cmp(&b.length_squared())
+                .unwrap_or(Ordering::Equal)
         })
 }
 
-fn seek() -> Box<dyn Runnable> {
+// With `Predictive` on this becomes pursuit, heading for where the target is
+// going rather than where it is
+fn seek() -> impl ParallelRunnable {
     SystemBuilder::new("seek")
-        .read_resource::<Target>()
-        .with_query(<(Read<Pos>, Write<Forces>)>::query())
-        .build_thread_local(|cmd, world, target, query| unsafe {
-            let destination = target.0.get_global_position();
-            for (pos, mut force) in query.iter_mut(world) {
-                let direction = destination - pos.0;
-                force.seek = direction.with_max_length(MAX_SPEED);
+        .read_resource::<BoundaryMode>()
+        .read_resource::<Viewport>()
+        .read_resource::<TargetTracks>()
+        .read_resource::<Predictive>()
+        .read_resource::<PredictionHorizon>()
+        .with_query(
+            <(&Pos, &mut Forces)>::query()
+                .filter(!component::<GroupGoal>() & !component::<SplitHeading>()),
+        )
+        .build(|_, world, resources, boids| {
+            let (boundary, viewport, tracks, predictive, horizon) = resources;
+            let destinations = tracks
+                .0
+                .values()
+                .map(|track| (track.position, track.velocity))
+                .collect::<Vec<_>>();
+
+            for (pos, force) in boids.iter_mut(world) {
+                if let Some((direction, velocity)) =
+                    nearest_target(**boundary, viewport, pos.0, &destinations)
+                {
+                    let direction = if predictive.0 {
+                        intercept(direction, velocity, horizon.0)
+                    } else {
+                        direction
+                    };
+                    force.seek = direction.clamped(MAX_SPEED);
+                }
             }
         })
 }
 
-fn flee() -> Box<dyn Runnable> {
-    SystemBuilder::new("seek")
-        .read_resource::<Target>()
-        .with_query(<(Read<Pos>, Write<Forces>)>::query())
-        .build_thread_local(|cmd, world, target, query| unsafe {
-            let destination = target.0.get_global_position();
+// With `Predictive` on this becomes evasion, dodging where the threat is
+// going. The flee distance is still measured to where it is now.
+fn flee() -> impl ParallelRunnable {
+    SystemBuilder::new("flee")
+        .read_resource::<BoundaryMode>()
+        .read_resource::<Viewport>()
+        .read_resource::<TargetTracks>()
+        .read_resource::<Predictive>()
+        .read_resource::<PredictionHorizon>()
+        .with_query(
+            <(&Pos, &mut Forces)>::query()
+                .filter(!component::<GroupGoal>() & !component::<SplitHeading>()),
+        )
+        .build(|_, world, resources, boids| {
+            let (boundary, viewport, tracks, predictive, horizon) = resources;
+            let threats = tracks
+                .0
+                .values()
+                .map(|track| (track.position, track.velocity))
+                .collect::<Vec<_>>();
             let flee_dist = 150.;
 
-            for (pos, mut force) in query.iter_mut(world) {
-                let direction = pos.0 - destination;
-                if direction.length() < flee_dist {
-                    force.flee = direction.with_max_length(MAX_SPEED);
+            for (pos, force) in boids.iter_mut(world) {
+                if let Some((direction, velocity)) =
+                    nearest_target(**boundary, viewport, pos.0, &threats)
+                {
+                    if direction.length() < flee_dist {
+                        let direction = if predictive.0 {
+                            intercept(direction, velocity, horizon.0)
+                        } else {
+                            direction
+                        };
+                        force.flee = (-direction).clamped(MAX_SPEED);
+                    }
+                }
+            }
+        })
+}
+
+pub fn is_finite(v: Vector2) -> bool {
+    v.x.is_finite() && v.y.is_finite()
+}
+
+// Zero rather than NaN for vectors with no direction
+pub fn safe_normalize(v: Vector2) -> Vector2 {
+    let length = v.length();
+    if length > 0. && length.is_finite() {
+        v / length
+    } else {
+        Vector2::ZERO
+    }
+}
+
+pub fn rotated(v: Vector2, angle: f32) -> Vector2 {
+    let (sin, cos) = angle.sin_cos();
+    Vector2::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos)
+}
+
+// The velocity one step after `previous`. The steering is capped at the max
+// speed (or whatever is left of an earlier overspeed) and turns by at most
+// `max_turn` radians, the impulse goes on top of both so an explosion can
+// throw a boid faster than it flies.
+fn step_velocity(
+    previous: Vector2,
+    acceleration: Vector2,
+    impulse: Vector2,
+    max_speed: f32,
+    max_turn: Option<f32>,
+    delta: f32,
+) -> Vector2 {
+    let mut vel = previous;
+    if is_finite(acceleration) {
+        vel += acceleration * delta;
+    }
+    let limit = max_speed.max(previous.length() * (-OVERSPEED_FALLOFF * delta).exp());
+    vel = vel.clamped(limit);
+
+    if let Some(max_turn) = max_turn {
+        vel = limit_turn(previous, vel, max_turn);
+    }
+    if !is_finite(vel) {
+        vel = previous;
+    }
+    if is_finite(impulse) {
+        vel += impulse;
+    }
+    vel
+}
+
+// Turn `from` towards `to` by no more than `max_angle` radians, keeping the
+// speed of `to`
+fn limit_turn(from: Vector2, to: Vector2, max_angle: f32) -> Vector2 {
+    if from.length_squared() == 0. || to.length_squared() == 0. {
+        return to;
+    }
+
+    let angle = from.cross(to).atan2(from.dot(to));
+    if angle.abs() <= max_angle {
+        return to;
+    }
+
+    rotated(from.normalized(), max_angle.copysign(angle)) * to.length()
+}
+
+// Arrive at the slot next to the nearest target
+fn escort() -> impl ParallelRunnable {
+    SystemBuilder::new("escort")
+        .read_resource::<BoundaryMode>()
+        .read_resource::<Viewport>()
+        .read_resource::<TargetTracks>()
+        .with_query(<(&Pos, &Velocity, &EscortOffset, &mut Forces)>::query())
+        .build(|_, world, resources, escorts| {
+            let (boundary, viewport, tracks) = resources;
+            let targets = tracks
+                .0
+                .values()
+                .map(|track| (track.position, track.rotation))
+                .collect::<Vec<_>>();
+
+            for (pos, vel, offset, force) in escorts.iter_mut(world) {
+                let nearest = targets.iter().min_by(|(a, _), (b, _)| {
+                    let a = boundary.delta(viewport, pos.0, *a).length_squared();
+                    let b = boundary.delta(viewport, pos.0, *b).length_squared();
+                    a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+                });
+
+                if let Some((target_pos, target_rot)) = nearest {
+                    let slot = *target_pos + rotated(offset.0, *target_rot);
+                    let to_slot = boundary.delta(viewport, pos.0, slot);
+                    let arrive = (to_slot.length() / ESCORT_ARRIVE_RADIUS).min(1.);
+                    let desired = to_slot.clamped(MAX_SPEED) * arrive;
+                    force.escort = (desired - vel.0).clamped(MAX_SPEED);
                 }
             }
         })
 }
 
-fn reset_acceleration() -> Box<dyn Runnable> {
+// Turn away from whatever static collider the ray along the heading hit,
+// harder the closer the hit is. The rays are cast by the `GameWorld`, see
+// `ColliderHits`.
+fn avoid_colliders() -> impl ParallelRunnable {
+    SystemBuilder::new("avoid colliders")
+        .read_resource::<ColliderHits>()
+        .with_query(<(Entity, &Pos, &Radius, &mut Forces)>::query())
+        .build(|_, world, hits, query| {
+            if hits.0.is_empty() {
+                return;
+            }
+
+            for (&entity, pos, radius, force) in query.iter_mut(world) {
+                let hit = match hits.0.get(&entity) {
+                    Some(hit) => hit,
+                    None => continue,
+                };
+
+                let lookahead = AVOID_DISTANCE + radius.0;
+                let closeness = 1. - (hit.point - pos.0).length() / lookahead;
+                force.avoid = hit.normal * MAX_SPEED * closeness.max(0.);
+            }
+        })
+}
+
+fn reset_acceleration() -> impl ParallelRunnable {
     SystemBuilder::new("reset acceleration")
-        .with_query(<Write<Acceleration>>::query())
-        .build_thread_local(|_, world, _, accelerations| {
-            for mut acc in accelerations.iter_mut(world) {
-                acc.0 = Vector2::zero();
+        .with_query(<&mut Acceleration>::query())
+        .build(|_, world, _, accelerations| {
+            for acc in accelerations.iter_mut(world) {
+                acc.0 = Vector2::ZERO;
             }
         })
 }
 
-fn reset_forces() -> Box<dyn Runnable> {
+fn reset_forces() -> impl ParallelRunnable {
     SystemBuilder::new("reset forces")
-        .with_query(<Write<Forces>>::query())
-        .build_thread_local(|_, world, _, accelerations| {
-            for mut force in accelerations.iter_mut(world) {
-                force.reset();
+        .write_resource::<SteeringInterval>()
+        .with_query(<(Option<&BoidId>, &mut Forces)>::query())
+        .build(|_, world, steering, query| {
+            steering.tick = steering.tick.wrapping_add(1);
+
+            for (id, force) in query.iter_mut(world) {
+                if steering.due(id) {
+                    force.reset();
+                } else {
+                    force.reset_unsteered();
+                }
             }
         })
 }
 
-fn screen_wrap() -> Box<dyn Runnable> {
+fn screen_wrap() -> impl ParallelRunnable {
     SystemBuilder::new("sceen_wrap")
         .read_resource::<Viewport>()
-        .with_query(<(Write<Pos>, Write<Boid>)>::query())
-        .build_thread_local(|_, world, viewport, boids| unsafe {
-            let offset = 16.;
-            for (mut pos, mut boid) in boids.iter_mut(world) {
-                if pos.0.x < viewport.0.min_x() - offset {
-                    pos.0.x = viewport.0.max_x() + offset;
-                    boid.0.set_global_position(pos.0);
-                } else if pos.0.x > viewport.0.max_x() + offset {
-                    pos.0.x = viewport.0.min_x() - offset;
-                    boid.0.set_global_position(pos.0);
+        .read_resource::<BoundaryMode>()
+        .with_query(<&mut Pos>::query())
+        .build(|_, world, resources, positions| {
+            let (viewport, boundary) = resources;
+            if **boundary != BoundaryMode::Wrap {
+                return;
+            }
+
+            let offset = WRAP_MARGIN;
+            let (min_x, max_x) = (viewport.0.position.x - offset, viewport.0.end().x + offset);
+            let (min_y, max_y) = (viewport.0.position.y - offset, viewport.0.end().y + offset);
+
+            for pos in positions.iter_mut(world) {
+                // After the window shrinks a boid can be more than a full
+                // width outside, so wrap by the distance rather than jumping
+                // to the opposite edge
+                if pos.0.x < min_x || pos.0.x > max_x {
+                    pos.0.x = min_x + (pos.0.x - min_x).rem_euclid(max_x - min_x);
                 }
 
-                if pos.0.y < viewport.0.min_y() - offset {
-                    pos.0.y = viewport.0.max_y() + offset;
-                    boid.0.set_global_position(pos.0);
-                } else if pos.0.y > viewport.0.max_y() + offset {
-                    pos.0.y = viewport.0.min_y() - offset;
-                    boid.0.set_global_position(pos.0);
+                if pos.0.y < min_y || pos.0.y > max_y {
+                    pos.0.y = min_y + (pos.0.y - min_y).rem_euclid(max_y - min_y);
                 }
             }
         })
 }
 
-fn move_boids() -> Box<dyn Runnable> {
+fn move_boids() -> impl ParallelRunnable {
     SystemBuilder::new("move_boids")
         .read_resource::<Delta>()
+        .read_resource::<MaxTurnRate>()
         .with_query(<(
-            Read<Acceleration>,
-            Write<Velocity>,
-            Write<Pos>,
-            Write<Boid>,
+            &Acceleration,
+            Option<&Energy>,
+            Option<&Traits>,
+            Option<&Mood>,
+            Option<&Age>,
+            Option<&mut Impulse>,
+            &mut Velocity,
+            &mut Pos,
         )>::query())
-        .build_thread_local(|_, world, delta, query| unsafe {
-            for (acc, mut vel, mut pos, mut boid) in query.iter_mut(world) {
-                vel.0 += acc.0;
-                vel.0 = vel.0.with_max_length(MAX_SPEED);
-                boid.0.global_translate(vel.0 * delta.0);
-                pos.0 = boid.0.get_global_position();
+        .build(|_, world, resources, query| {
+            let (delta, max_turn_rate) = resources;
+            let max_turn = if max_turn_rate.0 > 0. {
+                Some(max_turn_rate.0.to_radians() * delta.0)
+            } else {
+                None
+            };
+
+            for (acc, energy, traits, mood, age, impulse, vel, pos) in query.iter_mut(world) {
+                let mood = mood.map(|mood| mood.state).unwrap_or(MoodState::Calm);
+                let max_speed = traits.map(|traits| traits.max_speed).unwrap_or(MAX_SPEED)
+                    * mood.modifiers().speed
+                    * age.map(|age| age.speed).unwrap_or(1.);
+
+                // Exhausted boids glide, barely steering and at a lower speed
+                let exhausted = energy.map(|energy| energy.exhausted).unwrap_or(false);
+                let (steering, max_speed) = if exhausted {
+                    (EXHAUSTED_STEERING, max_speed * EXHAUSTED_SPEED_FACTOR)
+                } else {
+                    (1., max_speed)
+                };
+
+                let impulse = match impulse {
+                    Some(impulse) => std::mem::replace(&mut impulse.0, Vector2::ZERO),
+                    None => Vector2::ZERO,
+                };
+                let acceleration = acc.0 * steering * FORCE_SCALE;
+                vel.0 = step_velocity(vel.0, acceleration, impulse, max_speed, max_turn, delta.0);
+                pos.0 += vel.0 * delta.0;
             }
         })
 }
 
-fn rotate() -> Box<dyn Runnable> {
+pub fn sync_sprites() -> impl ParallelRunnable {
+    SystemBuilder::new("sync sprites")
+        .read_resource::<FixedTimestep>()
+        .write_resource::<NodeCommands>()
+        .with_query(<(Entity, &Pos, Option<&PreviousPos>)>::query().filter(component::<Boid>()))
+        .build(|_, world, resources, query| {
+            let (timestep, commands) = resources;
+            // Anything further than a couple of steps at full speed is a jump
+            let max_jump = MAX_SPEED * timestep.step * 2.;
+
+            for (&entity, pos, previous) in query.iter(world) {
+                let pos = match previous {
+                    Some(previous) => timestep.render_position(previous.0, pos.0, max_jump),
+                    None => pos.0,
+                };
+                commands.push(entity, NodeCommand::SetPosition(pos));
+            }
+        })
+}
+
+fn rotate() -> impl ParallelRunnable {
     SystemBuilder::new("rotate")
-        .with_query(<(Write<Boid>, Read<Velocity>)>::query())
-        .build_thread_local(|_, world, _, query| {
-            for (mut boid, vel) in query.iter_mut(world) {
-                let rot = vel.0.y.atan2(vel.0.x) as f64;
-                unsafe { boid.0.set_global_rotation(rot) };
+        .read_resource::<LodView>()
+        .write_resource::<NodeCommands>()
+        .with_query(
+            <(Entity, &Velocity, Option<&BoidId>, Option<&Lod>)>::query()
+                .filter(component::<Boid>()),
+        )
+        .build(|_, world, resources, query| {
+            let (view, commands) = resources;
+            for (&entity, vel, id, lod) in query.iter(world) {
+                let interval = lod.map(|lod| lod.level.rotation_interval()).unwrap_or(1);
+                let offset = id.map(|id| id.0 as usize).unwrap_or(0);
+                if (view.frame + offset) % interval != 0 {
+                    continue;
+                }
+
+                // A boid at rest keeps pointing the way it last went
+                if vel.0.length_squared() == 0. || !is_finite(vel.0) {
+                    continue;
+                }
+
+                let rot = vel.0.y.atan2(vel.0.x);
+                commands.push(entity, NodeCommand::SetRotation(rot));
             }
         })
 }
 
-fn apply_forces() -> Box<dyn Runnable> {
+fn apply_forces() -> impl ParallelRunnable {
     SystemBuilder::new("apply forces")
         .read_resource::<CohesionMul>()
         .read_resource::<SeparationMul>()
         .read_resource::<AlignmentMul>()
-        .read_resource::<ShouldSeek>()
-        .read_resource::<ShouldFlee>()
-        .with_query(<(Read<Forces>, Write<Acceleration>)>::query())
-        .build_thread_local(|cmd, world, resources, query| {
-            let (cohesion_mul, separation_mul, alignment_mul, seek, flee) = resources;
-            for (force, mut acc) in query.iter_mut(world) {
-                acc.0 += force.cohesion * cohesion_mul.0;
-                acc.0 += force.separation * separation_mul.0;
-                acc.0 += force.alignment * alignment_mul.0;
-
-                if seek.0 {
-                    acc.0 += force.seek;
-                }
+        .read_resource::<BehaviorBlend>()
+        .read_resource::<DensityControl>()
+        .with_query(<(
+            &Forces,
+            Option<&EscortOffset>,
+            Option<&Traits>,
+            Option<&ActiveZone>,
+            Option<&Mood>,
+            Option<&Age>,
+            &mut Acceleration,
+        )>::query())
+        .build(|_, world, resources, query| {
+            let (cohesion_mul, separation_mul, alignment_mul, blend, density) = resources;
+            let (seek, flee) = (eased(blend.seek), eased(blend.flee));
+            for (force, escort, traits, zone, mood, age, acc) in query.iter_mut(world) {
+                let traits = traits.copied().unwrap_or_default();
+                let mood = mood
+                    .map(|mood| mood.state)
+                    .unwrap_or(MoodState::Calm)
+                    .modifiers();
+                let zone = zone.and_then(|zone| zone.0).unwrap_or_default();
+                let cohesion_mul = zone.cohesion.unwrap_or(cohesion_mul.0);
+                let separation_mul = zone.separation.unwrap_or(separation_mul.0);
+                let alignment_mul = zone.alignment.unwrap_or(alignment_mul.0);
+                let flocking = if escort.is_some() {
+                    ESCORT_FLOCKING
+                } else {
+                    1.
+                };
+                let cohesion_mul = cohesion_mul
+                    * density.cohesion
+                    * mood.cohesion
+                    * age.map(|age| age.cohesion).unwrap_or(1.);
+                let separation_mul = separation_mul * density.separation * mood.separation;
+                let alignment_mul = alignment_mul * mood.alignment;
 
-                if flee.0 {
-                    acc.0 += force.flee;
-                    eprintln!("{:?}", "fleeeee");
-                }
+                acc.0 += force.cohesion * cohesion_mul * traits.cohesion * flocking;
+                acc.0 += force.separation * separation_mul * traits.separation * flocking;
+                acc.0 += force.alignment * alignment_mul * traits.alignment * flocking;
+
+                acc.0 += force.seek * seek;
+                acc.0 += force.flee * flee;
+
+                acc.0 += force.behaviors;
+                acc.0 += force.escort;
+                acc.0 += force.avoid;
+                acc.0 += force.follow;
+                acc.0 += force.flow;
+                acc.0 += force.scatter;
+                acc.0 += force.food_chain;
+                acc.0 += force.forage;
+                acc.0 += force.point;
+                acc.0 += force.wander;
+                acc.0 += force.wall;
             }
         })
 }
 
-pub fn add_boid_systems(builder: Builder) -> Builder {
-    builder
-        .add_thread_local(reset_acceleration())
-        .add_thread_local(reset_forces())
-        .add_thread_local(cohesion())
-        .add_thread_local(separation())
-        .add_thread_local(alignment())
-        .add_thread_local(seek())
-        .add_thread_local(flee())
-        .add_thread_local(apply_forces())
-        .add_thread_local(move_boids())
-        .add_thread_local(rotate())
-        .add_thread_local(screen_wrap())
+// Everything that only touches plain components and resources, this is what
+// runs in a headless world.
+pub fn add_flocking_systems(stages: StagedSchedule) -> StagedSchedule {
+    stages
+        .add_system(Stage::Perception, store_previous_positions())
+        .add_system(Stage::Perception, reset_acceleration())
+        .add_system(Stage::Perception, reset_forces())
+        .add_system(Stage::Perception, blend_behavior_weights())
+        .add_system(Stage::Perception, build_index())
+        .add_system(Stage::Perception, cache_neighbours())
+        .add_system(Stage::Steering, pressure())
+        .add_system(Stage::Steering, telemetry())
+        .add_system(Stage::Steering, analyse())
+        .add_system(Stage::Steering, flock_stats())
+        .add_system(Stage::Steering, control_density())
+        .add_system(Stage::Steering, classify_flock_state())
+        .add_system(Stage::Steering, detect_flocks())
+        .add_system(Stage::Steering, capture_splits())
+        .add_system(Stage::Steering, flow())
+        .add_system(Stage::Steering, flock_sound())
+        .add_system(Stage::Steering, scatter())
+        .add_system(Stage::Steering, update_moods())
+        .add_system(Stage::Steering, point_forces())
+        .add_system(Stage::Steering, wander())
+        .add_system(Stage::Steering, avoid_walls())
+        .add_system(Stage::Steering, resolve_zones())
+        .add_system(Stage::Steering, food_chain())
+        .add_system(Stage::Steering, forage())
+        .add_system(Stage::Steering, expire_group_goals())
+        .add_system(Stage::Steering, advance_migration())
+        .add_system(Stage::Steering, assign_formation_slots())
+        .add_fn(Stage::Steering, run_behaviors)
+}
+
+pub fn add_integration_systems(stages: StagedSchedule) -> StagedSchedule {
+    let stages = stages
+        .add_system(Stage::Integration, apply_forces())
+        .add_system(Stage::Integration, stamina())
+        .add_system(Stage::Integration, hold_landed())
+        .add_system(Stage::Integration, perch())
+        .add_system(Stage::Integration, move_boids())
+        .add_system(Stage::Integration, resolve_collisions())
+        .add_system(Stage::Integration, screen_wrap())
+        .add_system(Stage::Integration, contain_in_walls())
+        .add_system(Stage::Integration, track_turn_rate())
+        .add_system(Stage::Integration, age_boids())
+        .add_system(Stage::Integration, grow_boids());
+
+    #[cfg(debug_assertions)]
+    let stages = stages.add_system(Stage::Integration, check_finite());
+
+    stages
+}
+
+pub fn add_boid_systems(stages: StagedSchedule) -> StagedSchedule {
+    // The schedule goes first so every system this tick sees its config.
+    // Point forces and areas follow their nodes, which the flocking systems
+    // can't touch.
+    let stages = stages
+        .add_fn(Stage::Perception, run_schedule)
+        .add_thread_local(Stage::Perception, sync_point_forces())
+        .add_thread_local(Stage::Perception, sync_areas())
+        .add_thread_local(Stage::Perception, sync_emitters())
+        .add_thread_local(Stage::Perception, sync_sinks())
+        .add_thread_local(Stage::Perception, sync_perches());
+    let stages = add_flocking_systems(stages)
+        .add_thread_local(Stage::Steering, advance_patrol())
+        .add_system(Stage::Steering, seek())
+        .add_system(Stage::Steering, flee())
+        .add_system(Stage::Steering, escort())
+        .add_system(Stage::Steering, avoid_colliders())
+        .add_system(Stage::Steering, follow_leaders());
+
+    add_integration_systems(stages)
+        .add_system(Stage::Integration, detect_areas())
+        .add_system(Stage::Integration, detect_arrivals())
+        .add_system(Stage::Integration, ecology())
+        .add_system(Stage::Integration, emit_boids())
+        .add_system(Stage::Integration, drain_sinks())
+        .add_system(Stage::Integration, accumulate_density())
+}
+
+// Once per frame however many simulation steps ran, these only show the
+// current state
+pub fn add_render_systems(stages: StagedSchedule) -> StagedSchedule {
+    stages
+        .add_system(Stage::Presentation, sync_sprites())
+        .add_system(Stage::Presentation, assign_lod())
+        .add_system(Stage::Presentation, rotate())
+        .add_system(Stage::Presentation, bank())
+        .add_system(Stage::Presentation, animate())
+        .add_system(Stage::Presentation, role_tint())
+        .add_system(Stage::Presentation, pressure_tint())
+        .add_system(Stage::Presentation, flock_tint())
+        .add_system(Stage::Presentation, color_tint())
+        .add_system(Stage::Presentation, record_stamp())
+        .add_system(Stage::Presentation, record_trajectory())
+        .add_system(Stage::Presentation, play_stamps())
+        .add_thread_local(Stage::Presentation, apply_node_commands())
+        .add_thread_local(Stage::Presentation, follow_markers())
+}
+
+// -----------------------------------------------------------------------------
+//     - Spawning -
+// -----------------------------------------------------------------------------
+
+// A boid with everything the simulation reads, which is all a headless boid
+// has. The `GameWorld` adds the node and what only the sprites need on top.
+pub fn insert_simulated_boid(
+    world: &mut World,
+    resources: &Resources,
+    rng: &mut impl Rng,
+    pos: Vector2,
+    velocity: Vector2,
+    radius: Radius,
+    species: Species,
+) -> Result<Entity> {
+    let id = {
+        let mut next = resources
+            .get_mut::<NextBoidId>()
+            .ok_or_else(|| BoidsError::Missing("NextBoidId resource".to_string()))?;
+        next.0 += 1;
+        BoidId(next.0 - 1)
+    };
+    let traits = resources
+        .get::<TraitRanges>()
+        .map(|ranges| ranges.sample(rng))
+        .unwrap_or_default();
+    let role = resources
+        .get::<RoleRatios>()
+        .map(|ratios| ratios.sample(rng))
+        .unwrap_or(Role::Follower);
+    let traits = role.apply(traits);
+
+    let entity = world.push((
+        id,
+        Velocity(velocity),
+        Acceleration(Vector2::ZERO),
+        Pos(pos),
+        radius,
+        Forces::zero(),
+        Pressure(0.),
+        Energy::full(),
+        traits,
+        FlockId(0),
+        ActiveZone(None),
+        species,
+    ));
+    let lifetime = resources
+        .get::<LifetimeRange>()
+        .and_then(|range| range.0)
+        .map(|range| range.sample(rng));
+    let home = resources.get::<HomeAnchor>().map(|anchor| anchor.on_spawn);
+
+    // Just pushed, so the entry is always there
+    if let Some(mut entry) = world.entry(entity) {
+        entry.add_component(Nourishment::newborn());
+        entry.add_component(PreviousPos(pos));
+        entry.add_component(role);
+        entry.add_component(Wander::default());
+        entry.add_component(Neighbours::default());
+        entry.add_component(Lod::default());
+        entry.add_component(Mood::default());
+        entry.add_component(Age::newborn());
+        entry.add_component(PerceptionRng(SmallRng::seed_from_u64(rng.gen())));
+
+        if let Some(lifetime) = lifetime {
+            entry.add_component(Lifetime(lifetime));
+        }
+        if home.unwrap_or(false) {
+            entry.add_component(Home(pos));
+        }
+    }
+
+    Ok(entity)
+}
+
+// -----------------------------------------------------------------------------
+//     - Tests -
+// -----------------------------------------------------------------------------
+
+#[cfg(feature = "godot_test")]
+pub mod tests {
+    use super::*;
+    use crate::assert_gd;
+
+    const STEP: f32 = 1. / 60.;
+
+    pub fn impulses_exceed_max_speed() -> bool {
+        // Already at cruise speed, the impulse still doubles it
+        let cruise = Vector2::new(MAX_SPEED, 0.);
+        let vel = step_velocity(cruise, Vector2::ZERO, cruise, MAX_SPEED, None, STEP);
+        assert_gd!((vel.x - MAX_SPEED * 2.).abs() < 1e-3);
+
+        // Then fades back towards the cap without snapping to it
+        let next = step_velocity(vel, Vector2::ZERO, Vector2::ZERO, MAX_SPEED, None, STEP);
+        assert_gd!(next.length() > MAX_SPEED && next.length() < vel.length());
+
+        // The turn limit doesn't bend it
+        let sideways = Vector2::new(0., 300.);
+        let vel = step_velocity(
+            Vector2::new(100., 0.),
+            Vector2::ZERO,
+            sideways,
+            MAX_SPEED,
+            Some(0.),
+            STEP,
+        );
+        assert_gd!((vel - Vector2::new(100., 300.)).length() < 1e-3);
+
+        // Broken impulses are dropped
+        let broken = Vector2::new(std::f32::NAN, 0.);
+        let vel = step_velocity(cruise, Vector2::ZERO, broken, MAX_SPEED, None, STEP);
+        assert_gd!(vel == cruise)
+    }
 }
@@ -0,0 +1,65 @@
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::flocks::FlockDetection;
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Frames of the viewport for the `GameWorld` to save, as numbered PNGs in
+/// `user://`. Started by `capture_frames`, or when a flock splits with
+/// `on_split` set.
+#[derive(Debug, Default)]
+pub struct Capture {
+    // Frames still to save in the current sequence
+    pub remaining: usize,
+    pub sequence: usize,
+    pub frame: usize,
+    // Frames to save when a flock splits, zero for none
+    pub on_split: usize,
+    // Flocks found by the last detection pass
+    flocks: usize,
+}
+
+impl Capture {
+    // Runs on with the longer of the two if a sequence is already going
+    pub fn start(&mut self, frames: usize) {
+        if self.remaining == 0 {
+            self.sequence += 1;
+            self.frame = 0;
+        }
+        self.remaining = self.remaining.max(frames);
+    }
+
+    // Where to save the next frame, if there is one to save
+    pub fn next_path(&mut self) -> Option<String> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let path = format!("user://capture_{:03}_{:04}.png", self.sequence, self.frame);
+        self.remaining -= 1;
+        self.frame += 1;
+        Some(path)
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+// Runs after `detect_flocks`
+pub fn capture_splits() -> impl ParallelRunnable {
+    SystemBuilder::new("capture splits")
+        .read_resource::<FlockDetection>()
+        .write_resource::<Capture>()
+        .build(|_, _, resources, _| {
+            let (detection, capture) = resources;
+            if capture.on_split > 0 && capture.flocks > 0 && detection.count > capture.flocks {
+                let frames = capture.on_split;
+                capture.start(frames);
+            }
+            capture.flocks = detection.count;
+        })
+}
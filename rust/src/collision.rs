@@ -0,0 +1,97 @@
+use gdnative::core_types::Vector2;
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::boids::{Pos, Radius};
+use crate::gameworld::{BoundaryMode, Viewport};
+use crate::spatial::SpatialGrid;
+
+// More passes settle dense clumps better, each one is a full sweep
+const ITERATIONS: usize = 2;
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+pub struct ResolveCollisions(pub bool);
+
+// Radius every boid is kept apart by, zero uses each boid's own `Radius`
+pub struct CollisionRadius(pub f32);
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+// Position based, overlapping boids are each moved half the overlap apart.
+// Runs after `move_boids` so nothing is drawn overlapping. In wrap mode boids
+// overlap through the edges too.
+pub fn resolve_collisions() -> impl ParallelRunnable {
+    SystemBuilder::new("resolve collisions")
+        .read_resource::<ResolveCollisions>()
+        .read_resource::<CollisionRadius>()
+        .read_resource::<BoundaryMode>()
+        .read_resource::<Viewport>()
+        .with_query(<(&mut Pos, &Radius)>::query())
+        .build(|_, world, resources, query| {
+            let (resolve, collision_radius, boundary, viewport) = resources;
+            if !resolve.0 {
+                return;
+            }
+
+            let (mut positions, radii): (Vec<_>, Vec<_>) = query
+                .iter_mut(world)
+                .map(|(pos, radius)| {
+                    let radius = if collision_radius.0 > 0. {
+                        collision_radius.0
+                    } else {
+                        radius.0
+                    };
+                    (pos.0, radius)
+                })
+                .unzip();
+
+            let max_radius = radii.iter().cloned().fold(0f32, f32::max);
+            if max_radius <= 0. {
+                return;
+            }
+
+            let mut grid = SpatialGrid::new(max_radius * 2.);
+            for _ in 0..ITERATIONS {
+                grid.rebuild(&positions);
+
+                for index in 0..positions.len() {
+                    let reach = radii[index] + max_radius;
+                    let candidates = match **boundary {
+                        BoundaryMode::Wrap => {
+                            grid.wrapped_candidates(positions[index], reach, viewport)
+                        }
+                        BoundaryMode::Open | BoundaryMode::Walls => {
+                            grid.candidates(positions[index], reach).collect()
+                        }
+                    };
+
+                    // Each pair is handled once, by its lower index
+                    for other in candidates.into_iter().filter(|other| *other > index) {
+                        let between = boundary.delta(viewport, positions[index], positions[other]);
+                        let distance = between.length();
+                        let overlap = radii[index] + radii[other] - distance;
+                        if overlap <= 0. {
+                            continue;
+                        }
+
+                        // Boids right on top of each other get pushed apart sideways
+                        let normal = if distance > 0. {
+                            between / distance
+                        } else {
+                            Vector2::new(1., 0.)
+                        };
+                        positions[index] -= normal * overlap / 2.;
+                        positions[other] += normal * overlap / 2.;
+                    }
+                }
+            }
+
+            for ((pos, _), resolved) in query.iter_mut(world).zip(positions) {
+                pos.0 = resolved;
+            }
+        })
+}
@@ -0,0 +1,179 @@
+use gdnative::api::Gradient;
+use gdnative::core_types::{Color, Vector2};
+use legion::systems::ParallelRunnable;
+use legion::*;
+use serde::{Deserialize, Serialize};
+
+use crate::boids::{Boid, Velocity, COHESION_RADIUS, MAX_SPEED};
+use crate::debug::Selected;
+use crate::error::{BoidsError, Result};
+use crate::node_commands::{NodeCommand, NodeCommands};
+use crate::spatial::FlockIndex;
+
+// Neighbours inside the cohesion radius that count as the densest colour
+const FULL_DENSITY: f32 = 12.;
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorMode {
+    Off,
+    // Up to the max speed
+    Speed,
+    // Neighbours inside the cohesion radius
+    Density,
+    // Heading against the neighbours' headings, from opposite to the same
+    Alignment,
+}
+
+impl ColorMode {
+    pub fn parse(mode: &str) -> Result<Self> {
+        match mode {
+            "off" => Ok(ColorMode::Off),
+            "speed" => Ok(ColorMode::Speed),
+            "density" => Ok(ColorMode::Density),
+            "alignment" => Ok(ColorMode::Alignment),
+            _ => Err(BoidsError::InvalidArgument(format!(
+                "unknown color mode \"{}\"",
+                mode
+            ))),
+        }
+    }
+}
+
+/// Colours at offsets from 0 to 1, blended in between
+#[derive(Debug, Clone)]
+pub struct ColorGradient(pub Vec<(f32, Color)>);
+
+impl Default for ColorGradient {
+    fn default() -> Self {
+        Self(vec![
+            (0., Color::from_rgb(0.2, 0.3, 1.)),
+            (0.35, Color::from_rgb(0.2, 0.9, 0.9)),
+            (0.65, Color::from_rgb(1., 0.9, 0.2)),
+            (1., Color::from_rgb(1., 0.2, 0.2)),
+        ])
+    }
+}
+
+impl ColorGradient {
+    /// Takes the points of a Godot `Gradient`, the default gradient if it
+    /// has none
+    pub fn from_godot(gradient: &Gradient) -> Self {
+        let mut stops = (0..gradient.get_point_count())
+            .map(|point| (gradient.get_offset(point) as f32, gradient.get_color(point)))
+            .collect::<Vec<_>>();
+        if stops.is_empty() {
+            return Self::default();
+        }
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        Self(stops)
+    }
+
+    pub fn sample(&self, t: f32) -> Color {
+        let stops = &self.0;
+        let t = if t.is_finite() { t } else { 0. };
+        match stops.iter().position(|(offset, _)| *offset > t) {
+            Some(0) => stops[0].1,
+            Some(next) => {
+                let (from, to) = (stops[next - 1], stops[next]);
+                let span = to.0 - from.0;
+                let t = if span > 0. { (t - from.0) / span } else { 1. };
+                lerp_color(from.1, to.1, t)
+            }
+            None => stops
+                .last()
+                .map(|(_, color)| *color)
+                .unwrap_or(Color::from_rgb(1., 1., 1.)),
+        }
+    }
+}
+
+fn lerp_color(from: Color, to: Color, t: f32) -> Color {
+    Color::from_rgba(
+        from.r + (to.r - from.r) * t,
+        from.g + (to.g - from.g) * t,
+        from.b + (to.b - from.b) * t,
+        from.a + (to.a - from.a) * t,
+    )
+}
+
+/// Set with `set_color_mode` and `set_color_gradient`
+#[derive(Debug, Clone)]
+pub struct ColorMapping {
+    pub mode: ColorMode,
+    pub gradient: ColorGradient,
+}
+
+impl Default for ColorMapping {
+    fn default() -> Self {
+        Self {
+            mode: ColorMode::Off,
+            gradient: ColorGradient::default(),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn color_tint() -> impl ParallelRunnable {
+    SystemBuilder::new("color tint")
+        .read_resource::<ColorMapping>()
+        .read_resource::<FlockIndex>()
+        .write_resource::<NodeCommands>()
+        .with_query(
+            <(Entity, &Velocity)>::query().filter(component::<Boid>() & !component::<Selected>()),
+        )
+        .build(|_, world, resources, query| {
+            let (mapping, index, commands) = resources;
+            if mapping.mode == ColorMode::Off {
+                return;
+            }
+
+            for (&entity, vel) in query.iter(world) {
+                let value = match mapping.mode {
+                    ColorMode::Off => continue,
+                    ColorMode::Speed => vel.0.length() / MAX_SPEED,
+                    ColorMode::Density | ColorMode::Alignment => {
+                        let own = match index.index_of(entity) {
+                            Some(own) => own,
+                            None => continue,
+                        };
+                        let neighbours = index
+                            .within(index.positions[own], COHESION_RADIUS)
+                            .into_iter()
+                            .filter(|neighbour| *neighbour != own)
+                            .collect::<Vec<_>>();
+
+                        if mapping.mode == ColorMode::Density {
+                            neighbours.len() as f32 / FULL_DENSITY
+                        } else {
+                            alignment(vel.0, &neighbours, index)
+                        }
+                    }
+                };
+                let color = mapping.gradient.sample(value.max(0.).min(1.));
+                commands.push(entity, NodeCommand::SetModulate(color));
+            }
+        })
+}
+
+// 0.5 without neighbours or with neighbours flying every which way
+fn alignment(vel: Vector2, neighbours: &[usize], index: &FlockIndex) -> f32 {
+    let speed = vel.length();
+    if neighbours.is_empty() || speed == 0. {
+        return 0.5;
+    }
+
+    let heading = neighbours
+        .iter()
+        .map(|neighbour| index.velocities[*neighbour])
+        .filter(|vel| vel.length_squared() > 0.)
+        .fold(Vector2::ZERO, |sum, vel| sum + vel / vel.length())
+        / neighbours.len() as f32;
+    (1. + heading.dot(vel / speed)) / 2.
+}
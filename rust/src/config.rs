@@ -0,0 +1,657 @@
+use std::collections::BTreeMap;
+
+use gdnative::core_types::Vector2;
+use legion::*;
+use serde::{Deserialize, Serialize};
+
+use crate::age::AgePhases;
+use crate::analysis::Analysis;
+use crate::arrival::TargetArrival;
+use crate::bank::BankFactor;
+use crate::blend::BehaviorBlend;
+use crate::capture::Capture;
+use crate::collision::{CollisionRadius, ResolveCollisions};
+use crate::color_mode::{ColorMapping, ColorMode};
+use crate::debug::DebugOverlay;
+use crate::density::DensityMap;
+use crate::density_control::DensityControl;
+use crate::ecology::Ecology;
+use crate::energy::{EnergyDrain, EnergyRecovery};
+use crate::error::Result;
+use crate::flocks::{FlockDetection, ShowFlocks};
+use crate::flow::FlowField;
+use crate::formation::Formation;
+use crate::gameworld::{
+    AlignmentMul, AvoidColliders, BoidCount, BoundaryMode, CohesionMaxForce, CohesionMul,
+    MaxTurnRate, MouseForce, MouseInteraction, NearestCount, NeighbourMode, NeighbourSearch,
+    NeighbourStaleness, PerceptionRadii, PredictionHorizon, Predictive, SeparationMul, ShouldFlee,
+    ShouldSeek, SpawnSpacing, SteeringInterval, TimeScale, ZonalBands,
+};
+use crate::gpu::Backend;
+use crate::home::HomeAnchor;
+use crate::lifetime::LifetimeRange;
+use crate::lod::LodSettings;
+use crate::log::Verbosity;
+use crate::metrics::Telemetry;
+use crate::mood::Moods;
+use crate::noise::PerceptionNoise;
+use crate::pressure::ShowPressure;
+use crate::quality::QualityGovernor;
+use crate::roles::{RoleRatios, ShowRoles};
+use crate::spatial::NearestCounts;
+use crate::spawner::SpawnVelocity;
+use crate::timestep::FixedTimestep;
+use crate::traits::{TraitRange, TraitRanges};
+use crate::walls::Walls;
+
+const TRAIT_NAMES: [&str; 5] = [
+    "max_speed",
+    "perception",
+    "cohesion",
+    "separation",
+    "alignment",
+];
+
+// -----------------------------------------------------------------------------
+//     - Config -
+// -----------------------------------------------------------------------------
+
+/// Every tunable in one place, as exchanged with GDScript through
+/// `apply_config` / `get_config`. Missing fields are left as they are when
+/// applying, unknown ones are an error so typos don't go unnoticed.
+///
+/// Fields with side effects outside the resources (`boid_count`, `backend`,
+/// the tints, `debug_overlay` and `lifetime`) are handled by the `GameWorld`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub boid_count: Option<usize>,
+    // Speeds and headings new boids start with
+    pub spawn_velocity: Option<SpawnVelocity>,
+    // Least distance between boids spawned at random positions
+    pub spawn_spacing: Option<f32>,
+
+    // Multipliers
+    pub cohesion: Option<f32>,
+    pub separation: Option<f32>,
+    pub alignment: Option<f32>,
+    pub cohesion_max_force: Option<f32>,
+    // Mean nearest neighbour distance cohesion and separation are adjusted
+    // to hold, zero for off
+    pub target_density: Option<f32>,
+    pub density_gain: Option<f32>,
+
+    pub cohesion_radius: Option<f32>,
+    pub separation_radius: Option<f32>,
+    pub alignment_radius: Option<f32>,
+    // Standard deviations of the error in perceived neighbours
+    pub position_noise: Option<f32>,
+    pub velocity_noise: Option<f32>,
+    // Separation, alignment and cohesion by distance band, see `ZonalBands`
+    pub zonal_bands: Option<ZonalBands>,
+    // Juvenile and adult phases and the max age
+    pub age_phases: Option<AgePhases>,
+
+    // Per-boid trait ranges by name, see `TraitRanges::get_mut`
+    pub traits: Option<BTreeMap<String, TraitRange>>,
+    // Shares of new boids spawned as scouts and stragglers
+    pub roles: Option<RoleRatios>,
+
+    pub max_turn_rate: Option<f32>,
+    pub time_scale: Option<f32>,
+    // Zero integrates once per physics tick
+    pub fixed_timestep: Option<f32>,
+    pub interpolate: Option<bool>,
+    // Interpolate the sprites every rendered frame, not every physics tick
+    pub interpolate_every_frame: Option<bool>,
+    // Milliseconds per physics frame before quality drops, zero for never
+    pub frame_budget: Option<f32>,
+
+    pub seek: Option<bool>,
+    pub flee: Option<bool>,
+    // Seconds seek and flee take to fade in and out when toggled
+    pub behavior_blend: Option<f32>,
+    pub predictive: Option<bool>,
+    pub prediction_horizon: Option<f32>,
+    // Distance a boid has reached a target at, zero for never
+    pub arrival_radius: Option<f32>,
+    pub landing: Option<bool>,
+    pub despawn_on_arrival: Option<bool>,
+    pub avoid_colliders: Option<bool>,
+    pub resolve_collisions: Option<bool>,
+    // Zero uses each boid's own radius
+    pub collision_radius: Option<f32>,
+    pub mouse_interaction: Option<bool>,
+
+    pub backend: Option<Backend>,
+    pub neighbour_search: Option<NeighbourSearch>,
+    pub neighbour_mode: Option<NeighbourMode>,
+    pub nearest_count: Option<usize>,
+    // Counts of their own for cohesion, separation and alignment
+    pub rule_nearest_counts: Option<NearestCounts>,
+    // Ticks cached neighbours are reused for
+    pub neighbour_staleness: Option<usize>,
+    // Ticks between neighbour rule updates
+    pub steering_interval: Option<usize>,
+    pub boundary: Option<BoundaryMode>,
+    // Push of the walls in walls mode, in units of the max speed
+    pub wall_strength: Option<f32>,
+    pub wall_margin: Option<f32>,
+    // Pull back towards each boid's home, see `set_home`
+    pub home_anchor: Option<HomeAnchor>,
+    // V or echelon formations, see `set_formation`
+    pub formation: Option<Formation>,
+
+    pub energy_drain: Option<f32>,
+    pub energy_recovery: Option<f32>,
+    pub wind: Option<Vector2>,
+    // A max of zero turns lifetimes off
+    pub lifetime: Option<TraitRange>,
+
+    pub ecology: Option<bool>,
+    // Calm, alert and panicked boids
+    pub moods: Option<bool>,
+    pub feed_rate: Option<f32>,
+    pub starve_rate: Option<f32>,
+    pub max_population: Option<usize>,
+
+    pub show_pressure: Option<bool>,
+    pub show_flocks: Option<bool>,
+    pub show_roles: Option<bool>,
+    // Sprites coloured by "speed", "density" or "alignment", or "off"
+    pub color_mode: Option<ColorMode>,
+    // Banking of the sprites in turns, zero for none
+    pub bank_factor: Option<f32>,
+    // Detail levels by distance from the camera set with `set_lod_camera`
+    pub lod: Option<LodSettings>,
+    pub debug_overlay: Option<bool>,
+    pub metrics_interval: Option<usize>,
+    pub analysis_interval: Option<usize>,
+    pub flock_interval: Option<usize>,
+    // Frames captured when a flock splits
+    pub capture_on_split: Option<usize>,
+    // Seconds for the density heat map to fade, zero to keep everything
+    pub density_fade: Option<f32>,
+    pub verbosity: Option<Verbosity>,
+}
+
+// Copy `value` into the resource, if both exist
+fn set<T: 'static + Send + Sync, V>(
+    resources: &Resources,
+    value: Option<V>,
+    apply: impl FnOnce(&mut T, V),
+) {
+    if let Some(value) = value {
+        if let Some(mut resource) = resources.get_mut::<T>() {
+            apply(&mut *resource, value);
+        }
+    }
+}
+
+impl Config {
+    pub fn capture(resources: &Resources) -> Self {
+        let traits = resources.get::<TraitRanges>().map(|ranges| {
+            let ranges = [
+                ranges.max_speed,
+                ranges.perception,
+                ranges.cohesion,
+                ranges.separation,
+                ranges.alignment,
+            ];
+            TRAIT_NAMES
+                .iter()
+                .zip(ranges.iter())
+                .map(|(name, range)| (name.to_string(), *range))
+                .collect()
+        });
+
+        let radii = resources.get::<PerceptionRadii>().map(|radii| *radii);
+        let ecology = resources.get::<Ecology>().map(|ecology| *ecology);
+
+        Self {
+            boid_count: resources.get::<BoidCount>().map(|count| count.0),
+            spawn_velocity: resources.get::<SpawnVelocity>().map(|velocity| *velocity),
+            spawn_spacing: resources.get::<SpawnSpacing>().map(|spacing| spacing.0),
+            cohesion: resources.get::<CohesionMul>().map(|mul| mul.0),
+            separation: resources.get::<SeparationMul>().map(|mul| mul.0),
+            alignment: resources.get::<AlignmentMul>().map(|mul| mul.0),
+            cohesion_max_force: resources.get::<CohesionMaxForce>().map(|max| max.0),
+            target_density: resources
+                .get::<DensityControl>()
+                .map(|control| control.target),
+            density_gain: resources
+                .get::<DensityControl>()
+                .map(|control| control.gain),
+            cohesion_radius: radii.map(|radii| radii.cohesion),
+            separation_radius: radii.map(|radii| radii.separation),
+            alignment_radius: radii.map(|radii| radii.alignment),
+            position_noise: resources
+                .get::<PerceptionNoise>()
+                .map(|noise| noise.position),
+            velocity_noise: resources
+                .get::<PerceptionNoise>()
+                .map(|noise| noise.velocity),
+            zonal_bands: resources.get::<ZonalBands>().map(|bands| *bands),
+            age_phases: resources.get::<AgePhases>().map(|phases| *phases),
+            traits,
+            roles: resources.get::<RoleRatios>().map(|ratios| *ratios),
+            max_turn_rate: resources.get::<MaxTurnRate>().map(|rate| rate.0),
+            time_scale: resources.get::<TimeScale>().map(|scale| scale.0),
+            fixed_timestep: resources
+                .get::<FixedTimestep>()
+                .map(|timestep| timestep.step),
+            interpolate: resources
+                .get::<FixedTimestep>()
+                .map(|timestep| timestep.interpolate),
+            interpolate_every_frame: resources
+                .get::<FixedTimestep>()
+                .map(|timestep| timestep.every_frame),
+            frame_budget: resources
+                .get::<QualityGovernor>()
+                .map(|governor| governor.budget),
+            seek: resources.get::<ShouldSeek>().map(|seek| seek.0),
+            flee: resources.get::<ShouldFlee>().map(|flee| flee.0),
+            behavior_blend: resources.get::<BehaviorBlend>().map(|blend| blend.duration),
+            predictive: resources.get::<Predictive>().map(|predictive| predictive.0),
+            prediction_horizon: resources
+                .get::<PredictionHorizon>()
+                .map(|horizon| horizon.0),
+            arrival_radius: resources
+                .get::<TargetArrival>()
+                .map(|arrival| arrival.radius),
+            landing: resources
+                .get::<TargetArrival>()
+                .map(|arrival| arrival.landing),
+            despawn_on_arrival: resources
+                .get::<TargetArrival>()
+                .map(|arrival| arrival.despawn),
+            avoid_colliders: resources.get::<AvoidColliders>().map(|avoid| avoid.0),
+            resolve_collisions: resources
+                .get::<ResolveCollisions>()
+                .map(|resolve| resolve.0),
+            collision_radius: resources.get::<CollisionRadius>().map(|radius| radius.0),
+            mouse_interaction: resources
+                .get::<MouseInteraction>()
+                .map(|interaction| interaction.0),
+            backend: resources.get::<Backend>().map(|backend| *backend),
+            neighbour_search: resources.get::<NeighbourSearch>().map(|search| *search),
+            neighbour_mode: resources.get::<NeighbourMode>().map(|mode| *mode),
+            nearest_count: resources.get::<NearestCount>().map(|count| count.0),
+            rule_nearest_counts: resources.get::<NearestCounts>().map(|counts| *counts),
+            neighbour_staleness: resources
+                .get::<NeighbourStaleness>()
+                .map(|staleness| staleness.0),
+            steering_interval: resources
+                .get::<SteeringInterval>()
+                .map(|steering| steering.interval),
+            boundary: resources.get::<BoundaryMode>().map(|boundary| *boundary),
+            wall_strength: resources.get::<Walls>().map(|walls| walls.strength),
+            wall_margin: resources.get::<Walls>().map(|walls| walls.margin),
+            home_anchor: resources.get::<HomeAnchor>().map(|anchor| *anchor),
+            formation: resources.get::<Formation>().map(|formation| *formation),
+            energy_drain: resources.get::<EnergyDrain>().map(|drain| drain.0),
+            energy_recovery: resources.get::<EnergyRecovery>().map(|recovery| recovery.0),
+            wind: resources.get::<FlowField>().map(|field| field.wind),
+            lifetime: resources
+                .get::<LifetimeRange>()
+                .map(|range| range.0.unwrap_or_else(|| TraitRange::new(0., 0.))),
+            ecology: ecology.map(|ecology| ecology.enabled),
+            moods: resources.get::<Moods>().map(|moods| moods.0),
+            feed_rate: ecology.map(|ecology| ecology.feed_rate),
+            starve_rate: ecology.map(|ecology| ecology.starve_rate),
+            max_population: ecology.map(|ecology| ecology.max_population),
+            show_pressure: resources.get::<ShowPressure>().map(|show| show.0),
+            show_flocks: resources.get::<ShowFlocks>().map(|show| show.0),
+            show_roles: resources.get::<ShowRoles>().map(|show| show.0),
+            color_mode: resources.get::<ColorMapping>().map(|mapping| mapping.mode),
+            bank_factor: resources.get::<BankFactor>().map(|bank| bank.0),
+            lod: resources.get::<LodSettings>().map(|settings| *settings),
+            debug_overlay: resources.get::<DebugOverlay>().map(|overlay| overlay.0),
+            metrics_interval: resources
+                .get::<Telemetry>()
+                .map(|telemetry| telemetry.interval),
+            analysis_interval: resources
+                .get::<Analysis>()
+                .map(|analysis| analysis.interval),
+            flock_interval: resources
+                .get::<FlockDetection>()
+                .map(|detection| detection.interval),
+            capture_on_split: resources.get::<Capture>().map(|capture| capture.on_split),
+            density_fade: resources.get::<DensityMap>().map(|density| density.fade),
+            verbosity: resources.get::<Verbosity>().map(|verbosity| *verbosity),
+        }
+    }
+
+    /// Writes everything except the fields the `GameWorld` handles itself.
+    /// Trait names are checked before anything is changed.
+    pub fn apply(&self, resources: &mut Resources) -> Result<()> {
+        if let (Some(traits), Some(mut ranges)) = (&self.traits, resources.get_mut::<TraitRanges>())
+        {
+            for name in traits.keys() {
+                ranges.get_mut(name)?;
+            }
+            for (name, range) in traits {
+                *ranges.get_mut(name)? =
+                    TraitRange::new(range.min.min(range.max), range.max.max(range.min));
+            }
+        }
+
+        set(resources, self.cohesion, |mul: &mut CohesionMul, val| {
+            mul.0 = val
+        });
+        set(
+            resources,
+            self.separation,
+            |mul: &mut SeparationMul, val| mul.0 = val,
+        );
+        set(resources, self.alignment, |mul: &mut AlignmentMul, val| {
+            mul.0 = val
+        });
+        set(
+            resources,
+            self.spawn_velocity,
+            |velocity: &mut SpawnVelocity, val: SpawnVelocity| *velocity = val.sanitized(),
+        );
+        set(
+            resources,
+            self.spawn_spacing,
+            |spacing: &mut SpawnSpacing, val: f32| spacing.0 = val.max(0.),
+        );
+        set(
+            resources,
+            self.cohesion_max_force,
+            |max: &mut CohesionMaxForce, val: f32| max.0 = val.max(0.),
+        );
+        set(
+            resources,
+            self.density_gain,
+            |control: &mut DensityControl, val: f32| control.gain = val.max(0.),
+        );
+        set(
+            resources,
+            self.cohesion_radius,
+            |radii: &mut PerceptionRadii, val: f32| radii.cohesion = val.max(0.),
+        );
+        set(
+            resources,
+            self.separation_radius,
+            |radii: &mut PerceptionRadii, val: f32| radii.separation = val.max(0.),
+        );
+        set(
+            resources,
+            self.alignment_radius,
+            |radii: &mut PerceptionRadii, val: f32| radii.alignment = val.max(0.),
+        );
+        // After the radii, the target is clamped to the cohesion radius
+        let cohesion_radius = resources
+            .get::<PerceptionRadii>()
+            .map(|radii| *radii)
+            .unwrap_or_default()
+            .cohesion;
+        set(
+            resources,
+            self.target_density,
+            |control: &mut DensityControl, val: f32| {
+                control.target = DensityControl::sanitized_target(val, cohesion_radius)
+            },
+        );
+        set(
+            resources,
+            self.roles,
+            |ratios: &mut RoleRatios, val: RoleRatios| {
+                ratios.scout = val.scout.max(0.).min(1.);
+                ratios.straggler = val.straggler.max(0.).min(1. - ratios.scout);
+            },
+        );
+        set(
+            resources,
+            self.bank_factor,
+            |bank: &mut BankFactor, val: f32| bank.0 = val.max(0.),
+        );
+        set(
+            resources,
+            self.lod,
+            |settings: &mut LodSettings, val: LodSettings| {
+                settings.near = val.near.max(0.);
+                settings.far = val.far.max(settings.near);
+                settings.hide_far = val.hide_far;
+            },
+        );
+        set(
+            resources,
+            self.position_noise,
+            |noise: &mut PerceptionNoise, val: f32| noise.position = val.max(0.),
+        );
+        set(
+            resources,
+            self.velocity_noise,
+            |noise: &mut PerceptionNoise, val: f32| noise.velocity = val.max(0.),
+        );
+        set(
+            resources,
+            self.zonal_bands,
+            |bands: &mut ZonalBands, val: ZonalBands| *bands = val.sanitized(),
+        );
+        set(
+            resources,
+            self.age_phases,
+            |phases: &mut AgePhases, val: AgePhases| *phases = val.sanitized(),
+        );
+        set(
+            resources,
+            self.max_turn_rate,
+            |rate: &mut MaxTurnRate, val: f32| rate.0 = val.max(0.),
+        );
+        set(
+            resources,
+            self.time_scale,
+            |scale: &mut TimeScale, val: f32| scale.0 = val.max(0.),
+        );
+        set(
+            resources,
+            self.fixed_timestep,
+            |timestep: &mut FixedTimestep, val: f32| timestep.step = val.max(0.),
+        );
+        set(
+            resources,
+            self.interpolate,
+            |timestep: &mut FixedTimestep, val| timestep.interpolate = val,
+        );
+        set(
+            resources,
+            self.interpolate_every_frame,
+            |timestep: &mut FixedTimestep, val| timestep.every_frame = val,
+        );
+        set(
+            resources,
+            self.frame_budget,
+            |governor: &mut QualityGovernor, val| governor.set_budget(val),
+        );
+        set(
+            resources,
+            self.behavior_blend,
+            |blend: &mut BehaviorBlend, val: f32| blend.duration = val.max(0.),
+        );
+        set(resources, self.seek, |seek: &mut ShouldSeek, val| {
+            seek.0 = val
+        });
+        set(resources, self.flee, |flee: &mut ShouldFlee, val| {
+            flee.0 = val
+        });
+        set(
+            resources,
+            self.predictive,
+            |predictive: &mut Predictive, val| predictive.0 = val,
+        );
+        set(
+            resources,
+            self.prediction_horizon,
+            |horizon: &mut PredictionHorizon, val: f32| horizon.0 = val.max(0.),
+        );
+        set(
+            resources,
+            self.arrival_radius,
+            |arrival: &mut TargetArrival, val: f32| arrival.radius = val.max(0.),
+        );
+        set(
+            resources,
+            self.landing,
+            |arrival: &mut TargetArrival, val| arrival.landing = val,
+        );
+        set(
+            resources,
+            self.despawn_on_arrival,
+            |arrival: &mut TargetArrival, val| arrival.despawn = val,
+        );
+        set(
+            resources,
+            self.avoid_colliders,
+            |avoid: &mut AvoidColliders, val| avoid.0 = val,
+        );
+        set(
+            resources,
+            self.resolve_collisions,
+            |resolve: &mut ResolveCollisions, val| resolve.0 = val,
+        );
+        set(
+            resources,
+            self.collision_radius,
+            |radius: &mut CollisionRadius, val: f32| radius.0 = val.max(0.),
+        );
+        set(
+            resources,
+            self.mouse_interaction,
+            |interaction: &mut MouseInteraction, val| interaction.0 = val,
+        );
+        set(
+            resources,
+            self.mouse_interaction,
+            |mouse: &mut MouseForce, _| mouse.strength = 0.,
+        );
+        set(
+            resources,
+            self.neighbour_search,
+            |search: &mut NeighbourSearch, val| *search = val,
+        );
+        set(
+            resources,
+            self.neighbour_mode,
+            |mode: &mut NeighbourMode, val| *mode = val,
+        );
+        set(
+            resources,
+            self.nearest_count,
+            |count: &mut NearestCount, val: usize| count.0 = val.max(1),
+        );
+        set(
+            resources,
+            self.rule_nearest_counts,
+            |counts: &mut NearestCounts, val: NearestCounts| *counts = val.sanitized(),
+        );
+        set(
+            resources,
+            self.neighbour_staleness,
+            |staleness: &mut NeighbourStaleness, val: usize| staleness.0 = val.max(1),
+        );
+        set(
+            resources,
+            self.steering_interval,
+            |steering: &mut SteeringInterval, val: usize| steering.interval = val.max(1),
+        );
+        set(
+            resources,
+            self.boundary,
+            |boundary: &mut BoundaryMode, val| *boundary = val,
+        );
+        set(
+            resources,
+            self.wall_strength,
+            |walls: &mut Walls, val: f32| walls.strength = val.max(0.),
+        );
+        set(
+            resources,
+            self.wall_margin,
+            |walls: &mut Walls, val: f32| walls.margin = val.max(0.),
+        );
+        set(
+            resources,
+            self.home_anchor,
+            |anchor: &mut HomeAnchor, val: HomeAnchor| *anchor = val.sanitized(),
+        );
+        set(
+            resources,
+            self.formation,
+            |formation: &mut Formation, val: Formation| {
+                formation.enabled = val.enabled;
+                formation.behind = val.behind.max(0.);
+                formation.side = val.side.max(0.);
+                formation.echelon = val.echelon;
+            },
+        );
+        set(
+            resources,
+            self.energy_drain,
+            |drain: &mut EnergyDrain, val| drain.0 = val,
+        );
+        set(
+            resources,
+            self.energy_recovery,
+            |recovery: &mut EnergyRecovery, val| recovery.0 = val,
+        );
+        set(resources, self.wind, |field: &mut FlowField, val| {
+            field.wind = val
+        });
+        set(resources, self.ecology, |ecology: &mut Ecology, val| {
+            ecology.enabled = val
+        });
+        set(resources, self.moods, |moods: &mut Moods, val| {
+            moods.0 = val
+        });
+        set(
+            resources,
+            self.feed_rate,
+            |ecology: &mut Ecology, val: f32| ecology.feed_rate = val.max(0.),
+        );
+        set(
+            resources,
+            self.starve_rate,
+            |ecology: &mut Ecology, val: f32| ecology.starve_rate = val.max(0.),
+        );
+        set(
+            resources,
+            self.max_population,
+            |ecology: &mut Ecology, val| ecology.max_population = val,
+        );
+        set(
+            resources,
+            self.metrics_interval,
+            |telemetry: &mut Telemetry, val| telemetry.interval = val,
+        );
+        set(
+            resources,
+            self.analysis_interval,
+            |analysis: &mut Analysis, val| analysis.interval = val,
+        );
+        set(
+            resources,
+            self.flock_interval,
+            |detection: &mut FlockDetection, val| detection.interval = val,
+        );
+        set(
+            resources,
+            self.capture_on_split,
+            |capture: &mut Capture, val| capture.on_split = val,
+        );
+        set(
+            resources,
+            self.density_fade,
+            |density: &mut DensityMap, val: f32| density.fade = val.max(0.),
+        );
+
+        if let Some(verbosity) = self.verbosity {
+            resources.insert(verbosity);
+        }
+
+        Ok(())
+    }
+}
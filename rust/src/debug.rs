@@ -0,0 +1,168 @@
+use std::f64::consts::PI;
+
+use gdnative::api::Node2D;
+use gdnative::core_types::{Color, Vector2};
+use gdnative::log::godot_error;
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::boids::{is_finite, Acceleration, BoidId, Forces, Pos, Velocity};
+use crate::gameworld::PerceptionRadii;
+use crate::timestep::PreviousPos;
+
+// Forces and velocities are in pixels per second, scale them down so they
+// fit on screen next to the boid
+const VECTOR_SCALE: f32 = 0.25;
+const LINE_WIDTH: f64 = 2.;
+const CIRCLE_POINTS: i64 = 48;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+// The boid picked with `select_boid_at`
+pub struct Selected;
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+// Draw the steering of every boid, not just the selected one
+pub struct DebugOverlay(pub bool);
+
+pub fn selected_tint() -> Color {
+    Color::from_rgb(0.3, 1., 0.3)
+}
+
+// -----------------------------------------------------------------------------
+//     - Drawing -
+// -----------------------------------------------------------------------------
+
+/// Everything needed to draw one boid's steering, with `pos` already local to
+/// the canvas it's drawn on.
+pub struct BoidGeometry {
+    pub pos: Vector2,
+    pub velocity: Vector2,
+    pub cohesion: Vector2,
+    pub separation: Vector2,
+    pub alignment: Vector2,
+    // Every other force added up
+    pub other: Vector2,
+    pub perception: f32,
+}
+
+impl BoidGeometry {
+    pub fn new(pos: Vector2, velocity: Vector2, forces: &Forces, perception: f32) -> Self {
+        let other = forces.seek
+            + forces.flee
+            + forces.behaviors
+            + forces.escort
+            + forces.avoid
+            + forces.follow
+            + forces.flow
+            + forces.scatter
+            + forces.food_chain
+            + forces.forage
+            + forces.point
+            + forces.wander
+            + forces.wall;
+
+        Self {
+            pos,
+            velocity,
+            cohesion: forces.cohesion,
+            separation: forces.separation,
+            alignment: forces.alignment,
+            other,
+            perception,
+        }
+    }
+
+    pub fn draw(&self, canvas: &Node2D, radii: &PerceptionRadii) {
+        let circles = [
+            (radii.cohesion, Color::from_rgba(0.3, 1., 0.3, 0.4)),
+            (radii.separation, Color::from_rgba(1., 0.3, 0.3, 0.4)),
+            (radii.alignment, Color::from_rgba(0.3, 0.3, 1., 0.4)),
+        ];
+
+        for &(radius, color) in circles.iter() {
+            canvas.draw_arc(
+                self.pos,
+                (radius * self.perception) as f64,
+                0.,
+                PI * 2.,
+                CIRCLE_POINTS,
+                color,
+                1.,
+                true,
+            );
+        }
+
+        let vectors = [
+            (self.velocity, Color::from_rgb(1., 1., 1.)),
+            (self.cohesion, Color::from_rgb(0.3, 1., 0.3)),
+            (self.separation, Color::from_rgb(1., 0.3, 0.3)),
+            (self.alignment, Color::from_rgb(0.3, 0.3, 1.)),
+            (self.other, Color::from_rgb(1., 1., 0.3)),
+        ];
+
+        for &(vector, color) in vectors.iter() {
+            let end = self.pos + vector * VECTOR_SCALE;
+            canvas.draw_line(self.pos, end, color, LINE_WIDTH, true);
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+// Debug builds only, at the end of each step. A NaN spreads to every
+// neighbour within a tick, so boids that have one are reported with where
+// it showed up and put back at rest where they last were.
+pub fn check_finite() -> impl ParallelRunnable {
+    SystemBuilder::new("check finite")
+        .with_query(<(
+            Option<&BoidId>,
+            Option<&PreviousPos>,
+            &Forces,
+            &mut Acceleration,
+            &mut Velocity,
+            &mut Pos,
+        )>::query())
+        .build(|_, world, _, query| {
+            for (id, previous, forces, acc, vel, pos) in query.iter_mut(world) {
+                let mut broken = forces
+                    .named()
+                    .iter()
+                    .filter(|(_, force)| !is_finite(*force))
+                    .map(|(name, _)| format!("{} force", name))
+                    .collect::<Vec<_>>();
+                for (name, value) in &[
+                    ("acceleration", acc.0),
+                    ("velocity", vel.0),
+                    ("position", pos.0),
+                ] {
+                    if !is_finite(*value) {
+                        broken.push(name.to_string());
+                    }
+                }
+
+                if broken.is_empty() {
+                    continue;
+                }
+
+                let id = id.map(|id| id.0 as i64).unwrap_or(-1);
+                godot_error!("boid {} isn't finite: {}", id, broken.join(", "));
+
+                acc.0 = Vector2::ZERO;
+                vel.0 = Vector2::ZERO;
+                if !is_finite(pos.0) {
+                    pos.0 = previous
+                        .map(|previous| previous.0)
+                        .filter(|previous| is_finite(*previous))
+                        .unwrap_or(Vector2::ZERO);
+                }
+            }
+        })
+}
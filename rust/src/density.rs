@@ -0,0 +1,132 @@
+use gdnative::api::{Image, ImageTexture};
+use gdnative::core_types::{ByteArray, Rect2, Vector2};
+use gdnative::object::ownership::Unique;
+use gdnative::object::Ref;
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::boids::Pos;
+use crate::gameworld::{Delta, Viewport};
+
+// Side of a heat map cell, and so one texel of the texture, in pixels
+pub const DENSITY_CELL_SIZE: f32 = 16.;
+// Default seconds for the heat left by the boids to fade to a third
+const DEFAULT_DENSITY_FADE: f32 = 10.;
+// Godot's `Texture.FLAG_FILTER`
+const TEXTURE_FLAG_FILTER: i64 = 4;
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Seconds boids have spent in each cell of a grid over the viewport, fading
+/// with time so it shows where the flock has been lately. A `fade` of zero
+/// keeps everything since the start.
+#[derive(Debug)]
+pub struct DensityMap {
+    pub fade: f32,
+    bounds: Rect2,
+    width: usize,
+    height: usize,
+    cells: Vec<f32>,
+}
+
+impl Default for DensityMap {
+    fn default() -> Self {
+        Self {
+            fade: DEFAULT_DENSITY_FADE,
+            bounds: Rect2::new(Vector2::ZERO, Vector2::ZERO),
+            width: 0,
+            height: 0,
+            cells: Vec::new(),
+        }
+    }
+}
+
+impl DensityMap {
+    pub fn bounds(&self) -> Rect2 {
+        self.bounds
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.iter_mut().for_each(|cell| *cell = 0.);
+    }
+
+    // Starts over whenever the viewport changes size or moves
+    fn fit(&mut self, bounds: Rect2) {
+        if bounds == self.bounds {
+            return;
+        }
+        self.bounds = bounds;
+        self.width = (bounds.size.x / DENSITY_CELL_SIZE).ceil().max(1.) as usize;
+        self.height = (bounds.size.y / DENSITY_CELL_SIZE).ceil().max(1.) as usize;
+        self.cells = vec![0.; self.width * self.height];
+    }
+
+    fn add(&mut self, pos: Vector2, amount: f32) {
+        let x = ((pos.x - self.bounds.position.x) / DENSITY_CELL_SIZE).floor();
+        let y = ((pos.y - self.bounds.position.y) / DENSITY_CELL_SIZE).floor();
+        if x < 0. || y < 0. || x as usize >= self.width || y as usize >= self.height {
+            return;
+        }
+        self.cells[y as usize * self.width + x as usize] += amount;
+    }
+
+    /// One texel per cell, scaled so the busiest cell is fully lit. Goes
+    /// from transparent through blue and red to yellow.
+    pub fn to_texture(&self) -> Ref<ImageTexture, Unique> {
+        let max = self.cells.iter().copied().fold(0f32, f32::max);
+
+        let mut data = ByteArray::new();
+        for cell in &self.cells {
+            let heat = if max > 0. { cell / max } else { 0. };
+            let (r, g, b) = if heat < 0.5 {
+                (heat * 2., 0., 1. - heat * 2.)
+            } else {
+                (1., heat * 2. - 1., 0.)
+            };
+            for channel in &[r, g, b, heat.sqrt()] {
+                data.push((channel * 255.) as u8);
+            }
+        }
+
+        let image = Image::new();
+        let texture = ImageTexture::new();
+        if !self.cells.is_empty() {
+            image.create_from_data(
+                self.width as i64,
+                self.height as i64,
+                false,
+                Image::FORMAT_RGBA8,
+                data,
+            );
+            texture.create_from_image(image, TEXTURE_FLAG_FILTER);
+        }
+        texture
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+pub fn accumulate_density() -> impl ParallelRunnable {
+    SystemBuilder::new("accumulate density")
+        .read_resource::<Delta>()
+        .read_resource::<Viewport>()
+        .write_resource::<DensityMap>()
+        .with_query(<&Pos>::query())
+        .build(|_, world, resources, query| {
+            let (delta, viewport, density) = resources;
+            density.fit(viewport.0);
+
+            if density.fade > 0. {
+                let keep = (-delta.0 / density.fade).exp();
+                density.cells.iter_mut().for_each(|cell| *cell *= keep);
+            }
+
+            for pos in query.iter(world) {
+                density.add(pos.0, delta.0);
+            }
+        })
+}
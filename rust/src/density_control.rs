@@ -0,0 +1,124 @@
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::gameworld::{Delta, PerceptionRadii};
+use crate::spatial::FlockIndex;
+
+// Bounds on how far the controller can push the flock, as the log of the
+// multipliers, so a target the flock can't reach doesn't wind up forever
+const MAX_TIGHTNESS: f32 = 1.5;
+// Ticks between measurements
+const MEASURE_INTERVAL: usize = 10;
+// How much of each new measurement goes into the running one
+const SMOOTHING: f32 = 0.3;
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Holds the mean nearest neighbour distance at `target` pixels by scaling
+/// cohesion up and separation down while the flock is too spread out, and
+/// the other way around while it's too packed. The multipliers apply on top
+/// of `CohesionMul` and `SeparationMul`, so the sliders still set the base.
+/// A `target` of zero turns it off.
+#[derive(Debug, Clone, Copy)]
+pub struct DensityControl {
+    pub target: f32,
+    // Change in tightness per second for being off by the whole target
+    pub gain: f32,
+    // Smoothed mean distance to the nearest neighbour, zero before the first
+    // measurement
+    pub measured: f32,
+    pub cohesion: f32,
+    pub separation: f32,
+    // Log of the cohesion multiplier, separation gets the opposite
+    tightness: f32,
+    ticks: usize,
+}
+
+impl Default for DensityControl {
+    fn default() -> Self {
+        Self {
+            target: 0.,
+            gain: 0.5,
+            measured: 0.,
+            cohesion: 1.,
+            separation: 1.,
+            tightness: 0.,
+            ticks: 0,
+        }
+    }
+}
+
+impl DensityControl {
+    // Past the cohesion radius there's no nearest neighbour to measure
+    pub fn sanitized_target(target: f32, radius: f32) -> f32 {
+        target.max(0.).min(radius)
+    }
+
+    // Back to plain multipliers, for when it's turned off
+    pub fn reset(&mut self) {
+        *self = Self {
+            target: self.target,
+            gain: self.gain,
+            ..Self::default()
+        };
+    }
+
+    fn adjust(&mut self, error: f32, delta: f32) {
+        self.tightness = (self.tightness + error * self.gain * delta)
+            .max(-MAX_TIGHTNESS)
+            .min(MAX_TIGHTNESS);
+        self.cohesion = self.tightness.exp();
+        self.separation = (-self.tightness).exp();
+    }
+}
+
+// Boids with nobody in the cohesion radius are left out, they aren't part of
+// any flock to tighten
+fn mean_nearest(index: &FlockIndex, radius: f32) -> Option<f32> {
+    let distances = (0..index.positions.len())
+        .filter_map(|boid| index.nearest_distance(boid, radius))
+        .collect::<Vec<_>>();
+    if distances.is_empty() {
+        return None;
+    }
+    Some(distances.iter().sum::<f32>() / distances.len() as f32)
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn control_density() -> impl ParallelRunnable {
+    SystemBuilder::new("control density")
+        .read_resource::<Delta>()
+        .read_resource::<FlockIndex>()
+        .read_resource::<PerceptionRadii>()
+        .write_resource::<DensityControl>()
+        .build(|_, _, resources, _| {
+            let (delta, index, radii, control) = resources;
+            if control.target <= 0. {
+                control.reset();
+                return;
+            }
+
+            control.ticks += 1;
+            if control.ticks % MEASURE_INTERVAL != 0 {
+                return;
+            }
+
+            let nearest = match mean_nearest(index, radii.cohesion) {
+                Some(nearest) => nearest,
+                None => return,
+            };
+            control.measured = if control.measured > 0. {
+                control.measured + (nearest - control.measured) * SMOOTHING
+            } else {
+                nearest
+            };
+
+            // Positive while too spread out
+            let error = (control.measured - control.target) / control.target;
+            control.adjust(error, delta.0 * MEASURE_INTERVAL as f32);
+        })
+}
@@ -0,0 +1,128 @@
+use gdnative::core_types::Vector2;
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::boids::{Pos, Velocity};
+use crate::gameworld::Delta;
+use crate::species::Species;
+
+// Newborns start with what is left after the parent has paid for the split
+const NEWBORN_NOURISHMENT: f32 = 0.5;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+/// How well fed a boid is, between 0 (starved) and 1 (ready to split).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Nourishment(pub f32);
+
+impl Nourishment {
+    pub fn newborn() -> Self {
+        Self(NEWBORN_NOURISHMENT)
+    }
+}
+
+/// Food that feeds every boid within `radius`. Patches are never used up,
+/// consumable morsels are eaten by the first boid to touch them.
+#[derive(Debug, Clone, Copy)]
+pub struct Food {
+    pub position: Vector2,
+    pub radius: f32,
+    pub consumable: bool,
+}
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Rates are per second. New boids are only born while the population is
+/// below `max_population`.
+#[derive(Debug, Clone, Copy)]
+pub struct Ecology {
+    pub enabled: bool,
+    pub feed_rate: f32,
+    pub starve_rate: f32,
+    pub max_population: usize,
+}
+
+impl Default for Ecology {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            feed_rate: 0.25,
+            starve_rate: 0.05,
+            max_population: 300,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Birth {
+    pub pos: Vector2,
+    pub velocity: Vector2,
+    pub species: Species,
+}
+
+/// Births and deaths from the last tick. Spawning and freeing boids needs
+/// their sprites, so the `GameWorld` drains these after the schedule has run.
+#[derive(Debug, Default)]
+pub struct PopulationChanges {
+    pub births: Vec<Birth>,
+    pub deaths: Vec<Entity>,
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn ecology() -> impl ParallelRunnable {
+    SystemBuilder::new("ecology")
+        .read_resource::<Delta>()
+        .read_resource::<Ecology>()
+        .write_resource::<PopulationChanges>()
+        .with_query(<&Food>::query())
+        .with_query(<(
+            Entity,
+            &Pos,
+            &Velocity,
+            &mut Nourishment,
+            Option<&Species>,
+        )>::query())
+        .build(|_, world, resources, queries| {
+            let (delta, ecology, changes) = resources;
+            let (food, boids) = queries;
+            if !ecology.enabled {
+                return;
+            }
+
+            let food = food.iter(world).copied().collect::<Vec<_>>();
+            let mut population = boids.iter_mut(world).count();
+
+            for (&entity, pos, velocity, nourishment, species) in boids.iter_mut(world) {
+                let fed = food.iter().any(|food| {
+                    (food.position - pos.0).length_squared() <= food.radius * food.radius
+                });
+
+                let rate = if fed {
+                    ecology.feed_rate
+                } else {
+                    -ecology.starve_rate
+                };
+                nourishment.0 = (nourishment.0 + rate * delta.0).min(1.);
+
+                if nourishment.0 <= 0. {
+                    changes.deaths.push(entity);
+                    population = population.saturating_sub(1);
+                } else if nourishment.0 >= 1. && population < ecology.max_population {
+                    // Both halves carry on with what the parent had left
+                    nourishment.0 = NEWBORN_NOURISHMENT;
+                    changes.births.push(Birth {
+                        pos: pos.0,
+                        velocity: -velocity.0,
+                        species: species.copied().unwrap_or_default(),
+                    });
+                    population += 1;
+                }
+            }
+        })
+}
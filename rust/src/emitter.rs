@@ -0,0 +1,229 @@
+use gdnative::prelude::*;
+use legion::systems::{ParallelRunnable, Runnable};
+use legion::*;
+use rand::prelude::*;
+
+use crate::boids::{live_node, rotated, MAX_SPEED};
+use crate::ecology::{Birth, PopulationChanges};
+use crate::gameworld::Delta;
+use crate::species::Species;
+
+// Boids per second
+const DEFAULT_RATE: f32 = 5.;
+// Width of the cone boids leave in, in degrees
+const DEFAULT_SPREAD: f32 = 30.;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+/// Spawns boids at `position` heading along `direction`, give or take half
+/// of `spread` radians. Fractions of a boid carry over to the next tick.
+#[derive(Debug, Clone, Copy)]
+pub struct Emitter {
+    pub position: Vector2,
+    pub direction: f32,
+    pub rate: f32,
+    pub spread: f32,
+    pub speed: f32,
+    pub species: Species,
+    pub owed: f32,
+}
+
+// The `BoidEmitter` node an `Emitter` follows
+pub struct EmitterNode(pub Ref<Node2D>);
+
+impl EmitterNode {
+    pub unsafe fn node(&self) -> Option<TRef<Node2D>> {
+        live_node(&self.0)
+    }
+
+    pub unsafe fn is_alive(&self) -> bool {
+        self.node().is_some()
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn sync_emitters() -> impl Runnable {
+    SystemBuilder::new("sync emitters")
+        .with_query(<(&EmitterNode, &mut Emitter)>::query())
+        .build(|_, world, _, query| {
+            for (node, emitter) in query.iter_mut(world) {
+                if let Some(node) = unsafe { node.node() } {
+                    emitter.position = node.global_position();
+                    emitter.direction = node.global_rotation() as f32;
+                }
+            }
+        })
+}
+
+// Queues births for the `GameWorld` to spawn, the same as the ecology
+pub fn emit_boids() -> impl ParallelRunnable {
+    SystemBuilder::new("emit boids")
+        .read_resource::<Delta>()
+        .write_resource::<PopulationChanges>()
+        .with_query(<&mut Emitter>::query())
+        .build(|_, world, resources, query| {
+            let (delta, changes) = resources;
+            let mut rng = thread_rng();
+
+            for emitter in query.iter_mut(world) {
+                emitter.owed += emitter.rate * delta.0;
+                while emitter.owed >= 1. {
+                    emitter.owed -= 1.;
+
+                    let half = emitter.spread / 2.;
+                    let offset = if half > 0. {
+                        rng.gen_range(-half, half)
+                    } else {
+                        0.
+                    };
+                    let heading = Vector2::new(emitter.speed, 0.);
+                    changes.births.push(Birth {
+                        pos: emitter.position,
+                        velocity: rotated(heading, emitter.direction + offset),
+                        species: emitter.species,
+                    });
+                }
+            }
+        })
+}
+
+// -----------------------------------------------------------------------------
+//     - Godot nodes -
+// -----------------------------------------------------------------------------
+
+/// Spawns boids into the parent `GameWorld` for as long as it's in the tree.
+/// Boids leave along the node's rotation, `spread` is in degrees and
+/// `speed` in pixels per second.
+#[derive(NativeClass)]
+#[inherit(Node2D)]
+#[register_with(Self::register_properties)]
+pub struct BoidEmitter {
+    emitting: bool,
+    rate: f32,
+    spread: f32,
+    speed: f32,
+    species: i64,
+}
+
+#[methods]
+impl BoidEmitter {
+    pub fn new(_owner: &Node2D) -> Self {
+        Self {
+            emitting: true,
+            rate: DEFAULT_RATE,
+            spread: DEFAULT_SPREAD,
+            speed: MAX_SPEED,
+            species: 0,
+        }
+    }
+
+    fn register_properties(builder: &ClassBuilder<Self>) {
+        builder
+            .property("emitting")
+            .with_default(true)
+            .with_getter(|this: &Self, _| this.emitting)
+            .with_setter(|this: &mut Self, owner: TRef<Node2D>, emitting: bool| {
+                this.emitting = emitting;
+                this.update(owner);
+            })
+            .done();
+
+        builder
+            .property("rate")
+            .with_default(DEFAULT_RATE)
+            .with_getter(|this: &Self, _| this.rate)
+            .with_setter(|this: &mut Self, owner: TRef<Node2D>, rate: f32| {
+                this.rate = rate.max(0.);
+                this.update(owner);
+            })
+            .done();
+
+        builder
+            .property("spread")
+            .with_default(DEFAULT_SPREAD)
+            .with_getter(|this: &Self, _| this.spread)
+            .with_setter(|this: &mut Self, owner: TRef<Node2D>, spread: f32| {
+                this.spread = spread.max(0.).min(360.);
+                this.update(owner);
+            })
+            .done();
+
+        builder
+            .property("speed")
+            .with_default(MAX_SPEED)
+            .with_getter(|this: &Self, _| this.speed)
+            .with_setter(|this: &mut Self, owner: TRef<Node2D>, speed: f32| {
+                this.speed = speed.max(0.);
+                this.update(owner);
+            })
+            .done();
+
+        builder
+            .property("species")
+            .with_default(0)
+            .with_getter(|this: &Self, _| this.species)
+            .with_setter(|this: &mut Self, owner: TRef<Node2D>, species: i64| {
+                this.species = species.max(0);
+                this.update(owner);
+            })
+            .done();
+    }
+
+    fn update(&self, owner: TRef<Node2D>) {
+        if owner.is_inside_tree() {
+            unsafe { self.register(owner) };
+        }
+    }
+
+    // Registering again replaces the emitter, stopping removes it
+    unsafe fn register(&self, owner: TRef<Node2D>) {
+        let parent = match owner.get_parent() {
+            Some(parent) => parent.assume_safe(),
+            None => return,
+        };
+
+        if !self.emitting {
+            unregister(owner);
+            return;
+        }
+
+        if !parent.has_method("add_emitter") {
+            godot_error!("boid emitter must be a child of a GameWorld");
+            return;
+        }
+
+        parent.call(
+            "add_emitter",
+            &[
+                owner.to_variant(),
+                Variant::new(self.rate as f64),
+                Variant::new(self.spread.to_radians() as f64),
+                Variant::new(self.speed as f64),
+                Variant::new(self.species),
+            ],
+        );
+    }
+
+    #[method]
+    pub fn _ready(&mut self, #[base] owner: TRef<Node2D>) {
+        unsafe { self.register(owner) };
+    }
+
+    #[method]
+    pub fn _exit_tree(&mut self, #[base] owner: TRef<Node2D>) {
+        unsafe { unregister(owner) };
+    }
+}
+
+unsafe fn unregister(owner: TRef<Node2D>) {
+    if let Some(parent) = owner.get_parent() {
+        let parent = parent.assume_safe();
+        if parent.has_method("remove_emitter") {
+            parent.call("remove_emitter", &[owner.to_variant()]);
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::boids::{Acceleration, Velocity, MAX_SPEED};
+use crate::gameworld::Delta;
+
+// Exhausted boids only get going again once they are back to this level
+const RECOVERED_LEVEL: f32 = 0.5;
+
+// Fraction of their max speed exhausted boids fly at
+pub const EXHAUSTED_SPEED_FACTOR: f32 = 0.4;
+// How much steering an exhausted, gliding boid still does
+pub const EXHAUSTED_STEERING: f32 = 0.2;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+pub struct Energy {
+    pub level: f32,
+    pub exhausted: bool,
+}
+
+impl Energy {
+    pub fn full() -> Self {
+        Self {
+            level: 1.,
+            exhausted: false,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+// Energy per second spent at full effort
+pub struct EnergyDrain(pub f32);
+
+// Energy per second regained when not making any effort
+pub struct EnergyRecovery(pub f32);
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+// Effort is how fast the boid flies plus how hard it's accelerating, both as
+// a fraction of `MAX_SPEED`
+pub fn stamina() -> impl ParallelRunnable {
+    SystemBuilder::new("stamina")
+        .read_resource::<Delta>()
+        .read_resource::<EnergyDrain>()
+        .read_resource::<EnergyRecovery>()
+        .with_query(<(&Velocity, &Acceleration, &mut Energy)>::query())
+        .build(|_, world, resources, query| {
+            let (delta, drain, recovery) = resources;
+
+            for (vel, acc, energy) in query.iter_mut(world) {
+                let effort = ((vel.0.length() + acc.0.length()) / (MAX_SPEED * 2.)).min(1.);
+                let change = recovery.0 * (1. - effort) - drain.0 * effort;
+                energy.level = (energy.level + change * delta.0).max(0.).min(1.);
+
+                if energy.level <= 0. {
+                    energy.exhausted = true;
+                } else if energy.level >= RECOVERED_LEVEL {
+                    energy.exhausted = false;
+                }
+            }
+        })
+}
@@ -0,0 +1,46 @@
+use std::fmt;
+
+use gdnative::core_types::GodotError;
+
+pub type Result<T> = std::result::Result<T, BoidsError>;
+
+#[derive(Debug)]
+pub enum BoidsError {
+    /// A scene or resource at this path couldn't be loaded or instanced
+    ResourceLoad(String),
+    /// No node of the expected type at this path
+    NodeNotFound(String),
+    /// Something asked for by name (a stamp, a preset) that doesn't exist
+    Missing(String),
+    /// A value passed in from GDScript or a config file that makes no sense
+    InvalidArgument(String),
+    Godot(GodotError),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for BoidsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BoidsError::ResourceLoad(path) => write!(f, "failed to load \"{}\"", path),
+            BoidsError::NodeNotFound(path) => write!(f, "no matching node at \"{}\"", path),
+            BoidsError::Missing(name) => write!(f, "nothing named \"{}\"", name),
+            BoidsError::InvalidArgument(msg) => write!(f, "invalid argument: {}", msg),
+            BoidsError::Godot(err) => write!(f, "godot error: {:?}", err),
+            BoidsError::Json(err) => write!(f, "json error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for BoidsError {}
+
+impl From<GodotError> for BoidsError {
+    fn from(err: GodotError) -> Self {
+        BoidsError::Godot(err)
+    }
+}
+
+impl From<serde_json::Error> for BoidsError {
+    fn from(err: serde_json::Error) -> Self {
+        BoidsError::Json(err)
+    }
+}
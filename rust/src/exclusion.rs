@@ -0,0 +1,112 @@
+use gdnative::core_types::{Rect2, Transform2D, Vector2};
+use legion::*;
+
+use crate::boids::MAX_SPEED;
+use crate::steering::{Neighbourhood, SteeringBehavior, SteeringBoid};
+
+// Boids start turning away this far from an exclusion rect
+const EXCLUSION_MARGIN: f32 = 60.;
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Screen rects set with `register_exclusion_rect`, like the tuning panel,
+/// that the boids keep out of. `world` are the same rects where the camera
+/// shows them, updated by the `GameWorld` every physics frame.
+#[derive(Debug, Default, Clone)]
+pub struct ExclusionRects {
+    pub screen: Vec<Rect2>,
+    pub world: Vec<Rect2>,
+}
+
+impl ExclusionRects {
+    /// `screen_to_world` takes viewport pixels to the `GameWorld`'s canvas.
+    /// Removed rects are left empty and skipped.
+    pub fn update(&mut self, screen_to_world: &Transform2D) {
+        self.world = self
+            .screen
+            .iter()
+            .filter(|rect| rect.size.x > 0. && rect.size.y > 0.)
+            .map(|rect| transformed_bounds(rect, screen_to_world))
+            .collect();
+    }
+}
+
+// Smallest rect holding the transformed corners
+fn transformed_bounds(rect: &Rect2, transform: &Transform2D) -> Rect2 {
+    let corners = [
+        Vector2::new(rect.position.x, rect.position.y),
+        Vector2::new(rect.end().x, rect.position.y),
+        Vector2::new(rect.position.x, rect.end().y),
+        Vector2::new(rect.end().x, rect.end().y),
+    ];
+    let mut min = Vector2::new(std::f32::MAX, std::f32::MAX);
+    let mut max = Vector2::new(std::f32::MIN, std::f32::MIN);
+    for corner in &corners {
+        let corner = transform.xform(*corner);
+        min = Vector2::new(min.x.min(corner.x), min.y.min(corner.y));
+        max = Vector2::new(max.x.max(corner.x), max.y.max(corner.y));
+    }
+    Rect2::new(min, (max - min))
+}
+
+// -----------------------------------------------------------------------------
+//     - Behaviours -
+// -----------------------------------------------------------------------------
+
+// Away from the closest point of each rect, stronger the closer, and out the
+// nearest side at full speed for boids already inside
+#[derive(Default)]
+pub struct ExclusionBehavior {
+    rects: Vec<Rect2>,
+}
+
+impl SteeringBehavior for ExclusionBehavior {
+    fn name(&self) -> &str {
+        "exclusion"
+    }
+
+    fn prepare(&mut self, _: &World, resources: &Resources) {
+        self.rects.clear();
+        if let Some(exclusion) = resources.get::<ExclusionRects>() {
+            self.rects.extend_from_slice(&exclusion.world);
+        }
+    }
+
+    fn compute(&mut self, boid: &SteeringBoid, _: &Neighbourhood, _: &Resources) -> Vector2 {
+        let mut push = Vector2::ZERO;
+        for rect in &self.rects {
+            let closest = Vector2::new(
+                boid.pos.x.max(rect.position.x).min(rect.end().x),
+                boid.pos.y.max(rect.position.y).min(rect.end().y),
+            );
+            let away = boid.pos - closest;
+            let distance = away.length();
+
+            if distance == 0. {
+                push += out_of(rect, boid.pos) * MAX_SPEED;
+            } else if distance < EXCLUSION_MARGIN {
+                push += away / distance * MAX_SPEED * (1. - distance / EXCLUSION_MARGIN);
+            }
+        }
+        push
+    }
+}
+
+// Towards the nearest side of a rect the point is inside
+fn out_of(rect: &Rect2, pos: Vector2) -> Vector2 {
+    let sides = [
+        (pos.x - rect.position.x, Vector2::new(-1., 0.)),
+        (rect.end().x - pos.x, Vector2::new(1., 0.)),
+        (pos.y - rect.position.y, Vector2::new(0., -1.)),
+        (rect.end().y - pos.y, Vector2::new(0., 1.)),
+    ];
+    let mut nearest = sides[0];
+    for side in &sides[1..] {
+        if side.0 < nearest.0 {
+            nearest = *side;
+        }
+    }
+    nearest.1
+}
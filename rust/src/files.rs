@@ -0,0 +1,25 @@
+use gdnative::api::File;
+
+use crate::error::Result;
+
+// Godot's File understands res:// and user:// paths, std::fs doesn't
+
+pub fn write_string(path: &str, text: &str) -> Result<()> {
+    let file = File::new();
+    file.open(path, File::WRITE)?;
+    file.store_string(text);
+    file.close();
+    Ok(())
+}
+
+pub fn read_string(path: &str) -> Result<String> {
+    let file = File::new();
+    file.open(path, File::READ)?;
+    let text = file.get_as_text().to_string();
+    file.close();
+    Ok(text)
+}
+
+pub fn exists(path: &str) -> bool {
+    File::new().file_exists(path)
+}
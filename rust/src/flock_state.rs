@@ -0,0 +1,140 @@
+use gdnative::core_types::Vector2;
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::gameworld::Delta;
+use crate::metrics::FlockStats;
+use crate::spatial::FlockIndex;
+
+// Order parameters above this count as high, below `LOW_ORDER` as low, and
+// in between the flock is changing from one state to another
+const HIGH_ORDER: f32 = 0.65;
+const LOW_ORDER: f32 = 0.35;
+// Seconds a new state has to last before it's reported, so a flock on the
+// edge doesn't flicker between two
+const STATE_HOLD: f32 = 1.;
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlockState {
+    // Everybody flying the same way
+    Polarized,
+    // Circling around the centre, a torus
+    Milling,
+    // Bunched up with no common direction
+    Swarming,
+}
+
+impl FlockState {
+    pub fn name(self) -> &'static str {
+        match self {
+            FlockState::Polarized => "polarized",
+            FlockState::Milling => "milling",
+            FlockState::Swarming => "swarming",
+        }
+    }
+
+    // `None` in between states
+    fn classify(polarization: f32, milling: f32) -> Option<Self> {
+        if polarization > HIGH_ORDER && milling < LOW_ORDER {
+            Some(FlockState::Polarized)
+        } else if milling > HIGH_ORDER && polarization < LOW_ORDER {
+            Some(FlockState::Milling)
+        } else if polarization < LOW_ORDER && milling < LOW_ORDER {
+            Some(FlockState::Swarming)
+        } else {
+            None
+        }
+    }
+}
+
+/// The collective state of all the boids, from the polarization (length of
+/// the mean heading) and the milling (mean angular momentum of the headings
+/// around the centroid). Both go from 0 to 1.
+#[derive(Debug, Clone, Copy)]
+pub struct CollectiveState {
+    pub state: FlockState,
+    pub polarization: f32,
+    pub milling: f32,
+    // State the flock is heading into, and for how long it has been
+    pending: Option<FlockState>,
+    held: f32,
+}
+
+impl Default for CollectiveState {
+    fn default() -> Self {
+        Self {
+            state: FlockState::Swarming,
+            polarization: 0.,
+            milling: 0.,
+            pending: None,
+            held: 0.,
+        }
+    }
+}
+
+/// Set when the state changes. Drained by the `GameWorld`, which turns it
+/// into a `flock_state_changed` signal.
+#[derive(Debug, Default)]
+pub struct FlockStateChanged(pub Option<FlockState>);
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+// Runs after `flock_stats`, for the centroid
+pub fn classify_flock_state() -> impl ParallelRunnable {
+    SystemBuilder::new("classify flock state")
+        .read_resource::<Delta>()
+        .read_resource::<FlockIndex>()
+        .read_resource::<FlockStats>()
+        .write_resource::<CollectiveState>()
+        .write_resource::<FlockStateChanged>()
+        .build(|_, _, resources, _| {
+            let (delta, index, stats, collective, changed) = resources;
+            let boids = index.positions.len();
+            if boids == 0 {
+                return;
+            }
+
+            let mut heading = Vector2::ZERO;
+            let mut rotation = 0.;
+            for (pos, vel) in index.positions.iter().zip(&index.velocities) {
+                let speed = vel.length();
+                let from_centroid = *pos - stats.centroid;
+                let distance = from_centroid.length();
+                if speed == 0. || !speed.is_finite() {
+                    continue;
+                }
+                heading += *vel / speed;
+                if distance > 0. {
+                    rotation += (from_centroid / distance).cross(*vel / speed);
+                }
+            }
+            collective.polarization = heading.length() / boids as f32;
+            collective.milling = (rotation / boids as f32).abs();
+
+            let state = FlockState::classify(collective.polarization, collective.milling);
+            match state {
+                Some(state) if state != collective.state => {
+                    if collective.pending == Some(state) {
+                        collective.held += delta.0;
+                    } else {
+                        collective.pending = Some(state);
+                        collective.held = delta.0;
+                    }
+                    if collective.held >= STATE_HOLD {
+                        collective.state = state;
+                        collective.pending = None;
+                        changed.0 = Some(state);
+                    }
+                }
+                Some(_) => collective.pending = None,
+                // In between neither counts towards what's coming nor resets it
+                None => {}
+            }
+        })
+}
@@ -0,0 +1,163 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+use gdnative::core_types::Color;
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::boids::{Boid, ALIGNMENT_RADIUS};
+use crate::debug::Selected;
+use crate::node_commands::{NodeCommand, NodeCommands};
+use crate::spatial::FlockIndex;
+
+// Boids closer than this belong to the same flock
+const LINK_RADIUS: f32 = ALIGNMENT_RADIUS;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+// Which connected group of boids this one is part of. A flock keeps its id
+// from pass to pass through splits and merges, see `detect_flocks`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlockId(pub usize);
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+pub struct FlockDetection {
+    // Ticks between detection passes, zero turns detection off
+    pub interval: usize,
+    pub ticks: usize,
+    pub count: usize,
+    // The flock each boid was in after the last pass
+    previous: HashMap<Entity, usize>,
+    next_id: usize,
+}
+
+impl Default for FlockDetection {
+    fn default() -> Self {
+        Self {
+            interval: 10,
+            ticks: 0,
+            count: 0,
+            previous: HashMap::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl FlockDetection {
+    // Each cluster takes the id of the old flock it shares the most boids
+    // with, biggest overlaps first. Clusters left over are new flocks and get
+    // fresh ids, so a split keeps its colour on the bigger half.
+    fn assign_ids(&mut self, entities: &[Entity], labels: &[usize], count: usize) -> Vec<usize> {
+        let mut overlaps = HashMap::new();
+        for (entity, label) in entities.iter().zip(labels) {
+            if let Some(id) = self.previous.get(entity) {
+                *overlaps.entry((*label, *id)).or_insert(0) += 1;
+            }
+        }
+        let mut overlaps = overlaps.into_iter().collect::<Vec<_>>();
+        // Ties go to the lower id so the result doesn't depend on hash order
+        overlaps.sort_by_key(|&((label, id), count)| (Reverse(count), id, label));
+
+        let mut ids = vec![None; count];
+        let mut taken = HashSet::new();
+        for ((label, id), _) in overlaps {
+            if ids[label].is_none() && taken.insert(id) {
+                ids[label] = Some(id);
+            }
+        }
+        let ids = ids
+            .into_iter()
+            .map(|id| {
+                id.unwrap_or_else(|| {
+                    self.next_id += 1;
+                    self.next_id - 1
+                })
+            })
+            .collect::<Vec<_>>();
+
+        self.previous = entities
+            .iter()
+            .zip(labels)
+            .map(|(entity, label)| (*entity, ids[*label]))
+            .collect();
+        ids
+    }
+}
+
+pub struct ShowFlocks(pub bool);
+
+pub fn flock_color(flock: FlockId) -> Color {
+    let palette = [
+        Color::from_rgb(1., 1., 1.),
+        Color::from_rgb(1., 0.5, 0.5),
+        Color::from_rgb(0.5, 1., 0.5),
+        Color::from_rgb(0.5, 0.6, 1.),
+        Color::from_rgb(1., 1., 0.4),
+        Color::from_rgb(1., 0.5, 1.),
+        Color::from_rgb(0.4, 1., 1.),
+        Color::from_rgb(1., 0.7, 0.3),
+    ];
+    palette[flock.0 % palette.len()]
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn detect_flocks() -> impl ParallelRunnable {
+    SystemBuilder::new("detect flocks")
+        .read_resource::<FlockIndex>()
+        .write_resource::<FlockDetection>()
+        .with_query(<(Entity, &mut FlockId)>::query())
+        .build(|_, world, resources, query| {
+            let (index, detection) = resources;
+            if detection.interval == 0 {
+                return;
+            }
+
+            detection.ticks += 1;
+            if detection.ticks < detection.interval {
+                return;
+            }
+            detection.ticks = 0;
+
+            let (labels, count) = index.clusters(LINK_RADIUS);
+            detection.count = count;
+
+            let ids = detection.assign_ids(&index.entities, &labels, count);
+            let flocks = index
+                .entities
+                .iter()
+                .zip(labels)
+                .map(|(entity, label)| (*entity, ids[label]))
+                .collect::<HashMap<_, _>>();
+
+            for (entity, flock) in query.iter_mut(world) {
+                if let Some(id) = flocks.get(entity) {
+                    flock.0 = *id;
+                }
+            }
+        })
+}
+
+pub fn flock_tint() -> impl ParallelRunnable {
+    SystemBuilder::new("flock tint")
+        .read_resource::<ShowFlocks>()
+        .write_resource::<NodeCommands>()
+        .with_query(
+            <(Entity, &FlockId)>::query().filter(component::<Boid>() & !component::<Selected>()),
+        )
+        .build(|_, world, resources, query| {
+            let (show, commands) = resources;
+            if !show.0 {
+                return;
+            }
+
+            for (&entity, flock) in query.iter(world) {
+                commands.push(entity, NodeCommand::SetModulate(flock_color(*flock)));
+            }
+        })
+}
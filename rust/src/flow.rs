@@ -0,0 +1,125 @@
+use gdnative::api::Image;
+use gdnative::core_types::{Rect2, Vector2};
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::boids::{Forces, Pos};
+use crate::error::{BoidsError, Result};
+
+// A fully red or green pixel in a flow texture means this much flow
+const IMAGE_FLOW_STRENGTH: f32 = 50.;
+
+// -----------------------------------------------------------------------------
+//     - Flow grid -
+// -----------------------------------------------------------------------------
+
+/// Flow vectors sampled on a regular grid stretched over `bounds`
+pub struct FlowGrid {
+    bounds: Rect2,
+    width: usize,
+    height: usize,
+    vectors: Vec<Vector2>,
+}
+
+impl FlowGrid {
+    /// Red is the x component and green the y component, with 0.5 meaning no
+    /// flow along that axis.
+    pub fn from_image(path: &str, bounds: Rect2) -> Result<Self> {
+        let image = Image::new();
+        image.load(path)?;
+
+        let width = image.get_width() as usize;
+        let height = image.get_height() as usize;
+        if width == 0 || height == 0 {
+            return Err(BoidsError::InvalidArgument(format!(
+                "\"{}\" is empty",
+                path
+            )));
+        }
+
+        image.lock();
+        let vectors = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let pixel = image.get_pixel(x as i64, y as i64);
+                Vector2::new(pixel.r - 0.5, pixel.g - 0.5) * 2. * IMAGE_FLOW_STRENGTH
+            })
+            .collect();
+        image.unlock();
+
+        Ok(Self {
+            bounds,
+            width,
+            height,
+            vectors,
+        })
+    }
+
+    fn at(&self, x: usize, y: usize) -> Vector2 {
+        self.vectors[y.min(self.height - 1) * self.width + x.min(self.width - 1)]
+    }
+
+    /// Bilinear sample, positions outside the bounds get the nearest edge
+    pub fn sample(&self, pos: Vector2) -> Vector2 {
+        let u = (pos.x - self.bounds.position.x) / self.bounds.size.x;
+        let v = (pos.y - self.bounds.position.y) / self.bounds.size.y;
+        let x = (u.max(0.).min(1.) * (self.width - 1) as f32).max(0.);
+        let y = (v.max(0.).min(1.) * (self.height - 1) as f32).max(0.);
+
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (tx, ty) = (x.fract(), y.fract());
+
+        let top = self.at(x0, y0).linear_interpolate(self.at(x0 + 1, y0), tx);
+        let bottom = self
+            .at(x0, y0 + 1)
+            .linear_interpolate(self.at(x0 + 1, y0 + 1), tx);
+        top.linear_interpolate(bottom, ty)
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Constant wind plus an optional authored flow texture, added to every
+/// boid's acceleration each tick
+pub struct FlowField {
+    pub wind: Vector2,
+    pub grid: Option<FlowGrid>,
+}
+
+impl Default for FlowField {
+    fn default() -> Self {
+        Self {
+            wind: Vector2::ZERO,
+            grid: None,
+        }
+    }
+}
+
+impl FlowField {
+    pub fn at(&self, pos: Vector2) -> Vector2 {
+        match &self.grid {
+            Some(grid) => self.wind + grid.sample(pos),
+            None => self.wind,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn flow() -> impl ParallelRunnable {
+    SystemBuilder::new("flow")
+        .read_resource::<FlowField>()
+        .with_query(<(&Pos, &mut Forces)>::query())
+        .build(|_, world, field, query| {
+            if field.wind == Vector2::ZERO && field.grid.is_none() {
+                return;
+            }
+
+            for (pos, force) in query.iter_mut(world) {
+                force.flow = field.at(pos.0);
+            }
+        })
+}
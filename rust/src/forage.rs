@@ -0,0 +1,94 @@
+use gdnative::core_types::Vector2;
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::boids::{BoidId, Forces, Pos, Radius, MAX_SPEED};
+use crate::ecology::{Food, Nourishment};
+
+// Boids notice food closer than this
+pub const FORAGE_RANGE: f32 = 250.;
+pub const MORSEL_RADIUS: f32 = 8.;
+const MORSEL_NOURISHMENT: f32 = 0.25;
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Morsels eaten in the last tick, with the boid that ate them. Drained by
+/// the `GameWorld`, which turns them into `food_eaten` signals.
+#[derive(Debug, Default)]
+pub struct FoodEaten(pub Vec<(Vector2, BoidId)>);
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+// Steer towards the nearest food in range, and eat morsels on contact. Food
+// patches are only steered towards until the boid is inside them.
+pub fn forage() -> impl ParallelRunnable {
+    SystemBuilder::new("forage")
+        .write_resource::<FoodEaten>()
+        .with_query(<(Entity, &Food)>::query())
+        .with_query(<(
+            &Pos,
+            &Radius,
+            Option<&BoidId>,
+            Option<&mut Nourishment>,
+            &mut Forces,
+        )>::query())
+        .build(|cmd, world, eaten, queries| {
+            let (food, boids) = queries;
+            let mut food = food
+                .iter(world)
+                .map(|(&entity, food)| (entity, *food))
+                .collect::<Vec<_>>();
+            if food.is_empty() {
+                return;
+            }
+
+            for (pos, radius, id, nourishment, force) in boids.iter_mut(world) {
+                let nearest = food
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (_, food))| (i, food.position - pos.0))
+                    .filter(|(_, to)| to.length_squared() < FORAGE_RANGE * FORAGE_RANGE)
+                    .min_by(|(_, a), (_, b)| {
+                        a.length_squared()
+                            .partial_cmp(&b.length_squared())
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    });
+
+                let (index, to_food) = match nearest {
+                    Some(nearest) => nearest,
+                    None => continue,
+                };
+                let (entity, target) = food[index];
+                let distance = to_food.length();
+
+                if !target.consumable {
+                    if distance > target.radius {
+                        force.forage = to_food.clamped(MAX_SPEED);
+                    }
+                    continue;
+                }
+
+                if distance > target.radius + radius.0 {
+                    force.forage = to_food.clamped(MAX_SPEED);
+                    continue;
+                }
+
+                // Eaten, so nobody else goes for it this tick
+                food.swap_remove(index);
+                cmd.remove(entity);
+                if let Some(nourishment) = nourishment {
+                    nourishment.0 = (nourishment.0 + MORSEL_NOURISHMENT).min(1.);
+                }
+                if let Some(id) = id {
+                    eaten.0.push((target.position, *id));
+                }
+                if food.is_empty() {
+                    return;
+                }
+            }
+        })
+}
@@ -0,0 +1,208 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use gdnative::core_types::Vector2;
+use legion::systems::ParallelRunnable;
+use legion::*;
+use serde::{Deserialize, Serialize};
+
+use crate::boids::{Boid, Pos, Velocity, MAX_SPEED};
+use crate::flocks::FlockId;
+use crate::gameworld::{BoundaryMode, Viewport};
+use crate::leader::Leader;
+use crate::steering::{Neighbourhood, SteeringBehavior, SteeringBoid};
+
+// Seconds a boid takes to close the gap to its slot
+const FORMATION_CATCH_UP_TIME: f32 = 0.5;
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Each boid flies `behind` back from and `side` out to the side of the boid
+/// ahead of it, in the upwash of its wing. A V has an arm on both sides of
+/// the leader, an echelon puts everyone on one.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Formation {
+    pub enabled: bool,
+    pub behind: f32,
+    pub side: f32,
+    pub echelon: bool,
+}
+
+impl Default for Formation {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            behind: 30.,
+            side: 25.,
+            echelon: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FormationSlot {
+    pub position: Vector2,
+    // Velocity of the boid ahead, to keep pace with it
+    pub velocity: Vector2,
+}
+
+/// Where every boid in a formation should be, redone each tick by
+/// `assign_formation_slots`. The leaders have no slot.
+#[derive(Debug, Default)]
+pub struct FormationSlots(pub HashMap<Entity, FormationSlot>);
+
+// -----------------------------------------------------------------------------
+//     - Behaviours -
+// -----------------------------------------------------------------------------
+
+// Seek the slot, matching the pace of the boid ahead
+#[derive(Default)]
+pub struct FormationBehavior {
+    slots: HashMap<Entity, FormationSlot>,
+    boundary: Option<BoundaryMode>,
+    viewport: Option<Viewport>,
+}
+
+impl SteeringBehavior for FormationBehavior {
+    fn name(&self) -> &str {
+        "formation"
+    }
+
+    fn prepare(&mut self, _: &World, resources: &Resources) {
+        self.slots = resources
+            .get::<FormationSlots>()
+            .map(|slots| slots.0.clone())
+            .unwrap_or_default();
+        self.boundary = resources.get::<BoundaryMode>().map(|boundary| *boundary);
+        self.viewport = resources.get::<Viewport>().map(|viewport| *viewport);
+    }
+
+    fn compute(&mut self, boid: &SteeringBoid, _: &Neighbourhood, _: &Resources) -> Vector2 {
+        let slot = match self.slots.get(&boid.entity) {
+            Some(slot) => slot,
+            None => return Vector2::ZERO,
+        };
+
+        let to_slot = match (self.boundary, &self.viewport) {
+            (Some(boundary), Some(viewport)) => boundary.delta(viewport, boid.pos, slot.position),
+            _ => slot.position - boid.pos,
+        };
+        let desired = slot.velocity + to_slot / FORMATION_CATCH_UP_TIME;
+        desired.clamped(MAX_SPEED) - boid.vel
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+struct Member {
+    entity: Entity,
+    pos: Vector2,
+    vel: Vector2,
+    leader: bool,
+}
+
+// One formation per flock. The `Leader` boid leads it if there is one,
+// otherwise whoever is furthest ahead. The rest are split into arms by which
+// side of the leader they are on, so nobody has to cross over, and take
+// their place in the arm by how far back they are.
+pub fn assign_formation_slots() -> impl ParallelRunnable {
+    SystemBuilder::new("assign formation slots")
+        .read_resource::<Formation>()
+        .read_resource::<BoundaryMode>()
+        .read_resource::<Viewport>()
+        .write_resource::<FormationSlots>()
+        .with_query(
+            <(Entity, &Pos, &Velocity, Option<&FlockId>, Option<&Leader>)>::query()
+                .filter(component::<Boid>()),
+        )
+        .build(|_, world, resources, query| {
+            let (formation, boundary, viewport, slots) = resources;
+            slots.0.clear();
+            if !formation.enabled {
+                return;
+            }
+
+            let mut flocks = HashMap::<Option<usize>, Vec<Member>>::new();
+            for (&entity, pos, vel, flock, leader) in query.iter(world) {
+                flocks
+                    .entry(flock.map(|flock| flock.0))
+                    .or_default()
+                    .push(Member {
+                        entity,
+                        pos: pos.0,
+                        vel: vel.0,
+                        leader: leader.is_some(),
+                    });
+            }
+
+            for members in flocks.values() {
+                let heading = members.iter().fold(Vector2::ZERO, |sum, m| sum + m.vel);
+                if members.len() < 2 || heading.length_squared() == 0. {
+                    continue;
+                }
+                let heading = heading.normalized();
+                let across = Vector2::new(-heading.y, heading.x);
+
+                let forward = |m: &Member| m.pos.dot(heading);
+                let leader = members
+                    .iter()
+                    .find(|m| m.leader)
+                    .or_else(|| {
+                        members.iter().max_by(|a, b| {
+                            forward(a)
+                                .partial_cmp(&forward(b))
+                                .unwrap_or(Ordering::Equal)
+                        })
+                    })
+                    .unwrap_or(&members[0]);
+
+                // Offsets from the leader along and across its heading
+                let mut followers = members
+                    .iter()
+                    .filter(|m| m.entity != leader.entity)
+                    .map(|m| {
+                        let offset = boundary.delta(viewport, leader.pos, m.pos);
+                        (m, offset.dot(heading), offset.dot(across))
+                    })
+                    .collect::<Vec<_>>();
+                followers.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(Ordering::Equal));
+
+                let split = if formation.echelon {
+                    let left = followers
+                        .iter()
+                        .filter(|(_, _, across)| *across > 0.)
+                        .count();
+                    if left * 2 >= followers.len() {
+                        followers.len()
+                    } else {
+                        0
+                    }
+                } else {
+                    followers.len() / 2
+                };
+                let (left, right) = followers.split_at_mut(split);
+
+                for (arm, side) in vec![(left, 1.), (right, -1.)] {
+                    arm.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+
+                    let mut ahead = leader;
+                    for (member, _, _) in arm.iter() {
+                        let position =
+                            ahead.pos - heading * formation.behind + across * formation.side * side;
+                        slots.0.insert(
+                            member.entity,
+                            FormationSlot {
+                                position,
+                                velocity: ahead.vel,
+                            },
+                        );
+                        ahead = *member;
+                    }
+                }
+            }
+        })
+}
@@ -1,46 +1,483 @@
-use gdextras::input::InputEventExt;
-use gdextras::node_ext::NodeExt;
-use gdnative::{
-    godot_error, godot_wrap_method, godot_wrap_method_inner, godot_wrap_method_parameter_count,
-    methods, InputEvent, NativeClass, Node2D, Rect2, Vector2, InputEventMouse, Sprite
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::Instant;
+
+use gdnative::api::utils::NodeExt;
+use gdnative::api::{AudioStreamPlayer, Camera2D, Node2D, Object, JSON};
+use gdnative::core_types::{
+    Dictionary, GodotString, NodePath, Rect2, Transform2D, VariantArray, VariantType, Vector2,
 };
-use legion::prelude::*;
-use rand::prelude::*;
+use gdnative::derive::{methods, NativeClass};
+use gdnative::export::ClassBuilder;
+use gdnative::log::godot_error;
+use gdnative::object::{Ref, TRef};
+use legion::storage::Component;
+use legion::systems::Builder;
+use legion::*;
+use serde::{Deserialize, Serialize};
 
-use crate::boids::{Acceleration, Boid, Velocity, Pos, Forces, add_boid_systems};
+use crate::boids::{
+    add_boid_systems, add_render_systems, sync_sprites, BoidId, Target, ALIGNMENT_RADIUS,
+    COHESION_RADIUS, SEPARATION_RADIUS,
+};
+use crate::debug::Selected;
+use crate::error::{BoidsError, Result};
+use crate::gpu::GpuSteering;
+use crate::log::Verbosity;
+use crate::marker::follow_markers;
+use crate::node_commands::apply_node_commands;
+use crate::quality::QualityGovernor;
+use crate::replay::{replay, Replay};
 use crate::spawner;
+use crate::stages::{BuiltStages, Stage, StagedSchedule};
+use crate::timestep::FixedTimestep;
+
+// A class only gets one `#[methods]` block, so each feature module below
+// registers its exports with this instead, listed as
+// `fn name(&mut self, owner: &Node2D, arg: T) -> R;`
+macro_rules! register_exports {
+    (@add $builder:expr, $name:ident, $params:tt, ()) => {
+        register_exports!(@add $builder, $name, $params, (()));
+    };
+    (@add $builder:expr, $name:ident, $params:tt, ($ret:ty)) => {
+        $builder
+            .method(stringify!($name), register_exports!(@wrap $name, $params, $ret))
+            .done();
+    };
+    // The owner is always the first argument, passed as the base object
+    (
+        @wrap $name:ident,
+        (&mut self, $owner:ident: $base:ty $(, $arg:ident: $ty:ty)* $(,)?),
+        $ret:ty
+    ) => {
+        ::gdnative::export::godot_wrap_method!(
+            GameWorld,
+            false,
+            fn $name(&mut self, #[base] $owner: $base $(, $arg: $ty)*) -> $ret
+        )
+    };
+    (
+        @wrap $name:ident,
+        (&self, $owner:ident: $base:ty $(, $arg:ident: $ty:ty)* $(,)?),
+        $ret:ty
+    ) => {
+        ::gdnative::export::godot_wrap_method!(
+            GameWorld,
+            false,
+            fn $name(&self, #[base] $owner: $base $(, $arg: $ty)*) -> $ret
+        )
+    };
+    ($builder:expr, $(fn $name:ident $params:tt $(-> $ret:ty)?;)*) => {
+        $(register_exports!(@add $builder, $name, $params, ($($ret)?));)*
+    };
+}
+
+
+mod audio;
+mod boundaries;
+mod config;
+mod debug;
+mod ecology;
+mod environment;
+mod groups;
+mod impulses;
+mod input;
+mod linked;
+mod lookup;
+mod metrics;
+mod navigation;
+mod neighbours;
+mod nodes;
+mod recording;
+mod reload;
+mod rendering;
+mod spawning;
+mod species;
+mod steering;
+mod targets;
+mod timing;
+mod zones;
+
+pub use reload::stash_for_reload;
+
 const BOID_COUNT: usize = 80;
+const DEFAULT_TARGET_PATH: &str = "Target";
+// Owner metadata the flock is kept in while the library is reloaded
+const RELOAD_STATE_META: &str = "boids_reload_state";
+
+thread_local! {
+    // The owners of every started `GameWorld`, for `stash_for_reload`
+    static STARTED_WORLDS: RefCell<Vec<Ref<Node2D>>> = RefCell::new(Vec::new());
+}
+
+// A system for a stage, built again each time the stages are
+type CustomSystem = (Stage, fn(&mut Builder));
 
-fn physics_systems() -> Schedule {
-    let schedule = Schedule::builder();
-    let schedule = add_boid_systems(schedule);
-    schedule.build()
+// Systems from `add_system` go at the end of their stages
+fn boid_stages(custom: &[CustomSystem]) -> BuiltStages {
+    let stages = add_render_systems(add_boid_systems(StagedSchedule::new()));
+    custom
+        .iter()
+        .fold(stages, |stages, (stage, system)| stages.add_with(*stage, *system))
+        .build()
+}
+
+// Just enough to move the sprites between physics ticks
+fn frame_systems() -> Schedule {
+    Schedule::builder()
+        .add_system(sync_sprites())
+        .add_thread_local(apply_node_commands())
+        .add_thread_local(follow_markers())
+        .build()
+}
+
+fn replay_systems() -> Schedule {
+    Schedule::builder()
+        .add_system(replay())
+        .add_thread_local(apply_node_commands())
+        .add_thread_local(follow_markers())
+        .build()
 }
 
 // -----------------------------------------------------------------------------
 //     - Resources -
 // -----------------------------------------------------------------------------
 pub struct Delta(pub f32);
+pub struct NextBoidId(pub u64);
 pub struct CohesionMul(pub f32);
+// Upper bound on the cohesion steering force, before `CohesionMul`
+pub struct CohesionMaxForce(pub f32);
 pub struct SeparationMul(pub f32);
 pub struct AlignmentMul(pub f32);
+
+#[derive(Debug, Clone, Copy)]
+pub struct PerceptionRadii {
+    pub cohesion: f32,
+    pub separation: f32,
+    pub alignment: f32,
+}
+
+impl Default for PerceptionRadii {
+    fn default() -> Self {
+        Self {
+            cohesion: COHESION_RADIUS,
+            separation: SEPARATION_RADIUS,
+            alignment: ALIGNMENT_RADIUS,
+        }
+    }
+}
+
+/// The classic zonal model: with `enabled` each neighbour counts for
+/// separation inside `repulsion`, for alignment out to `orientation` and for
+/// cohesion out to `attraction`, blended over `falloff` around each edge,
+/// instead of each rule having a radius of its own. Scaled by the boid's
+/// perception like the radii.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ZonalBands {
+    pub enabled: bool,
+    pub repulsion: f32,
+    pub orientation: f32,
+    pub attraction: f32,
+    pub falloff: f32,
+}
+
+impl Default for ZonalBands {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            repulsion: 40.,
+            orientation: 100.,
+            attraction: 200.,
+            falloff: 20.,
+        }
+    }
+}
+
+impl ZonalBands {
+    // Keeps the edges in order
+    pub fn sanitized(self) -> Self {
+        let repulsion = self.repulsion.max(0.);
+        let orientation = self.orientation.max(repulsion);
+        Self {
+            enabled: self.enabled,
+            repulsion,
+            orientation,
+            attraction: self.attraction.max(orientation),
+            falloff: self.falloff.max(0.),
+        }
+    }
+}
+
+// How many boids `_ready` spawns
+pub struct BoidCount(pub usize);
+// Scene instanced for every new boid
+pub struct BoidScene(pub String);
+// Boids spawned at random positions keep at least this far apart where there
+// is room, zero lets them land on top of each other
+pub struct SpawnSpacing(pub f32);
 pub struct ShouldFlee(pub bool);
 pub struct ShouldSeek(pub bool);
 
-pub struct Target(pub Sprite);
+// Seek and flee aim at where targets will be rather than where they are,
+// looking at most `PredictionHorizon` seconds ahead
+pub struct Predictive(pub bool);
+pub struct PredictionHorizon(pub f32);
+
+// Degrees per second, zero turns it off
+pub struct MaxTurnRate(pub f32);
+
+// Scales the simulated time per physics tick, zero pauses the flock
+pub struct TimeScale(pub f32);
+
+// When on, the mouse pushes the flock around instead of moving the target
+pub struct MouseInteraction(pub bool);
+
+// Positive strength attracts, negative scatters, zero is off
+#[derive(Debug, Clone, Copy)]
+pub struct MouseForce {
+    pub position: Vector2,
+    pub strength: f32,
+}
+
+pub struct AvoidColliders(pub bool);
+
+#[derive(Debug, Clone, Copy)]
+pub struct ColliderHit {
+    pub point: Vector2,
+    pub normal: Vector2,
+}
+
+// What the ray along each boid's heading hit, cast before every tick since
+// the physics space is only valid inside `_physics_process`
+#[derive(Debug, Default)]
+pub struct ColliderHits(pub HashMap<Entity, ColliderHit>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NeighbourSearch {
+    BruteForce,
+    SpatialIndex,
+}
+
+// Metric: everything inside the perception radius.
+// Topological: only the `NearestCount` closest boids inside it, or a count
+// of the rule's own from `NearestCounts`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NeighbourMode {
+    Metric,
+    Topological,
+}
+
+pub struct NearestCount(pub usize);
+
+// Ticks each boid's `Neighbours` are reused for, 1 searches every tick
+pub struct NeighbourStaleness(pub usize);
+
+/// Runs the neighbour rules (cohesion, separation and alignment) for a boid
+/// only every `interval` ticks. Boids take turns so the work is spread out,
+/// in between they keep steering with their last result.
+#[derive(Debug, Clone, Copy)]
+pub struct SteeringInterval {
+    pub interval: usize,
+    pub tick: usize,
+}
+
+impl Default for SteeringInterval {
+    fn default() -> Self {
+        Self {
+            interval: 1,
+            tick: 0,
+        }
+    }
+}
+
+impl SteeringInterval {
+    // Boids without an id are never skipped
+    pub fn due(&self, id: Option<&BoidId>) -> bool {
+        match id {
+            Some(id) if self.interval > 1 => (id.0 as usize + self.tick) % self.interval == 0,
+            _ => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryMode {
+    Wrap,
+    Open,
+    // The edges are solid, see `Walls`
+    Walls,
+}
+
+impl BoundaryMode {
+    // Shortest vector from `from` to `to`, going through the edges when wrapping
+    pub fn delta(self, viewport: &Viewport, from: Vector2, to: Vector2) -> Vector2 {
+        match self {
+            BoundaryMode::Wrap => viewport.wrapped_delta(from, to),
+            BoundaryMode::Open | BoundaryMode::Walls => to - from,
+        }
+    }
+}
 
-unsafe impl Send for Target {}
-unsafe impl Sync for Target {}
+// How far past the edge a boid goes before it wraps around
+pub const WRAP_MARGIN: f32 = 16.;
 
+// The area the boids live in. This is the visible viewport unless
+// `set_world_bounds` gave a world of its own.
 #[derive(Debug, Clone, Copy)]
 pub struct Viewport(pub Rect2);
 
+// World set with `set_world_bounds`, `None` follows the viewport size
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WorldBounds(pub Option<Rect2>);
+
 impl Viewport {
-    fn from_vec2(size: Vector2) -> Self {
+    pub fn from_vec2(size: Vector2) -> Self {
         let origin = size / 2.;
-        let rect = Rect2::new(-origin.to_point(), size.to_size());
+        let rect = Rect2::new(-origin, size);
         Self(rect)
     }
+
+    // The viewport and the margin boids leave it by before wrapping
+    pub fn wrap_size(&self) -> Vector2 {
+        Vector2::new(
+            self.0.size.x + WRAP_MARGIN * 2.,
+            self.0.size.y + WRAP_MARGIN * 2.,
+        )
+    }
+
+    pub fn wrapped_delta(&self, from: Vector2, to: Vector2) -> Vector2 {
+        let size = self.wrap_size();
+        let mut delta = to - from;
+
+        if delta.x > size.x / 2. {
+            delta.x -= size.x;
+        } else if delta.x < -size.x / 2. {
+            delta.x += size.x;
+        }
+
+        if delta.y > size.y / 2. {
+            delta.y -= size.y;
+        } else if delta.y < -size.y / 2. {
+            delta.y += size.y;
+        }
+
+        delta
+    }
+
+    // Shifts that map `pos` onto the copies of the wrapped world that a square
+    // of `reach` around it overlaps, zero (the world itself) included
+    pub fn wrap_offsets(&self, pos: Vector2, reach: f32) -> Vec<Vector2> {
+        let size = self.wrap_size();
+        let min = self.0.position - Vector2::new(WRAP_MARGIN, WRAP_MARGIN);
+        let max = min + size;
+
+        let axis = |pos: f32, min: f32, max: f32, size: f32| {
+            let mut shifts = vec![0.];
+            if pos - reach < min {
+                shifts.push(size);
+            }
+            if pos + reach > max {
+                shifts.push(-size);
+            }
+            shifts
+        };
+
+        let xs = axis(pos.x, min.x, max.x, size.x);
+        let ys = axis(pos.y, min.y, max.y, size.y);
+        xs.iter()
+            .flat_map(|x| ys.iter().map(move |y| Vector2::new(*x, *y)))
+            .collect()
+    }
+}
+
+// Shared by the Godot node and the headless world
+// From the `GameWorld`'s canvas to viewport pixels, through the camera
+fn world_to_screen(owner: &Node2D) -> Transform2D {
+    owner.get_viewport_transform() * owner.get_canvas_transform()
+}
+
+// `affine_inverse` asserts, a node scaled to nothing gets the identity
+fn inverse_or_identity(transform: Transform2D) -> Transform2D {
+    if transform.determinant() == 0. {
+        Transform2D::IDENTITY
+    } else {
+        transform.affine_inverse()
+    }
+}
+
+pub fn default_resources() -> Resources {
+    let mut resources = Resources::default();
+
+    resources.insert(Verbosity::Warn);
+    resources.insert(Delta(0.));
+
+    spawning::insert_resources(&mut resources);
+    groups::insert_resources(&mut resources);
+    nodes::insert_resources(&mut resources);
+    debug::insert_resources(&mut resources);
+    input::insert_resources(&mut resources);
+    zones::insert_resources(&mut resources);
+    targets::insert_resources(&mut resources);
+    ecology::insert_resources(&mut resources);
+    species::insert_resources(&mut resources);
+    recording::insert_resources(&mut resources);
+    metrics::insert_resources(&mut resources);
+    config::insert_resources(&mut resources);
+    steering::insert_resources(&mut resources);
+    neighbours::insert_resources(&mut resources);
+    environment::insert_resources(&mut resources);
+    boundaries::insert_resources(&mut resources);
+    navigation::insert_resources(&mut resources);
+    rendering::insert_resources(&mut resources);
+    audio::insert_resources(&mut resources);
+    timing::insert_resources(&mut resources);
+    linked::insert_resources(&mut resources);
+
+    resources
+}
+
+fn json_dictionary(json: &str) -> Result<Dictionary> {
+    let parsed = JSON::godot_singleton()
+        .parse(json)
+        .ok_or_else(|| BoidsError::InvalidArgument("not valid JSON".to_string()))?;
+
+    unsafe { parsed.assume_safe() }
+        .result()
+        .to::<Dictionary>()
+        .ok_or_else(|| BoidsError::InvalidArgument("not a dictionary".to_string()))
+}
+
+// `None` if the entity is gone or has no `T`
+fn component_of<T: Component>(world: &World, entity: Entity) -> Option<&T> {
+    world.entry_ref(entity).ok()?.into_component::<T>().ok()
+}
+
+fn component_of_mut<T: Component>(world: &mut World, entity: Entity) -> Option<&mut T> {
+    world.entry(entity)?.into_component_mut::<T>().ok()
+}
+
+// These return false if the entity is gone
+fn add_component<T: Component>(world: &mut World, entity: Entity, component: T) -> bool {
+    match world.entry(entity) {
+        Some(mut entry) => {
+            entry.add_component(component);
+            true
+        }
+        None => false,
+    }
+}
+
+fn remove_component<T: Component>(world: &mut World, entity: Entity) -> bool {
+    match world.entry(entity) {
+        Some(mut entry) => {
+            entry.remove_component::<T>();
+            true
+        }
+        None => false,
+    }
 }
 
 // -----------------------------------------------------------------------------
@@ -49,123 +486,470 @@ impl Viewport {
 
 #[derive(NativeClass)]
 #[inherit(Node2D)]
+#[register_with(Self::register)]
 pub struct GameWorld {
     world: World,
     physics: Schedule,
+    render: Schedule,
+    frame: Schedule,
+    replay: Schedule,
     resources: Resources,
+    // Only while the gpu backend is in use
+    gpu: Option<GpuSteering>,
+    // Set with `set_lod_camera`
+    lod_camera: Option<Ref<Camera2D>>,
+    // Set with `attach_audio` and `attach_whoosh`
+    audio: Option<Ref<AudioStreamPlayer>>,
+    whoosh: Option<Ref<AudioStreamPlayer>>,
+    // Target and method set with `set_custom_force_callback`
+    custom_force: Option<(Ref<Object>, GodotString)>,
+    // Set with `link_world`
+    linked_worlds: Vec<Ref<Node2D>>,
+    // Added with `add_system`
+    custom_systems: Vec<CustomSystem>,
+    // The last viewport size seen, while it's polled for because
+    // `size_changed` couldn't be connected
+    polled_viewport_size: Option<Vector2>,
+    // What `walls_toggled(false)` goes back to, wrapping or open edges
+    boundary_under_walls: BoundaryMode,
+    // The `target_path` and `quit_on_cancel` properties
+    target_path: String,
+    quit_on_cancel: bool,
+    // False until `setup` ran, which it hasn't after a library reload
+    started: bool,
 }
 
 #[methods]
 impl GameWorld {
-    pub fn _init(_owner: Node2D) -> Self {
-        let mut resources = Resources::default();
-
-        // Resources
-        resources.insert(Delta(0.));
-        resources.insert(CohesionMul(1.0));
-        resources.insert(SeparationMul(1.0));
-        resources.insert(AlignmentMul(1.0));
-        resources.insert(ShouldSeek(false));
-        resources.insert(ShouldFlee(false));
-
-        let physics = physics_systems();
+    pub fn new(_owner: &Node2D) -> Self {
+        let resources = default_resources();
+        let BuiltStages {
+            physics,
+            presentation: render,
+        } = boid_stages(&[]);
+        let frame = frame_systems();
+        let replay = replay_systems();
 
         Self {
-            world: Universe::new().create_world(),
+            world: World::default(),
             resources,
             physics,
+            render,
+            frame,
+            replay,
+            gpu: None,
+            lod_camera: None,
+            audio: None,
+            whoosh: None,
+            custom_force: None,
+            linked_worlds: Vec::new(),
+            custom_systems: Vec::new(),
+            polled_viewport_size: None,
+            boundary_under_walls: BoundaryMode::Wrap,
+            target_path: DEFAULT_TARGET_PATH.to_string(),
+            quit_on_cancel: true,
+            started: false,
         }
     }
 
-    #[export]
-    pub unsafe fn _ready(&mut self, mut owner: Node2D) {
-        let mut rng = thread_rng();
+    fn register(builder: &ClassBuilder<Self>) {
+        Self::register_signals(builder);
+        Self::register_properties(builder);
 
-        // Add target
-        let target = owner.get_and_cast::<Sprite>("Target").expect("failed to get the target");
-        self.resources.insert(Target(target));
+        reload::register(builder);
+        spawning::register(builder);
+        lookup::register(builder);
+        groups::register(builder);
+        nodes::register(builder);
+        debug::register(builder);
+        input::register(builder);
+        impulses::register(builder);
+        zones::register(builder);
+        targets::register(builder);
+        ecology::register(builder);
+        species::register(builder);
+        recording::register(builder);
+        metrics::register(builder);
+        config::register(builder);
+        steering::register(builder);
+        neighbours::register(builder);
+        environment::register(builder);
+        boundaries::register(builder);
+        navigation::register(builder);
+        rendering::register(builder);
+        audio::register(builder);
+        timing::register(builder);
+        linked::register(builder);
+    }
 
-        // Add viewport rect
-        let size = owner.get_viewport().unwrap().get_size();
-        let viewport = Viewport::from_vec2(size);
-        self.resources.insert(viewport);
+    // Inspector and AnimationPlayer access to the main tunables, these go
+    // straight into the resources
+    fn register_properties(builder: &ClassBuilder<Self>) {
+        builder
+            .property("boid_count")
+            .with_default(BOID_COUNT as i64)
+            .with_getter(|this: &Self, _| {
+                this.resources.get::<BoidCount>().map(|count| count.0 as i64).unwrap_or(0)
+            })
+            .with_setter(|this: &mut Self, owner: TRef<Node2D>, count: i64| {
+                let count = count.max(0) as usize;
+                this.resources.get_mut::<BoidCount>().map(|mut boid_count| boid_count.0 = count);
 
-        for _ in 0..BOID_COUNT {
-            let mut boid = spawner::spawn_boid();
-            let x = rng.gen_range(viewport.0.min_x(), viewport.0.max_x());
-            let y = rng.gen_range(viewport.0.min_y(), viewport.0.max_y());
+                // Before `_ready` the count is only stored, `setup` spawns them
+                if this.resources.contains::<Viewport>() {
+                    if let Err(e) = unsafe { this.resize_flock(&owner, count) } {
+                        godot_error!("boid_count: {}", e);
+                    }
+                }
+            })
+            .done();
 
-            let pos = Vector2::new(x, y);
-            owner.add_child(Some(boid.to_node()), false);
-            boid.set_global_position(pos);
+        builder
+            .property("cohesion_mul")
+            .with_default(1.)
+            .with_getter(|this: &Self, _| {
+                this.resources.get::<CohesionMul>().map(|mul| mul.0).unwrap_or(0.)
+            })
+            .with_setter(|this: &mut Self, _, val: f32| {
+                this.resources.get_mut::<CohesionMul>().map(|mut mul| mul.0 = val);
+            })
+            .done();
 
-            let velocity = Vector2::new(rng.gen_range(-500., 500.), rng.gen_range(-500., 500.))
-                .normalize()
-                * 500f32;
+        builder
+            .property("separation_mul")
+            .with_default(1.)
+            .with_getter(|this: &Self, _| {
+                this.resources.get::<SeparationMul>().map(|mul| mul.0).unwrap_or(0.)
+            })
+            .with_setter(|this: &mut Self, _, val: f32| {
+                this.resources.get_mut::<SeparationMul>().map(|mut mul| mul.0 = val);
+            })
+            .done();
 
-            self.world.insert(
-                (),
-                Some((
-                    Boid(boid),
-                    Velocity(velocity),
-                    Acceleration(Vector2::zero()),
-                    Pos(pos),
-                    Forces::zero(),
-                )),
-            );
-        }
+        builder
+            .property("alignment_mul")
+            .with_default(1.)
+            .with_getter(|this: &Self, _| {
+                this.resources.get::<AlignmentMul>().map(|mul| mul.0).unwrap_or(0.)
+            })
+            .with_setter(|this: &mut Self, _, val: f32| {
+                this.resources.get_mut::<AlignmentMul>().map(|mut mul| mul.0 = val);
+            })
+            .done();
+
+        builder
+            .property("cohesion_radius")
+            .with_default(COHESION_RADIUS)
+            .with_getter(|this: &Self, _| this.perception_radii().cohesion)
+            .with_setter(|this: &mut Self, _, val: f32| {
+                this.resources
+                    .get_mut::<PerceptionRadii>()
+                    .map(|mut radii| radii.cohesion = val.max(0.));
+            })
+            .done();
+
+        builder
+            .property("separation_radius")
+            .with_default(SEPARATION_RADIUS)
+            .with_getter(|this: &Self, _| this.perception_radii().separation)
+            .with_setter(|this: &mut Self, _, val: f32| {
+                this.resources
+                    .get_mut::<PerceptionRadii>()
+                    .map(|mut radii| radii.separation = val.max(0.));
+            })
+            .done();
+
+        builder
+            .property("alignment_radius")
+            .with_default(ALIGNMENT_RADIUS)
+            .with_getter(|this: &Self, _| this.perception_radii().alignment)
+            .with_setter(|this: &mut Self, _, val: f32| {
+                this.resources
+                    .get_mut::<PerceptionRadii>()
+                    .map(|mut radii| radii.alignment = val.max(0.));
+            })
+            .done();
+
+        // Node lookups are relative to the GameWorld, so several of them can
+        // run side by side, each with its own target and boid scene
+        builder
+            .property("target_path")
+            .with_default(NodePath::from_str(DEFAULT_TARGET_PATH))
+            .with_getter(|this: &Self, _| NodePath::from_str(&this.target_path))
+            .with_setter(|this: &mut Self, _, path: NodePath| {
+                // Read in `_ready`, targets added later go through `add_target`
+                this.target_path = path.to_string();
+            })
+            .done();
+
+        builder
+            .property("boid_scene")
+            .with_default(GodotString::from_str(spawner::DEFAULT_BOID_SCENE))
+            .with_getter(|this: &Self, _| GodotString::from_str(&this.boid_scene()))
+            .with_setter(|this: &mut Self, owner: TRef<Node2D>, path: GodotString| {
+                // Before `_ready` the scene is only stored, `setup` spawns the flock
+                if !this.resources.contains::<Viewport>() {
+                    this.resources.insert(BoidScene(path.to_string()));
+                    return;
+                }
+                if let Err(e) = unsafe { this.use_boid_scene(&owner, path.to_string()) } {
+                    godot_error!("boid_scene: {}", e);
+                }
+            })
+            .done();
+
+        // Off for a GameWorld that isn't the whole game
+        builder
+            .property("quit_on_cancel")
+            .with_default(true)
+            .with_getter(|this: &Self, _| this.quit_on_cancel)
+            .with_setter(|this: &mut Self, _, quit: bool| this.quit_on_cancel = quit)
+            .done();
+    }
+
+    fn perception_radii(&self) -> PerceptionRadii {
+        self.resources.get::<PerceptionRadii>().map(|radii| *radii).unwrap_or_default()
+    }
+
+    fn register_signals(builder: &ClassBuilder<Self>) {
+        builder
+            .signal("food_eaten")
+            .with_param("position", VariantType::Vector2)
+            .with_param("boid_id", VariantType::I64)
+            .done();
+
+        builder
+            .signal("boid_entered_area")
+            .with_param("area_name", VariantType::GodotString)
+            .with_param("boid_id", VariantType::I64)
+            .done();
+
+        builder.signal("migration_completed").done();
+
+        builder
+            .signal("quality_changed")
+            .with_param("tier", VariantType::I64)
+            .done();
+
+        builder
+            .signal("flock_state_changed")
+            .with_param("state", VariantType::GodotString)
+            .done();
+
+        builder
+            .signal("waypoint_reached")
+            .with_param("index", VariantType::I64)
+            .done();
+
+        builder
+            .signal("boid_reached_target")
+            .with_param("id", VariantType::I64)
+            .done();
+
+        builder
+            .signal("startle_wave")
+            .with_param("origin", VariantType::Vector2)
+            .done();
     }
 
-    #[export]
-    pub fn _unhandled_input(&self, owner: Node2D, event: InputEvent) {
-        if event.action_pressed("ui_cancel") {
-            unsafe { owner.get_tree().map(|mut tree| tree.quit(0)) };
+    fn verbosity(&self) -> Verbosity {
+        self.resources.get::<Verbosity>().map(|v| *v).unwrap_or(Verbosity::Warn)
+    }
+
+    #[method]
+    pub unsafe fn _ready(&mut self, #[base] owner: TRef<Node2D>) {
+        if let Err(e) = self.setup(owner) {
+            godot_error!("GameWorld failed to start: {}", e);
         }
+    }
+
+    /// Runs whatever `system` adds to the builder at the end of `stage` from
+    /// now on, for crates building on the simulation. The schedules are
+    /// rebuilt, so this is best done once at startup.
+    pub fn add_system(&mut self, stage: Stage, system: fn(&mut Builder)) {
+        self.custom_systems.push((stage, system));
+        let stages = boid_stages(&self.custom_systems);
+        self.physics = stages.physics;
+        self.render = stages.presentation;
+    }
+
+    unsafe fn setup(&mut self, owner: TRef<Node2D>) -> Result<()> {
+        self.started = true;
+        STARTED_WORLDS.with(|worlds| worlds.borrow_mut().push(owner.claim()));
+        let verbosity = self.verbosity();
 
-        if let Some(ev) = event.cast::<InputEventMouse>() {
-            if ev.is_pressed() {
-                unsafe {
-                    let pos = owner.get_global_mouse_position();
-                    self.resources.get_mut::<Target>().map(|mut target| target.0.set_global_position(pos));
+        // Add target, the flock still works without one
+        if !self.target_path.is_empty() {
+            match owner.get_node_as::<Node2D>(self.target_path.as_str()) {
+                Some(target) => {
+                    self.world.push((Target(target.claim()),));
                 }
+                None => log_warn!(
+                    verbosity,
+                    "GameWorld: no target at \"{}\", nothing to seek or flee",
+                    self.target_path
+                ),
             }
         }
+
+        // Add viewport rect, and keep it up to date when the window is resized
+        let godot_viewport = owner
+            .get_viewport()
+            .ok_or_else(|| BoidsError::NodeNotFound("viewport".to_string()))?
+            .assume_safe();
+        self.resize_world(godot_viewport.size());
+        let (signal, method) = ("size_changed", "viewport_size_changed");
+        // Still connected from before a reload
+        if !godot_viewport.is_connected(signal, owner, method) {
+            // Not worth giving up the flock for, `poll_viewport_size` covers it
+            let binds = VariantArray::new_shared();
+            if let Err(e) = godot_viewport.connect(signal, owner, method, binds, 0) {
+                log_warn!(
+                    verbosity,
+                    "GameWorld: couldn't connect size_changed ({:?}), polling the viewport",
+                    e
+                );
+                self.polled_viewport_size = Some(godot_viewport.size());
+            }
+        }
+
+        if owner.has_meta(RELOAD_STATE_META) {
+            let state = owner.get_meta(RELOAD_STATE_META);
+            owner.remove_meta(RELOAD_STATE_META);
+            self.restore_state(&owner, &state)?;
+            let count = self.resources.get::<BoidCount>().map(|count| count.0).unwrap_or(0);
+            log_info!(verbosity, "GameWorld: restored {} boids after reloading", count);
+            return Ok(());
+        }
+
+        let count = self.resources.get::<BoidCount>().map(|count| count.0).unwrap_or(BOID_COUNT);
+        self.resize_flock(&owner, count)?;
+
+        log_info!(verbosity, "GameWorld: spawned {} boids", count);
+        Ok(())
     }
 
-    // -----------------------------------------------------------------------------
-    //     - signals -
-    // -----------------------------------------------------------------------------
-    #[export]
-    pub fn _physics_process(&mut self, owner: Node2D, delta: f64) {
+    #[method]
+    pub fn _physics_process(&mut self, #[base] owner: TRef<Node2D>, delta: f64) {
+        if !self.started {
+            if let Err(e) = unsafe { self.setup(owner) } {
+                godot_error!("GameWorld failed to start: {}", e);
+            }
+        }
+
+        let physics_delta = delta as f32;
+        let time_scale = self.resources.get::<TimeScale>().map(|scale| scale.0).unwrap_or(1.);
+        let delta = delta as f32 * time_scale;
+
+        self.despawn_freed_boids();
+        unsafe { self.update_lod_view(&owner) };
+        self.update_batch_transforms(&owner);
+        self.update_exclusion_rects(&owner);
+        unsafe { self.poll_viewport_size(&owner) };
+
+        let replaying = self
+            .resources
+            .get::<Replay>()
+            .map(|replay| replay.is_playing())
+            .unwrap_or(false);
+        if replaying {
+            self.replay.execute(&mut self.world, &mut self.resources);
+            return;
+        }
+
+        if let Err(e) = unsafe { self.read_gpu_results() } {
+            godot_error!("gpu backend: {}", e);
+        }
+
+        unsafe { self.read_linked_worlds() };
+        unsafe { self.track_targets(physics_delta) };
+        unsafe { self.read_leader_nodes() };
+        unsafe { self.cast_avoid_rays(&owner) };
+
+        if let Err(e) = unsafe { self.call_custom_force() } {
+            godot_error!("custom force callback: {}", e);
+        }
+
+        let (steps, step) = self
+            .resources
+            .get_mut::<FixedTimestep>()
+            .map(|mut timestep| timestep.advance(delta))
+            .unwrap_or((1, delta));
+        self.resources.get_mut::<Delta>().map(|mut d| d.0 = step);
+
+        let started = Instant::now();
+        for _ in 0..steps {
+            self.physics.execute(&mut self.world, &mut self.resources);
+            if let Err(e) = unsafe { self.apply_population_changes(&owner) } {
+                godot_error!("_physics_process: {}", e);
+            }
+        }
+        let elapsed = started.elapsed().as_secs_f32() * 1000.;
         self.resources
-            .get_mut::<Delta>()
-            .map(|mut d| d.0 = delta as f32);
-        self.physics.execute(&mut self.world, &mut self.resources);
-    }
+            .get_mut::<QualityGovernor>()
+            .map(|mut governor| governor.record(elapsed, delta));
+        unsafe { self.emit_food_eaten(&owner) };
+        unsafe { self.emit_areas_entered(&owner) };
+        unsafe { self.emit_sinks_drained() };
+        unsafe { self.emit_migration_completed(&owner) };
+        unsafe { self.emit_waypoints_reached(&owner) };
+        unsafe { self.emit_targets_reached(&owner) };
+        unsafe { self.emit_flock_state_changed(&owner) };
+        unsafe { self.emit_quality_changed(&owner) };
+        unsafe { self.emit_startle_waves(&owner) };
+        unsafe { self.update_audio() };
+        self.render.execute(&mut self.world, &mut self.resources);
 
-    #[export]
-    pub fn cohesion_value_changed(&mut self, owner: Node2D, val: f32) {
-        self.resources.get_mut::<CohesionMul>().map(|mut mul| mul.0 = val);
-    }
+        if let Err(e) = unsafe { self.upload_gpu_boids() } {
+            godot_error!("gpu backend: {}", e);
+        }
 
-    #[export]
-    pub fn separation_value_changed(&mut self, owner: Node2D, val: f32) {
-        self.resources.get_mut::<SeparationMul>().map(|mut mul| mul.0 = val);
-    }
+        if let Err(e) = unsafe { self.capture_frame(&owner) } {
+            godot_error!("capture: {}", e);
+        }
 
-    #[export]
-    pub fn alignment_value_changed(&mut self, owner: Node2D, val: f32) {
-        self.resources.get_mut::<AlignmentMul>().map(|mut mul| mul.0 = val);
+        // Debug geometry changes every tick
+        let selection = <&Selected>::query().iter(&self.world).next().is_some();
+        if self.show_debug_overlay() || selection {
+            owner.update();
+        }
     }
 
-    #[export]
-    pub fn seek_toggled(&mut self, owner: Node2D, toggle: bool) {
-        self.resources.get_mut::<ShouldSeek>().map(|mut seek| seek.0 = toggle);
+    // With `interpolate_every_frame` the sprites are moved on between physics
+    // ticks, the simulation itself only runs in `_physics_process`
+    #[method]
+    pub fn _process(&mut self, #[base] owner: TRef<Node2D>, delta: f64) {
+        let every_frame = self
+            .resources
+            .get::<FixedTimestep>()
+            .map(|timestep| timestep.every_frame && timestep.interpolate && timestep.step > 0.)
+            .unwrap_or(false);
+        let replaying = self
+            .resources
+            .get::<Replay>()
+            .map(|replay| replay.is_playing())
+            .unwrap_or(false);
+        if !self.started || !every_frame || replaying {
+            return;
+        }
+
+        let time_scale = self.resources.get::<TimeScale>().map(|scale| scale.0).unwrap_or(1.);
+        self.resources
+            .get_mut::<FixedTimestep>()
+            .map(|mut timestep| timestep.advance_frame(delta as f32 * time_scale));
+        self.despawn_freed_boids();
+        self.update_batch_transforms(&owner);
+        self.frame.execute(&mut self.world, &mut self.resources);
     }
 
-    #[export]
-    pub fn flee_toggled(&mut self, owner: Node2D, toggle: bool) {
-        self.resources.get_mut::<ShouldFlee>().map(|mut flee| flee.0 = toggle);
-        eprintln!("{:?}", "flee toggle");
+    // One of "error", "warn", "info" or "debug"
+    #[method]
+    pub fn set_verbosity(&mut self, level: GodotString) {
+        match Verbosity::parse(&level.to_string()) {
+            Ok(verbosity) => {
+                self.resources.insert(verbosity);
+            }
+            Err(e) => godot_error!("set_verbosity: {}", e),
+        }
     }
 }
@@ -0,0 +1,95 @@
+use gdnative::api::utils::NodeExt;
+use gdnative::api::{AudioStreamPlayer, Node2D};
+use gdnative::core_types::NodePath;
+use gdnative::export::ClassBuilder;
+use gdnative::log::godot_error;
+use gdnative::object::Ref;
+use legion::*;
+
+use crate::audio::FlockSound;
+use crate::boids::live_node;
+use crate::error::{BoidsError, Result};
+
+use super::GameWorld;
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn attach_audio(&mut self, owner: &Node2D, player_path: NodePath);
+        fn attach_whoosh(&mut self, owner: &Node2D, player_path: NodePath);
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(FlockSound::default());
+}
+
+// `None` for an empty path
+unsafe fn find_audio_player(
+    owner: &Node2D,
+    node_path: NodePath,
+) -> Result<Option<Ref<AudioStreamPlayer>>> {
+    let path = node_path.to_string();
+    if path.is_empty() {
+        return Ok(None);
+    }
+
+    owner
+        .get_node_as::<AudioStreamPlayer>(node_path)
+        .map(|player| Some(player.claim()))
+        .ok_or_else(|| BoidsError::NodeNotFound(path))
+}
+
+impl GameWorld {
+    // The player loops the flock sound, its pitch following the speed of the
+    // flock and its volume how dense it is. An empty path detaches it.
+    pub fn attach_audio(&mut self, owner: &Node2D, player_path: NodePath) {
+        match unsafe { find_audio_player(owner, player_path) } {
+            Ok(player) => self.audio = player,
+            Err(e) => godot_error!("attach_audio: {}", e),
+        }
+    }
+
+    // Played from the start whenever a scatter goes off
+    pub fn attach_whoosh(&mut self, owner: &Node2D, player_path: NodePath) {
+        match unsafe { find_audio_player(owner, player_path) } {
+            Ok(player) => self.whoosh = player,
+            Err(e) => godot_error!("attach_whoosh: {}", e),
+        }
+    }
+
+    // Players are dropped once freed
+    pub(super) unsafe fn update_audio(&mut self) {
+        let sound = match self.resources.get_mut::<FlockSound>() {
+            Some(mut sound) => {
+                let current = *sound;
+                sound.whoosh = false;
+                current
+            }
+            None => return,
+        };
+
+        let alive = |player: &Ref<AudioStreamPlayer>| live_node(player).is_some();
+        if !self.audio.as_ref().map(alive).unwrap_or(true) {
+            self.audio = None;
+        }
+        if !self.whoosh.as_ref().map(alive).unwrap_or(true) {
+            self.whoosh = None;
+        }
+
+        if let Some(audio) = &self.audio {
+            let audio = audio.assume_safe();
+            audio.set_pitch_scale(sound.pitch as f64);
+            audio.set_volume_db(sound.volume_db as f64);
+            if !audio.is_playing() {
+                audio.play(0.);
+            }
+        }
+
+        if sound.whoosh {
+            if let Some(whoosh) = &self.whoosh {
+                whoosh.assume_safe().play(0.);
+            }
+        }
+    }
+}
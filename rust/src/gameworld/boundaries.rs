@@ -0,0 +1,157 @@
+use gdnative::api::Node2D;
+use gdnative::core_types::{Rect2, Vector2};
+use gdnative::export::ClassBuilder;
+use legion::*;
+
+use crate::home::{Home, HomeAnchor};
+use crate::walls::Walls;
+
+use super::{add_component, remove_component, BoundaryMode, GameWorld, Viewport, WorldBounds};
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn viewport_size_changed(&mut self, owner: &Node2D);
+        fn set_world_bounds(&mut self, owner: &Node2D, rect: Rect2);
+        fn wrap_toggled(&mut self, owner: &Node2D, toggle: bool);
+        fn walls_toggled(&mut self, owner: &Node2D, toggle: bool);
+        fn set_walls(&mut self, owner: &Node2D, strength: f32, margin: f32);
+        fn set_home(&mut self, owner: &Node2D, id: i64, position: Vector2) -> bool;
+        fn clear_home(&mut self, owner: &Node2D, id: i64) -> bool;
+        fn homes_on_spawn_toggled(&mut self, owner: &Node2D, toggle: bool);
+        fn set_home_anchor(&mut self, owner: &Node2D, strength: f32, radius: f32, falloff: f32);
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(BoundaryMode::Wrap);
+    resources.insert(Walls::default());
+    resources.insert(HomeAnchor::default());
+    resources.insert(WorldBounds::default());
+}
+
+impl GameWorld {
+    pub fn viewport_size_changed(&mut self, owner: &Node2D) {
+        let size = match owner.get_viewport() {
+            Some(viewport) => unsafe { viewport.assume_safe() }.size(),
+            None => return,
+        };
+
+        log_debug!(self.verbosity(), "GameWorld: viewport resized to {:?}", size);
+        self.resize_world(size);
+    }
+
+    // Stands in for `viewport_size_changed` when the signal couldn't be
+    // connected
+    pub(super) unsafe fn poll_viewport_size(&mut self, owner: &Node2D) {
+        let last = match self.polled_viewport_size {
+            Some(size) => size,
+            None => return,
+        };
+        let size = match owner.get_viewport() {
+            Some(viewport) => viewport.assume_safe().size(),
+            None => return,
+        };
+        if size != last {
+            self.polled_viewport_size = Some(size);
+            self.resize_world(size);
+        }
+    }
+
+    // Makes the viewport the world, unless it has bounds of its own
+    pub(super) fn resize_world(&mut self, viewport_size: Vector2) {
+        let bounds = self.resources.get::<WorldBounds>().and_then(|bounds| bounds.0);
+        let viewport = match bounds {
+            Some(rect) => Viewport(rect),
+            None => Viewport::from_vec2(viewport_size),
+        };
+        self.resources.insert(viewport);
+    }
+
+    // Lets the boids roam `rect` rather than the visible area, for worlds that
+    // scroll with a camera. An empty rect goes back to the viewport.
+    pub fn set_world_bounds(&mut self, owner: &Node2D, rect: Rect2) {
+        let bounds = if rect.size.x > 0. && rect.size.y > 0. {
+            Some(rect)
+        } else {
+            None
+        };
+        self.resources.get_mut::<WorldBounds>().map(|mut world| world.0 = bounds);
+
+        // Before `_ready` the bounds are only stored, `setup` picks them up
+        if !self.resources.contains::<Viewport>() {
+            return;
+        }
+        if let Some(viewport) = owner.get_viewport() {
+            self.resize_world(unsafe { viewport.assume_safe() }.size());
+        }
+    }
+
+    // With the walls up this only picks what turning them off goes back to
+    pub fn wrap_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        let mode = if toggle { BoundaryMode::Wrap } else { BoundaryMode::Open };
+        self.boundary_under_walls = mode;
+        self.resources.get_mut::<BoundaryMode>().map(|mut boundary| {
+            if *boundary != BoundaryMode::Walls {
+                *boundary = mode;
+            }
+        });
+    }
+
+    // Walls keep the flock inside the viewport, off goes back to wrapping or
+    // open edges, whichever was on before
+    pub fn walls_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        let under_walls = &mut self.boundary_under_walls;
+        self.resources.get_mut::<BoundaryMode>().map(|mut boundary| {
+            if toggle && *boundary != BoundaryMode::Walls {
+                *under_walls = *boundary;
+            }
+            *boundary = if toggle { BoundaryMode::Walls } else { *under_walls };
+        });
+    }
+
+    // `strength` is in units of the max speed, `margin` is how far from the
+    // edge the push starts
+    pub fn set_walls(&mut self, owner: &Node2D, strength: f32, margin: f32) {
+        self.resources.get_mut::<Walls>().map(|mut walls| {
+            walls.strength = strength.max(0.);
+            walls.margin = margin.max(0.);
+        });
+    }
+
+    // Tethers the boid to `position`. Returns false if there is no boid with
+    // that id.
+    pub fn set_home(&mut self, owner: &Node2D, id: i64, position: Vector2) -> bool {
+        match self.find_boid(id) {
+            Ok(entity) => add_component(&mut self.world, entity, Home(position)),
+            Err(_) => false,
+        }
+    }
+
+    pub fn clear_home(&mut self, owner: &Node2D, id: i64) -> bool {
+        match self.find_boid(id) {
+            Ok(entity) => remove_component::<Home>(&mut self.world, entity),
+            Err(_) => false,
+        }
+    }
+
+    // Boids spawned from now on get the point they spawned at as their home,
+    // the ones already there keep theirs
+    pub fn homes_on_spawn_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        self.resources.get_mut::<HomeAnchor>().map(|mut anchor| anchor.on_spawn = toggle);
+    }
+
+    // `strength` is in units of the max speed, reached `falloff` pixels past
+    // `radius`
+    pub fn set_home_anchor(&mut self, owner: &Node2D, strength: f32, radius: f32, falloff: f32) {
+        self.resources.get_mut::<HomeAnchor>().map(|mut anchor| {
+            *anchor = HomeAnchor {
+                strength,
+                radius,
+                falloff,
+                ..*anchor
+            }
+            .sanitized();
+        });
+    }
+}
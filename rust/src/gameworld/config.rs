@@ -0,0 +1,181 @@
+use gdnative::api::Node2D;
+use gdnative::core_types::{Dictionary, GodotString, VariantArray};
+use gdnative::export::ClassBuilder;
+use gdnative::log::godot_error;
+use legion::*;
+
+use crate::color_mode::{ColorMapping, ColorMode};
+use crate::config::Config;
+use crate::debug::DebugOverlay;
+use crate::error::{BoidsError, Result};
+use crate::flocks::ShowFlocks;
+use crate::preset;
+use crate::pressure::ShowPressure;
+use crate::roles::ShowRoles;
+use crate::schedule::{BehaviorSchedule, ScheduleSpec};
+
+use super::{json_dictionary, GameWorld};
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn apply_config(&mut self, owner: &Node2D, config: Dictionary);
+        fn load_preset(&mut self, owner: &Node2D, name: GodotString);
+        fn set_schedule(&mut self, owner: &Node2D, schedule: Dictionary);
+        fn clear_schedule(&mut self, owner: &Node2D);
+        fn get_schedule_time(&self, owner: &Node2D) -> f32;
+        fn save_preset(&mut self, owner: &Node2D, name: GodotString);
+        fn get_preset_names(&self, owner: &Node2D) -> VariantArray;
+        fn get_config(&self, owner: &Node2D) -> Dictionary;
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(BehaviorSchedule::default());
+}
+
+impl GameWorld {
+    // Takes any subset of the fields of `Config`, see config.rs for the names
+    pub fn apply_config(&mut self, owner: &Node2D, config: Dictionary) {
+        if let Err(e) = self.load_config(owner, &config.to_json().to_string()) {
+            godot_error!("apply_config: {}", e);
+        }
+    }
+
+    fn load_config(&mut self, owner: &Node2D, json: &str) -> Result<()> {
+        let config = serde_json::from_str::<Config>(json)?;
+        self.use_config(owner, &config)?;
+        log_debug!(self.verbosity(), "applied config: {}", json);
+        Ok(())
+    }
+
+    pub(super) fn use_config(&mut self, owner: &Node2D, config: &Config) -> Result<()> {
+        config.apply(&mut self.resources)?;
+
+        if let Some(count) = config.boid_count {
+            unsafe { self.resize_flock(owner, count)? };
+        }
+
+        if let Some(backend) = config.backend {
+            unsafe { self.use_backend(owner, backend)? };
+        }
+
+        if let Some(range) = config.lifetime {
+            self.set_lifetimes(range);
+        }
+
+        if let Some(show) = config.show_pressure {
+            self.resources.get_mut::<ShowPressure>().map(|mut show_pressure| show_pressure.0 = show);
+        }
+
+        if let Some(show) = config.show_flocks {
+            self.resources.get_mut::<ShowFlocks>().map(|mut show_flocks| show_flocks.0 = show);
+        }
+
+        if let Some(show) = config.show_roles {
+            self.resources.get_mut::<ShowRoles>().map(|mut show_roles| show_roles.0 = show);
+        }
+
+        if let Some(mode) = config.color_mode {
+            self.resources.get_mut::<ColorMapping>().map(|mut mapping| mapping.mode = mode);
+        }
+
+        let hidden = Some(false);
+        if config.show_pressure == hidden
+            || config.show_flocks == hidden
+            || config.show_roles == hidden
+            || config.color_mode == Some(ColorMode::Off)
+        {
+            self.reset_tint();
+        }
+
+        if let Some(show) = config.debug_overlay {
+            self.resources.get_mut::<DebugOverlay>().map(|mut overlay| overlay.0 = show);
+            owner.update();
+        }
+
+        Ok(())
+    }
+
+    // Looks in user://presets.json first, then the presets shipped in
+    // res://presets.json
+    pub fn load_preset(&mut self, owner: &Node2D, name: GodotString) {
+        let result = preset::load(&name.to_string())
+            .and_then(|config| self.use_config(owner, &config));
+        match result {
+            Ok(()) => log_info!(self.verbosity(), "loaded preset \"{}\"", name.to_string()),
+            Err(e) => godot_error!("load_preset: {}", e),
+        }
+    }
+
+    // Blends between configs over time, like `{"keyframes": [{"time": 0,
+    // "preset": "calm"}, {"time": 60, "config": {...}}], "loop": true}`. Runs
+    // on the simulation clock from zero, and `boid_count` is ignored.
+    pub fn set_schedule(&mut self, owner: &Node2D, schedule: Dictionary) {
+        if let Err(e) = self.load_schedule(&schedule.to_json().to_string()) {
+            godot_error!("set_schedule: {}", e);
+        }
+    }
+
+    fn load_schedule(&mut self, json: &str) -> Result<()> {
+        let spec = serde_json::from_str::<ScheduleSpec>(json)?;
+        let keyframes = spec
+            .keyframes
+            .into_iter()
+            .map(|keyframe| match (keyframe.preset, keyframe.config) {
+                (Some(name), None) => Ok((keyframe.time, preset::load(&name)?)),
+                (None, Some(config)) => Ok((keyframe.time, config)),
+                _ => Err(BoidsError::InvalidArgument(format!(
+                    "the keyframe at {}s needs either a preset or a config",
+                    keyframe.time
+                ))),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let schedule = BehaviorSchedule::new(keyframes, spec.looping, spec.length)?;
+        self.resources.insert(schedule);
+        Ok(())
+    }
+
+    pub fn clear_schedule(&mut self, owner: &Node2D) {
+        self.resources.insert(BehaviorSchedule::default());
+    }
+
+    pub fn get_schedule_time(&self, owner: &Node2D) -> f32 {
+        self.resources.get::<BehaviorSchedule>().map(|schedule| schedule.time).unwrap_or(0.)
+    }
+
+    // Saves the current config to user://presets.json
+    pub fn save_preset(&mut self, owner: &Node2D, name: GodotString) {
+        if let Err(e) = preset::save(&name.to_string(), Config::capture(&self.resources)) {
+            godot_error!("save_preset: {}", e);
+        }
+    }
+
+    pub fn get_preset_names(&self, owner: &Node2D) -> VariantArray {
+        let names = VariantArray::new();
+        match preset::names() {
+            Ok(preset_names) => {
+                for name in preset_names {
+                    names.push(name);
+                }
+            }
+            Err(e) => godot_error!("get_preset_names: {}", e),
+        }
+        names.into_shared()
+    }
+
+    pub fn get_config(&self, owner: &Node2D) -> Dictionary {
+        match self.config_dictionary() {
+            Ok(config) => config,
+            Err(e) => {
+                godot_error!("get_config: {}", e);
+                Dictionary::new_shared()
+            }
+        }
+    }
+
+    fn config_dictionary(&self) -> Result<Dictionary> {
+        let json = serde_json::to_string(&Config::capture(&self.resources))?;
+        json_dictionary(&json)
+    }
+}
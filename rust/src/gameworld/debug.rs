@@ -0,0 +1,144 @@
+use std::cmp::Ordering;
+
+use gdnative::api::Node2D;
+use gdnative::core_types::{Color, Rect2, VariantArray, Vector2};
+use gdnative::export::ClassBuilder;
+use legion::*;
+
+use crate::boids::{Boid, BoidId, Forces, Pos, Radius, Velocity};
+use crate::debug::{selected_tint, BoidGeometry, DebugOverlay, Selected};
+use crate::traits::Traits;
+
+use super::{add_component, component_of, remove_component, world_to_screen, GameWorld};
+
+// Clicks further than this from every boid select nothing
+const PICK_RADIUS: f32 = 48.;
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn select_boid_at(&mut self, owner: &Node2D, position: Vector2) -> i64;
+        fn select_in_rect(&mut self, owner: &Node2D, rect: Rect2) -> VariantArray;
+        fn clear_selection(&mut self, owner: &Node2D);
+        fn _draw(&mut self, owner: &Node2D);
+        fn debug_overlay_toggled(&mut self, owner: &Node2D, toggle: bool);
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(DebugOverlay(false));
+}
+
+impl GameWorld {
+    // Returns the id of the selected boid, or -1 if there is none near `position`
+    pub fn select_boid_at(&mut self, owner: &Node2D, position: Vector2) -> i64 {
+        self.despawn_freed_boids();
+        self.deselect();
+
+        let nearest = <(Entity, &Pos, &Radius)>::query()
+            .iter(&self.world)
+            .map(|(&entity, pos, radius)| (entity, (pos.0 - position).length() - radius.0))
+            .filter(|(_, distance)| *distance < PICK_RADIUS)
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+            .map(|(entity, _)| entity);
+
+        let entity = match nearest {
+            Some(entity) => entity,
+            None => return -1,
+        };
+
+        add_component(&mut self.world, entity, Selected);
+        let boid = component_of::<Boid>(&self.world, entity);
+        if let Some(node) = boid.and_then(|boid| unsafe { boid.node() }) {
+            node.set_modulate(selected_tint());
+        }
+
+        owner.update();
+        component_of::<BoidId>(&self.world, entity).map(|id| id.0 as i64).unwrap_or(-1)
+    }
+
+    // Selects every boid inside a rect of the screen, in viewport pixels, and
+    // returns their ids. The rect can be dragged out in any direction.
+    pub fn select_in_rect(&mut self, owner: &Node2D, rect: Rect2) -> VariantArray {
+        self.despawn_freed_boids();
+        self.deselect();
+
+        let corner = rect.position + rect.size;
+        let min = Vector2::new(rect.position.x.min(corner.x), rect.position.y.min(corner.y));
+        let max = Vector2::new(rect.position.x.max(corner.x), rect.position.y.max(corner.y));
+        let to_screen = world_to_screen(owner);
+
+        let inside = <(Entity, &Pos, &Boid)>::query()
+            .iter(&self.world)
+            .filter_map(|(&entity, pos, boid)| {
+                let pos = to_screen.xform(pos.0);
+                if pos.x < min.x || pos.x > max.x || pos.y < min.y || pos.y > max.y {
+                    return None;
+                }
+                if let Some(node) = unsafe { boid.node() } {
+                    node.set_modulate(selected_tint());
+                }
+                Some(entity)
+            })
+            .collect::<Vec<_>>();
+
+        let ids = VariantArray::new();
+        for entity in inside {
+            add_component(&mut self.world, entity, Selected);
+            if let Some(id) = component_of::<BoidId>(&self.world, entity) {
+                ids.push(id.0 as i64);
+            }
+        }
+
+        owner.update();
+        ids.into_shared()
+    }
+
+    pub fn clear_selection(&mut self, owner: &Node2D) {
+        self.deselect();
+        owner.update();
+    }
+
+    fn deselect(&mut self) {
+        let selected = <(Entity, &Boid)>::query()
+            .filter(component::<Selected>())
+            .iter(&self.world)
+            .map(|(&entity, boid)| {
+                if let Some(node) = unsafe { boid.node() } {
+                    node.set_modulate(Color::from_rgb(1., 1., 1.));
+                }
+                entity
+            })
+            .collect::<Vec<_>>();
+
+        for entity in selected {
+            remove_component::<Selected>(&mut self.world, entity);
+        }
+    }
+
+    pub(super) fn show_debug_overlay(&self) -> bool {
+        self.resources.get::<DebugOverlay>().map(|overlay| overlay.0).unwrap_or(false)
+    }
+
+    pub fn _draw(&mut self, owner: &Node2D) {
+        let show_all = self.show_debug_overlay();
+        let radii = self.perception_radii();
+        let mut query = <(&Pos, &Velocity, &Forces, Option<&Traits>, Option<&Selected>)>::query();
+
+        for (pos, vel, forces, traits, selected) in query.iter(&self.world) {
+            if !show_all && selected.is_none() {
+                continue;
+            }
+
+            let perception = traits.map(|traits| traits.perception).unwrap_or(1.);
+            let local = owner.to_local(pos.0);
+            BoidGeometry::new(local, vel.0, &forces, perception).draw(owner, &radii);
+        }
+    }
+
+    pub fn debug_overlay_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        self.resources.get_mut::<DebugOverlay>().map(|mut overlay| overlay.0 = toggle);
+        // Clear what was drawn while it was on
+        owner.update();
+    }
+}
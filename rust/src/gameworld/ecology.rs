@@ -0,0 +1,214 @@
+use gdnative::api::Node2D;
+use gdnative::core_types::{GodotString, Variant, Vector2};
+use gdnative::export::ClassBuilder;
+use legion::*;
+use rand::prelude::*;
+
+use crate::age::{Age, AgePhases};
+use crate::boids::Boid;
+use crate::ecology::{Ecology, Food, PopulationChanges};
+use crate::energy::{EnergyDrain, EnergyRecovery};
+use crate::forage::{FoodEaten, MORSEL_RADIUS};
+use crate::lifetime::{Lifetime, LifetimeRange};
+use crate::traits::TraitRange;
+
+use super::{add_component, remove_component, GameWorld};
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn ecology_toggled(&mut self, owner: &Node2D, enabled: bool);
+        fn set_feed_rate(&mut self, owner: &Node2D, rate: f32);
+        fn set_starve_rate(&mut self, owner: &Node2D, rate: f32);
+        fn set_max_population(&mut self, owner: &Node2D, max: i64);
+        fn add_food(&mut self, owner: &Node2D, position: Vector2, radius: f32);
+        fn spawn_food(&mut self, owner: &Node2D, position: Vector2);
+        fn clear_food(&mut self, owner: &Node2D);
+        fn set_lifetime_range(&mut self, owner: &Node2D, min: f32, max: f32);
+        fn clear_lifetimes(&mut self, owner: &Node2D);
+        fn aging_toggled(&mut self, owner: &Node2D, toggle: bool);
+        fn set_age_phases(
+            &mut self, owner: &Node2D, juvenile: f32, scale: f32, speed: f32, cohesion: f32
+        );
+        fn set_max_age(&mut self, owner: &Node2D, seconds: f32);
+        fn energy_drain_changed(&mut self, owner: &Node2D, val: f32);
+        fn energy_recovery_changed(&mut self, owner: &Node2D, val: f32);
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(LifetimeRange(None));
+    resources.insert(AgePhases::default());
+    resources.insert(Ecology::default());
+    resources.insert(PopulationChanges::default());
+    resources.insert(FoodEaten::default());
+    resources.insert(EnergyDrain(0.2));
+    resources.insert(EnergyRecovery(0.1));
+}
+
+impl GameWorld {
+    pub fn ecology_toggled(&mut self, owner: &Node2D, enabled: bool) {
+        self.resources.get_mut::<Ecology>().map(|mut ecology| ecology.enabled = enabled);
+    }
+
+    // Nourishment gained per second near food, from 0 to 1
+    pub fn set_feed_rate(&mut self, owner: &Node2D, rate: f32) {
+        self.resources.get_mut::<Ecology>().map(|mut ecology| ecology.feed_rate = rate.max(0.));
+    }
+
+    // Nourishment lost per second away from food
+    pub fn set_starve_rate(&mut self, owner: &Node2D, rate: f32) {
+        self.resources
+            .get_mut::<Ecology>()
+            .map(|mut ecology| ecology.starve_rate = rate.max(0.));
+    }
+
+    pub fn set_max_population(&mut self, owner: &Node2D, max: i64) {
+        self.resources
+            .get_mut::<Ecology>()
+            .map(|mut ecology| ecology.max_population = max.max(0) as usize);
+    }
+
+    pub fn add_food(&mut self, owner: &Node2D, position: Vector2, radius: f32) {
+        let food = Food { position, radius: radius.max(0.), consumable: false };
+        self.world.push((food,));
+    }
+
+    // A single morsel, eaten by the first boid to reach it
+    pub fn spawn_food(&mut self, owner: &Node2D, position: Vector2) {
+        let food = Food { position, radius: MORSEL_RADIUS, consumable: true };
+        self.world.push((food,));
+    }
+
+    pub fn clear_food(&mut self, owner: &Node2D) {
+        let food = <(Entity, &Food)>::query()
+            .iter(&self.world)
+            .map(|(&entity, _)| entity)
+            .collect::<Vec<_>>();
+
+        for entity in food {
+            self.world.remove(entity);
+        }
+    }
+
+    pub(super) unsafe fn emit_food_eaten(&mut self, owner: &Node2D) {
+        let eaten = match self.resources.get_mut::<FoodEaten>() {
+            Some(mut eaten) => std::mem::take(&mut eaten.0),
+            None => return,
+        };
+
+        for (position, id) in eaten {
+            owner.emit_signal(
+                GodotString::from_str("food_eaten"),
+                &[Variant::new(position), Variant::new(id.0 as i64)],
+            );
+        }
+    }
+
+    // Seconds, boids that already exist get a lifetime somewhere inside the
+    // range so they don't all expire at once. A max of zero turns lifetimes off.
+    pub fn set_lifetime_range(&mut self, owner: &Node2D, min: f32, max: f32) {
+        self.set_lifetimes(TraitRange::new(min, max));
+    }
+
+    pub fn clear_lifetimes(&mut self, owner: &Node2D) {
+        self.set_lifetimes(TraitRange::new(0., 0.));
+    }
+
+    pub(super) fn set_lifetimes(&mut self, range: TraitRange) {
+        if range.max <= 0. {
+            self.remove_lifetimes();
+            return;
+        }
+
+        let range = TraitRange::new(range.min.min(range.max).max(0.), range.max.max(range.min));
+        self.resources.get_mut::<LifetimeRange>().map(|mut lifetimes| lifetimes.0 = Some(range));
+
+        let mut rng = thread_rng();
+        let immortal = <(Entity, &Boid)>::query()
+            .filter(!component::<Lifetime>())
+            .iter(&self.world)
+            .map(|(&entity, _)| entity)
+            .collect::<Vec<_>>();
+
+        for entity in immortal {
+            let lifetime = range.sample(&mut rng) * rng.gen::<f32>();
+            add_component(&mut self.world, entity, Lifetime(lifetime));
+        }
+    }
+
+    fn remove_lifetimes(&mut self) {
+        self.resources.get_mut::<LifetimeRange>().map(|mut lifetimes| lifetimes.0 = None);
+
+        let mortal = <(Entity, &Lifetime)>::query()
+            .iter(&self.world)
+            .map(|(&entity, _)| entity)
+            .collect::<Vec<_>>();
+
+        for entity in mortal {
+            remove_component::<Lifetime>(&mut self.world, entity);
+        }
+    }
+
+    // Young boids are smaller, slower and stay closer to the flock. Turning
+    // it on gives the boids random ages, so not all of them are young.
+    pub fn aging_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        let phases = self.resources.get_mut::<AgePhases>().map(|mut phases| {
+            phases.enabled = toggle;
+            *phases
+        });
+        if let Some(phases) = phases.filter(|phases| phases.enabled) {
+            self.spread_ages(&phases);
+        }
+    }
+
+    // Seconds until fully grown, and what newborns' scale, max speed and
+    // cohesion are multiplied by
+    pub fn set_age_phases(
+        &mut self,
+        owner: &Node2D,
+        juvenile: f32,
+        scale: f32,
+        speed: f32,
+        cohesion: f32,
+    ) {
+        self.resources.get_mut::<AgePhases>().map(|mut phases| {
+            *phases = AgePhases {
+                juvenile,
+                juvenile_scale: scale,
+                juvenile_speed: speed,
+                juvenile_cohesion: cohesion,
+                ..*phases
+            }
+            .sanitized();
+        });
+    }
+
+    // Seconds before a boid dies of old age and hatches at an edge, zero for
+    // never
+    pub fn set_max_age(&mut self, owner: &Node2D, seconds: f32) {
+        self.resources.get_mut::<AgePhases>().map(|mut phases| phases.max_age = seconds.max(0.));
+    }
+
+    // Up to the max age, or twice the time to grow up without one
+    fn spread_ages(&mut self, phases: &AgePhases) {
+        let oldest = if phases.max_age > 0. {
+            phases.max_age
+        } else {
+            phases.juvenile * 2.
+        };
+        let mut rng = thread_rng();
+        let mut query = <&mut Age>::query();
+        for age in query.iter_mut(&mut self.world) {
+            age.seconds = oldest * rng.gen::<f32>();
+        }
+    }
+
+    pub fn energy_drain_changed(&mut self, owner: &Node2D, val: f32) {
+        self.resources.get_mut::<EnergyDrain>().map(|mut drain| drain.0 = val);
+    }
+
+    pub fn energy_recovery_changed(&mut self, owner: &Node2D, val: f32) {
+        self.resources.get_mut::<EnergyRecovery>().map(|mut recovery| recovery.0 = val);
+    }
+}
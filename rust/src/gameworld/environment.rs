@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use gdnative::api::Node2D;
+use gdnative::core_types::{GodotString, VariantArray, Vector2};
+use gdnative::export::ClassBuilder;
+use gdnative::log::godot_error;
+use legion::*;
+
+use crate::boids::{Pos, Radius, Velocity, AVOID_DISTANCE};
+use crate::collision::{CollisionRadius, ResolveCollisions};
+use crate::flow::{FlowField, FlowGrid};
+
+use super::{AvoidColliders, ColliderHit, ColliderHits, GameWorld, Viewport};
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn resolve_collisions_toggled(&mut self, owner: &Node2D, toggle: bool);
+        fn collision_radius_changed(&mut self, owner: &Node2D, val: f32);
+        fn avoid_colliders_toggled(&mut self, owner: &Node2D, toggle: bool);
+        fn set_wind(&mut self, owner: &Node2D, x: f32, y: f32);
+        fn load_flow_field(&mut self, owner: &Node2D, image_path: GodotString);
+        fn clear_flow_field(&mut self, owner: &Node2D);
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(AvoidColliders(true));
+    resources.insert(ResolveCollisions(true));
+    resources.insert(CollisionRadius(0.));
+    resources.insert(ColliderHits::default());
+    resources.insert(FlowField::default());
+}
+
+impl GameWorld {
+    // For `avoid_colliders`, which can't touch the physics space itself
+    pub(super) unsafe fn cast_avoid_rays(&mut self, owner: &Node2D) {
+        let avoid = self.resources.get::<AvoidColliders>().map(|avoid| avoid.0).unwrap_or(false);
+        let space = owner.get_world_2d().and_then(|world| world.assume_safe().direct_space_state());
+        let mut hits = HashMap::new();
+
+        if let (true, Some(space)) = (avoid, space) {
+            let space = space.assume_safe();
+            let mut boids = <(Entity, &Pos, &Velocity, &Radius)>::query();
+            for (&entity, pos, vel, radius) in boids.iter(&self.world) {
+                if vel.0.length_squared() == 0. {
+                    continue;
+                }
+
+                let lookahead = AVOID_DISTANCE + radius.0;
+                let ray_end = pos.0 + vel.0.normalized() * lookahead;
+                let exclude = VariantArray::new_shared();
+                let hit = space.intersect_ray(pos.0, ray_end, exclude, 0x7FFF_FFFF, true, false);
+                if hit.is_empty() {
+                    continue;
+                }
+
+                let vector = |key: &str| hit.get(key).and_then(|value| value.to::<Vector2>());
+                if let (Some(point), Some(normal)) = (vector("position"), vector("normal")) {
+                    hits.insert(entity, ColliderHit { point, normal });
+                }
+            }
+        }
+
+        self.resources.get_mut::<ColliderHits>().map(|mut colliders| colliders.0 = hits);
+    }
+
+    pub fn resolve_collisions_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        self.resources.get_mut::<ResolveCollisions>().map(|mut resolve| resolve.0 = toggle);
+    }
+
+    // Zero goes back to each boid's own radius
+    pub fn collision_radius_changed(&mut self, owner: &Node2D, val: f32) {
+        self.resources.get_mut::<CollisionRadius>().map(|mut radius| radius.0 = val.max(0.));
+    }
+
+    pub fn avoid_colliders_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        self.resources.get_mut::<AvoidColliders>().map(|mut avoid| avoid.0 = toggle);
+    }
+
+    pub fn set_wind(&mut self, owner: &Node2D, x: f32, y: f32) {
+        self.resources.get_mut::<FlowField>().map(|mut field| field.wind = Vector2::new(x, y));
+    }
+
+    // The texture is stretched over the viewport, see `FlowGrid::from_image`
+    // for how pixels map to flow
+    pub fn load_flow_field(&mut self, owner: &Node2D, image_path: GodotString) {
+        let bounds = match self.resources.get::<Viewport>() {
+            Some(viewport) => viewport.0,
+            None => {
+                godot_error!("load_flow_field: called before the viewport is known");
+                return;
+            }
+        };
+
+        match FlowGrid::from_image(&image_path.to_string(), bounds) {
+            Ok(grid) => {
+                self.resources.get_mut::<FlowField>().map(|mut field| field.grid = Some(grid));
+            }
+            Err(e) => godot_error!("load_flow_field: {}", e),
+        }
+    }
+
+    pub fn clear_flow_field(&mut self, owner: &Node2D) {
+        self.resources.get_mut::<FlowField>().map(|mut field| field.grid = None);
+    }
+}
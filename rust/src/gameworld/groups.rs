@@ -0,0 +1,178 @@
+use std::cmp::Ordering;
+
+use gdnative::api::utils::NodeExt;
+use gdnative::api::Node2D;
+use gdnative::core_types::{NodePath, VariantArray, Vector2};
+use gdnative::export::ClassBuilder;
+use gdnative::log::godot_error;
+use legion::*;
+
+use crate::boids::{is_finite, rotated, Boid, EscortOffset, Pos};
+use crate::error::{BoidsError, Result};
+use crate::group::{GroupGoal, SplitHeading};
+use crate::leader::{Leader, LeaderNode, LeaderPoses};
+
+use super::{add_component, remove_component, GameWorld};
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn command_group(&mut self, owner: &Node2D, ids: VariantArray, target: Vector2);
+        fn cancel_group_command(&mut self, owner: &Node2D, ids: VariantArray);
+        fn split_flock(&mut self, owner: &Node2D, fraction: f32, direction: Vector2);
+        fn merge_flocks(&mut self, owner: &Node2D);
+        fn promote_leader(&mut self, owner: &Node2D, id: i64);
+        fn demote_leader(&mut self, owner: &Node2D, id: i64);
+        fn add_leader_node(&mut self, owner: &Node2D, node_path: NodePath);
+        fn clear_leader_nodes(&mut self, owner: &Node2D);
+        fn assign_escorts(&mut self, owner: &Node2D, count: i64, radius: f32);
+        fn clear_escorts(&mut self, owner: &Node2D);
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(LeaderPoses::default());
+}
+
+impl GameWorld {
+    // The boids with these ids head for `target` instead of the targets, each
+    // until it gets there or `GOAL_TIMEOUT` runs out. Unknown ids are skipped.
+    pub fn command_group(&mut self, owner: &Node2D, ids: VariantArray, target: Vector2) {
+        for id in ids.iter().filter_map(|id| id.to::<i64>()) {
+            if let Ok(entity) = self.find_boid(id) {
+                add_component(&mut self.world, entity, GroupGoal::new(target));
+            }
+        }
+    }
+
+    // Sends the boids back to the targets
+    pub fn cancel_group_command(&mut self, owner: &Node2D, ids: VariantArray) {
+        for id in ids.iter().filter_map(|id| id.to::<i64>()) {
+            if let Ok(entity) = self.find_boid(id) {
+                remove_component::<GroupGoal>(&mut self.world, entity);
+            }
+        }
+    }
+
+    // The `fraction` of the boids furthest along `direction` break away and
+    // fly that way, ignoring the targets, until `merge_flocks`
+    pub fn split_flock(&mut self, owner: &Node2D, fraction: f32, direction: Vector2) {
+        if let Err(e) = self.split_boids(fraction, direction) {
+            godot_error!("split_flock: {}", e);
+        }
+    }
+
+    fn split_boids(&mut self, fraction: f32, direction: Vector2) -> Result<()> {
+        if direction.length_squared() == 0. || !is_finite(direction) {
+            return Err(BoidsError::InvalidArgument("direction is zero".to_string()));
+        }
+        let heading = direction.normalized();
+
+        let mut boids = <(Entity, &Pos)>::query()
+            .filter(component::<Boid>())
+            .iter(&self.world)
+            .map(|(&entity, pos)| (entity, pos.0.dot(heading)))
+            .collect::<Vec<_>>();
+        boids.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+
+        let count = (boids.len() as f32 * fraction.max(0.).min(1.)).round() as usize;
+        for (entity, _) in boids.into_iter().take(count) {
+            add_component(&mut self.world, entity, SplitHeading(heading));
+        }
+        Ok(())
+    }
+
+    // Everybody that `split_flock` sent away goes back to the targets and
+    // rejoins the flock
+    pub fn merge_flocks(&mut self, owner: &Node2D) {
+        let split = <(Entity, &SplitHeading)>::query()
+            .iter(&self.world)
+            .map(|(&entity, _)| entity)
+            .collect::<Vec<_>>();
+        for entity in split {
+            remove_component::<SplitHeading>(&mut self.world, entity);
+        }
+    }
+
+    pub fn promote_leader(&mut self, owner: &Node2D, id: i64) {
+        match self.find_boid(id) {
+            Ok(entity) => {
+                add_component(&mut self.world, entity, Leader);
+            }
+            Err(e) => godot_error!("promote_leader: {}", e),
+        }
+    }
+
+    pub fn demote_leader(&mut self, owner: &Node2D, id: i64) {
+        match self.find_boid(id) {
+            Ok(entity) => {
+                remove_component::<Leader>(&mut self.world, entity);
+            }
+            Err(e) => godot_error!("demote_leader: {}", e),
+        }
+    }
+
+    // Let a user controlled node lead the flock
+    pub fn add_leader_node(&mut self, owner: &Node2D, node_path: NodePath) {
+        let path = node_path.to_string();
+        match unsafe { owner.get_node_as::<Node2D>(node_path) } {
+            Some(node) => {
+                self.world.push((LeaderNode(node.claim()), Leader));
+            }
+            None => godot_error!("add_leader_node: {}", BoidsError::NodeNotFound(path)),
+        }
+    }
+
+    pub fn clear_leader_nodes(&mut self, owner: &Node2D) {
+        let nodes = <(Entity, &LeaderNode)>::query()
+            .iter(&self.world)
+            .map(|(&entity, _)| entity)
+            .collect::<Vec<_>>();
+
+        for entity in nodes {
+            self.world.remove(entity);
+        }
+    }
+
+    // Spread `count` boids evenly on a halo of `radius` around the target
+    pub fn assign_escorts(&mut self, owner: &Node2D, count: i64, radius: f32) {
+        self.clear_escorts(owner);
+
+        let boids = <(Entity, &Boid)>::query()
+            .iter(&self.world)
+            .map(|(&entity, _)| entity)
+            .take(count.max(0) as usize)
+            .collect::<Vec<_>>();
+
+        let step = std::f32::consts::PI * 2. / boids.len().max(1) as f32;
+        for (i, entity) in boids.into_iter().enumerate() {
+            let angle = step * i as f32;
+            let offset = Vector2::new(angle.cos(), angle.sin()) * radius;
+            add_component(&mut self.world, entity, EscortOffset(offset));
+        }
+    }
+
+    pub fn clear_escorts(&mut self, owner: &Node2D) {
+        let escorts = <(Entity, &EscortOffset)>::query()
+            .iter(&self.world)
+            .map(|(&entity, _)| entity)
+            .collect::<Vec<_>>();
+
+        for entity in escorts {
+            remove_component::<EscortOffset>(&mut self.world, entity);
+        }
+    }
+
+    pub(super) unsafe fn read_leader_nodes(&mut self) {
+        let poses = <&LeaderNode>::query()
+            .filter(component::<Leader>())
+            .iter(&self.world)
+            .filter_map(|node| node.node())
+            .map(|node| {
+                let heading = rotated(Vector2::new(1., 0.), node.global_rotation() as f32);
+                (node.global_position(), heading)
+            })
+            .collect();
+        self.resources.get_mut::<LeaderPoses>().map(|mut leaders| leaders.0 = poses);
+    }
+}
@@ -0,0 +1,83 @@
+use gdnative::api::Node2D;
+use gdnative::core_types::Vector2;
+use gdnative::export::ClassBuilder;
+use gdnative::log::godot_error;
+use legion::*;
+
+use crate::boids::{Impulse, Pos, MAX_SPEED};
+use crate::scatter::Scatter;
+
+use super::{add_component, component_of_mut, BoundaryMode, GameWorld, Viewport};
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn scatter(&mut self, owner: &Node2D, origin: Vector2, strength: f32, duration: f32);
+        fn apply_impulse_to_boid(&mut self, owner: &Node2D, id: i64, impulse: Vector2);
+        fn apply_impulse_in_radius(
+            &mut self, owner: &Node2D, center: Vector2, radius: f32, strength: f32
+        );
+    );
+}
+
+impl GameWorld {
+    // `strength` is in units of the max speed, the push fades out over
+    // `duration` seconds
+    pub fn scatter(&mut self, owner: &Node2D, origin: Vector2, strength: f32, duration: f32) {
+        self.world.push((Scatter::new(origin, strength, duration),));
+    }
+
+    // Changes the boid's velocity on the next tick, on top of its steering
+    pub fn apply_impulse_to_boid(&mut self, owner: &Node2D, id: i64, impulse: Vector2) {
+        match self.find_boid(id) {
+            Ok(entity) => self.add_impulse(entity, impulse),
+            Err(e) => godot_error!("apply_impulse_to_boid: {}", e),
+        }
+    }
+
+    // Pushes every boid within `radius` away from `center`, or pulls them in
+    // for a negative strength. `strength` is in units of the max speed and
+    // fades out towards the edge.
+    pub fn apply_impulse_in_radius(
+        &mut self,
+        owner: &Node2D,
+        center: Vector2,
+        radius: f32,
+        strength: f32,
+    ) {
+        if radius <= 0. {
+            return;
+        }
+
+        let boundary = self.resources.get::<BoundaryMode>().map(|mode| *mode);
+        let viewport = self.resources.get::<Viewport>().map(|viewport| *viewport);
+        let impulses = <(Entity, &Pos)>::query()
+            .iter(&self.world)
+            .filter_map(|(&entity, pos)| {
+                let away = match (boundary, viewport) {
+                    (Some(boundary), Some(viewport)) => boundary.delta(&viewport, center, pos.0),
+                    _ => pos.0 - center,
+                };
+                let distance = away.length();
+                if distance >= radius {
+                    return None;
+                }
+
+                let direction = if distance > 0. { away / distance } else { Vector2::new(1., 0.) };
+                Some((entity, direction * MAX_SPEED * strength * (1. - distance / radius)))
+            })
+            .collect::<Vec<_>>();
+
+        for (entity, impulse) in impulses {
+            self.add_impulse(entity, impulse);
+        }
+    }
+
+    fn add_impulse(&mut self, entity: Entity, impulse: Vector2) {
+        if let Some(pending) = component_of_mut::<Impulse>(&mut self.world, entity) {
+            pending.0 += impulse;
+            return;
+        }
+        add_component(&mut self.world, entity, Impulse(impulse));
+    }
+}
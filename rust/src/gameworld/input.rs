@@ -0,0 +1,82 @@
+use std::cmp::Ordering;
+
+use gdnative::api::{GlobalConstants, InputEvent, InputEventMouse, InputEventMouseButton, Node2D};
+use gdnative::core_types::Vector2;
+use gdnative::export::ClassBuilder;
+use gdnative::object::{Ref, TRef};
+use legion::*;
+
+use crate::boids::Target;
+
+use super::{GameWorld, MouseForce, MouseInteraction};
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn _unhandled_input(&mut self, owner: &Node2D, event: Ref<InputEvent>);
+        fn mouse_interaction_toggled(&mut self, owner: &Node2D, toggle: bool);
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(MouseInteraction(false));
+    resources.insert(MouseForce { position: Vector2::ZERO, strength: 0. });
+}
+
+impl GameWorld {
+    pub fn _unhandled_input(&mut self, owner: &Node2D, event: Ref<InputEvent>) {
+        let event = unsafe { event.assume_safe() };
+        if self.quit_on_cancel && event.is_action_pressed("ui_cancel", false, false) {
+            unsafe { owner.get_tree().map(|tree| tree.assume_safe().quit(0)) };
+        }
+
+        let interactive = self.resources.get::<MouseInteraction>().map(|i| i.0).unwrap_or(false);
+
+        if interactive {
+            unsafe { self.update_mouse_force(owner, event) };
+        } else if let Some(ev) = event.cast::<InputEventMouse>() {
+            if ev.is_pressed() {
+                let pos = owner.get_global_mouse_position();
+                unsafe { self.move_nearest_target(pos) };
+            }
+        }
+    }
+
+    unsafe fn update_mouse_force(&mut self, owner: &Node2D, event: TRef<InputEvent>) {
+        let mut mouse = match self.resources.get_mut::<MouseForce>() {
+            Some(mouse) => mouse,
+            None => return,
+        };
+
+        // Follow the cursor while a button is held
+        mouse.position = owner.get_global_mouse_position();
+
+        if let Some(button) = event.cast::<InputEventMouseButton>() {
+            let strength = match button.button_index() {
+                GlobalConstants::BUTTON_LEFT => 1.,
+                GlobalConstants::BUTTON_RIGHT => -1.,
+                _ => return,
+            };
+
+            mouse.strength = if button.is_pressed() { strength } else { 0. };
+        }
+    }
+
+    unsafe fn move_nearest_target(&mut self, pos: Vector2) {
+        let nearest = <&Target>::query()
+            .iter(&self.world)
+            .filter_map(|target| target.node())
+            .map(|node| ((node.global_position() - pos).length_squared(), node))
+            .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+            .map(|(_, node)| node);
+
+        if let Some(node) = nearest {
+            node.set_global_position(pos);
+        }
+    }
+
+    pub fn mouse_interaction_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        self.resources.get_mut::<MouseInteraction>().map(|mut interaction| interaction.0 = toggle);
+        self.resources.get_mut::<MouseForce>().map(|mut mouse| mouse.strength = 0.);
+    }
+}
@@ -0,0 +1,82 @@
+use gdnative::api::utils::NodeExt;
+use gdnative::api::Node2D;
+use gdnative::core_types::{NodePath, Vector2Array};
+use gdnative::export::ClassBuilder;
+use gdnative::log::godot_error;
+use gdnative::object::Ref;
+use legion::*;
+
+use crate::boids::live_node;
+use crate::error::{BoidsError, Result};
+use crate::linked::LinkedBoids;
+
+use super::GameWorld;
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn link_world(&mut self, owner: &Node2D, other_world_path: NodePath);
+        fn unlink_world(&mut self, owner: &Node2D, other_world_path: NodePath);
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(LinkedBoids::default());
+}
+
+impl GameWorld {
+    // The boids here flee from the boids of the other GameWorld, see
+    // `LinkedFleeBehavior`. Each world only reads the other's positions.
+    pub fn link_world(&mut self, owner: &Node2D, other_world_path: NodePath) {
+        if let Err(e) = unsafe { self.insert_linked_world(owner, other_world_path) } {
+            godot_error!("link_world: {}", e);
+        }
+    }
+
+    unsafe fn insert_linked_world(&mut self, owner: &Node2D, node_path: NodePath) -> Result<()> {
+        let path = node_path.to_string();
+        let other = owner
+            .get_node_as::<Node2D>(node_path)
+            .filter(|node| node.has_method("get_boid_positions"))
+            .ok_or_else(|| BoidsError::NodeNotFound(path))?;
+
+        let instance_id = other.get_instance_id();
+        if instance_id == owner.get_instance_id() {
+            return Err(BoidsError::InvalidArgument("can't link a world to itself".to_string()));
+        }
+        self.unlink_freed_worlds();
+        let linked = |world: &Ref<Node2D>| world.assume_safe().get_instance_id() == instance_id;
+        if !self.linked_worlds.iter().any(linked) {
+            self.linked_worlds.push(other.claim());
+        }
+        Ok(())
+    }
+
+    pub fn unlink_world(&mut self, owner: &Node2D, other_world_path: NodePath) {
+        let instance_id = match owner.get_node(other_world_path) {
+            Some(node) => unsafe { node.assume_safe() }.get_instance_id(),
+            None => return,
+        };
+        unsafe { self.unlink_freed_worlds() };
+        self.linked_worlds
+            .retain(|world| unsafe { world.assume_safe() }.get_instance_id() != instance_id);
+    }
+
+    unsafe fn unlink_freed_worlds(&mut self) {
+        self.linked_worlds.retain(|world| live_node(world).is_some());
+    }
+
+    // Freed worlds are unlinked
+    pub(super) unsafe fn read_linked_worlds(&mut self) {
+        self.unlink_freed_worlds();
+
+        let mut positions = Vec::new();
+        for world in &self.linked_worlds {
+            let snapshot = world.assume_safe().call("get_boid_positions", &[]);
+            if let Some(snapshot) = snapshot.to::<Vector2Array>() {
+                positions.extend(snapshot.to_vec());
+            }
+        }
+        self.resources.get_mut::<LinkedBoids>().map(|mut linked| linked.set(positions));
+    }
+}
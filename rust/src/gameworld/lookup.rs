@@ -0,0 +1,140 @@
+use gdnative::api::Node2D;
+use gdnative::core_types::{Rect2, VariantArray, Vector2, Vector2Array};
+use gdnative::export::ClassBuilder;
+use legion::*;
+
+use crate::boids::{Boid, BoidId, Neighbours, Pos, Velocity};
+use crate::error::{BoidsError, Result};
+use crate::metrics::FlockStats;
+use crate::node_commands::{NodeCommand, NodeCommands, SpriteTransform};
+use crate::timestep::PreviousPos;
+
+use super::{component_of, component_of_mut, GameWorld};
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn get_boid_ids(&self, owner: &Node2D) -> VariantArray;
+        fn has_boid(&self, owner: &Node2D, id: i64) -> bool;
+        fn get_boid_position(&self, owner: &Node2D, id: i64) -> Vector2;
+        fn get_boid_velocity(&self, owner: &Node2D, id: i64) -> Vector2;
+        fn teleport_boid(&mut self, owner: &Node2D, id: i64, position: Vector2) -> bool;
+        fn warp_flock(&mut self, owner: &Node2D, offset: Vector2);
+        fn get_boid_positions(&self, owner: &Node2D) -> Vector2Array;
+        fn get_flock_centroid(&self, owner: &Node2D) -> Vector2;
+        fn get_flock_bounds(&self, owner: &Node2D) -> Rect2;
+    );
+}
+
+impl GameWorld {
+    pub(super) fn find_boid(&self, id: i64) -> Result<Entity> {
+        <(Entity, &BoidId)>::query()
+            .iter(&self.world)
+            .find(|(_, boid_id)| boid_id.0 as i64 == id)
+            .map(|(&entity, _)| entity)
+            .ok_or_else(|| BoidsError::Missing(format!("boid {}", id)))
+    }
+
+    // Ids stay with a boid for as long as it lives and are never reused, so
+    // gameplay code can keep hold of them across frames
+    pub fn get_boid_ids(&self, owner: &Node2D) -> VariantArray {
+        let mut ids = <&BoidId>::query()
+            .filter(component::<Boid>())
+            .iter(&self.world)
+            .map(|id| id.0)
+            .collect::<Vec<_>>();
+        ids.sort();
+
+        let array = VariantArray::new();
+        for id in ids {
+            array.push(id as i64);
+        }
+        array.into_shared()
+    }
+
+    pub fn has_boid(&self, owner: &Node2D, id: i64) -> bool {
+        self.find_boid(id).is_ok()
+    }
+
+    // Zero for an unknown id, see `has_boid`
+    pub fn get_boid_position(&self, owner: &Node2D, id: i64) -> Vector2 {
+        self.find_boid(id)
+            .ok()
+            .and_then(|entity| component_of::<Pos>(&self.world, entity))
+            .map(|pos| pos.0)
+            .unwrap_or(Vector2::ZERO)
+    }
+
+    pub fn get_boid_velocity(&self, owner: &Node2D, id: i64) -> Vector2 {
+        self.find_boid(id)
+            .ok()
+            .and_then(|entity| component_of::<Velocity>(&self.world, entity))
+            .map(|vel| vel.0)
+            .unwrap_or(Vector2::ZERO)
+    }
+
+    // Moves the boid and its sprite at once, without interpolating between
+    // the two positions. Returns false if there is no boid with that id.
+    pub fn teleport_boid(&mut self, owner: &Node2D, id: i64, position: Vector2) -> bool {
+        match self.find_boid(id) {
+            Ok(entity) => {
+                self.move_boid(entity, position);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    // Moves every boid by `offset`, for when the game shifts the world under
+    // the flock
+    pub fn warp_flock(&mut self, owner: &Node2D, offset: Vector2) {
+        let boids = <(Entity, &Pos)>::query()
+            .filter(component::<Boid>())
+            .iter(&self.world)
+            .map(|(&entity, pos)| (entity, pos.0 + offset))
+            .collect::<Vec<_>>();
+        for (entity, pos) in boids {
+            self.move_boid(entity, pos);
+        }
+    }
+
+    fn move_boid(&mut self, entity: Entity, pos: Vector2) {
+        component_of_mut::<Pos>(&mut self.world, entity).map(|current| current.0 = pos);
+        component_of_mut::<PreviousPos>(&mut self.world, entity).map(|previous| previous.0 = pos);
+        // The cached neighbours are from where it was
+        component_of_mut::<Neighbours>(&mut self.world, entity).map(|neighbours| {
+            neighbours.expires = 0
+        });
+
+        // Batched sprites only move through the node commands
+        if component_of::<SpriteTransform>(&self.world, entity).is_some() {
+            self.resources
+                .get_mut::<NodeCommands>()
+                .map(|mut commands| commands.push(entity, NodeCommand::SetPosition(pos)));
+        } else if let Some(boid) = component_of::<Boid>(&self.world, entity) {
+            if let Some(node) = unsafe { boid.node() } {
+                node.set_global_position(pos);
+            }
+        }
+    }
+
+    // Every boid's position as of the last tick, for linked worlds
+    pub fn get_boid_positions(&self, owner: &Node2D) -> Vector2Array {
+        let mut positions = Vector2Array::new();
+        for pos in <&Pos>::query().filter(component::<Boid>()).iter(&self.world) {
+            positions.push(pos.0);
+        }
+        positions
+    }
+
+    // Mean position of every boid, as of the last tick
+    pub fn get_flock_centroid(&self, owner: &Node2D) -> Vector2 {
+        self.resources.get::<FlockStats>().map(|stats| stats.centroid).unwrap_or(Vector2::ZERO)
+    }
+
+    // Smallest rect holding every boid, as of the last tick
+    pub fn get_flock_bounds(&self, owner: &Node2D) -> Rect2 {
+        let stats = self.resources.get::<FlockStats>().map(|stats| *stats).unwrap_or_default();
+        stats.bounds
+    }
+}
@@ -0,0 +1,212 @@
+use gdnative::api::Node2D;
+use gdnative::core_types::{GodotString, Rect2, Variant, Vector2};
+use gdnative::export::ClassBuilder;
+use gdnative::log::godot_error;
+use legion::*;
+
+use crate::analysis::Analysis;
+use crate::capture::Capture;
+use crate::density::DensityMap;
+use crate::error::{BoidsError, Result};
+use crate::files;
+use crate::flock_state::{CollectiveState, FlockStateChanged};
+use crate::flocks::FlockDetection;
+use crate::metrics::{FlockStats, Telemetry};
+use crate::pressure::CrowdPressure;
+
+use super::GameWorld;
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn export_metrics(&mut self, owner: &Node2D, path: GodotString);
+        fn set_metrics_interval(&mut self, owner: &Node2D, ticks: i64);
+        fn clear_metrics(&mut self, owner: &Node2D);
+        fn dump_analysis(&mut self, owner: &Node2D, path: GodotString);
+        fn set_analysis_interval(&mut self, owner: &Node2D, ticks: i64);
+        fn clear_analysis(&mut self, owner: &Node2D);
+        fn get_mean_pressure(&self, owner: &Node2D) -> f32;
+        fn get_flock_state(&self, owner: &Node2D) -> GodotString;
+        fn get_flock_order(&self, owner: &Node2D) -> Vector2;
+        fn get_max_pressure(&self, owner: &Node2D) -> f32;
+        fn capture_frames(&mut self, owner: &Node2D, frames: i64);
+        fn set_capture_on_split(&mut self, owner: &Node2D, frames: i64);
+        fn get_density_texture(&self, owner: &Node2D) -> Variant;
+        fn get_density_rect(&self, owner: &Node2D) -> Rect2;
+        fn set_density_fade(&mut self, owner: &Node2D, seconds: f32);
+        fn clear_density(&mut self, owner: &Node2D);
+        fn get_flock_count(&self, owner: &Node2D) -> i64;
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(CrowdPressure::default());
+    resources.insert(Telemetry::default());
+    resources.insert(Analysis::default());
+    resources.insert(FlockStats::default());
+    resources.insert(Capture::default());
+    resources.insert(DensityMap::default());
+    resources.insert(CollectiveState::default());
+    resources.insert(FlockStateChanged::default());
+}
+
+impl GameWorld {
+    // Writes CSV when `path` ends in .csv, JSON otherwise
+    pub fn export_metrics(&mut self, owner: &Node2D, path: GodotString) {
+        if let Err(e) = self.write_metrics(&path.to_string()) {
+            godot_error!("export_metrics: {}", e);
+        }
+    }
+
+    fn write_metrics(&self, path: &str) -> Result<()> {
+        let telemetry = self
+            .resources
+            .get::<Telemetry>()
+            .ok_or_else(|| BoidsError::Missing("telemetry".to_string()))?;
+
+        let text = if path.ends_with(".csv") {
+            telemetry.to_csv()
+        } else {
+            telemetry.to_json()?
+        };
+
+        files::write_string(path, &text)?;
+        log_info!(self.verbosity(), "wrote {} metrics samples to {}", telemetry.samples.len(), path);
+        Ok(())
+    }
+
+    // Ticks between samples, zero stops sampling
+    pub fn set_metrics_interval(&mut self, owner: &Node2D, ticks: i64) {
+        self.resources
+            .get_mut::<Telemetry>()
+            .map(|mut telemetry| telemetry.interval = ticks.max(0) as usize);
+    }
+
+    pub fn clear_metrics(&mut self, owner: &Node2D) {
+        self.resources.get_mut::<Telemetry>().map(|mut telemetry| telemetry.samples.clear());
+    }
+
+    // Histograms of speed, nearest neighbour distance and polarization over
+    // the whole run, as JSON
+    pub fn dump_analysis(&mut self, owner: &Node2D, path: GodotString) {
+        if let Err(e) = self.write_analysis(&path.to_string()) {
+            godot_error!("dump_analysis: {}", e);
+        }
+    }
+
+    fn write_analysis(&self, path: &str) -> Result<()> {
+        let analysis = self
+            .resources
+            .get::<Analysis>()
+            .ok_or_else(|| BoidsError::Missing("analysis".to_string()))?;
+
+        files::write_string(path, &analysis.to_json()?)?;
+        log_info!(self.verbosity(), "wrote {} analysis samples to {}", analysis.samples, path);
+        Ok(())
+    }
+
+    // Ticks between analysis samples, zero stops sampling
+    pub fn set_analysis_interval(&mut self, owner: &Node2D, ticks: i64) {
+        self.resources
+            .get_mut::<Analysis>()
+            .map(|mut analysis| analysis.interval = ticks.max(0) as usize);
+    }
+
+    pub fn clear_analysis(&mut self, owner: &Node2D) {
+        self.resources.get_mut::<Analysis>().map(|mut analysis| analysis.clear());
+    }
+
+    pub fn get_mean_pressure(&self, owner: &Node2D) -> f32 {
+        self.resources.get::<CrowdPressure>().map(|crowd| crowd.mean).unwrap_or(0.)
+    }
+
+    // "polarized", "milling" or "swarming"
+    pub fn get_flock_state(&self, owner: &Node2D) -> GodotString {
+        let state = self.resources.get::<CollectiveState>().map(|collective| collective.state);
+        GodotString::from_str(state.map(|state| state.name()).unwrap_or("swarming"))
+    }
+
+    // The order parameters the state comes from, both from 0 to 1
+    pub fn get_flock_order(&self, owner: &Node2D) -> Vector2 {
+        self.resources
+            .get::<CollectiveState>()
+            .map(|collective| Vector2::new(collective.polarization, collective.milling))
+            .unwrap_or(Vector2::ZERO)
+    }
+
+    pub fn get_max_pressure(&self, owner: &Node2D) -> f32 {
+        self.resources.get::<CrowdPressure>().map(|crowd| crowd.max).unwrap_or(0.)
+    }
+
+    pub(super) unsafe fn emit_flock_state_changed(&mut self, owner: &Node2D) {
+        let changed = self
+            .resources
+            .get_mut::<FlockStateChanged>()
+            .and_then(|mut changed| changed.0.take());
+        if let Some(state) = changed {
+            let state = Variant::new(state.name());
+            owner.emit_signal(GodotString::from_str("flock_state_changed"), &[state]);
+        }
+    }
+
+    // Saves the next `frames` frames of the viewport to
+    // user://capture_<sequence>_<frame>.png
+    pub fn capture_frames(&mut self, owner: &Node2D, frames: i64) {
+        let frames = frames.max(0) as usize;
+        self.resources.get_mut::<Capture>().map(|mut capture| capture.start(frames));
+    }
+
+    // Frames to capture whenever a flock splits in two, 0 to stop
+    pub fn set_capture_on_split(&mut self, owner: &Node2D, frames: i64) {
+        let frames = frames.max(0) as usize;
+        self.resources.get_mut::<Capture>().map(|mut capture| capture.on_split = frames);
+    }
+
+    pub(super) unsafe fn capture_frame(&mut self, owner: &Node2D) -> Result<()> {
+        let path = self.resources.get_mut::<Capture>().and_then(|mut capture| capture.next_path());
+        let path = match path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let image = owner
+            .get_viewport()
+            .and_then(|viewport| viewport.assume_safe().get_texture())
+            .and_then(|texture| texture.assume_safe().get_data())
+            .ok_or_else(|| BoidsError::Missing("viewport texture".to_string()))?;
+        let image = image.assume_safe();
+        // Viewport textures come out upside down
+        image.flip_y();
+        image.save_png(path)?;
+        Ok(())
+    }
+
+    // Heat map of where the boids have been lately, one texel per
+    // `DENSITY_CELL_SIZE` pixels, stretched over `get_density_rect`
+    pub fn get_density_texture(&self, owner: &Node2D) -> Variant {
+        match self.resources.get::<DensityMap>() {
+            Some(density) => Variant::new(density.to_texture()),
+            None => Variant::nil(),
+        }
+    }
+
+    pub fn get_density_rect(&self, owner: &Node2D) -> Rect2 {
+        self.resources
+            .get::<DensityMap>()
+            .map(|density| density.bounds())
+            .unwrap_or_else(|| Rect2::new(Vector2::ZERO, Vector2::ZERO))
+    }
+
+    // Seconds for the heat to fade, 0 to keep everything since the start
+    pub fn set_density_fade(&mut self, owner: &Node2D, seconds: f32) {
+        self.resources.get_mut::<DensityMap>().map(|mut density| density.fade = seconds.max(0.));
+    }
+
+    pub fn clear_density(&mut self, owner: &Node2D) {
+        self.resources.get_mut::<DensityMap>().map(|mut density| density.clear());
+    }
+
+    pub fn get_flock_count(&self, owner: &Node2D) -> i64 {
+        self.resources.get::<FlockDetection>().map(|detection| detection.count as i64).unwrap_or(0)
+    }
+}
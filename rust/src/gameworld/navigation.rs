@@ -0,0 +1,97 @@
+use gdnative::api::Node2D;
+use gdnative::core_types::{GodotString, Variant, Vector2Array};
+use gdnative::export::ClassBuilder;
+use legion::*;
+
+use crate::formation::{Formation, FormationSlots};
+use crate::migration::{Migration, MigrationCompleted};
+use crate::patrol::{Patrol, WaypointsReached};
+
+use super::GameWorld;
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn set_migration_route(&mut self, owner: &Node2D, points: Vector2Array);
+        fn set_waypoints(&mut self, owner: &Node2D, points: Vector2Array, looping: bool);
+        fn formation_toggled(&mut self, owner: &Node2D, toggle: bool);
+        fn set_formation(&mut self, owner: &Node2D, behind: f32, side: f32, echelon: bool);
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(Migration::default());
+    resources.insert(MigrationCompleted::default());
+    resources.insert(Patrol::default());
+    resources.insert(WaypointsReached::default());
+    resources.insert(Formation::default());
+    resources.insert(FormationSlots::default());
+}
+
+impl GameWorld {
+    pub(super) unsafe fn emit_migration_completed(&mut self, owner: &Node2D) {
+        let completed = self
+            .resources
+            .get_mut::<MigrationCompleted>()
+            .map(|mut completed| std::mem::replace(&mut completed.0, false))
+            .unwrap_or(false);
+        if completed {
+            owner.emit_signal(GodotString::from_str("migration_completed"), &[]);
+        }
+    }
+
+    pub(super) unsafe fn emit_waypoints_reached(&mut self, owner: &Node2D) {
+        let reached = match self.resources.get_mut::<WaypointsReached>() {
+            Some(mut reached) => std::mem::take(&mut reached.0),
+            None => return,
+        };
+        for index in reached {
+            let index = Variant::new(index as i64);
+            owner.emit_signal(GodotString::from_str("waypoint_reached"), &[index]);
+        }
+    }
+
+    // A leader point moves along the waypoints and the flock follows it, until
+    // `migration_completed` once the flock gets to the end. An empty route
+    // stops the migration.
+    pub fn set_migration_route(&mut self, owner: &Node2D, points: Vector2Array) {
+        let route = points.to_vec();
+        self.resources.get_mut::<Migration>().map(|mut migration| {
+            if route.is_empty() {
+                migration.stop();
+            } else {
+                migration.start(route);
+            }
+        });
+    }
+
+    // Moves the targets from waypoint to waypoint as the flock reaches them,
+    // with `waypoint_reached` for each. Without `looping` they stay on the
+    // last one. No points stops the patrol and leaves the targets where they
+    // are.
+    pub fn set_waypoints(&mut self, owner: &Node2D, points: Vector2Array, looping: bool) {
+        let waypoints = points.to_vec();
+        self.resources.get_mut::<Patrol>().map(|mut patrol| {
+            if waypoints.is_empty() {
+                patrol.stop();
+            } else {
+                patrol.start(waypoints, looping);
+            }
+        });
+    }
+
+    // Boids line up in the upwash of the boid ahead, one formation per flock
+    pub fn formation_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        self.resources.get_mut::<Formation>().map(|mut formation| formation.enabled = toggle);
+    }
+
+    // `behind` and `side` are the offsets from the boid ahead, `echelon` puts
+    // every boid on the same side of the leader instead of in a V
+    pub fn set_formation(&mut self, owner: &Node2D, behind: f32, side: f32, echelon: bool) {
+        self.resources.get_mut::<Formation>().map(|mut formation| {
+            formation.behind = behind.max(0.);
+            formation.side = side.max(0.);
+            formation.echelon = echelon;
+        });
+    }
+}
@@ -0,0 +1,144 @@
+use gdnative::api::Node2D;
+use gdnative::core_types::GodotString;
+use gdnative::export::ClassBuilder;
+use gdnative::log::godot_error;
+use legion::*;
+
+use crate::boids::{Pos, Velocity, COHESION_RADIUS};
+use crate::error::{BoidsError, Result};
+use crate::gpu::{Backend, GpuResults, GpuSteering};
+use crate::spatial::{FlockIndex, NearestCounts, NeighbourRule};
+
+use super::{
+    BoundaryMode, GameWorld, NearestCount, NeighbourMode, NeighbourSearch, NeighbourStaleness,
+    PerceptionRadii, SteeringInterval, Viewport,
+};
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn set_backend(&mut self, owner: &Node2D, backend: GodotString);
+        fn topological_toggled(&mut self, owner: &Node2D, toggle: bool);
+        fn nearest_count_changed(&mut self, owner: &Node2D, val: f32);
+        fn set_rule_nearest_count(&mut self, owner: &Node2D, rule: GodotString, count: i64);
+        fn set_steering_interval(&mut self, owner: &Node2D, ticks: i64);
+        fn set_neighbour_staleness(&mut self, owner: &Node2D, ticks: i64);
+        fn spatial_index_toggled(&mut self, owner: &Node2D, toggle: bool);
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(NeighbourSearch::SpatialIndex);
+    resources.insert(Backend::Cpu);
+    resources.insert(GpuResults::default());
+    resources.insert(NeighbourMode::Metric);
+    resources.insert(NearestCount(7));
+    resources.insert(NearestCounts::default());
+    resources.insert(NeighbourStaleness(1));
+    resources.insert(SteeringInterval::default());
+    resources.insert(FlockIndex::new(COHESION_RADIUS));
+}
+
+impl GameWorld {
+    pub(super) unsafe fn read_gpu_results(&mut self) -> Result<()> {
+        if let Some(gpu) = &self.gpu {
+            let results = gpu.read_back()?;
+            self.resources.insert(results);
+        }
+        Ok(())
+    }
+
+    pub(super) unsafe fn upload_gpu_boids(&mut self) -> Result<()> {
+        let gpu = match &mut self.gpu {
+            Some(gpu) => gpu,
+            None => return Ok(()),
+        };
+
+        let boids = <(Entity, &Pos, &Velocity)>::query()
+            .iter(&self.world)
+            .map(|(&entity, pos, vel)| (entity, pos.0, vel.0))
+            .collect::<Vec<_>>();
+        let radii = self.resources.get::<PerceptionRadii>().map(|radii| *radii).unwrap_or_default();
+        let boundary = self.resources.get::<BoundaryMode>().map(|mode| *mode);
+        let wrap = match (boundary, self.resources.get::<Viewport>()) {
+            (Some(BoundaryMode::Wrap), Some(viewport)) => Some(viewport.wrap_size()),
+            _ => None,
+        };
+        gpu.upload(&boids, &radii, wrap)
+    }
+
+    // "cpu" or "gpu", the gpu backend runs the cohesion, separation and
+    // alignment neighbour math in a shader
+    pub fn set_backend(&mut self, owner: &Node2D, backend: GodotString) {
+        let result = Backend::parse(&backend.to_string())
+            .and_then(|backend| unsafe { self.use_backend(owner, backend) });
+        if let Err(e) = result {
+            godot_error!("set_backend: {}", e);
+        }
+    }
+
+    pub(super) unsafe fn use_backend(
+        &mut self,
+        owner: &Node2D,
+        backend: Backend,
+    ) -> Result<()> {
+        match (backend, self.gpu.take()) {
+            (Backend::Gpu, Some(gpu)) => self.gpu = Some(gpu),
+            (Backend::Gpu, None) => self.gpu = Some(GpuSteering::new(owner)?),
+            (Backend::Cpu, Some(gpu)) => gpu.free(),
+            (Backend::Cpu, None) => {}
+        }
+
+        self.resources.insert(backend);
+        self.resources.insert(GpuResults::default());
+        log_info!(self.verbosity(), "GameWorld: using the {:?} backend", backend);
+        Ok(())
+    }
+
+    pub fn topological_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        let mode = if toggle { NeighbourMode::Topological } else { NeighbourMode::Metric };
+        self.resources.get_mut::<NeighbourMode>().map(|mut neighbour_mode| *neighbour_mode = mode);
+    }
+
+    pub fn nearest_count_changed(&mut self, owner: &Node2D, val: f32) {
+        self.resources.get_mut::<NearestCount>().map(|mut count| count.0 = val.max(1.) as usize);
+    }
+
+    // How many neighbours one of cohesion, separation or alignment sees in
+    // topological mode. Zero goes back to the shared count.
+    pub fn set_rule_nearest_count(&mut self, owner: &Node2D, rule: GodotString, count: i64) {
+        if let Err(e) = self.use_rule_nearest_count(&rule.to_string(), count) {
+            godot_error!("set_rule_nearest_count: {}", e);
+        }
+    }
+
+    fn use_rule_nearest_count(&mut self, rule: &str, count: i64) -> Result<()> {
+        let rule = NeighbourRule::parse(rule)?;
+        let mut counts = self
+            .resources
+            .get_mut::<NearestCounts>()
+            .ok_or_else(|| BoidsError::Missing("NearestCounts resource".to_string()))?;
+        *counts.get_mut(rule) = if count > 0 { Some(count as usize) } else { None };
+        Ok(())
+    }
+
+    // Ticks between neighbour rule updates for each boid, movement still
+    // integrates every tick
+    pub fn set_steering_interval(&mut self, owner: &Node2D, ticks: i64) {
+        self.resources
+            .get_mut::<SteeringInterval>()
+            .map(|mut steering| steering.interval = ticks.max(1) as usize);
+    }
+
+    // Higher is faster but less accurate, boids then react late to newcomers
+    pub fn set_neighbour_staleness(&mut self, owner: &Node2D, ticks: i64) {
+        self.resources
+            .get_mut::<NeighbourStaleness>()
+            .map(|mut staleness| staleness.0 = ticks.max(1) as usize);
+    }
+
+    pub fn spatial_index_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        let search = if toggle { NeighbourSearch::SpatialIndex } else { NeighbourSearch::BruteForce };
+        self.resources.get_mut::<NeighbourSearch>().map(|mut search_mode| *search_mode = search);
+    }
+}
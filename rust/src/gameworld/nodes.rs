@@ -0,0 +1,308 @@
+use gdnative::api::utils::NodeExt;
+use gdnative::api::Node2D;
+use gdnative::core_types::{NodePath, Variant};
+use gdnative::export::ClassBuilder;
+use gdnative::log::godot_error;
+use gdnative::object::Ref;
+use legion::*;
+
+use crate::area::{collision_shapes, Area, AreaNode, AreasEntered};
+use crate::emitter::{Emitter, EmitterNode};
+use crate::error::{BoidsError, Result};
+use crate::marker::Marker;
+use crate::perch::{Perch, PerchNode};
+use crate::point_force::{ForceNode, PointForce};
+use crate::sink::{Sink, SinkNode, SinksDrained};
+use crate::species::Species;
+
+use super::{component_of, GameWorld};
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn attach_marker_to_boid(&mut self, owner: &Node2D, id: i64, node_path: NodePath);
+        fn detach_marker(&mut self, owner: &Node2D, node_path: NodePath);
+        fn add_point_force(
+            &mut self, owner: &Node2D, node: Ref<Node2D>, strength: f32, falloff: f32
+        );
+        fn remove_point_force(&mut self, owner: &Node2D, node: Ref<Node2D>);
+        fn add_emitter(
+            &mut self, owner: &Node2D, node: Ref<Node2D>,
+            rate: f32, spread: f32, speed: f32, species: i64
+        );
+        fn remove_emitter(&mut self, owner: &Node2D, node: Ref<Node2D>);
+        fn add_sink(&mut self, owner: &Node2D, node: Ref<Node2D>);
+        fn remove_sink(&mut self, owner: &Node2D, node: Ref<Node2D>);
+        fn register_area(&mut self, owner: &Node2D, node_path: NodePath);
+        fn register_perch(&mut self, owner: &Node2D, node_path: NodePath, capacity: i64);
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(AreasEntered::default());
+    resources.insert(SinksDrained::default());
+}
+
+impl GameWorld {
+    // Keeps the node on the boid's position and rotation every frame, until
+    // the boid is gone or the node freed
+    pub fn attach_marker_to_boid(&mut self, owner: &Node2D, id: i64, node_path: NodePath) {
+        if let Err(e) = self.insert_marker(owner, id, node_path) {
+            godot_error!("attach_marker_to_boid: {}", e);
+        }
+    }
+
+    fn insert_marker(&mut self, owner: &Node2D, id: i64, node_path: NodePath) -> Result<()> {
+        let boid = self.find_boid(id)?;
+        let path = node_path.to_string();
+        let node = unsafe { owner.get_node_as::<Node2D>(node_path) }
+            .ok_or_else(|| BoidsError::NodeNotFound(path))?;
+
+        // A node marks one boid at a time
+        self.remove_marker(node.get_instance_id());
+        self.world.push((Marker { node: node.claim(), boid },));
+        Ok(())
+    }
+
+    pub fn detach_marker(&mut self, owner: &Node2D, node_path: NodePath) {
+        if let Some(node) = owner.get_node(node_path) {
+            self.remove_marker(unsafe { node.assume_safe() }.get_instance_id());
+        }
+    }
+
+    fn remove_marker(&mut self, instance_id: i64) {
+        let markers = <(Entity, &Marker)>::query()
+            .iter(&self.world)
+            .filter(|(_, marker)| {
+                unsafe { marker.node() }.map(|node| node.get_instance_id()) == Some(instance_id)
+            })
+            .map(|(&entity, _)| entity)
+            .collect::<Vec<_>>();
+        for entity in markers {
+            self.world.remove(entity);
+        }
+    }
+
+    // Called by `Attractor` and `Repeller` children, registering the same node
+    // again replaces its force
+    pub fn add_point_force(
+        &mut self,
+        owner: &Node2D,
+        node: Ref<Node2D>,
+        strength: f32,
+        falloff: f32,
+    ) {
+        let node = unsafe { node.assume_safe() };
+        self.remove_force_node(node.get_instance_id());
+
+        let force = PointForce {
+            position: node.global_position(),
+            strength,
+            falloff,
+        };
+        self.world.push((ForceNode(node.claim()), force));
+    }
+
+    pub fn remove_point_force(&mut self, owner: &Node2D, node: Ref<Node2D>) {
+        self.remove_force_node(unsafe { node.assume_safe() }.get_instance_id());
+    }
+
+    fn remove_force_node(&mut self, instance_id: i64) {
+        let forces = <(Entity, &ForceNode)>::query()
+            .iter(&self.world)
+            .filter(|(_, force)| {
+                unsafe { force.node() }.map(|node| node.get_instance_id()) == Some(instance_id)
+            })
+            .map(|(&entity, _)| entity)
+            .collect::<Vec<_>>();
+
+        for entity in forces {
+            self.world.remove(entity);
+        }
+    }
+
+    // Called by `BoidEmitter` children, registering the same node again
+    // replaces its emitter. `spread` is in radians.
+    pub fn add_emitter(
+        &mut self,
+        owner: &Node2D,
+        node: Ref<Node2D>,
+        rate: f32,
+        spread: f32,
+        speed: f32,
+        species: i64,
+    ) {
+        let node = unsafe { node.assume_safe() };
+        let owed = self.remove_emitter_node(node.get_instance_id()).unwrap_or(0.);
+
+        let emitter = Emitter {
+            position: node.global_position(),
+            direction: node.global_rotation() as f32,
+            rate,
+            spread,
+            speed,
+            species: Species(species.max(0) as u32),
+            owed,
+        };
+        self.world.push((EmitterNode(node.claim()), emitter));
+    }
+
+    pub fn remove_emitter(&mut self, owner: &Node2D, node: Ref<Node2D>) {
+        self.remove_emitter_node(unsafe { node.assume_safe() }.get_instance_id());
+    }
+
+    // What the removed emitter still owed, so changing a property doesn't
+    // lose the fraction of a boid
+    fn remove_emitter_node(&mut self, instance_id: i64) -> Option<f32> {
+        let emitters = <(Entity, &EmitterNode, &Emitter)>::query()
+            .iter(&self.world)
+            .filter(|(_, node, _)| {
+                unsafe { node.node() }.map(|node| node.get_instance_id()) == Some(instance_id)
+            })
+            .map(|(&entity, _, emitter)| (entity, emitter.owed))
+            .collect::<Vec<_>>();
+
+        let owed = emitters.first().map(|(_, owed)| *owed);
+        for (entity, _) in emitters {
+            self.world.remove(entity);
+        }
+        owed
+    }
+
+    // Called by `BoidSink` children
+    pub fn add_sink(&mut self, owner: &Node2D, node: Ref<Node2D>) {
+        let node = unsafe { node.assume_safe() };
+        self.remove_sink_node(node.get_instance_id());
+
+        let sink = Sink {
+            shapes: unsafe { collision_shapes(&node) },
+            total: 0,
+        };
+        self.world.push((SinkNode(node.claim()), sink));
+    }
+
+    pub fn remove_sink(&mut self, owner: &Node2D, node: Ref<Node2D>) {
+        self.remove_sink_node(unsafe { node.assume_safe() }.get_instance_id());
+    }
+
+    fn remove_sink_node(&mut self, instance_id: i64) {
+        let sinks = <(Entity, &SinkNode)>::query()
+            .iter(&self.world)
+            .filter(|(_, sink)| {
+                unsafe { sink.node() }.map(|node| node.get_instance_id()) == Some(instance_id)
+            })
+            .map(|(&entity, _)| entity)
+            .collect::<Vec<_>>();
+
+        for entity in sinks {
+            self.world.remove(entity);
+        }
+    }
+
+    // The `CollisionShape2D` children of the node (rects and circles) make up
+    // the area, boids entering it emit `boid_entered_area` with its name
+    pub fn register_area(&mut self, owner: &Node2D, node_path: NodePath) {
+        if let Err(e) = self.insert_area(owner, node_path) {
+            godot_error!("register_area: {}", e);
+        }
+    }
+
+    fn insert_area(&mut self, owner: &Node2D, node_path: NodePath) -> Result<()> {
+        let path = node_path.to_string();
+        let node = unsafe { owner.get_node_as::<Node2D>(node_path) }
+            .ok_or_else(|| BoidsError::NodeNotFound(path))?;
+
+        // Registering a node twice replaces it
+        let instance_id = node.get_instance_id();
+        let existing = <(Entity, &AreaNode)>::query()
+            .iter(&self.world)
+            .filter(|(_, area)| {
+                unsafe { area.node() }.map(|node| node.get_instance_id()) == Some(instance_id)
+            })
+            .map(|(&entity, _)| entity)
+            .collect::<Vec<_>>();
+        for entity in existing {
+            self.world.remove(entity);
+        }
+
+        let area = Area {
+            name: node.name().to_string(),
+            shapes: unsafe { collision_shapes(&node) },
+            inside: Default::default(),
+        };
+        self.world.push((AreaNode(node.claim()), area));
+        Ok(())
+    }
+
+    // Calm boids nearby land on one of the `capacity` slots along the node's
+    // x axis, sit for a while and take off again
+    pub fn register_perch(&mut self, owner: &Node2D, node_path: NodePath, capacity: i64) {
+        if let Err(e) = self.insert_perch(owner, node_path, capacity) {
+            godot_error!("register_perch: {}", e);
+        }
+    }
+
+    fn insert_perch(&mut self, owner: &Node2D, node_path: NodePath, capacity: i64) -> Result<()> {
+        if capacity <= 0 {
+            return Err(BoidsError::InvalidArgument(format!(
+                "perch capacity must be positive, got {}",
+                capacity
+            )));
+        }
+        let path = node_path.to_string();
+        let node = unsafe { owner.get_node_as::<Node2D>(node_path) }
+            .ok_or_else(|| BoidsError::NodeNotFound(path))?;
+
+        // Registering a node twice replaces it
+        let instance_id = node.get_instance_id();
+        let existing = <(Entity, &PerchNode)>::query()
+            .iter(&self.world)
+            .filter(|(_, perch)| {
+                unsafe { perch.node() }.map(|node| node.get_instance_id()) == Some(instance_id)
+            })
+            .map(|(&entity, _)| entity)
+            .collect::<Vec<_>>();
+        for entity in existing {
+            self.world.remove(entity);
+        }
+
+        let perch = Perch::new(&node, capacity as usize);
+        self.world.push((PerchNode(node.claim()), perch));
+        Ok(())
+    }
+
+    pub(super) unsafe fn emit_areas_entered(&mut self, owner: &Node2D) {
+        let entered = match self.resources.get_mut::<AreasEntered>() {
+            Some(mut entered) => std::mem::take(&mut entered.0),
+            None => return,
+        };
+
+        for (name, id) in entered {
+            owner.emit_signal(
+                "boid_entered_area",
+                &[Variant::new(name), Variant::new(id.0 as i64)],
+            );
+        }
+    }
+
+    pub(super) unsafe fn emit_sinks_drained(&mut self) {
+        let drained = match self.resources.get_mut::<SinksDrained>() {
+            Some(mut drained) => std::mem::take(&mut drained.0),
+            None => return,
+        };
+
+        for (entity, count) in drained {
+            let total = match component_of::<Sink>(&self.world, entity) {
+                Some(sink) => sink.total,
+                None => continue,
+            };
+            let node = component_of::<SinkNode>(&self.world, entity).and_then(|node| node.node());
+            if let Some(node) = node {
+                node.emit_signal(
+                    "boids_absorbed",
+                    &[Variant::new(count as i64), Variant::new(total as i64)],
+                );
+            }
+        }
+    }
+}
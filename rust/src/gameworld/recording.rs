@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use gdnative::api::{Engine, Node2D};
+use gdnative::core_types::{GodotString, Vector2};
+use gdnative::export::ClassBuilder;
+use gdnative::log::godot_error;
+use legion::*;
+
+use crate::error::{BoidsError, Result};
+use crate::files;
+use crate::replay::{Replay, ReplayPlayback, Trajectory, TrajectoryRecorder};
+use crate::spawner;
+use crate::stamp::{MotionStamp, MotionStamps, StampPlayback, StampRecorder, StampRecording};
+
+use super::GameWorld;
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn record_motion_stamp(&mut self, owner: &Node2D, name: GodotString, seconds: f32);
+        fn play_motion_stamp(
+            &mut self, owner: &Node2D, name: GodotString, pos: Vector2, scale: f32
+        );
+        fn save_motion_stamp(&mut self, owner: &Node2D, name: GodotString, path: GodotString);
+        fn load_motion_stamp(&mut self, owner: &Node2D, name: GodotString, path: GodotString);
+        fn start_recording(&mut self, owner: &Node2D);
+        fn stop_recording(&mut self, owner: &Node2D, path: GodotString);
+        fn play_replay(&mut self, owner: &Node2D, path: GodotString);
+        fn stop_replay(&mut self, owner: &Node2D);
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(StampRecorder::default());
+    resources.insert(MotionStamps::default());
+    resources.insert(TrajectoryRecorder::default());
+    resources.insert(Replay::default());
+}
+
+impl GameWorld {
+    pub fn record_motion_stamp(&mut self, owner: &Node2D, name: GodotString, seconds: f32) {
+        let ticks_per_second = Engine::godot_singleton().iterations_per_second();
+        let recording = StampRecording {
+            name: name.to_string(),
+            frames_left: (seconds * ticks_per_second as f32).max(1.) as usize,
+            frames: Vec::new(),
+            tracks: HashMap::new(),
+        };
+
+        self.resources.get_mut::<StampRecorder>().map(|mut recorder| recorder.0 = Some(recording));
+    }
+
+    pub fn play_motion_stamp(
+        &mut self,
+        owner: &Node2D,
+        name: GodotString,
+        pos: Vector2,
+        scale: f32,
+    ) {
+        if let Err(e) = self.start_stamp(owner, &name.to_string(), pos, scale) {
+            godot_error!("play_motion_stamp: {}", e);
+        }
+    }
+
+    fn start_stamp(&mut self, owner: &Node2D, name: &str, pos: Vector2, scale: f32) -> Result<()> {
+        let stamp = self
+            .resources
+            .get::<MotionStamps>()
+            .and_then(|stamps| stamps.0.get(name).cloned())
+            .ok_or_else(|| BoidsError::Missing(name.to_string()))?;
+
+        let scene = self.boid_scene();
+        let sprites = (0..stamp.boid_count())
+            .map(|_| {
+                let sprite = spawner::spawn_boid(&scene)?;
+                sprite.set_visible(false);
+                let sprite = sprite.into_shared();
+                owner.add_child(&sprite, false);
+                Ok(sprite)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let playback = StampPlayback {
+            stamp,
+            frame: 0,
+            origin: pos,
+            scale,
+            sprites,
+        };
+
+        self.world.push((playback,));
+        Ok(())
+    }
+
+    pub fn save_motion_stamp(&mut self, owner: &Node2D, name: GodotString, path: GodotString) {
+        if let Err(e) = self.save_stamp(&name.to_string(), &path.to_string()) {
+            godot_error!("save_motion_stamp: {}", e);
+        }
+    }
+
+    fn save_stamp(&self, name: &str, path: &str) -> Result<()> {
+        let stamp = self
+            .resources
+            .get::<MotionStamps>()
+            .and_then(|stamps| stamps.0.get(name).cloned())
+            .ok_or_else(|| BoidsError::Missing(name.to_string()))?;
+
+        let json = serde_json::to_string(&*stamp)?;
+        files::write_string(path, &json)
+    }
+
+    pub fn load_motion_stamp(&mut self, owner: &Node2D, name: GodotString, path: GodotString) {
+        if let Err(e) = self.load_stamp(&name.to_string(), &path.to_string()) {
+            godot_error!("load_motion_stamp: {}", e);
+        }
+    }
+
+    fn load_stamp(&mut self, name: &str, path: &str) -> Result<()> {
+        let json = files::read_string(path)?;
+        let stamp = serde_json::from_str::<MotionStamp>(&json)?;
+
+        self.resources
+            .get_mut::<MotionStamps>()
+            .map(|mut stamps| stamps.0.insert(name.to_string(), Arc::new(stamp)));
+        Ok(())
+    }
+
+    // Records until `stop_recording`, keeping the last ten minutes
+    pub fn start_recording(&mut self, owner: &Node2D) {
+        self.resources
+            .get_mut::<TrajectoryRecorder>()
+            .map(|mut recorder| recorder.0 = Some(Trajectory::default()));
+    }
+
+    pub fn stop_recording(&mut self, owner: &Node2D, path: GodotString) {
+        if let Err(e) = self.save_recording(&path.to_string()) {
+            godot_error!("stop_recording: {}", e);
+        }
+    }
+
+    fn save_recording(&mut self, path: &str) -> Result<()> {
+        let trajectory = self
+            .resources
+            .get_mut::<TrajectoryRecorder>()
+            .and_then(|mut recorder| recorder.0.take())
+            .ok_or_else(|| BoidsError::Missing("recording".to_string()))?;
+
+        let json = serde_json::to_string(&trajectory)?;
+        files::write_string(path, &json)?;
+        log_info!(self.verbosity(), "saved {} frames to {}", trajectory.frames.len(), path);
+        Ok(())
+    }
+
+    pub fn play_replay(&mut self, owner: &Node2D, path: GodotString) {
+        if let Err(e) = self.start_replay(&path.to_string()) {
+            godot_error!("play_replay: {}", e);
+        }
+    }
+
+    fn start_replay(&mut self, path: &str) -> Result<()> {
+        let json = files::read_string(path)?;
+        let trajectory = serde_json::from_str::<Trajectory>(&json)?;
+
+        self.resources
+            .get_mut::<Replay>()
+            .map(|mut replay| replay.0 = Some(ReplayPlayback { trajectory, frame: 0 }));
+        Ok(())
+    }
+
+    // Ends the replay early, the flock picks up where it was before it started
+    pub fn stop_replay(&mut self, owner: &Node2D) {
+        self.resources.get_mut::<Replay>().map(|mut replay| {
+            if let Some(playback) = replay.0.as_mut() {
+                playback.frame = playback.trajectory.frames.len();
+            }
+        });
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::{HashMap, HashSet};
+
+use gdnative::api::Node2D;
+use gdnative::core_types::{Dictionary, Variant};
+use gdnative::export::ClassBuilder;
+use gdnative::log::godot_error;
+use legion::*;
+
+use crate::boids::{live_node, Boid, BoidId};
+use crate::energy::Energy;
+use crate::error::{BoidsError, Result};
+use crate::roles::Role;
+use crate::snapshot::FlockSnapshot;
+use crate::species::Species;
+use crate::traits::Traits;
+
+use super::{
+    component_of_mut, json_dictionary, GameWorld, NextBoidId, RELOAD_STATE_META, STARTED_WORLDS,
+};
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn serialize_to_variant(&self, owner: &Node2D) -> Variant;
+        fn restore_from_variant(&mut self, owner: &Node2D, data: Variant);
+    );
+}
+
+// Called when the library is terminated, which for a reloadable library is
+// right before it's swapped out while the game keeps running. Each flock is
+// stashed on its owner, and the instance that comes back after the reload
+// never has `_ready` called, so `_physics_process` sets it up from the stash.
+pub fn stash_for_reload() {
+    STARTED_WORLDS.with(|worlds| {
+        for owner in worlds.borrow_mut().drain(..) {
+            let instance = match unsafe { live_node(&owner) }
+                .and_then(|owner| owner.cast_instance::<GameWorld>())
+            {
+                Some(instance) => instance,
+                None => continue,
+            };
+            let stashed = instance.map(|world, owner| world.stash_state(&owner));
+            if let Err(e) = stashed {
+                godot_error!("stash_for_reload: {:?}", e);
+            }
+        }
+    });
+}
+
+impl GameWorld {
+    // See `stash_for_reload`
+    fn stash_state(&self, owner: &Node2D) {
+        match self.state_dictionary() {
+            Ok(state) => owner.set_meta(RELOAD_STATE_META, Variant::new(state)),
+            Err(e) => godot_error!("stash_for_reload: {}", e),
+        }
+    }
+
+    // Boids, ids and config as a dictionary, for `restore_from_variant`. The
+    // scene nodes the flock was told about (markers, point forces, areas,
+    // emitters, sinks, perches and linked worlds) aren't part of it, see
+    // `FlockSnapshot`.
+    pub fn serialize_to_variant(&self, owner: &Node2D) -> Variant {
+        match self.state_dictionary() {
+            Ok(state) => Variant::new(state),
+            Err(e) => {
+                godot_error!("serialize_to_variant: {}", e);
+                Variant::nil()
+            }
+        }
+    }
+
+    pub fn restore_from_variant(&mut self, owner: &Node2D, data: Variant) {
+        if let Err(e) = unsafe { self.restore_state(owner, &data) } {
+            godot_error!("restore_from_variant: {}", e);
+        }
+    }
+
+    fn state_dictionary(&self) -> Result<Dictionary> {
+        let json = FlockSnapshot::capture(&self.world, &self.resources).to_json()?;
+        json_dictionary(&json)
+    }
+
+    pub(super) unsafe fn restore_state(
+        &mut self,
+        owner: &Node2D,
+        data: &Variant,
+    ) -> Result<()> {
+        let state = data.to::<Dictionary>().ok_or_else(|| {
+            let message = "expected a dictionary from serialize_to_variant";
+            BoidsError::InvalidArgument(message.to_string())
+        })?;
+        let mut snapshot = FlockSnapshot::from_json(&state.to_json().to_string())?;
+
+        // The boids come from the snapshot, not from the count
+        snapshot.config.boid_count = None;
+        self.use_config(owner, &snapshot.config)?;
+
+        // Nodes still in the tree are taken over, the rest spawned again
+        let mut children = HashMap::new();
+        for child in owner.get_children().iter() {
+            if let Some(node) = child.to_object::<Node2D>() {
+                children.insert(node.assume_safe().get_instance_id(), node);
+            }
+        }
+
+        let previous = <(Entity, &Boid)>::query()
+            .iter(&self.world)
+            .map(|(&entity, boid)| (entity, boid.0.clone()))
+            .collect::<Vec<_>>();
+        for (entity, _) in &previous {
+            self.world.remove(*entity);
+        }
+
+        let mut reused = HashSet::new();
+        let mut next_id = snapshot.next_id;
+        for saved in &snapshot.boids {
+            let species = Species(saved.species);
+            let node = children.remove(&saved.node);
+            let entity = match node.as_ref().and_then(|node| live_node(node)) {
+                Some(node) => {
+                    reused.insert(saved.node);
+                    self.insert_boid(node, saved.pos, saved.vel, species)?
+                }
+                None => self.spawn_boid_at(owner, saved.pos, saved.vel, species)?,
+            };
+
+            component_of_mut::<BoidId>(&mut self.world, entity).map(|id| id.0 = saved.id);
+            if let Some(traits) = saved.traits {
+                component_of_mut::<Traits>(&mut self.world, entity).map(|t| *t = traits);
+            }
+            if let Some(role) = saved.role {
+                component_of_mut::<Role>(&mut self.world, entity).map(|r| *r = role);
+            }
+            if let Some(level) = saved.energy {
+                component_of_mut::<Energy>(&mut self.world, entity).map(|e| e.level = level);
+            }
+            next_id = next_id.max(saved.id + 1);
+        }
+
+        for (_, node) in previous {
+            if let Some(node) = live_node(&node) {
+                if !reused.contains(&node.get_instance_id()) {
+                    node.queue_free();
+                }
+            }
+        }
+
+        self.resources.get_mut::<NextBoidId>().map(|mut next| next.0 = next_id);
+        self.sync_boid_count();
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use gdnative::api::utils::NodeExt;
+use gdnative::api::{Camera2D, Gradient, Node2D};
+use gdnative::core_types::{Color, GodotString, NodePath, Rect2, Vector2};
+use gdnative::export::ClassBuilder;
+use gdnative::log::godot_error;
+use gdnative::object::Ref;
+use legion::*;
+
+use crate::bank::BankFactor;
+use crate::boids::{live_node, Boid};
+use crate::color_mode::{ColorGradient, ColorMapping, ColorMode};
+use crate::debug::Selected;
+use crate::error::BoidsError;
+use crate::flocks::{FlockDetection, ShowFlocks};
+use crate::lod::{LodSettings, LodView};
+use crate::node_commands::{BatchTransforms, NodeCommands, SpriteTransform};
+use crate::pressure::ShowPressure;
+use crate::roles::ShowRoles;
+
+use super::{inverse_or_identity, remove_component, GameWorld};
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn batch_transforms_toggled(&mut self, owner: &Node2D, toggle: bool);
+        fn set_lod_camera(&mut self, owner: &Node2D, node_path: NodePath);
+        fn set_bank_factor(&mut self, owner: &Node2D, factor: f32);
+        fn set_lod_thresholds(&mut self, owner: &Node2D, near: f32, far: f32, hide_far: bool);
+        fn pressure_tint_toggled(&mut self, owner: &Node2D, toggle: bool);
+        fn role_tint_toggled(&mut self, owner: &Node2D, toggle: bool);
+        fn set_color_mode(&mut self, owner: &Node2D, mode: GodotString);
+        fn set_color_gradient(&mut self, owner: &Node2D, gradient: Ref<Gradient>);
+        fn flock_colors_toggled(&mut self, owner: &Node2D, toggle: bool);
+        fn set_flock_interval(&mut self, owner: &Node2D, frames: i64);
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(LodSettings::default());
+    resources.insert(LodView::default());
+    resources.insert(ShowPressure(false));
+    resources.insert(FlockDetection::default());
+    resources.insert(ShowFlocks(false));
+    resources.insert(ColorMapping::default());
+    resources.insert(ShowRoles(true));
+    resources.insert(BankFactor(0.15));
+    resources.insert(NodeCommands::default());
+    resources.insert(BatchTransforms::default());
+}
+
+impl GameWorld {
+    pub(super) fn update_batch_transforms(&mut self, owner: &Node2D) {
+        let inverse = inverse_or_identity(owner.get_global_transform());
+        self.resources.get_mut::<BatchTransforms>().map(|mut batch| batch.parent_inverse = inverse);
+    }
+
+    // Moves the boids through the `VisualServer` instead of their nodes,
+    // which is a lot cheaper with thousands of them. Their node transforms
+    // aren't kept up to date while it's on, and are caught up when it's
+    // turned off.
+    pub fn batch_transforms_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        self.resources.get_mut::<BatchTransforms>().map(|mut batch| batch.enabled = toggle);
+        if toggle {
+            return;
+        }
+
+        let batched = <(Entity, &Boid, &SpriteTransform)>::query()
+            .iter(&self.world)
+            .map(|(&entity, boid, transform)| {
+                if let Some(node) = unsafe { boid.node() } {
+                    node.set_global_position(transform.position);
+                    node.set_global_rotation(transform.rotation as f64);
+                    node.set_scale(transform.scale);
+                }
+                entity
+            })
+            .collect::<Vec<_>>();
+        for entity in batched {
+            remove_component::<SpriteTransform>(&mut self.world, entity);
+        }
+    }
+
+    // Boids far from what this camera sees get less detail, see
+    // `LodSettings`. An empty path detaches the camera.
+    pub fn set_lod_camera(&mut self, owner: &Node2D, node_path: NodePath) {
+        let path = node_path.to_string();
+        if path.is_empty() {
+            self.lod_camera = None;
+            return;
+        }
+
+        match unsafe { owner.get_node_as::<Camera2D>(node_path) } {
+            Some(camera) => self.lod_camera = Some(camera.claim()),
+            None => godot_error!("set_lod_camera: {}", BoidsError::NodeNotFound(path)),
+        }
+    }
+
+    // Radians the sprites bank per radian per second of turning, 0 to stop
+    pub fn set_bank_factor(&mut self, owner: &Node2D, factor: f32) {
+        self.resources.get_mut::<BankFactor>().map(|mut bank| bank.0 = factor.max(0.));
+    }
+
+    // Distances from the edge of the camera view where detail drops
+    pub fn set_lod_thresholds(&mut self, owner: &Node2D, near: f32, far: f32, hide_far: bool) {
+        self.resources.get_mut::<LodSettings>().map(|mut settings| {
+            settings.near = near.max(0.);
+            settings.far = far.max(settings.near);
+            settings.hide_far = hide_far;
+        });
+    }
+
+    // The world rect the LOD camera shows, the camera is dropped once freed
+    pub(super) unsafe fn update_lod_view(&mut self, owner: &Node2D) {
+        let camera = self.lod_camera.as_ref().and_then(|camera| live_node(camera));
+        let view = match (camera, owner.get_viewport()) {
+            (Some(camera), Some(viewport)) => {
+                let size = viewport.assume_safe().size();
+                let zoom = camera.zoom();
+                let size = Vector2::new(size.x * zoom.x, size.y * zoom.y);
+                let center = camera.get_camera_screen_center();
+                Some(Rect2::new(center - size / 2., size))
+            }
+            _ => None,
+        };
+        if camera.is_none() {
+            self.lod_camera = None;
+        }
+
+        self.resources.get_mut::<LodView>().map(|mut lod| {
+            lod.view = view;
+            lod.frame = lod.frame.wrapping_add(1);
+        });
+    }
+
+    pub fn pressure_tint_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        self.resources.get_mut::<ShowPressure>().map(|mut show| show.0 = toggle);
+
+        if !toggle {
+            self.reset_tint();
+        }
+    }
+
+    pub(super) fn reset_tint(&mut self) {
+        let mut query = <&Boid>::query().filter(!component::<Selected>());
+        for boid in query.iter(&self.world) {
+            if let Some(node) = unsafe { boid.node() } {
+                node.set_modulate(Color::from_rgb(1., 1., 1.));
+            }
+        }
+    }
+
+    pub fn role_tint_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        self.resources.get_mut::<ShowRoles>().map(|mut show| show.0 = toggle);
+
+        if !toggle {
+            self.reset_tint();
+        }
+    }
+
+    // Colours the sprites along the gradient by "speed", "density" or
+    // "alignment" with their neighbours, "off" stops it
+    pub fn set_color_mode(&mut self, owner: &Node2D, mode: GodotString) {
+        match ColorMode::parse(&mode.to_string()) {
+            Ok(mode) => {
+                self.resources.get_mut::<ColorMapping>().map(|mut mapping| mapping.mode = mode);
+                if mode == ColorMode::Off {
+                    self.reset_tint();
+                }
+            }
+            Err(e) => godot_error!("set_color_mode: {}", e),
+        }
+    }
+
+    // Takes the points of a Godot `Gradient`, from the lowest value to the
+    // highest
+    pub fn set_color_gradient(&mut self, owner: &Node2D, gradient: Ref<Gradient>) {
+        let gradient = ColorGradient::from_godot(&unsafe { gradient.assume_safe() });
+        self.resources.get_mut::<ColorMapping>().map(|mut mapping| mapping.gradient = gradient);
+    }
+
+    pub fn flock_colors_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        self.resources.get_mut::<ShowFlocks>().map(|mut show| show.0 = toggle);
+
+        if !toggle {
+            self.reset_tint();
+        }
+    }
+
+    // Frames between flock detection passes, zero turns detection off
+    pub fn set_flock_interval(&mut self, owner: &Node2D, frames: i64) {
+        self.resources
+            .get_mut::<FlockDetection>()
+            .map(|mut detection| detection.interval = frames.max(0) as usize);
+    }
+}
@@ -0,0 +1,326 @@
+use gdnative::api::{Node, Node2D};
+use gdnative::core_types::{GodotString, NodePath, Vector2};
+use gdnative::export::ClassBuilder;
+use gdnative::log::godot_error;
+use gdnative::object::TRef;
+use legion::*;
+use rand::prelude::*;
+
+use crate::animation::BoidAnimation;
+use crate::bank::Bank;
+use crate::boids::{insert_simulated_boid, is_finite, Boid, BoidId, Pos, Radius, BOID_RADIUS};
+use crate::ecology::PopulationChanges;
+use crate::error::{BoidsError, Result};
+use crate::spawner::{self, SpawnVelocity};
+use crate::species::{Species, SpeciesLook, SpeciesLooks};
+
+use super::{
+    add_component, component_of, BoidCount, BoidScene, GameWorld, NextBoidId, SpawnSpacing,
+    Viewport, BOID_COUNT,
+};
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn spawn_circle(&mut self, owner: &Node2D, count: i64, radius: f32);
+        fn spawn_grid(&mut self, owner: &Node2D, rows: i64, cols: i64, spacing: f32);
+        fn spawn_line(&mut self, owner: &Node2D, count: i64, from: Vector2, to: Vector2);
+        fn set_spawn_spacing(&mut self, owner: &Node2D, spacing: f32);
+        fn set_boid_scene(&mut self, owner: &Node2D, path: GodotString);
+        fn despawn_boid_by_node(&mut self, owner: &Node2D, node_path: NodePath) -> bool;
+        fn despawn_boid(&mut self, owner: &Node2D, id: i64) -> bool;
+        fn clear_boids(&mut self, owner: &Node2D);
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(NextBoidId(0));
+    resources.insert(BoidCount(BOID_COUNT));
+    resources.insert(BoidScene(spawner::DEFAULT_BOID_SCENE.to_string()));
+    resources.insert(SpawnVelocity::default());
+    resources.insert(SpawnSpacing(BOID_RADIUS * 2.));
+}
+
+impl GameWorld {
+    // Spawns boids at random positions or frees the most recently spawned ones
+    // until there are `count` of them
+    pub(super) unsafe fn resize_flock(&mut self, owner: &Node2D, count: usize) -> Result<()> {
+        self.resources.get_mut::<BoidCount>().map(|mut boid_count| boid_count.0 = count);
+
+        let mut boids = <(Entity, &Boid, &BoidId)>::query()
+            .iter(&self.world)
+            .map(|(&entity, _, id)| (entity, *id))
+            .collect::<Vec<_>>();
+
+        if boids.len() > count {
+            boids.sort_by_key(|(_, id)| id.0);
+            for (entity, _) in boids.drain(count..) {
+                let boid = component_of::<Boid>(&self.world, entity).and_then(|b| b.node());
+                if let Some(boid) = boid {
+                    boid.queue_free();
+                }
+                self.world.remove(entity);
+            }
+            return Ok(());
+        }
+
+        let viewport = self
+            .resources
+            .get::<Viewport>()
+            .map(|viewport| *viewport)
+            .ok_or_else(|| BoidsError::Missing("viewport".to_string()))?;
+        let spawn_velocity = self.spawn_velocity();
+        let spacing = self.resources.get::<SpawnSpacing>().map(|spacing| spacing.0).unwrap_or(0.);
+        let mut rng = thread_rng();
+
+        let existing = <&Pos>::query()
+            .filter(component::<Boid>())
+            .iter(&self.world)
+            .map(|pos| pos.0)
+            .collect::<Vec<_>>();
+        let positions =
+            spawner::spaced_points(viewport.0, count - boids.len(), spacing, &existing, &mut rng);
+        for pos in positions {
+            let velocity = spawn_velocity.sample(&mut rng);
+            self.spawn_boid_at(owner, pos, velocity, Species::default())?;
+        }
+
+        Ok(())
+    }
+
+    pub(super) unsafe fn spawn_boid_at(
+        &mut self,
+        owner: &Node2D,
+        pos: Vector2,
+        velocity: Vector2,
+        species: Species,
+    ) -> Result<Entity> {
+        if !is_finite(pos) {
+            let message = format!("spawn position {:?} isn't finite", pos);
+            return Err(BoidsError::InvalidArgument(message));
+        }
+        let velocity = if is_finite(velocity) { velocity } else { Vector2::ZERO };
+
+        let look = self.species_look(species);
+        let scene = look.scene.unwrap_or_else(|| self.boid_scene());
+        let boid = spawner::spawn_boid(&scene)?;
+        if look.scale != 1. {
+            boid.set_scale(boid.scale() * look.scale);
+        }
+        let boid = boid.into_shared();
+        owner.add_child(&boid, false);
+        self.insert_boid(boid.assume_safe(), pos, velocity, species)
+    }
+
+    // The entity for a boid node that's already in the tree
+    pub(super) unsafe fn insert_boid(
+        &mut self,
+        boid: TRef<Node2D>,
+        pos: Vector2,
+        velocity: Vector2,
+        species: Species,
+    ) -> Result<Entity> {
+        boid.set_global_position(pos);
+
+        let scale = boid.scale();
+        let radius = Radius::from_scale(scale);
+        let entity = insert_simulated_boid(
+            &mut self.world,
+            &self.resources,
+            &mut thread_rng(),
+            pos,
+            velocity,
+            radius,
+            species,
+        )?;
+
+        let animation = spawner::find_animation(boid.upcast::<Node>())
+            .and_then(|sprite| BoidAnimation::new(sprite));
+        add_component(&mut self.world, entity, Boid(boid.claim()));
+        add_component(&mut self.world, entity, Bank::new(scale));
+        if let Some(animation) = animation {
+            add_component(&mut self.world, entity, animation);
+        }
+
+        Ok(entity)
+    }
+
+    // For when boids come and go outside `resize_flock`
+    pub(super) fn sync_boid_count(&mut self) {
+        let count = <&Boid>::query().iter(&self.world).count();
+        self.resources.get_mut::<BoidCount>().map(|mut boid_count| boid_count.0 = count);
+    }
+
+    fn spawn_velocity(&self) -> SpawnVelocity {
+        self.resources.get::<SpawnVelocity>().map(|velocity| *velocity).unwrap_or_default()
+    }
+
+    fn species_look(&self, species: Species) -> SpeciesLook {
+        self.resources
+            .get::<SpeciesLooks>()
+            .and_then(|looks| looks.0.get(&species).cloned())
+            .unwrap_or_default()
+    }
+
+    pub(super) fn boid_scene(&self) -> String {
+        self.resources
+            .get::<BoidScene>()
+            .map(|scene| scene.0.clone())
+            .unwrap_or_else(|| spawner::DEFAULT_BOID_SCENE.to_string())
+    }
+
+    fn spawn_formation(&mut self, owner: &Node2D, positions: Vec<Vector2>) {
+        let spawn_velocity = self.spawn_velocity();
+        let mut rng = thread_rng();
+
+        for pos in positions {
+            let velocity = spawn_velocity.sample(&mut rng);
+            let species = Species::default();
+            if let Err(e) = unsafe { self.spawn_boid_at(owner, pos, velocity, species) } {
+                godot_error!("failed to spawn boid: {}", e);
+                return;
+            }
+        }
+    }
+
+    // The formations are centred on the middle of the viewport
+    pub fn spawn_circle(&mut self, owner: &Node2D, count: i64, radius: f32) {
+        let positions = spawner::circle(count.max(0) as usize, radius, Vector2::ZERO);
+        self.spawn_formation(owner, positions);
+    }
+
+    pub fn spawn_grid(&mut self, owner: &Node2D, rows: i64, cols: i64, spacing: f32) {
+        let (rows, cols) = (rows.max(0) as usize, cols.max(0) as usize);
+        let positions = spawner::grid(rows, cols, spacing, Vector2::ZERO);
+        self.spawn_formation(owner, positions);
+    }
+
+    pub fn spawn_line(&mut self, owner: &Node2D, count: i64, from: Vector2, to: Vector2) {
+        let positions = spawner::line(count.max(0) as usize, from, to);
+        self.spawn_formation(owner, positions);
+    }
+
+    // Pixels, for boids spawned at random positions from now on
+    pub fn set_spawn_spacing(&mut self, owner: &Node2D, spacing: f32) {
+        self.resources.get_mut::<SpawnSpacing>().map(|mut current| current.0 = spacing.max(0.));
+    }
+
+    // Respawns the flock with the new scene, which must have a `Node2D` root
+    pub fn set_boid_scene(&mut self, owner: &Node2D, path: GodotString) {
+        if let Err(e) = unsafe { self.use_boid_scene(owner, path.to_string()) } {
+            godot_error!("set_boid_scene: {}", e);
+        }
+    }
+
+    pub(super) unsafe fn use_boid_scene(&mut self, owner: &Node2D, path: String) -> Result<()> {
+        // Make sure the scene loads before throwing the old flock away
+        spawner::spawn_boid(&path)?.free();
+        self.resources.insert(BoidScene(path));
+
+        let count = self.resources.get::<BoidCount>().map(|count| count.0).unwrap_or(BOID_COUNT);
+        self.resize_flock(owner, 0)?;
+        self.resize_flock(owner, count)
+    }
+
+    // Returns false if the node at `node_path` isn't a boid
+    pub fn despawn_boid_by_node(&mut self, owner: &Node2D, node_path: NodePath) -> bool {
+        let node = match owner.get_node(node_path) {
+            Some(node) => unsafe { node.assume_safe() },
+            None => return false,
+        };
+        let instance_id = node.get_instance_id();
+
+        let entity = <(Entity, &Boid)>::query()
+            .iter(&self.world)
+            .find(|(_, boid)| {
+                unsafe { boid.node() }.map(|boid| boid.get_instance_id()) == Some(instance_id)
+            })
+            .map(|(&entity, _)| entity);
+
+        match entity {
+            Some(entity) => {
+                node.queue_free();
+                self.world.remove(entity);
+                self.sync_boid_count();
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Returns false if there is no boid with that id
+    pub fn despawn_boid(&mut self, owner: &Node2D, id: i64) -> bool {
+        let entity = match self.find_boid(id) {
+            Ok(entity) => entity,
+            Err(_) => return false,
+        };
+
+        if let Some(boid) = component_of::<Boid>(&self.world, entity) {
+            if let Some(node) = unsafe { boid.node() } {
+                node.queue_free();
+            }
+        }
+        self.world.remove(entity);
+        self.sync_boid_count();
+        true
+    }
+
+    // Remove every boid, for starting over from a formation
+    pub fn clear_boids(&mut self, owner: &Node2D) {
+        let boids = <(Entity, &Boid)>::query()
+            .iter(&self.world)
+            .map(|(&entity, boid)| {
+                if let Some(node) = unsafe { boid.node() } {
+                    node.queue_free();
+                }
+                entity
+            })
+            .collect::<Vec<_>>();
+
+        for entity in boids {
+            self.world.remove(entity);
+        }
+    }
+
+    // Nodes freed outside the GameWorld leave their entities behind, drop
+    // those before any system touches the dangling node
+    pub(super) fn despawn_freed_boids(&mut self) {
+        let freed = <(Entity, &Boid)>::query()
+            .iter(&self.world)
+            .filter(|(_, boid)| !unsafe { boid.is_alive() })
+            .map(|(&entity, _)| entity)
+            .collect::<Vec<_>>();
+        if freed.is_empty() {
+            return;
+        }
+
+        for entity in &freed {
+            self.world.remove(*entity);
+        }
+
+        self.sync_boid_count();
+        log_info!(self.verbosity(), "GameWorld: despawned {} freed boids", freed.len());
+    }
+
+    // Boids born and starved in the ecology system, which can't touch sprites
+    pub(super) unsafe fn apply_population_changes(&mut self, owner: &Node2D) -> Result<()> {
+        let changes = match self.resources.get_mut::<PopulationChanges>() {
+            Some(mut changes) => std::mem::take(&mut *changes),
+            None => return Ok(()),
+        };
+
+        for entity in changes.deaths {
+            if let Some(boid) = component_of::<Boid>(&self.world, entity).and_then(|b| b.node()) {
+                boid.queue_free();
+            }
+            self.world.remove(entity);
+        }
+
+        for birth in changes.births {
+            self.spawn_boid_at(owner, birth.pos, birth.velocity, birth.species)?;
+        }
+
+        self.sync_boid_count();
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+use gdnative::api::Node2D;
+use gdnative::core_types::{GodotString, Variant};
+use gdnative::export::ClassBuilder;
+use gdnative::log::godot_error;
+use legion::*;
+
+use crate::error::Result;
+use crate::mood::{Mood, Moods, StartleWaves};
+use crate::roles::RoleRatios;
+use crate::spawner;
+use crate::species::{Relation, Species, SpeciesLook, SpeciesLooks, SpeciesRelations};
+use crate::traits::{TraitRange, TraitRanges};
+
+use super::{add_component, component_of, component_of_mut, GameWorld};
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn set_boid_species(&mut self, owner: &Node2D, id: i64, species: i64);
+        fn moods_toggled(&mut self, owner: &Node2D, toggle: bool);
+        fn get_boid_mood(&self, owner: &Node2D, id: i64) -> GodotString;
+        fn get_boid_species(&self, owner: &Node2D, id: i64) -> i64;
+        fn set_species_relation(
+            &mut self, owner: &Node2D, from: i64, to: i64, relation: GodotString
+        );
+        fn clear_species_relations(&mut self, owner: &Node2D);
+        fn set_species_scene(&mut self, owner: &Node2D, species: i64, path: GodotString);
+        fn set_species_scale(&mut self, owner: &Node2D, species: i64, scale: f32);
+        fn set_role_ratios(&mut self, owner: &Node2D, scout: f32, straggler: f32);
+        fn set_trait_range(&mut self, owner: &Node2D, name: GodotString, min: f32, max: f32);
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(Moods(false));
+    resources.insert(StartleWaves::default());
+    resources.insert(TraitRanges::default());
+    resources.insert(RoleRatios::default());
+    resources.insert(SpeciesRelations::default());
+    resources.insert(SpeciesLooks::default());
+}
+
+impl GameWorld {
+    pub fn set_boid_species(&mut self, owner: &Node2D, id: i64, species: i64) {
+        let species = Species(species.max(0) as u32);
+        let entity = match self.find_boid(id) {
+            Ok(entity) => entity,
+            Err(e) => {
+                godot_error!("set_boid_species: {}", e);
+                return;
+            }
+        };
+
+        if let Some(current) = component_of_mut::<Species>(&mut self.world, entity) {
+            *current = species;
+            return;
+        }
+        add_component(&mut self.world, entity, species);
+    }
+
+    // Boids get alert and then panic near threats, and pass that on to their
+    // neighbours
+    pub fn moods_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        self.resources.get_mut::<Moods>().map(|mut moods| moods.0 = toggle);
+    }
+
+    // "calm", "alert" or "panicked", empty for an unknown id
+    pub fn get_boid_mood(&self, owner: &Node2D, id: i64) -> GodotString {
+        let mood = self
+            .find_boid(id)
+            .ok()
+            .and_then(|entity| component_of::<Mood>(&self.world, entity))
+            .map(|mood| mood.state.name())
+            .unwrap_or("");
+        GodotString::from_str(mood)
+    }
+
+    pub fn get_boid_species(&self, owner: &Node2D, id: i64) -> i64 {
+        self.find_boid(id)
+            .ok()
+            .and_then(|entity| component_of::<Species>(&self.world, entity))
+            .map(|species| species.0 as i64)
+            .unwrap_or(-1)
+    }
+
+    // `relation` is "chase", "flee" or "neutral", and only says how `from`
+    // reacts to `to`
+    pub fn set_species_relation(
+        &mut self,
+        owner: &Node2D,
+        from: i64,
+        to: i64,
+        relation: GodotString,
+    ) {
+        let relation = match Relation::parse(&relation.to_string()) {
+            Ok(relation) => relation,
+            Err(e) => {
+                godot_error!("set_species_relation: {}", e);
+                return;
+            }
+        };
+
+        let (from, to) = (Species(from.max(0) as u32), Species(to.max(0) as u32));
+        self.resources
+            .get_mut::<SpeciesRelations>()
+            .map(|mut relations| relations.set(from, to, relation));
+    }
+
+    pub fn clear_species_relations(&mut self, owner: &Node2D) {
+        self.resources.get_mut::<SpeciesRelations>().map(|mut relations| relations.0.clear());
+    }
+
+    // Boids of the species spawned from now on use this scene instead of the
+    // boid scene, an empty path goes back to the boid scene
+    pub fn set_species_scene(&mut self, owner: &Node2D, species: i64, path: GodotString) {
+        if let Err(e) = unsafe { self.use_species_scene(Species(species.max(0) as u32), path) } {
+            godot_error!("set_species_scene: {}", e);
+        }
+    }
+
+    unsafe fn use_species_scene(&mut self, species: Species, path: GodotString) -> Result<()> {
+        let path = path.to_string();
+        let scene = if path.is_empty() {
+            None
+        } else {
+            spawner::spawn_boid(&path)?.free();
+            Some(path)
+        };
+        self.resources.get_mut::<SpeciesLooks>().map(|mut looks| {
+            looks.0.entry(species).or_insert_with(SpeciesLook::default).scene = scene
+        });
+        Ok(())
+    }
+
+    // Times the scale of the scene, for boids of the species spawned from now
+    // on
+    pub fn set_species_scale(&mut self, owner: &Node2D, species: i64, scale: f32) {
+        let species = Species(species.max(0) as u32);
+        let scale = if scale.is_finite() && scale > 0. { scale } else { 1. };
+        self.resources.get_mut::<SpeciesLooks>().map(|mut looks| {
+            looks.0.entry(species).or_insert_with(SpeciesLook::default).scale = scale
+        });
+    }
+
+    pub(super) unsafe fn emit_startle_waves(&mut self, owner: &Node2D) {
+        let waves = match self.resources.get_mut::<StartleWaves>() {
+            Some(mut waves) => std::mem::take(&mut waves.0),
+            None => return,
+        };
+
+        for origin in waves {
+            owner.emit_signal(
+                GodotString::from_str("startle_wave"),
+                &[Variant::new(origin)],
+            );
+        }
+    }
+
+    // Shares of new boids that are scouts and stragglers, the rest follow.
+    // Boids that already exist keep their role.
+    pub fn set_role_ratios(&mut self, owner: &Node2D, scout: f32, straggler: f32) {
+        let scout = scout.max(0.).min(1.);
+        let straggler = straggler.max(0.).min(1. - scout);
+        self.resources.get_mut::<RoleRatios>().map(|mut ratios| {
+            ratios.scout = scout;
+            ratios.straggler = straggler;
+        });
+    }
+
+    // `name` is one of "max_speed", "perception", "cohesion", "separation" or
+    // "alignment", only affects boids spawned afterwards
+    pub fn set_trait_range(&mut self, owner: &Node2D, name: GodotString, min: f32, max: f32) {
+        let mut ranges = match self.resources.get_mut::<TraitRanges>() {
+            Some(ranges) => ranges,
+            None => return,
+        };
+
+        match ranges.get_mut(&name.to_string()) {
+            Ok(range) => *range = TraitRange::new(min.min(max), max.max(min)),
+            Err(e) => godot_error!("set_trait_range: {}", e),
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use gdnative::api::{Node2D, Object};
+use gdnative::core_types::{GodotString, Variant, VariantArray, Vector2Array};
+use gdnative::export::ClassBuilder;
+use gdnative::log::godot_error;
+use gdnative::object::Ref;
+use legion::*;
+
+use crate::boids::{Boid, Pos, Velocity};
+use crate::density_control::DensityControl;
+use crate::error::{BoidsError, Result};
+use crate::noise::PerceptionNoise;
+use crate::steering::{CustomForces, SteeringBehaviors};
+
+use super::{
+    AlignmentMul, CohesionMaxForce, CohesionMul, GameWorld, MaxTurnRate, PerceptionRadii,
+    SeparationMul, ZonalBands,
+};
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn cohesion_value_changed(&mut self, owner: &Node2D, val: f32);
+        fn cohesion_max_force_changed(&mut self, owner: &Node2D, val: f32);
+        fn separation_value_changed(&mut self, owner: &Node2D, val: f32);
+        fn alignment_value_changed(&mut self, owner: &Node2D, val: f32);
+        fn set_target_density(&mut self, owner: &Node2D, px: f32);
+        fn set_density_gain(&mut self, owner: &Node2D, gain: f32);
+        fn get_measured_density(&self, owner: &Node2D) -> f32;
+        fn zonal_bands_toggled(&mut self, owner: &Node2D, toggle: bool);
+        fn set_zonal_bands(
+            &mut self,
+            owner: &Node2D,
+            repulsion: f32,
+            orientation: f32,
+            attraction: f32,
+            falloff: f32
+        );
+        fn set_perception_noise(&mut self, owner: &Node2D, position: f32, velocity: f32);
+        fn max_turn_rate_changed(&mut self, owner: &Node2D, val: f32);
+        fn set_custom_force_callback(
+            &mut self, owner: &Node2D, target: Ref<Object>, method: GodotString
+        );
+        fn clear_custom_force_callback(&mut self, owner: &Node2D);
+        fn get_behavior_names(&self, owner: &Node2D) -> VariantArray;
+        fn set_behavior_weight(&mut self, owner: &Node2D, name: GodotString, weight: f32);
+        fn behavior_toggled(&mut self, owner: &Node2D, name: GodotString, enabled: bool);
+        fn move_behavior(&mut self, owner: &Node2D, name: GodotString, position: i64);
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(CohesionMul(1.0));
+    resources.insert(CohesionMaxForce(100.));
+    resources.insert(SeparationMul(1.0));
+    resources.insert(AlignmentMul(1.0));
+    resources.insert(PerceptionRadii::default());
+    resources.insert(ZonalBands::default());
+    resources.insert(PerceptionNoise::default());
+    resources.insert(MaxTurnRate(360.));
+    resources.insert(SteeringBehaviors::builtin());
+    resources.insert(CustomForces::default());
+    resources.insert(DensityControl::default());
+}
+
+impl GameWorld {
+    pub(super) unsafe fn call_custom_force(&mut self) -> Result<()> {
+        let freed = match &self.custom_force {
+            Some((target, _)) => target.assume_safe_if_sane().is_none(),
+            None => return Ok(()),
+        };
+        if freed {
+            self.remove_custom_force();
+            return Err(BoidsError::Missing("callback target was freed".to_string()));
+        }
+
+        let boids = <(Entity, &Pos, &Velocity)>::query()
+            .filter(component::<Boid>())
+            .iter(&self.world)
+            .map(|(&entity, pos, vel)| (entity, pos.0, vel.0))
+            .collect::<Vec<_>>();
+        let mut positions = Vector2Array::new();
+        let mut velocities = Vector2Array::new();
+        for (_, pos, vel) in &boids {
+            positions.push(*pos);
+            velocities.push(*vel);
+        }
+
+        // A bad result leaves the boids without a custom force this frame
+        self.resources.insert(CustomForces::default());
+        let result = match &self.custom_force {
+            Some((target, method)) => target.assume_safe().call(
+                method.clone(),
+                &[
+                    Variant::new(positions),
+                    Variant::new(velocities),
+                ],
+            ),
+            None => return Ok(()),
+        };
+        let forces = result.to::<Vector2Array>().ok_or_else(|| {
+            BoidsError::InvalidArgument("callback has to return a Vector2Array".to_string())
+        })?;
+        if forces.len() as usize != boids.len() {
+            return Err(BoidsError::InvalidArgument(format!(
+                "callback returned {} forces for {} boids",
+                forces.len(),
+                boids.len()
+            )));
+        }
+
+        let forces = boids
+            .iter()
+            .enumerate()
+            .map(|(i, (entity, _, _))| (*entity, forces.get(i as i32)))
+            .collect();
+        self.resources.insert(CustomForces(forces));
+        Ok(())
+    }
+
+    pub fn cohesion_value_changed(&mut self, owner: &Node2D, val: f32) {
+        self.resources.get_mut::<CohesionMul>().map(|mut mul| mul.0 = val);
+    }
+
+    pub fn cohesion_max_force_changed(&mut self, owner: &Node2D, val: f32) {
+        self.resources.get_mut::<CohesionMaxForce>().map(|mut max| max.0 = val.max(0.));
+    }
+
+    pub fn separation_value_changed(&mut self, owner: &Node2D, val: f32) {
+        self.resources.get_mut::<SeparationMul>().map(|mut mul| mul.0 = val);
+    }
+
+    pub fn alignment_value_changed(&mut self, owner: &Node2D, val: f32) {
+        self.resources.get_mut::<AlignmentMul>().map(|mut mul| mul.0 = val);
+    }
+
+    // Mean distance in pixels to the nearest neighbour that cohesion and
+    // separation are adjusted to hold, zero turns the controller off
+    pub fn set_target_density(&mut self, owner: &Node2D, px: f32) {
+        let radius = self.perception_radii().cohesion;
+        self.resources.get_mut::<DensityControl>().map(|mut control| {
+            control.target = DensityControl::sanitized_target(px, radius);
+        });
+    }
+
+    // How fast the controller reacts, too high and the flock overshoots
+    pub fn set_density_gain(&mut self, owner: &Node2D, gain: f32) {
+        self.resources.get_mut::<DensityControl>().map(|mut control| control.gain = gain.max(0.));
+    }
+
+    // The smoothed mean nearest neighbour distance the controller last saw
+    pub fn get_measured_density(&self, owner: &Node2D) -> f32 {
+        self.resources.get::<DensityControl>().map(|control| control.measured).unwrap_or(0.)
+    }
+
+    // Separation, alignment and cohesion by distance band instead of each
+    // with its own radius
+    pub fn zonal_bands_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        self.resources.get_mut::<ZonalBands>().map(|mut bands| bands.enabled = toggle);
+    }
+
+    // Outer edges of the separation, alignment and cohesion bands, and the
+    // distance each edge is blended over
+    pub fn set_zonal_bands(
+        &mut self,
+        owner: &Node2D,
+        repulsion: f32,
+        orientation: f32,
+        attraction: f32,
+        falloff: f32,
+    ) {
+        self.resources.get_mut::<ZonalBands>().map(|mut bands| {
+            *bands = ZonalBands {
+                enabled: bands.enabled,
+                repulsion,
+                orientation,
+                attraction,
+                falloff,
+            }
+            .sanitized();
+        });
+    }
+
+    // Standard deviations of the error in perceived neighbour positions and
+    // velocities, zero for both turns the noise off
+    pub fn set_perception_noise(&mut self, owner: &Node2D, position: f32, velocity: f32) {
+        self.resources.get_mut::<PerceptionNoise>().map(|mut noise| {
+            noise.position = position.max(0.);
+            noise.velocity = velocity.max(0.);
+        });
+    }
+
+    pub fn max_turn_rate_changed(&mut self, owner: &Node2D, val: f32) {
+        self.resources.get_mut::<MaxTurnRate>().map(|mut rate| rate.0 = val.max(0.));
+    }
+
+    // Once per physics frame `target.method(positions, velocities)` is called
+    // with a Vector2Array of each, and has to return a Vector2Array with a
+    // force for every boid. They run as the "custom" steering behaviour.
+    pub fn set_custom_force_callback(
+        &mut self,
+        owner: &Node2D,
+        target: Ref<Object>,
+        method: GodotString,
+    ) {
+        self.custom_force = Some((target, method));
+    }
+
+    pub fn clear_custom_force_callback(&mut self, owner: &Node2D) {
+        self.remove_custom_force();
+    }
+
+    fn remove_custom_force(&mut self) {
+        self.custom_force = None;
+        self.resources.get_mut::<CustomForces>().map(|mut forces| forces.0.clear());
+    }
+
+    pub fn get_behavior_names(&self, owner: &Node2D) -> VariantArray {
+        let names = VariantArray::new();
+        if let Some(behaviors) = self.resources.get::<SteeringBehaviors>() {
+            for name in behaviors.names() {
+                names.push(name);
+            }
+        }
+        names.into_shared()
+    }
+
+    pub fn set_behavior_weight(&mut self, owner: &Node2D, name: GodotString, weight: f32) {
+        let result = self.with_behaviors(|behaviors| {
+            behaviors.get_mut(&name.to_string()).map(|behavior| behavior.weight = weight)
+        });
+        if let Err(e) = result {
+            godot_error!("set_behavior_weight: {}", e);
+        }
+    }
+
+    pub fn behavior_toggled(&mut self, owner: &Node2D, name: GodotString, enabled: bool) {
+        let result = self.with_behaviors(|behaviors| {
+            behaviors.get_mut(&name.to_string()).map(|behavior| behavior.enabled = enabled)
+        });
+        if let Err(e) = result {
+            godot_error!("behavior_toggled: {}", e);
+        }
+    }
+
+    // Moves the behaviour to `position` in the run order
+    pub fn move_behavior(&mut self, owner: &Node2D, name: GodotString, position: i64) {
+        let position = position.max(0) as usize;
+        let result =
+            self.with_behaviors(|behaviors| behaviors.reorder(&name.to_string(), position));
+        if let Err(e) = result {
+            godot_error!("move_behavior: {}", e);
+        }
+    }
+
+    fn with_behaviors<T>(
+        &mut self,
+        f: impl FnOnce(&mut SteeringBehaviors) -> Result<T>,
+    ) -> Result<T> {
+        let mut behaviors = self
+            .resources
+            .get_mut::<SteeringBehaviors>()
+            .ok_or_else(|| BoidsError::Missing("steering behaviors".to_string()))?;
+        f(&mut behaviors)
+    }
+}
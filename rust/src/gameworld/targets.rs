@@ -0,0 +1,143 @@
+use gdnative::api::utils::NodeExt;
+use gdnative::api::Node2D;
+use gdnative::core_types::{GodotString, NodePath, Variant};
+use gdnative::export::ClassBuilder;
+use gdnative::log::godot_error;
+use legion::*;
+
+use crate::arrival::{TargetArrival, TargetsReached};
+use crate::blend::BehaviorBlend;
+use crate::boids::Target;
+use crate::error::{BoidsError, Result};
+use crate::pursuit::TargetTracks;
+
+use super::{GameWorld, PredictionHorizon, Predictive, ShouldFlee, ShouldSeek};
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn add_target(&mut self, owner: &Node2D, node_path: NodePath);
+        fn clear_targets(&mut self, owner: &Node2D);
+        fn seek_toggled(&mut self, owner: &Node2D, toggle: bool);
+        fn flee_toggled(&mut self, owner: &Node2D, toggle: bool);
+        fn set_behavior_blend(&mut self, owner: &Node2D, seconds: f32);
+        fn predictive_toggled(&mut self, owner: &Node2D, toggle: bool);
+        fn prediction_horizon_changed(&mut self, owner: &Node2D, val: f32);
+        fn set_arrival_radius(&mut self, owner: &Node2D, radius: f32);
+        fn landing_toggled(&mut self, owner: &Node2D, toggle: bool);
+        fn landing_enabled(&self, owner: &Node2D) -> bool;
+        fn despawn_on_arrival_toggled(&mut self, owner: &Node2D, toggle: bool);
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(ShouldSeek(false));
+    resources.insert(ShouldFlee(false));
+    resources.insert(BehaviorBlend::default());
+    resources.insert(Predictive(false));
+    resources.insert(PredictionHorizon(1.));
+    resources.insert(TargetTracks::default());
+    resources.insert(TargetArrival::default());
+    resources.insert(TargetsReached::default());
+}
+
+impl GameWorld {
+    pub fn add_target(&mut self, owner: &Node2D, node_path: NodePath) {
+        if let Err(e) = self.insert_target(owner, node_path) {
+            godot_error!("add_target: {}", e);
+        }
+    }
+
+    fn insert_target(&mut self, owner: &Node2D, node_path: NodePath) -> Result<()> {
+        let path = node_path.to_string();
+        let node = unsafe { owner.get_node_as::<Node2D>(node_path) }
+            .ok_or_else(|| BoidsError::NodeNotFound(path))?;
+
+        self.world.push((Target(node.claim()),));
+        Ok(())
+    }
+
+    pub fn clear_targets(&mut self, owner: &Node2D) {
+        let targets = <(Entity, &Target)>::query()
+            .iter(&self.world)
+            .map(|(&entity, _)| entity)
+            .collect::<Vec<_>>();
+
+        for entity in targets {
+            self.world.remove(entity);
+        }
+    }
+
+    pub(super) unsafe fn emit_targets_reached(&mut self, owner: &Node2D) {
+        let reached = match self.resources.get_mut::<TargetsReached>() {
+            Some(mut reached) => std::mem::take(&mut reached.0),
+            None => return,
+        };
+        for id in reached {
+            let id = Variant::new(id.0 as i64);
+            owner.emit_signal(GodotString::from_str("boid_reached_target"), &[id]);
+        }
+    }
+
+    // Once per physics tick rather than per step, the targets only move in
+    // between ticks
+    pub(super) unsafe fn track_targets(&mut self, delta: f32) {
+        let targets = <(Entity, &Target)>::query()
+            .iter(&self.world)
+            .filter_map(|(&entity, target)| {
+                let node = target.node()?;
+                Some((entity, node.global_position(), node.global_rotation() as f32))
+            })
+            .collect::<Vec<_>>();
+        self.resources
+            .get_mut::<TargetTracks>()
+            .map(|mut tracks| tracks.update(targets.into_iter(), delta));
+    }
+
+    pub fn seek_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        self.resources.get_mut::<ShouldSeek>().map(|mut seek| seek.0 = toggle);
+    }
+
+    pub fn flee_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        self.resources.get_mut::<ShouldFlee>().map(|mut flee| flee.0 = toggle);
+        log_debug!(self.verbosity(), "flee toggled: {}", toggle);
+    }
+
+    // Seconds seek and flee take to fade in and out when toggled, zero
+    // switches them at once
+    pub fn set_behavior_blend(&mut self, owner: &Node2D, seconds: f32) {
+        self.resources.get_mut::<BehaviorBlend>().map(|mut blend| blend.duration = seconds.max(0.));
+    }
+
+    pub fn predictive_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        self.resources.get_mut::<Predictive>().map(|mut predictive| predictive.0 = toggle);
+    }
+
+    // Seconds
+    pub fn prediction_horizon_changed(&mut self, owner: &Node2D, val: f32) {
+        self.resources.get_mut::<PredictionHorizon>().map(|mut horizon| horizon.0 = val.max(0.));
+    }
+
+    // Boids within `radius` of a target have reached it, with a
+    // `boid_reached_target` signal. Zero stops looking.
+    pub fn set_arrival_radius(&mut self, owner: &Node2D, radius: f32) {
+        self.resources
+            .get_mut::<TargetArrival>()
+            .map(|mut arrival| arrival.radius = radius.max(0.));
+    }
+
+    // Boids that reach a target stop there, and take off again when this is
+    // turned off
+    pub fn landing_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        self.resources.get_mut::<TargetArrival>().map(|mut arrival| arrival.landing = toggle);
+    }
+
+    pub fn landing_enabled(&self, owner: &Node2D) -> bool {
+        self.resources.get::<TargetArrival>().map(|arrival| arrival.landing).unwrap_or(false)
+    }
+
+    // Boids that reach a target are freed, this wins over landing
+    pub fn despawn_on_arrival_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        self.resources.get_mut::<TargetArrival>().map(|mut arrival| arrival.despawn = toggle);
+    }
+}
@@ -0,0 +1,76 @@
+use gdnative::api::Node2D;
+use gdnative::core_types::{GodotString, Variant};
+use gdnative::export::ClassBuilder;
+use legion::*;
+
+use crate::quality::QualityGovernor;
+use crate::timestep::FixedTimestep;
+
+use super::{GameWorld, TimeScale};
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn set_frame_budget(&mut self, owner: &Node2D, milliseconds: f32);
+        fn get_quality_tier(&self, owner: &Node2D) -> i64;
+        fn set_fixed_timestep(&mut self, owner: &Node2D, step: f32);
+        fn interpolation_toggled(&mut self, owner: &Node2D, toggle: bool);
+        fn frame_interpolation_toggled(&mut self, owner: &Node2D, toggle: bool);
+        fn set_time_scale(&mut self, owner: &Node2D, scale: f32);
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(TimeScale(1.));
+    resources.insert(QualityGovernor::default());
+    resources.insert(FixedTimestep::default());
+}
+
+impl GameWorld {
+    pub(super) unsafe fn emit_quality_changed(&mut self, owner: &Node2D) {
+        let changed = self
+            .resources
+            .get_mut::<QualityGovernor>()
+            .and_then(|mut governor| governor.changed.take());
+        if let Some(tier) = changed {
+            let tier = Variant::new(tier as i64);
+            owner.emit_signal(GodotString::from_str("quality_changed"), &[tier]);
+        }
+    }
+
+    // Milliseconds the simulation may take per physics frame before the
+    // neighbour search is made cheaper, zero keeps full quality
+    pub fn set_frame_budget(&mut self, owner: &Node2D, milliseconds: f32) {
+        self.resources
+            .get_mut::<QualityGovernor>()
+            .map(|mut governor| governor.set_budget(milliseconds));
+    }
+
+    // 0 is full quality, `quality_changed` reports every change
+    pub fn get_quality_tier(&self, owner: &Node2D) -> i64 {
+        self.resources.get::<QualityGovernor>().map(|governor| governor.tier as i64).unwrap_or(0)
+    }
+
+    // Seconds per simulation step, zero steps once per physics tick
+    pub fn set_fixed_timestep(&mut self, owner: &Node2D, step: f32) {
+        self.resources.get_mut::<FixedTimestep>().map(|mut timestep| timestep.step = step.max(0.));
+    }
+
+    pub fn interpolation_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        self.resources
+            .get_mut::<FixedTimestep>()
+            .map(|mut timestep| timestep.interpolate = toggle);
+    }
+
+    // Moves the sprites every rendered frame rather than every physics tick,
+    // so a fixed timestep of 1/30 s still looks smooth at 60 fps or more
+    pub fn frame_interpolation_toggled(&mut self, owner: &Node2D, toggle: bool) {
+        self.resources
+            .get_mut::<FixedTimestep>()
+            .map(|mut timestep| timestep.every_frame = toggle);
+    }
+
+    pub fn set_time_scale(&mut self, owner: &Node2D, scale: f32) {
+        self.resources.get_mut::<TimeScale>().map(|mut time| time.0 = scale.max(0.));
+    }
+}
@@ -0,0 +1,137 @@
+use gdnative::api::Node2D;
+use gdnative::core_types::{Dictionary, Rect2, Vector2};
+use gdnative::export::ClassBuilder;
+use gdnative::log::godot_error;
+use legion::*;
+
+use crate::error::{BoidsError, Result};
+use crate::exclusion::ExclusionRects;
+use crate::zone::{NextZoneId, Zone, ZoneOverrides, ZoneShape};
+
+use super::{inverse_or_identity, world_to_screen, GameWorld};
+
+pub(super) fn register(builder: &ClassBuilder<GameWorld>) {
+    register_exports!(
+        builder,
+        fn add_zone(&mut self, owner: &Node2D, rect: Rect2, overrides: Dictionary) -> i64;
+        fn add_circle_zone(
+            &mut self, owner: &Node2D, center: Vector2, radius: f32, overrides: Dictionary
+        ) -> i64;
+        fn remove_zone(&mut self, owner: &Node2D, id: i64);
+        fn clear_zones(&mut self, owner: &Node2D);
+        fn register_exclusion_rect(&mut self, owner: &Node2D, rect: Rect2) -> i64;
+        fn remove_exclusion_rect(&mut self, owner: &Node2D, index: i64);
+        fn clear_exclusion_rects(&mut self, owner: &Node2D);
+    );
+}
+
+pub(super) fn insert_resources(resources: &mut Resources) {
+    resources.insert(ExclusionRects::default());
+    resources.insert(NextZoneId(0));
+}
+
+impl GameWorld {
+    // `overrides` can hold "cohesion", "separation" and "alignment", which
+    // replace the global multipliers inside the zone. Returns the zone id, or
+    // -1 if the overrides are invalid.
+    pub fn add_zone(&mut self, owner: &Node2D, rect: Rect2, overrides: Dictionary) -> i64 {
+        match self.insert_zone(ZoneShape::Rect(rect), &overrides) {
+            Ok(id) => id as i64,
+            Err(e) => {
+                godot_error!("add_zone: {}", e);
+                -1
+            }
+        }
+    }
+
+    pub fn add_circle_zone(
+        &mut self,
+        owner: &Node2D,
+        center: Vector2,
+        radius: f32,
+        overrides: Dictionary,
+    ) -> i64 {
+        match self.insert_zone(ZoneShape::Circle { center, radius }, &overrides) {
+            Ok(id) => id as i64,
+            Err(e) => {
+                godot_error!("add_circle_zone: {}", e);
+                -1
+            }
+        }
+    }
+
+    fn insert_zone(&mut self, shape: ZoneShape, overrides: &Dictionary) -> Result<u64> {
+        let overrides = serde_json::from_str::<ZoneOverrides>(&overrides.to_json().to_string())?;
+
+        let mut next = self
+            .resources
+            .get_mut::<NextZoneId>()
+            .ok_or_else(|| BoidsError::Missing("NextZoneId resource".to_string()))?;
+        let id = next.0;
+        next.0 += 1;
+
+        self.world.push((Zone { id, shape, overrides },));
+        Ok(id)
+    }
+
+    pub fn remove_zone(&mut self, owner: &Node2D, id: i64) {
+        let zones = <(Entity, &Zone)>::query()
+            .iter(&self.world)
+            .filter(|(_, zone)| zone.id as i64 == id)
+            .map(|(&entity, _)| entity)
+            .collect::<Vec<_>>();
+
+        for entity in zones {
+            self.world.remove(entity);
+        }
+    }
+
+    pub fn clear_zones(&mut self, owner: &Node2D) {
+        let zones = <(Entity, &Zone)>::query()
+            .iter(&self.world)
+            .map(|(&entity, _)| entity)
+            .collect::<Vec<_>>();
+
+        for entity in zones {
+            self.world.remove(entity);
+        }
+    }
+
+    // The screen rects follow the camera
+    pub(super) fn update_exclusion_rects(&mut self, owner: &Node2D) {
+        let screen_to_world = inverse_or_identity(world_to_screen(owner));
+        self.resources
+            .get_mut::<ExclusionRects>()
+            .map(|mut exclusion| exclusion.update(&screen_to_world));
+    }
+
+    // Boids steer around this rect of the screen, in viewport pixels like a
+    // `Control`'s `get_global_rect()`. Returns its index for
+    // `remove_exclusion_rect`.
+    pub fn register_exclusion_rect(&mut self, owner: &Node2D, rect: Rect2) -> i64 {
+        let mut exclusion = match self.resources.get_mut::<ExclusionRects>() {
+            Some(exclusion) => exclusion,
+            None => return -1,
+        };
+        exclusion.screen.push(rect);
+        exclusion.screen.len() as i64 - 1
+    }
+
+    // The others keep their indices, an emptied slot is skipped
+    pub fn remove_exclusion_rect(&mut self, owner: &Node2D, index: i64) {
+        let empty = Rect2::new(Vector2::ZERO, Vector2::ZERO);
+        self.resources.get_mut::<ExclusionRects>().map(|mut exclusion| {
+            match exclusion.screen.get_mut(index as usize) {
+                Some(rect) => *rect = empty,
+                _ => godot_error!("remove_exclusion_rect: no rect {}", index),
+            }
+        });
+    }
+
+    pub fn clear_exclusion_rects(&mut self, owner: &Node2D) {
+        self.resources.get_mut::<ExclusionRects>().map(|mut exclusion| {
+            exclusion.screen.clear();
+            exclusion.world.clear();
+        });
+    }
+}
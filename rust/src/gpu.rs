@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+
+use gdnative::api::{
+    ColorRect, Control, Image, ImageTexture, Node2D, Shader, ShaderMaterial,
+    Viewport as GodotViewport,
+};
+use gdnative::core_types::{ByteArray, Variant, Vector2};
+use gdnative::object::Ref;
+use legion::*;
+use serde::{Deserialize, Serialize};
+
+use crate::boids::live_node;
+use crate::error::{BoidsError, Result};
+use crate::gameworld::PerceptionRadii;
+
+// Widest texture Godot will make, one column per boid
+pub const MAX_GPU_BOIDS: usize = 16384;
+
+// Row 0 of `boids` holds positions and row 1 velocities. Each output row is
+// one rule: the mean offset to the cohesion neighbours, the mean push away
+// from the separation neighbours and the mean alignment velocity, with the
+// neighbour count in blue. Means rather than sums keep the values small
+// enough for a half float render target. With `wrap` the offsets go through
+// the edges of a `wrap_size` world, like `Viewport::wrapped_delta`.
+const SHADER: &str = r#"
+shader_type canvas_item;
+
+uniform sampler2D boids;
+uniform int count;
+uniform float cohesion_radius;
+uniform float separation_radius;
+uniform float alignment_radius;
+uniform bool wrap;
+uniform vec2 wrap_size;
+
+void fragment() {
+    int boid = int(UV.x * float(count));
+    int rule = int(UV.y * 3.0);
+    vec2 pos = texelFetch(boids, ivec2(boid, 0), 0).xy;
+
+    vec2 sum = vec2(0.0);
+    float n = 0.0;
+    for (int i = 0; i < count; i++) {
+        if (i == boid) {
+            continue;
+        }
+        vec2 offset = texelFetch(boids, ivec2(i, 0), 0).xy - pos;
+        if (wrap) {
+            offset -= wrap_size * round(offset / wrap_size);
+        }
+        float d = length(offset);
+        if (rule == 0 && d < cohesion_radius) {
+            sum += offset;
+            n += 1.0;
+        } else if (rule == 1 && d < separation_radius) {
+            sum -= offset;
+            n += 1.0;
+        } else if (rule == 2 && d < alignment_radius) {
+            sum += texelFetch(boids, ivec2(i, 1), 0).xy;
+            n += 1.0;
+        }
+    }
+
+    COLOR = vec4(n > 0.0 ? sum / n : vec2(0.0), n, 1.0);
+}
+"#;
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    Cpu,
+    Gpu,
+}
+
+impl Backend {
+    pub fn parse(backend: &str) -> Result<Self> {
+        match backend {
+            "cpu" => Ok(Backend::Cpu),
+            "gpu" => Ok(Backend::Gpu),
+            _ => Err(BoidsError::InvalidArgument(format!(
+                "unknown backend \"{}\"",
+                backend
+            ))),
+        }
+    }
+}
+
+/// What the shader saw around one boid, `None` where it had no neighbours
+/// for that rule.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GpuNeighbours {
+    pub cohesion: Option<Vector2>,
+    pub separation: Option<Vector2>,
+    pub alignment: Option<Vector2>,
+}
+
+/// Results of the last GPU pass. The neighbour rules use these instead of
+/// searching the index for any boid in here. They are a frame old, and
+/// ignore per-boid perception, boid radii and perception noise.
+#[derive(Debug, Default)]
+pub struct GpuResults(pub HashMap<Entity, GpuNeighbours>);
+
+// -----------------------------------------------------------------------------
+//     - GPU pass -
+// -----------------------------------------------------------------------------
+
+/// A viewport that renders the neighbour math for every boid into a float
+/// texture, which is read back on the next frame.
+pub struct GpuSteering {
+    viewport: Ref<GodotViewport>,
+    material: Ref<ShaderMaterial>,
+    // Column order of the last upload
+    entities: Vec<Entity>,
+}
+
+impl GpuSteering {
+    pub unsafe fn new(owner: &Node2D) -> Result<Self> {
+        let shader = Shader::new();
+        shader.set_code(SHADER);
+        let material = ShaderMaterial::new();
+        material.set_shader(shader);
+        let material = material.into_shared();
+
+        let rect = ColorRect::new();
+        rect.set_material(&material);
+        rect.set_anchors_and_margins_preset(Control::PRESET_WIDE, Control::PRESET_MODE_MINSIZE, 0);
+
+        let viewport = GodotViewport::new();
+        viewport.set_size(Vector2::new(1., 3.));
+        viewport.set_usage(GodotViewport::USAGE_2D);
+        viewport.set_hdr(true);
+        viewport.set_vflip(true);
+        viewport.set_disable_input(true);
+        viewport.set_update_mode(GodotViewport::UPDATE_ALWAYS);
+        viewport.add_child(rect, false);
+        let viewport = viewport.into_shared();
+        owner.add_child(&viewport, false);
+
+        Ok(Self {
+            viewport,
+            material,
+            entities: Vec::new(),
+        })
+    }
+
+    /// Hands the current positions and velocities to the shader, to be
+    /// rendered before the next frame. `wrap` is the size of the wrapping
+    /// world, `None` unless the edges wrap.
+    pub unsafe fn upload(
+        &mut self,
+        boids: &[(Entity, Vector2, Vector2)],
+        radii: &PerceptionRadii,
+        wrap: Option<Vector2>,
+    ) -> Result<()> {
+        if boids.len() > MAX_GPU_BOIDS {
+            return Err(BoidsError::InvalidArgument(format!(
+                "the gpu backend handles at most {} boids",
+                MAX_GPU_BOIDS
+            )));
+        }
+
+        self.entities = boids.iter().map(|(entity, _, _)| *entity).collect();
+        if boids.is_empty() {
+            return Ok(());
+        }
+
+        let mut data = ByteArray::new();
+        let rows = [
+            boids.iter().map(|(_, pos, _)| *pos).collect::<Vec<_>>(),
+            boids.iter().map(|(_, _, vel)| *vel).collect::<Vec<_>>(),
+        ];
+        for value in rows.iter().flatten() {
+            for byte in value
+                .x
+                .to_le_bytes()
+                .iter()
+                .chain(value.y.to_le_bytes().iter())
+            {
+                data.push(*byte);
+            }
+        }
+
+        let width = boids.len() as i64;
+        let image = Image::new();
+        image.create_from_data(width, 2, false, Image::FORMAT_RGF, data);
+        let texture = ImageTexture::new();
+        // No filtering, every texel is a boid
+        texture.create_from_image(image, 0);
+
+        let material = self.material.assume_safe();
+        material.set_shader_param("boids", Variant::new(texture));
+        material.set_shader_param("count", Variant::new(width));
+        material.set_shader_param("cohesion_radius", Variant::new(radii.cohesion as f64));
+        material.set_shader_param("separation_radius", Variant::new(radii.separation as f64));
+        material.set_shader_param("alignment_radius", Variant::new(radii.alignment as f64));
+        material.set_shader_param("wrap", Variant::new(wrap.is_some()));
+        material.set_shader_param("wrap_size", Variant::new(wrap.unwrap_or(Vector2::ZERO)));
+
+        if let Some(viewport) = live_node(&self.viewport) {
+            viewport.set_size(Vector2::new(width as f32, 3.));
+        }
+        Ok(())
+    }
+
+    /// What the viewport rendered for the last upload
+    pub unsafe fn read_back(&self) -> Result<GpuResults> {
+        let mut results = GpuResults::default();
+        if self.entities.is_empty() {
+            return Ok(results);
+        }
+
+        let image = live_node(&self.viewport)
+            .and_then(|viewport| viewport.get_texture())
+            .and_then(|texture| texture.assume_safe().get_data())
+            .ok_or_else(|| BoidsError::Missing("gpu viewport texture".to_string()))?;
+        let image = image.assume_safe();
+
+        if (image.get_width() as usize) < self.entities.len() {
+            // Not rendered at the new size yet
+            return Ok(results);
+        }
+
+        image.lock();
+        for (column, entity) in self.entities.iter().enumerate() {
+            let rule = |row: i64| {
+                let pixel = image.get_pixel(column as i64, row);
+                if pixel.b > 0. {
+                    Some(Vector2::new(pixel.r, pixel.g))
+                } else {
+                    None
+                }
+            };
+
+            let neighbours = GpuNeighbours {
+                cohesion: rule(0),
+                separation: rule(1),
+                alignment: rule(2),
+            };
+            results.0.insert(*entity, neighbours);
+        }
+        image.unlock();
+
+        Ok(results)
+    }
+
+    pub unsafe fn free(self) {
+        if let Some(viewport) = live_node(&self.viewport) {
+            viewport.queue_free();
+        }
+    }
+}
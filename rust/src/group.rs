@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use gdnative::core_types::Vector2;
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::boids::{Pos, MAX_SPEED};
+use crate::gameworld::{BoundaryMode, Delta, Viewport};
+use crate::steering::{Neighbourhood, SteeringBehavior, SteeringBoid};
+
+// A boid has reached its goal inside this distance
+const GOAL_ARRIVE_RADIUS: f32 = 40.;
+// And starts slowing down inside this one
+const GOAL_SLOW_RADIUS: f32 = 150.;
+// Seconds before a goal that wasn't reached is given up on
+pub const GOAL_TIMEOUT: f32 = 15.;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+/// Where `command_group` sent a boid. Until it gets there or the time runs
+/// out the boid ignores the targets and heads here instead.
+#[derive(Debug, Clone, Copy)]
+pub struct GroupGoal {
+    pub target: Vector2,
+    pub time_left: f32,
+}
+
+impl GroupGoal {
+    pub fn new(target: Vector2) -> Self {
+        Self {
+            target,
+            time_left: GOAL_TIMEOUT,
+        }
+    }
+}
+
+/// Given to part of the flock by `split_flock`. Those boids ignore the
+/// targets and fly along `heading`, a unit vector, until `merge_flocks`.
+#[derive(Debug, Clone, Copy)]
+pub struct SplitHeading(pub Vector2);
+
+// -----------------------------------------------------------------------------
+//     - Behaviours -
+// -----------------------------------------------------------------------------
+
+// Towards the `GroupGoal` or along the `SplitHeading`, in place of seek and
+// flee. A split wins over a goal.
+#[derive(Default)]
+pub struct GroupBehavior {
+    goals: HashMap<Entity, Vector2>,
+    headings: HashMap<Entity, Vector2>,
+    boundary: Option<BoundaryMode>,
+    viewport: Option<Viewport>,
+}
+
+impl SteeringBehavior for GroupBehavior {
+    fn name(&self) -> &str {
+        "group"
+    }
+
+    fn prepare(&mut self, world: &World, resources: &Resources) {
+        self.boundary = resources.get::<BoundaryMode>().map(|boundary| *boundary);
+        self.viewport = resources.get::<Viewport>().map(|viewport| *viewport);
+
+        self.goals.clear();
+        self.goals.extend(
+            <(Entity, &GroupGoal)>::query()
+                .iter(world)
+                .map(|(&entity, goal)| (entity, goal.target)),
+        );
+        self.headings.clear();
+        self.headings.extend(
+            <(Entity, &SplitHeading)>::query()
+                .iter(world)
+                .map(|(&entity, heading)| (entity, heading.0)),
+        );
+    }
+
+    fn compute(&mut self, boid: &SteeringBoid, _: &Neighbourhood, _: &Resources) -> Vector2 {
+        if let Some(heading) = self.headings.get(&boid.entity) {
+            return *heading * MAX_SPEED - boid.vel;
+        }
+
+        let target = match self.goals.get(&boid.entity) {
+            Some(target) => *target,
+            None => return Vector2::ZERO,
+        };
+        let to_goal = match (self.boundary, &self.viewport) {
+            (Some(boundary), Some(viewport)) => boundary.delta(viewport, boid.pos, target),
+            _ => target - boid.pos,
+        };
+        let distance = to_goal.length();
+        if distance < GOAL_ARRIVE_RADIUS {
+            return Vector2::ZERO;
+        }
+
+        let arrive = (distance / GOAL_SLOW_RADIUS).min(1.);
+        let desired = to_goal / distance * MAX_SPEED * arrive;
+        desired - boid.vel
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+// Drops the goals that were reached or timed out, before `GroupBehavior`
+// steers towards them
+pub fn expire_group_goals() -> impl ParallelRunnable {
+    SystemBuilder::new("expire group goals")
+        .read_resource::<Delta>()
+        .read_resource::<BoundaryMode>()
+        .read_resource::<Viewport>()
+        .with_query(<(Entity, &Pos, &mut GroupGoal)>::query())
+        .build(|cmd, world, resources, query| {
+            let (delta, boundary, viewport) = resources;
+
+            for (&entity, pos, goal) in query.iter_mut(world) {
+                let distance = boundary.delta(viewport, pos.0, goal.target).length();
+
+                goal.time_left -= delta.0;
+                if distance < GOAL_ARRIVE_RADIUS || goal.time_left <= 0. {
+                    cmd.remove_component::<GroupGoal>(entity);
+                }
+            }
+        })
+}
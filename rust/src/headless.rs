@@ -0,0 +1,93 @@
+use gdnative::core_types::Vector2;
+use legion::*;
+use rand::prelude::*;
+use rand::rngs::SmallRng;
+
+use crate::boids::{
+    add_flocking_systems, add_integration_systems, insert_simulated_boid, safe_normalize, Pos,
+    Radius, Velocity, BOID_RADIUS, MAX_SPEED,
+};
+use crate::gameworld::{default_resources, Delta, NeighbourSearch, Viewport};
+use crate::species::Species;
+use crate::stages::StagedSchedule;
+
+// Area per boid in the 1280x720 demo scene with 80 boids
+const AREA_PER_BOID: f32 = 11_520.;
+
+// -----------------------------------------------------------------------------
+//     - Headless world -
+// -----------------------------------------------------------------------------
+
+/// A flock without any Godot nodes attached, for benchmarks and tests.
+/// The area grows with the boid count so the density matches the demo.
+/// Boids get the same components as in the game, minus the node and sprite
+/// state, so per-boid perception and the steering stagger run too.
+pub struct HeadlessWorld {
+    world: World,
+    resources: Resources,
+    schedule: Schedule,
+}
+
+impl HeadlessWorld {
+    pub fn new(boid_count: usize, search: NeighbourSearch) -> Self {
+        let side = (boid_count as f32 * AREA_PER_BOID).sqrt();
+        Self::with_size(boid_count, search, Vector2::new(side, side))
+    }
+
+    pub fn with_size(boid_count: usize, search: NeighbourSearch, size: Vector2) -> Self {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut world = World::default();
+        let mut resources = default_resources();
+
+        let viewport = Viewport::from_vec2(size);
+        resources.insert(viewport);
+        resources.insert(search);
+
+        for _ in 0..boid_count {
+            let x = rng.gen_range(viewport.0.position.x, viewport.0.end().x);
+            let y = rng.gen_range(viewport.0.position.y, viewport.0.end().y);
+            let velocity = safe_normalize(Vector2::new(
+                rng.gen_range(-500., 500.),
+                rng.gen_range(-500., 500.),
+            )) * MAX_SPEED;
+
+            // `default_resources` has the boid ids, so this can't fail
+            let _ = insert_simulated_boid(
+                &mut world,
+                &resources,
+                &mut rng,
+                Vector2::new(x, y),
+                velocity,
+                Radius(BOID_RADIUS),
+                Species::default(),
+            );
+        }
+
+        let schedule = add_integration_systems(add_flocking_systems(StagedSchedule::new()))
+            .build()
+            .physics;
+
+        Self {
+            world,
+            resources,
+            schedule,
+        }
+    }
+
+    pub fn tick(&mut self, delta: f32) {
+        self.resources.get_mut::<Delta>().map(|mut d| d.0 = delta);
+        self.schedule.execute(&mut self.world, &mut self.resources);
+    }
+
+    pub fn resources_mut(&mut self) -> &mut Resources {
+        &mut self.resources
+    }
+
+    // Position and velocity of every boid
+    pub fn boids(&self) -> Vec<(Vector2, Vector2)> {
+        <(&Pos, &Velocity)>::query()
+            .iter(&self.world)
+            .map(|(pos, vel)| (pos.0, vel.0))
+            .collect()
+    }
+}
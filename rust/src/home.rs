@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use gdnative::core_types::Vector2;
+use legion::*;
+use serde::{Deserialize, Serialize};
+
+use crate::boids::MAX_SPEED;
+use crate::gameworld::{BoundaryMode, Viewport};
+use crate::steering::{Neighbourhood, SteeringBehavior, SteeringBoid};
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+/// Where a boid is tethered to, see `HomeAnchor`
+#[derive(Debug, Clone, Copy)]
+pub struct Home(pub Vector2);
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Boids with a `Home` are pulled back towards it once they stray further
+/// than `radius`, with up to `strength` times the max speed at `falloff`
+/// pixels beyond that. With `on_spawn` every boid is given the point it
+/// spawned at as its home.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct HomeAnchor {
+    pub strength: f32,
+    pub radius: f32,
+    pub falloff: f32,
+    pub on_spawn: bool,
+}
+
+impl Default for HomeAnchor {
+    fn default() -> Self {
+        Self {
+            strength: 0.3,
+            radius: 200.,
+            falloff: 600.,
+            on_spawn: false,
+        }
+    }
+}
+
+impl HomeAnchor {
+    pub fn sanitized(self) -> Self {
+        Self {
+            strength: self.strength.max(0.),
+            radius: self.radius.max(0.),
+            falloff: self.falloff.max(0.),
+            ..self
+        }
+    }
+
+    // Nothing inside the radius, growing linearly with the distance past it
+    fn pull(&self, distance: f32) -> f32 {
+        let beyond = distance - self.radius;
+        if beyond <= 0. {
+            return 0.;
+        }
+        if self.falloff <= 0. {
+            return self.strength;
+        }
+        (beyond / self.falloff).min(1.) * self.strength
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Behaviours -
+// -----------------------------------------------------------------------------
+
+// Pulls the boids with a `Home` back towards it, see `HomeAnchor`
+#[derive(Default)]
+pub struct HomeBehavior {
+    homes: HashMap<Entity, Vector2>,
+    anchor: HomeAnchor,
+    boundary: Option<BoundaryMode>,
+    viewport: Option<Viewport>,
+}
+
+impl SteeringBehavior for HomeBehavior {
+    fn name(&self) -> &str {
+        "home"
+    }
+
+    fn prepare(&mut self, world: &World, resources: &Resources) {
+        self.anchor = resources
+            .get::<HomeAnchor>()
+            .map(|anchor| *anchor)
+            .unwrap_or_default();
+        self.boundary = resources.get::<BoundaryMode>().map(|boundary| *boundary);
+        self.viewport = resources.get::<Viewport>().map(|viewport| *viewport);
+
+        self.homes.clear();
+        if self.anchor.strength > 0. {
+            self.homes.extend(
+                <(Entity, &Home)>::query()
+                    .iter(world)
+                    .map(|(&entity, home)| (entity, home.0)),
+            );
+        }
+    }
+
+    fn compute(&mut self, boid: &SteeringBoid, _: &Neighbourhood, _: &Resources) -> Vector2 {
+        let home = match self.homes.get(&boid.entity) {
+            Some(home) => *home,
+            None => return Vector2::ZERO,
+        };
+
+        let to_home = match (self.boundary, &self.viewport) {
+            (Some(boundary), Some(viewport)) => boundary.delta(viewport, boid.pos, home),
+            _ => home - boid.pos,
+        };
+        let distance = to_home.length();
+        let pull = self.anchor.pull(distance);
+        if pull > 0. {
+            to_home / distance * MAX_SPEED * pull
+        } else {
+            Vector2::ZERO
+        }
+    }
+}
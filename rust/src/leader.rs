@@ -0,0 +1,110 @@
+use std::cmp::Ordering;
+
+use gdnative::api::Node2D;
+use gdnative::core_types::Vector2;
+use gdnative::object::{Ref, TRef};
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::boids::{live_node, Forces, Pos, Velocity, MAX_SPEED};
+use crate::gameworld::{BoundaryMode, Viewport};
+
+// Followers only notice leaders within this distance
+const FOLLOW_RADIUS: f32 = 400.;
+// How far behind the leader followers try to be
+const FOLLOW_BEHIND: f32 = 60.;
+// Followers slow down inside this distance of the spot behind the leader
+const FOLLOW_ARRIVE_RADIUS: f32 = 80.;
+// Followers in front of the leader, closer than this, get out of the way
+const LEADER_SIGHT_RADIUS: f32 = 50.;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+/// Marks a boid (or a `LeaderNode` entity) that the rest of the flock follows
+pub struct Leader;
+
+/// A user controlled node leading the flock, heading where the node faces
+pub struct LeaderNode(pub Ref<Node2D>);
+
+impl LeaderNode {
+    pub unsafe fn node(&self) -> Option<TRef<Node2D>> {
+        live_node(&self.0)
+    }
+
+    pub unsafe fn is_alive(&self) -> bool {
+        self.node().is_some()
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Where every `LeaderNode` is and the unit vector it faces, read from the
+/// nodes by the `GameWorld` before each tick
+#[derive(Debug, Default)]
+pub struct LeaderPoses(pub Vec<(Vector2, Vector2)>);
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+// Arrive at a point behind the nearest leader, stepping aside when in its way
+pub fn follow_leaders() -> impl ParallelRunnable {
+    SystemBuilder::new("follow leaders")
+        .read_resource::<BoundaryMode>()
+        .read_resource::<Viewport>()
+        .read_resource::<LeaderPoses>()
+        .with_query(<(&Pos, &Velocity)>::query().filter(component::<Leader>()))
+        .with_query(<(&Pos, &Velocity, &mut Forces)>::query().filter(!component::<Leader>()))
+        .build(|_, world, resources, queries| {
+            let (boundary, viewport, leader_nodes) = resources;
+            let (leader_boids, followers) = queries;
+
+            let mut leaders = leader_boids
+                .iter_mut(world)
+                .filter(|(_, vel)| vel.0.length_squared() > 0.)
+                .map(|(pos, vel)| (pos.0, vel.0.normalized()))
+                .collect::<Vec<_>>();
+
+            leaders.extend_from_slice(&leader_nodes.0);
+
+            if leaders.is_empty() {
+                return;
+            }
+
+            for (pos, vel, force) in followers.iter_mut(world) {
+                let nearest = leaders
+                    .iter()
+                    .map(|(leader_pos, heading)| {
+                        (boundary.delta(viewport, pos.0, *leader_pos), *heading)
+                    })
+                    .min_by(|(a, _), (b, _)| {
+                        a.length_squared()
+                            .partial_cmp(&b.length_squared())
+                            .unwrap_or(Ordering::Equal)
+                    });
+
+                let (to_leader, heading) = match nearest {
+                    Some(nearest) if nearest.0.length() < FOLLOW_RADIUS => nearest,
+                    _ => continue,
+                };
+
+                let to_behind = to_leader - heading * FOLLOW_BEHIND;
+                let arrive = (to_behind.length() / FOLLOW_ARRIVE_RADIUS).min(1.);
+                let desired = to_behind.clamped(MAX_SPEED) * arrive;
+                force.follow = desired - vel.0;
+
+                // Just ahead of the leader, clear the way
+                let from_ahead = -(to_leader + heading * FOLLOW_BEHIND);
+                let ahead_distance = from_ahead.length();
+                if ahead_distance > 0. && ahead_distance < LEADER_SIGHT_RADIUS {
+                    force.follow += from_ahead / ahead_distance * MAX_SPEED;
+                }
+
+                force.follow = force.follow.clamped(MAX_SPEED);
+            }
+        })
+}
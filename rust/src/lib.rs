@@ -1,16 +1,95 @@
-use gdnative::*;
+use gdnative::init::{
+    godot_gdnative_init, godot_gdnative_terminate, godot_nativescript_init, InitHandle,
+    TerminateInfo,
+};
 
-mod gameworld;
+#[macro_use]
+mod log;
+
+pub mod age;
+pub mod analysis;
+pub mod animation;
+pub mod area;
+pub mod arrival;
+pub mod audio;
+pub mod bank;
+pub mod blend;
+pub mod capture;
+pub mod collision;
+pub mod color_mode;
+pub mod config;
+pub mod debug;
+pub mod density;
+pub mod density_control;
+pub mod ecology;
+pub mod emitter;
+pub mod energy;
+pub mod error;
+pub mod exclusion;
+mod files;
+pub mod flock_state;
+pub mod flocks;
+pub mod flow;
+pub mod forage;
+pub mod formation;
+pub mod gameworld;
+pub mod gpu;
+pub mod group;
+pub mod headless;
+pub mod home;
+pub mod leader;
+pub mod lifetime;
+pub mod linked;
+pub mod lod;
+pub mod marker;
+pub mod metrics;
+pub mod migration;
+pub mod mood;
+pub mod node_commands;
+pub mod noise;
+pub mod patrol;
+pub mod perch;
+pub mod point_force;
+pub mod preset;
+pub mod pressure;
+pub mod preview;
+pub mod pursuit;
+pub mod quality;
+pub mod replay;
+pub mod roles;
+pub mod scatter;
+pub mod schedule;
+pub mod sink;
+pub mod snapshot;
+pub mod spatial;
+pub mod species;
 mod spawner;
-mod boids;
+pub mod stages;
+pub mod stamp;
+pub mod steering;
+pub mod timestep;
+pub mod traits;
+pub mod walls;
+pub mod zone;
+pub mod boids;
 
-fn init(handle: init::InitHandle) {
+fn init(handle: InitHandle) {
     handle.add_class::<gameworld::GameWorld>();
+    handle.add_tool_class::<preview::BoidPreview>();
+    handle.add_class::<point_force::Attractor>();
+    handle.add_class::<point_force::Repeller>();
+    handle.add_class::<emitter::BoidEmitter>();
+    handle.add_class::<sink::BoidSink>();
+}
+
+// Also right before a reloadable library is swapped out
+fn terminate(_info: &TerminateInfo) {
+    gameworld::stash_for_reload();
 }
 
 godot_gdnative_init!();
 godot_nativescript_init!(init);
-godot_gdnative_terminate!();
+godot_gdnative_terminate!(terminate);
 
 
 #[cfg(feature = "godot_test")]
@@ -44,11 +123,27 @@ macro_rules! assert_gd {
 
 #[no_mangle]
 #[cfg(feature = "godot_test")]
-pub extern fn run_tests() -> sys::godot_variant {
+pub extern fn run_tests() -> gdnative::sys::godot_variant {
     let mut status = true;
 
-    eprintln!("Running tests: [add your tests here]");
-    // status &= run_test!(path::to::test_fn);
+    eprintln!("Running tests:");
+    status &= run_test!(steering::math::tests::no_neighbours);
+    status &= run_test!(steering::math::tests::cohesion_seeks_centroid);
+    status &= run_test!(steering::math::tests::coincident_positions);
+    status &= run_test!(steering::math::tests::separation_pushes_away);
+    status &= run_test!(steering::math::tests::alignment_averages_velocities);
+    status &= run_test!(steering::math::tests::weighted_mean_weighs);
+    status &= run_test!(steering::math::tests::bands_blend);
+    status &= run_test!(steering::math::tests::nan_guards);
+    status &= run_test!(blend::tests::approach_steps_to_target);
+    status &= run_test!(boids::tests::impulses_exceed_max_speed);
+    status &= run_test!(timestep::tests::advance_carries_remainder);
+    status &= run_test!(timestep::tests::advance_caps_substeps);
+    status &= run_test!(timestep::tests::advance_frame_moves_alpha);
+    status &= run_test!(timestep::tests::render_position_skips_jumps);
+    status &= run_test!(spatial::tests::delta_wraps_through_edges);
+    status &= run_test!(spatial::tests::keep_nearest_counts);
+    status &= run_test!(stages::tests::stage_names_parse);
 
-    gdnative::Variant::from_bool(status).forget()
+    gdnative::core_types::Variant::new(status).leak()
 }
@@ -0,0 +1,103 @@
+use std::f32::consts::PI;
+
+use gdnative::core_types::Vector2;
+use legion::systems::ParallelRunnable;
+use legion::*;
+use rand::prelude::*;
+
+use crate::boids::{rotated, Pos, Velocity, MAX_SPEED};
+use crate::energy::Energy;
+use crate::gameworld::{Delta, Viewport};
+use crate::traits::TraitRange;
+
+// Respawned boids head into the screen within this angle either side of
+// straight in
+const RESPAWN_SPREAD: f32 = PI / 4.;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+// Seconds left before the boid is recycled at the edge of the screen
+pub struct Lifetime(pub f32);
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+// `None` and boids live forever
+pub struct LifetimeRange(pub Option<TraitRange>);
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+// A point on a random edge of the viewport and a velocity pointing inwards
+pub fn edge_spawn(viewport: &Viewport, rng: &mut impl Rng) -> (Vector2, Vector2) {
+    let rect = viewport.0;
+    let (pos, inwards) = match rng.gen_range(0, 4) {
+        0 => (
+            Vector2::new(
+                rect.position.x,
+                rng.gen_range(rect.position.y, rect.end().y),
+            ),
+            Vector2::new(1., 0.),
+        ),
+        1 => (
+            Vector2::new(rect.end().x, rng.gen_range(rect.position.y, rect.end().y)),
+            Vector2::new(-1., 0.),
+        ),
+        2 => (
+            Vector2::new(
+                rng.gen_range(rect.position.x, rect.end().x),
+                rect.position.y,
+            ),
+            Vector2::new(0., 1.),
+        ),
+        _ => (
+            Vector2::new(rng.gen_range(rect.position.x, rect.end().x), rect.end().y),
+            Vector2::new(0., -1.),
+        ),
+    };
+
+    let heading = rotated(inwards, rng.gen_range(-RESPAWN_SPREAD, RESPAWN_SPREAD));
+    (pos, heading * MAX_SPEED)
+}
+
+// Expired boids are moved to the edge with a fresh lifetime instead of being
+// deleted, so the population stays the same and no sprites are freed
+pub fn age_boids() -> impl ParallelRunnable {
+    SystemBuilder::new("age boids")
+        .read_resource::<Delta>()
+        .read_resource::<Viewport>()
+        .read_resource::<LifetimeRange>()
+        .with_query(<(
+            &mut Lifetime,
+            &mut Pos,
+            &mut Velocity,
+            Option<&mut Energy>,
+        )>::query())
+        .build(|_, world, resources, query| {
+            let (delta, viewport, range) = resources;
+            let range = match range.0 {
+                Some(range) => range,
+                None => return,
+            };
+
+            let mut rng = thread_rng();
+            for (lifetime, pos, vel, energy) in query.iter_mut(world) {
+                lifetime.0 -= delta.0;
+                if lifetime.0 > 0. {
+                    continue;
+                }
+
+                let (spawn, velocity) = edge_spawn(viewport, &mut rng);
+                pos.0 = spawn;
+                vel.0 = velocity;
+                lifetime.0 = range.sample(&mut rng);
+                if let Some(energy) = energy {
+                    *energy = Energy::full();
+                }
+            }
+        })
+}
@@ -0,0 +1,78 @@
+use gdnative::core_types::Vector2;
+use legion::*;
+
+use crate::boids::MAX_SPEED;
+use crate::spatial::SpatialGrid;
+use crate::steering::{Neighbourhood, SteeringBehavior, SteeringBoid};
+
+// Boids of a linked world closer than this are fled from
+const LINKED_FLEE_RADIUS: f32 = 150.;
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Where the boids of the worlds linked with `link_world` were at the start
+/// of the physics frame. The worlds only ever read each other's positions,
+/// their simulations stay separate.
+pub struct LinkedBoids {
+    pub positions: Vec<Vector2>,
+    grid: SpatialGrid,
+}
+
+impl Default for LinkedBoids {
+    fn default() -> Self {
+        Self {
+            positions: Vec::new(),
+            grid: SpatialGrid::new(LINKED_FLEE_RADIUS),
+        }
+    }
+}
+
+impl LinkedBoids {
+    pub fn set(&mut self, positions: Vec<Vector2>) {
+        self.grid.rebuild(&positions);
+        self.positions = positions;
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Behaviours -
+// -----------------------------------------------------------------------------
+
+// Away from the boids of linked worlds, the closer the harder. A negative
+// weight turns it into a chase.
+#[derive(Default)]
+pub struct LinkedFleeBehavior;
+
+impl SteeringBehavior for LinkedFleeBehavior {
+    fn name(&self) -> &str {
+        "flee_linked"
+    }
+
+    fn compute(
+        &mut self,
+        boid: &SteeringBoid,
+        _: &Neighbourhood,
+        resources: &Resources,
+    ) -> Vector2 {
+        let linked = match resources.get::<LinkedBoids>() {
+            Some(linked) => linked,
+            None => return Vector2::ZERO,
+        };
+
+        let mut away = Vector2::ZERO;
+        for other in linked.grid.candidates(boid.pos, LINKED_FLEE_RADIUS) {
+            let from_other = boid.pos - linked.positions[other];
+            let distance = from_other.length();
+            if distance > 0. && distance < LINKED_FLEE_RADIUS {
+                away += from_other / distance * (1. - distance / LINKED_FLEE_RADIUS);
+            }
+        }
+
+        if away.length_squared() == 0. {
+            return Vector2::ZERO;
+        }
+        away.normalized() * MAX_SPEED - boid.vel
+    }
+}
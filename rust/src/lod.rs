@@ -0,0 +1,131 @@
+use gdnative::core_types::{Rect2, Vector2};
+use legion::systems::ParallelRunnable;
+use legion::*;
+use serde::{Deserialize, Serialize};
+
+use crate::boids::{Boid, Pos};
+use crate::node_commands::{NodeCommand, NodeCommands};
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LodLevel {
+    // On screen or close to it
+    Full,
+    Reduced,
+    // Well out of sight
+    Far,
+}
+
+impl LodLevel {
+    // Multiplier on how long cached neighbours are reused
+    pub fn staleness(self) -> usize {
+        match self {
+            LodLevel::Full => 1,
+            LodLevel::Reduced => 2,
+            LodLevel::Far => 4,
+        }
+    }
+
+    // Frames between rotation updates
+    pub fn rotation_interval(self) -> usize {
+        match self {
+            LodLevel::Full => 1,
+            LodLevel::Reduced => 2,
+            LodLevel::Far => 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Lod {
+    pub level: LodLevel,
+    // Whether the sprite was hidden for being far away
+    pub hidden: bool,
+}
+
+impl Default for Lod {
+    fn default() -> Self {
+        Self {
+            level: LodLevel::Full,
+            hidden: false,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Distances from the edge of the camera view past which boids drop to a
+/// lower level of detail. `hide_far` also hides the sprites of far boids.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct LodSettings {
+    pub near: f32,
+    pub far: f32,
+    pub hide_far: bool,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self {
+            near: 200.,
+            far: 800.,
+            hide_far: true,
+        }
+    }
+}
+
+impl LodSettings {
+    fn level(&self, view: Rect2, pos: Vector2) -> LodLevel {
+        let dx = (view.position.x - pos.x).max(pos.x - view.end().x).max(0.);
+        let dy = (view.position.y - pos.y).max(pos.y - view.end().y).max(0.);
+        let distance = (dx * dx + dy * dy).sqrt();
+
+        if distance <= self.near {
+            LodLevel::Full
+        } else if distance <= self.far {
+            LodLevel::Reduced
+        } else {
+            LodLevel::Far
+        }
+    }
+}
+
+/// What the camera set with `set_lod_camera` sees, updated by the `GameWorld`
+/// every physics frame. Without a camera every boid gets full detail.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LodView {
+    pub view: Option<Rect2>,
+    // Physics frames so far, to spread out the rotation updates
+    pub frame: usize,
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn assign_lod() -> impl ParallelRunnable {
+    SystemBuilder::new("assign lod")
+        .read_resource::<LodView>()
+        .read_resource::<LodSettings>()
+        .write_resource::<NodeCommands>()
+        .with_query(<(Entity, &Pos, &mut Lod)>::query().filter(component::<Boid>()))
+        .build(|_, world, resources, query| {
+            let (view, settings, commands) = resources;
+
+            for (&entity, pos, lod) in query.iter_mut(world) {
+                lod.level = match view.view {
+                    Some(view) => settings.level(view, pos.0),
+                    None => LodLevel::Full,
+                };
+
+                let hidden = settings.hide_far && lod.level == LodLevel::Far;
+                if hidden != lod.hidden {
+                    commands.push(entity, NodeCommand::SetVisible(!hidden));
+                    lod.hidden = hidden;
+                }
+            }
+        })
+}
@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BoidsError, Result};
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// How chatty the crate is in the Godot output panel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verbosity {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Verbosity {
+    pub fn parse(level: &str) -> Result<Self> {
+        match level {
+            "error" => Ok(Verbosity::Error),
+            "warn" => Ok(Verbosity::Warn),
+            "info" => Ok(Verbosity::Info),
+            "debug" => Ok(Verbosity::Debug),
+            _ => Err(BoidsError::InvalidArgument(format!(
+                "unknown verbosity \"{}\"",
+                level
+            ))),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Macros -
+// -----------------------------------------------------------------------------
+
+// Errors always go straight to `godot_error!`, these are filtered by the
+// `Verbosity` resource passed in as the first argument.
+
+macro_rules! log_warn {
+    ($verbosity:expr, $($args:tt)*) => {
+        if $verbosity >= $crate::log::Verbosity::Warn {
+            gdnative::log::godot_warn!($($args)*)
+        }
+    };
+}
+
+macro_rules! log_info {
+    ($verbosity:expr, $($args:tt)*) => {
+        if $verbosity >= $crate::log::Verbosity::Info {
+            gdnative::log::godot_print!($($args)*)
+        }
+    };
+}
+
+macro_rules! log_debug {
+    ($verbosity:expr, $($args:tt)*) => {
+        if $verbosity >= $crate::log::Verbosity::Debug {
+            gdnative::log::godot_print!("[debug] {}", format!($($args)*))
+        }
+    };
+}
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+
+use gdnative::api::Node2D;
+use gdnative::object::{Ref, TRef};
+use legion::systems::Runnable;
+use legion::*;
+
+use crate::boids::{live_node, Boid};
+use crate::node_commands::SpriteTransform;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+/// A node glued to a boid with `attach_marker_to_boid` (a crosshair, a name
+/// label, a particle emitter). Lives on its own entity, which is deleted
+/// once the boid is gone or the node freed. The node itself is left alone.
+pub struct Marker {
+    pub node: Ref<Node2D>,
+    pub boid: Entity,
+}
+
+impl Marker {
+    pub unsafe fn node(&self) -> Option<TRef<Node2D>> {
+        live_node(&self.node)
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+// Runs after `apply_node_commands`, copying the transforms the boids were
+// just given. Batched boids don't have them on the node.
+pub fn follow_markers() -> impl Runnable {
+    SystemBuilder::new("follow markers")
+        .with_query(<(Entity, &Boid, Option<&SpriteTransform>)>::query())
+        .with_query(<(Entity, &mut Marker)>::query())
+        .build(|cmd, world, _, queries| {
+            let (boids, markers) = queries;
+            let transforms = boids
+                .iter(world)
+                .filter_map(|(&entity, boid, batched)| {
+                    let transform = match batched {
+                        Some(batched) => (batched.position, batched.rotation as f64),
+                        None => {
+                            let node = unsafe { boid.node() }?;
+                            (node.global_position(), node.global_rotation())
+                        }
+                    };
+                    Some((entity, transform))
+                })
+                .collect::<HashMap<_, _>>();
+
+            for (&entity, marker) in markers.iter_mut(world) {
+                let transform = transforms.get(&marker.boid);
+                match (transform, unsafe { marker.node() }) {
+                    (Some((pos, rot)), Some(node)) => {
+                        node.set_global_position(*pos);
+                        node.set_global_rotation(*rot);
+                    }
+                    _ => cmd.remove(entity),
+                }
+            }
+        })
+}
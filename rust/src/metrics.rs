@@ -0,0 +1,199 @@
+use std::cmp::Ordering;
+use std::fmt::Write as _;
+
+use gdnative::core_types::{Rect2, Vector2};
+use legion::systems::ParallelRunnable;
+use legion::*;
+use serde::Serialize;
+
+use crate::boids::{ALIGNMENT_RADIUS, COHESION_RADIUS};
+use crate::error::Result;
+use crate::gameworld::Delta;
+use crate::spatial::FlockIndex;
+
+// -----------------------------------------------------------------------------
+//     - Samples -
+// -----------------------------------------------------------------------------
+
+/// Flock statistics at one point in time. Nearest neighbour distances leave
+/// out boids with nobody inside the cohesion radius.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct MetricsSample {
+    pub time: f32,
+    pub boids: usize,
+    // Length of the mean heading, 1 when every boid flies the same way
+    pub order: f32,
+    pub nearest_min: f32,
+    pub nearest_median: f32,
+    pub nearest_mean: f32,
+    pub nearest_max: f32,
+    pub flocks: usize,
+}
+
+impl MetricsSample {
+    fn take(time: f32, index: &FlockIndex) -> Self {
+        let boids = index.positions.len();
+
+        let heading = index
+            .velocities
+            .iter()
+            .filter(|vel| vel.length_squared() > 0.)
+            .fold(Vector2::ZERO, |acc, vel| acc + vel.normalized());
+        let order = if boids > 0 {
+            heading.length() / boids as f32
+        } else {
+            0.
+        };
+
+        let mut nearest = (0..boids)
+            .filter_map(|boid| index.nearest_distance(boid, COHESION_RADIUS))
+            .collect::<Vec<_>>();
+        nearest.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+        let (nearest_min, nearest_median, nearest_mean, nearest_max) = if nearest.is_empty() {
+            (0., 0., 0., 0.)
+        } else {
+            let mean = nearest.iter().sum::<f32>() / nearest.len() as f32;
+            (
+                nearest[0],
+                nearest[nearest.len() / 2],
+                mean,
+                nearest[nearest.len() - 1],
+            )
+        };
+
+        let (_, flocks) = index.clusters(ALIGNMENT_RADIUS);
+
+        Self {
+            time,
+            boids,
+            order,
+            nearest_min,
+            nearest_median,
+            nearest_mean,
+            nearest_max,
+            flocks,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+pub struct Telemetry {
+    // Ticks between samples, zero stops sampling
+    pub interval: usize,
+    pub ticks: usize,
+    pub time: f32,
+    pub samples: Vec<MetricsSample>,
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Self {
+            interval: 30,
+            ticks: 0,
+            time: 0.,
+            samples: Vec::new(),
+        }
+    }
+}
+
+impl Telemetry {
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "time,boids,order,nearest_min,nearest_median,nearest_mean,nearest_max,flocks\n",
+        );
+
+        for sample in &self.samples {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{}",
+                sample.time,
+                sample.boids,
+                sample.order,
+                sample.nearest_min,
+                sample.nearest_median,
+                sample.nearest_mean,
+                sample.nearest_max,
+                sample.flocks
+            );
+        }
+
+        csv
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(&self.samples)?)
+    }
+}
+
+/// Where the flock is this tick, both zero while there are no boids
+#[derive(Debug, Clone, Copy)]
+pub struct FlockStats {
+    pub centroid: Vector2,
+    // Smallest rect holding every boid's position
+    pub bounds: Rect2,
+}
+
+impl Default for FlockStats {
+    fn default() -> Self {
+        Self {
+            centroid: Vector2::ZERO,
+            bounds: Rect2::new(Vector2::ZERO, Vector2::ZERO),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn flock_stats() -> impl ParallelRunnable {
+    SystemBuilder::new("flock stats")
+        .read_resource::<FlockIndex>()
+        .write_resource::<FlockStats>()
+        .build(|_, _, resources, _| {
+            let (index, stats) = resources;
+            let positions = &index.positions;
+            if positions.is_empty() {
+                *stats = FlockStats::default();
+                return;
+            }
+
+            let sum = positions.iter().fold(Vector2::ZERO, |acc, pos| acc + *pos);
+            let (min, max) =
+                positions
+                    .iter()
+                    .skip(1)
+                    .fold((positions[0], positions[0]), |(min, max), pos| {
+                        (
+                            Vector2::new(min.x.min(pos.x), min.y.min(pos.y)),
+                            Vector2::new(max.x.max(pos.x), max.y.max(pos.y)),
+                        )
+                    });
+
+            stats.centroid = sum / positions.len() as f32;
+            stats.bounds = Rect2::new(min, (max - min));
+        })
+}
+
+pub fn telemetry() -> impl ParallelRunnable {
+    SystemBuilder::new("telemetry")
+        .read_resource::<Delta>()
+        .read_resource::<FlockIndex>()
+        .write_resource::<Telemetry>()
+        .build(|_, _, resources, _| {
+            let (delta, index, telemetry) = resources;
+            telemetry.time += delta.0;
+
+            if telemetry.interval == 0 {
+                return;
+            }
+
+            if telemetry.ticks % telemetry.interval == 0 {
+                let sample = MetricsSample::take(telemetry.time, index);
+                telemetry.samples.push(sample);
+            }
+            telemetry.ticks += 1;
+        })
+}
@@ -0,0 +1,144 @@
+use gdnative::core_types::Vector2;
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::boids::{Boid, Pos, MAX_SPEED};
+use crate::gameworld::{BoundaryMode, Delta, Viewport};
+use crate::steering::{Neighbourhood, SteeringBehavior, SteeringBoid};
+
+// How fast the leader point moves along the route
+const MIGRATION_SPEED: f32 = MAX_SPEED * 0.6;
+// The leader waits while the centre of the flock is further behind than this
+const MIGRATION_LEASH: f32 = 250.;
+// The route is done once the centre of the flock is this close to its end
+const MIGRATION_ARRIVE_RADIUS: f32 = 100.;
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// The route set with `set_migration_route`. A leader point moves from
+/// waypoint to waypoint and the flock seeks it through the "migration"
+/// steering behaviour.
+#[derive(Debug, Default, Clone)]
+pub struct Migration {
+    pub route: Vec<Vector2>,
+    // Waypoint the leader is heading for
+    pub next: usize,
+    // `None` when there is no route
+    pub leader: Option<Vector2>,
+}
+
+impl Migration {
+    pub fn start(&mut self, route: Vec<Vector2>) {
+        self.leader = route.first().copied();
+        self.next = 1;
+        self.route = route;
+    }
+
+    pub fn stop(&mut self) {
+        self.route.clear();
+        self.next = 0;
+        self.leader = None;
+    }
+}
+
+/// Set when the flock reaches the end of the route. Drained by the
+/// `GameWorld`, which turns it into a `migration_completed` signal.
+#[derive(Debug, Default)]
+pub struct MigrationCompleted(pub bool);
+
+// -----------------------------------------------------------------------------
+//     - Behaviours -
+// -----------------------------------------------------------------------------
+
+// Seek the leader point of the migration route
+#[derive(Default)]
+pub struct MigrationBehavior {
+    leader: Option<Vector2>,
+    boundary: Option<BoundaryMode>,
+    viewport: Option<Viewport>,
+}
+
+impl SteeringBehavior for MigrationBehavior {
+    fn name(&self) -> &str {
+        "migration"
+    }
+
+    fn prepare(&mut self, _: &World, resources: &Resources) {
+        self.leader = resources
+            .get::<Migration>()
+            .and_then(|migration| migration.leader);
+        self.boundary = resources.get::<BoundaryMode>().map(|boundary| *boundary);
+        self.viewport = resources.get::<Viewport>().map(|viewport| *viewport);
+    }
+
+    fn compute(&mut self, boid: &SteeringBoid, _: &Neighbourhood, _: &Resources) -> Vector2 {
+        let leader = match self.leader {
+            Some(leader) => leader,
+            None => return Vector2::ZERO,
+        };
+
+        let to_leader = match (self.boundary, &self.viewport) {
+            (Some(boundary), Some(viewport)) => boundary.delta(viewport, boid.pos, leader),
+            _ => leader - boid.pos,
+        };
+        to_leader.clamped(MAX_SPEED) - boid.vel
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn advance_migration() -> impl ParallelRunnable {
+    SystemBuilder::new("advance migration")
+        .read_resource::<Delta>()
+        .write_resource::<Migration>()
+        .write_resource::<MigrationCompleted>()
+        .with_query(<&Pos>::query().filter(component::<Boid>()))
+        .build(|_, world, resources, query| {
+            let (delta, migration, completed) = resources;
+            let mut leader = match migration.leader {
+                Some(leader) => leader,
+                None => return,
+            };
+
+            let (sum, count) = query
+                .iter(world)
+                .fold((Vector2::ZERO, 0), |(sum, count), pos| {
+                    (sum + pos.0, count + 1)
+                });
+            let behind = if count > 0 {
+                (leader - sum / count as f32).length()
+            } else {
+                0.
+            };
+
+            if migration.next >= migration.route.len() {
+                if behind <= MIGRATION_ARRIVE_RADIUS {
+                    migration.stop();
+                    completed.0 = true;
+                }
+                return;
+            }
+
+            if behind > MIGRATION_LEASH {
+                return;
+            }
+
+            let mut step = MIGRATION_SPEED * delta.0;
+            while let Some(waypoint) = migration.route.get(migration.next).copied() {
+                let to_waypoint = waypoint - leader;
+                let distance = to_waypoint.length();
+                if distance > step {
+                    leader += to_waypoint / distance * step;
+                    break;
+                }
+
+                leader = waypoint;
+                step -= distance;
+                migration.next += 1;
+            }
+            migration.leader = Some(leader);
+        })
+}
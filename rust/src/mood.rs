@@ -0,0 +1,314 @@
+use std::collections::{HashMap, HashSet};
+
+use gdnative::core_types::Vector2;
+use legion::systems::ParallelRunnable;
+use legion::*;
+use rand::prelude::*;
+
+use crate::boids::Pos;
+use crate::gameworld::{BoundaryMode, Delta, ShouldFlee, Viewport};
+use crate::pursuit::TargetTracks;
+use crate::scatter::{Scatter, SCATTER_RADIUS};
+use crate::spatial::{FlockIndex, SpatialGrid};
+use crate::species::{Relation, Species, SpeciesRelations};
+
+// Threats are felt inside this distance, the closer the more
+const FEAR_RADIUS: f32 = 250.;
+// Neighbours inside this distance pass on their fear, a little weaker each
+// time, which is what makes panic travel through the flock in waves
+const CONTAGION_RADIUS: f32 = 60.;
+const CONTAGION: f32 = 0.85;
+// Per second, how quickly fear rises to what a boid sees and fades after
+const FEAR_RISE: f32 = 8.;
+const FEAR_DECAY: f32 = 0.3;
+// Seconds a boid has to stay below a state's threshold to calm down a step
+const RECOVERY_TIME: f32 = 1.5;
+// A boid that panics startles the calm boids inside this distance into
+// alert, the nearer ones more likely
+const ALARM_RADIUS: f32 = 120.;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MoodState {
+    Calm,
+    Alert,
+    Panicked,
+}
+
+/// Multipliers a mood puts on the flocking rules and the max speed
+#[derive(Debug, Clone, Copy)]
+pub struct MoodModifiers {
+    pub cohesion: f32,
+    pub separation: f32,
+    pub alignment: f32,
+    pub speed: f32,
+}
+
+impl MoodState {
+    pub fn name(self) -> &'static str {
+        match self {
+            MoodState::Calm => "calm",
+            MoodState::Alert => "alert",
+            MoodState::Panicked => "panicked",
+        }
+    }
+
+    // Alert boids bunch up and line up, panicked ones mostly get away from
+    // each other and bolt
+    pub fn modifiers(self) -> MoodModifiers {
+        match self {
+            MoodState::Calm => MoodModifiers {
+                cohesion: 1.,
+                separation: 1.,
+                alignment: 1.,
+                speed: 1.,
+            },
+            MoodState::Alert => MoodModifiers {
+                cohesion: 1.3,
+                separation: 1.,
+                alignment: 1.4,
+                speed: 1.15,
+            },
+            MoodState::Panicked => MoodModifiers {
+                cohesion: 0.6,
+                separation: 1.5,
+                alignment: 1.2,
+                speed: 1.5,
+            },
+        }
+    }
+
+    // Fear that puts a boid in this state, and fear it has to stay below to
+    // leave it again. The gap keeps boids from flickering between states.
+    fn thresholds(self) -> (f32, f32) {
+        match self {
+            MoodState::Calm => (0., 0.),
+            MoodState::Alert => (0.3, 0.15),
+            MoodState::Panicked => (0.7, 0.45),
+        }
+    }
+
+    fn calmer(self) -> Self {
+        match self {
+            MoodState::Panicked => MoodState::Alert,
+            _ => MoodState::Calm,
+        }
+    }
+}
+
+/// How frightened a boid is, from 0 to 1, and the state that puts it in.
+/// States go up as soon as the fear is there, and only come down again
+/// after `RECOVERY_TIME`.
+#[derive(Debug, Clone, Copy)]
+pub struct Mood {
+    pub state: MoodState,
+    pub fear: f32,
+    // Seconds spent below the current state's threshold
+    recovery: f32,
+}
+
+impl Default for Mood {
+    fn default() -> Self {
+        Self {
+            state: MoodState::Calm,
+            fear: 0.,
+            recovery: 0.,
+        }
+    }
+}
+
+impl Mood {
+    fn update(&mut self, stimulus: f32, delta: f32) {
+        if stimulus > self.fear {
+            self.fear += (stimulus - self.fear) * (FEAR_RISE * delta).min(1.);
+        } else {
+            self.fear = (self.fear - FEAR_DECAY * delta).max(stimulus);
+        }
+
+        let scared = [MoodState::Panicked, MoodState::Alert]
+            .iter()
+            .copied()
+            .find(|state| self.fear >= state.thresholds().0)
+            .unwrap_or(MoodState::Calm);
+
+        if scared > self.state {
+            self.state = scared;
+            self.recovery = 0.;
+        } else if self.fear < self.state.thresholds().1 {
+            self.recovery += delta;
+            if self.recovery >= RECOVERY_TIME {
+                self.state = self.state.calmer();
+                self.recovery = 0.;
+            }
+        } else {
+            self.recovery = 0.;
+        }
+    }
+
+    fn alarm(&mut self) {
+        if self.state == MoodState::Calm {
+            self.state = MoodState::Alert;
+            self.fear = self.fear.max(MoodState::Alert.thresholds().0);
+            self.recovery = 0.;
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Whether boids have moods at all, off leaves every boid calm
+pub struct Moods(pub bool);
+
+/// Where boids panicked and set off a startle wave, one per spot. Drained
+/// by the `GameWorld`, which turns them into `startle_wave` signals.
+#[derive(Debug, Default)]
+pub struct StartleWaves(pub Vec<Vector2>);
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+// Fear comes from the targets while fleeing is on, from scatters, from
+// predator species and from frightened neighbours
+pub fn update_moods() -> impl ParallelRunnable {
+    SystemBuilder::new("update moods")
+        .read_resource::<Delta>()
+        .read_resource::<Moods>()
+        .read_resource::<ShouldFlee>()
+        .read_resource::<SpeciesRelations>()
+        .read_resource::<FlockIndex>()
+        .read_resource::<BoundaryMode>()
+        .read_resource::<Viewport>()
+        .read_resource::<TargetTracks>()
+        .write_resource::<StartleWaves>()
+        .with_query(<&Scatter>::query())
+        .with_query(<(&Pos, &Species)>::query())
+        .with_query(<(Entity, &Pos, Option<&Species>, &mut Mood)>::query())
+        .build(|_, world, resources, queries| {
+            let (delta, moods, flee, relations, index, boundary, viewport, tracks, waves) =
+                resources;
+            let (scatters, others, boids) = queries;
+
+            if !moods.0 {
+                for (_, _, _, mood) in boids.iter_mut(world) {
+                    if mood.state != MoodState::Calm || mood.fear > 0. {
+                        *mood = Mood::default();
+                    }
+                }
+                return;
+            }
+
+            let mut threats = Vec::new();
+            if flee.0 {
+                threats.extend(tracks.positions());
+            }
+
+            let scatters = scatters
+                .iter(world)
+                .filter(|scatter| scatter.duration > 0.)
+                .map(|scatter| {
+                    let fade = (1. - scatter.elapsed / scatter.duration).max(0.);
+                    (scatter.origin, fade)
+                })
+                .collect::<Vec<_>>();
+
+            let (positions, species): (Vec<_>, Vec<_>) = if relations.0.is_empty() {
+                (Vec::new(), Vec::new())
+            } else {
+                others
+                    .iter(world)
+                    .map(|(pos, species)| (pos.0, *species))
+                    .unzip()
+            };
+            let mut grid = SpatialGrid::new(FEAR_RADIUS);
+            grid.rebuild(&positions);
+
+            let fears = boids
+                .iter_mut(world)
+                .map(|(&entity, _, _, mood)| (entity, mood.fear))
+                .collect::<HashMap<_, _>>();
+
+            let closeness = |distance: f32, radius: f32| (1. - distance / radius).max(0.);
+
+            let mut panicked = Vec::new();
+            for (&entity, pos, own, mood) in boids.iter_mut(world) {
+                let mut stimulus = 0f32;
+
+                for threat in &threats {
+                    let distance = boundary.delta(viewport, pos.0, *threat).length();
+                    stimulus = stimulus.max(closeness(distance, FEAR_RADIUS));
+                }
+
+                for (origin, fade) in &scatters {
+                    let distance = (pos.0 - *origin).length();
+                    stimulus = stimulus.max(closeness(distance, SCATTER_RADIUS) * fade);
+                }
+
+                if let Some(own) = own {
+                    let candidates = match **boundary {
+                        BoundaryMode::Wrap => grid.wrapped_candidates(pos.0, FEAR_RADIUS, viewport),
+                        BoundaryMode::Open | BoundaryMode::Walls => {
+                            grid.candidates(pos.0, FEAR_RADIUS).collect()
+                        }
+                    };
+                    for other in candidates {
+                        let predator = relations.get(*own, species[other]) == Relation::Flee
+                            || relations.get(species[other], *own) == Relation::Chase;
+                        if predator {
+                            let distance = boundary.delta(viewport, pos.0, positions[other]);
+                            stimulus = stimulus.max(closeness(distance.length(), FEAR_RADIUS));
+                        }
+                    }
+                }
+
+                for neighbour in index.neighbours(pos.0, CONTAGION_RADIUS, index.shared_nearest()) {
+                    let other = index.entities[neighbour];
+                    if other != entity {
+                        let fear = fears.get(&other).copied().unwrap_or(0.);
+                        stimulus = stimulus.max(fear * CONTAGION);
+                    }
+                }
+
+                let before = mood.state;
+                mood.update(stimulus, delta.0);
+                if mood.state == MoodState::Panicked && before != MoodState::Panicked {
+                    panicked.push(pos.0);
+                }
+            }
+
+            if panicked.is_empty() {
+                return;
+            }
+
+            let mut rng = thread_rng();
+            let mut alarmed = HashSet::new();
+            for origin in &panicked {
+                for neighbour in index.neighbours(*origin, ALARM_RADIUS, index.shared_nearest()) {
+                    let distance = index.delta(*origin, neighbour).length();
+                    if rng.gen::<f32>() < closeness(distance, ALARM_RADIUS) {
+                        alarmed.insert(index.entities[neighbour]);
+                    }
+                }
+
+                // Boids panicking together make one wave
+                if !waves
+                    .0
+                    .iter()
+                    .any(|wave| (*wave - *origin).length() < ALARM_RADIUS)
+                {
+                    waves.0.push(*origin);
+                }
+            }
+
+            for (entity, _, _, mood) in boids.iter_mut(world) {
+                if alarmed.contains(entity) {
+                    mood.alarm();
+                }
+            }
+        })
+}
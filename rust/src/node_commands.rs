@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use gdnative::api::{Node2D, VisualServer};
+use gdnative::core_types::{Color, Transform2D, Vector2};
+use legion::systems::Runnable;
+use legion::*;
+
+use crate::animation::BoidAnimation;
+use crate::boids::{live_node, Boid};
+use crate::stamp::StampPlayback;
+
+// -----------------------------------------------------------------------------
+//     - Commands -
+// -----------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeCommand {
+    SetPosition(Vector2),
+    SetRotation(f32),
+    SetScale(Vector2),
+    SetModulate(Color),
+    SetVisible(bool),
+    // Plays an animation of the boid's `BoidAnimation` at a speed scale,
+    // restarting it only if it changed
+    Play(&'static str, f32),
+    QueueFree,
+}
+
+/// Changes to boid nodes, queued by the systems and applied in order by
+/// `apply_node_commands` at the end of the tick. That is the only place the
+/// boid systems call into Godot. The sprites of a `StampPlayback` are queued
+/// by the playback's entity and their index in it.
+#[derive(Debug, Default)]
+pub struct NodeCommands {
+    pub boids: HashMap<Entity, Vec<NodeCommand>>,
+    pub stamp_sprites: HashMap<(Entity, usize), Vec<NodeCommand>>,
+}
+
+impl NodeCommands {
+    pub fn push(&mut self, entity: Entity, command: NodeCommand) {
+        self.boids
+            .entry(entity)
+            .or_insert_with(Vec::new)
+            .push(command);
+    }
+
+    pub fn push_stamp_sprite(&mut self, playback: Entity, sprite: usize, command: NodeCommand) {
+        self.stamp_sprites
+            .entry((playback, sprite))
+            .or_insert_with(Vec::new)
+            .push(command);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.boids.is_empty() && self.stamp_sprites.is_empty()
+    }
+}
+
+/// With `enabled` boids are moved by setting their canvas items' transforms
+/// on the `VisualServer` directly, skipping the scene tree's transform
+/// notifications. The nodes' own transforms go stale until it's turned off
+/// again. `parent_inverse` takes the global transforms the commands are in
+/// to the `GameWorld`, which the boid nodes are children of.
+#[derive(Debug, Clone, Copy)]
+pub struct BatchTransforms {
+    pub enabled: bool,
+    pub parent_inverse: Transform2D,
+}
+
+impl Default for BatchTransforms {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            parent_inverse: Transform2D::IDENTITY,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+/// Where a boid was last drawn while `BatchTransforms` is on, in place of its
+/// node's transform. Position and rotation are global, the scale is local
+/// like `set_scale`.
+#[derive(Debug, Clone, Copy)]
+pub struct SpriteTransform {
+    pub position: Vector2,
+    pub rotation: f32,
+    pub scale: Vector2,
+}
+
+impl SpriteTransform {
+    pub unsafe fn of(node: &Node2D) -> Self {
+        Self {
+            position: node.global_position(),
+            rotation: node.global_rotation() as f32,
+            scale: node.scale(),
+        }
+    }
+
+    pub fn to_transform(&self) -> Transform2D {
+        Transform2D::from_rotation_translation_scale(self.position, self.rotation, self.scale)
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn apply_node_commands() -> impl Runnable {
+    SystemBuilder::new("apply node commands")
+        .read_resource::<BatchTransforms>()
+        .write_resource::<NodeCommands>()
+        .with_query(<(
+            Entity,
+            &Boid,
+            Option<&mut SpriteTransform>,
+            Option<&BoidAnimation>,
+        )>::query())
+        .with_query(<(Entity, &StampPlayback)>::query())
+        .build(|cmd, world, resources, queries| {
+            let (batch, commands) = resources;
+            let (boids, playbacks) = queries;
+            if commands.is_empty() {
+                return;
+            }
+
+            let server = if batch.enabled {
+                Some(VisualServer::godot_singleton())
+            } else {
+                None
+            };
+
+            for (&entity, boid, cached, animation) in boids.iter_mut(world) {
+                let commands = match commands.boids.get(&entity) {
+                    Some(commands) => commands,
+                    None => continue,
+                };
+                // Gameplay code freed the node, the `GameWorld` despawns it
+                // next frame
+                let node = match unsafe { boid.node() } {
+                    Some(node) => node,
+                    None => continue,
+                };
+
+                if let Some(server) = server {
+                    // The node's own transform is only read the first time
+                    let mut transform = match &cached {
+                        Some(cached) => **cached,
+                        None => unsafe { SpriteTransform::of(&node) },
+                    };
+                    let mut moved = false;
+                    for command in commands {
+                        match *command {
+                            NodeCommand::SetPosition(pos) => transform.position = pos,
+                            NodeCommand::SetRotation(rot) => transform.rotation = rot,
+                            NodeCommand::SetScale(scale) => transform.scale = scale,
+                            _ => {
+                                unsafe { apply(&node, animation, *command) };
+                                continue;
+                            }
+                        }
+                        moved = true;
+                    }
+
+                    if moved {
+                        let local = batch.parent_inverse * transform.to_transform();
+                        server.canvas_item_set_transform(node.get_canvas_item(), local);
+                    }
+                    match cached {
+                        Some(cached) => *cached = transform,
+                        None => cmd.add_component(entity, transform),
+                    }
+                } else {
+                    for command in commands {
+                        unsafe { apply(&node, animation, *command) };
+                    }
+                }
+            }
+
+            for (&entity, playback) in playbacks.iter(world) {
+                for (i, sprite) in playback.sprites.iter().enumerate() {
+                    if let Some(commands) = commands.stamp_sprites.get(&(entity, i)) {
+                        let sprite = match unsafe { live_node(sprite) } {
+                            Some(sprite) => sprite,
+                            None => continue,
+                        };
+                        for command in commands {
+                            unsafe { apply(&sprite, None, *command) };
+                        }
+                    }
+                }
+            }
+
+            // Commands for entities without a node are dropped too
+            commands.boids.clear();
+            commands.stamp_sprites.clear();
+        })
+}
+
+unsafe fn apply(node: &Node2D, animation: Option<&BoidAnimation>, command: NodeCommand) {
+    match command {
+        NodeCommand::SetPosition(pos) => node.set_global_position(pos),
+        NodeCommand::SetRotation(rot) => node.set_global_rotation(rot as f64),
+        NodeCommand::SetScale(scale) => node.set_scale(scale),
+        NodeCommand::SetModulate(color) => node.set_modulate(color),
+        NodeCommand::SetVisible(visible) => node.set_visible(visible),
+        NodeCommand::Play(name, speed_scale) => {
+            if let Some(sprite) = animation.and_then(|animation| live_node(&animation.0)) {
+                if sprite.animation().to_string() != name {
+                    sprite.play(name, false);
+                }
+                sprite.set_speed_scale(speed_scale as f64);
+            }
+        }
+        NodeCommand::QueueFree => node.queue_free(),
+    }
+}
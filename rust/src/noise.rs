@@ -0,0 +1,41 @@
+use gdnative::core_types::Vector2;
+use rand::rngs::SmallRng;
+use rand::Rng;
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Standard deviation of the error in what boids perceive of their
+/// neighbours, in pixels and pixels per second. Zero is perfect perception.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PerceptionNoise {
+    pub position: f32,
+    pub velocity: f32,
+}
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+// Each boid gets its own generator so the noise doesn't depend on the order
+// the systems visit boids in
+pub struct PerceptionRng(pub SmallRng);
+
+impl PerceptionRng {
+    // Two independent standard normal samples (Box-Muller)
+    fn gaussian(&mut self) -> Vector2 {
+        let u = self.0.gen_range(std::f32::EPSILON, 1.);
+        let angle = self.0.gen_range(0., std::f32::consts::PI * 2.);
+        let length = (-2. * u.ln()).sqrt();
+        Vector2::new(angle.cos(), angle.sin()) * length
+    }
+}
+
+/// Error to add to a perceived value, nothing for boids without a generator
+pub fn jitter(rng: Option<&mut PerceptionRng>, std_dev: f32) -> Vector2 {
+    match rng {
+        Some(rng) if std_dev > 0. => rng.gaussian() * std_dev,
+        _ => Vector2::ZERO,
+    }
+}
@@ -0,0 +1,95 @@
+use gdnative::core_types::Vector2;
+use legion::systems::Runnable;
+use legion::*;
+
+use crate::boids::{Boid, Pos, Target};
+use crate::gameworld::{BoundaryMode, Viewport};
+
+// A waypoint counts as reached once the centre of the flock is this close
+const PATROL_ARRIVE_RADIUS: f32 = 80.;
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// The waypoints set with `set_waypoints`. The targets are moved to the next
+/// one as soon as the flock reaches the current one. Without `looping` the
+/// patrol ends at the last waypoint and the targets stay there.
+#[derive(Debug, Default, Clone)]
+pub struct Patrol {
+    pub waypoints: Vec<Vector2>,
+    pub looping: bool,
+    // Waypoint the targets are on
+    pub next: usize,
+}
+
+impl Patrol {
+    pub fn start(&mut self, waypoints: Vec<Vector2>, looping: bool) {
+        self.waypoints = waypoints;
+        self.looping = looping;
+        self.next = 0;
+    }
+
+    pub fn stop(&mut self) {
+        self.waypoints.clear();
+        self.next = 0;
+    }
+}
+
+/// Indices of the waypoints the flock got to. Drained by the `GameWorld`,
+/// which turns them into `waypoint_reached` signals.
+#[derive(Debug, Default)]
+pub struct WaypointsReached(pub Vec<usize>);
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+// Runs before anything looks at the targets, so a patrol overrides wherever
+// else they were put
+pub fn advance_patrol() -> impl Runnable {
+    SystemBuilder::new("advance patrol")
+        .read_resource::<BoundaryMode>()
+        .read_resource::<Viewport>()
+        .write_resource::<Patrol>()
+        .write_resource::<WaypointsReached>()
+        .with_query(<&mut Target>::query())
+        .with_query(<&Pos>::query().filter(component::<Boid>()))
+        .build(|_, world, resources, queries| {
+            let (boundary, viewport, patrol, reached) = resources;
+            let (targets, boids) = queries;
+            let waypoint = match patrol.waypoints.get(patrol.next) {
+                Some(waypoint) => *waypoint,
+                None => return,
+            };
+
+            for target in targets.iter_mut(world) {
+                if let Some(node) = unsafe { target.node() } {
+                    node.set_global_position(waypoint);
+                }
+            }
+
+            let (sum, count) = boids
+                .iter(world)
+                .fold((Vector2::ZERO, 0), |(sum, count), pos| {
+                    (sum + pos.0, count + 1)
+                });
+            if count == 0 {
+                return;
+            }
+            let centroid = sum / count as f32;
+            if boundary.delta(viewport, centroid, waypoint).length() > PATROL_ARRIVE_RADIUS {
+                return;
+            }
+
+            reached.0.push(patrol.next);
+            patrol.next += 1;
+            if patrol.next >= patrol.waypoints.len() {
+                if patrol.looping {
+                    patrol.next = 0;
+                } else {
+                    patrol.stop();
+                }
+            }
+        })
+}
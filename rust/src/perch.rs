@@ -0,0 +1,255 @@
+use std::collections::{HashMap, HashSet};
+
+use gdnative::api::Node2D;
+use gdnative::core_types::Vector2;
+use gdnative::object::{Ref, TRef};
+use legion::systems::{CommandBuffer, ParallelRunnable, Runnable};
+use legion::*;
+use rand::prelude::*;
+
+use crate::boids::{live_node, rotated, Acceleration, Boid, Pos, Velocity, MAX_SPEED};
+use crate::gameworld::{BoundaryMode, Delta, Viewport};
+use crate::mood::{Mood, MoodState};
+
+// Distance between the slots along the perch
+const PERCH_SPACING: f32 = 14.;
+// Calm boids this close to a free slot may land on it, each with this chance
+// per second
+const PERCH_RADIUS: f32 = 160.;
+const LANDING_RATE: f32 = 0.25;
+// Speed boids come in at, slowing down over the last `PERCH_SLOWING`
+const APPROACH_SPEED: f32 = MAX_SPEED * 0.6;
+const PERCH_SLOWING: f32 = 60.;
+// Close enough to sit down, and seconds before giving up on getting there
+const PERCH_SNAP: f32 = 4.;
+const APPROACH_TIMEOUT: f32 = 6.;
+// Seconds a boid sits for, and seconds after taking off before it lands again
+const IDLE_RANGE: (f32, f32) = (3., 10.);
+const COOLDOWN_RANGE: (f32, f32) = (5., 12.);
+const TAKEOFF_SPEED: f32 = MAX_SPEED * 0.5;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+/// A wire or branch registered with `register_perch`. `capacity` slots run
+/// along the node's x axis, centred on it.
+#[derive(Debug, Clone)]
+pub struct Perch {
+    pub capacity: usize,
+    pub slots: Vec<Vector2>,
+    // Away from the perch, the way boids take off
+    pub up: Vector2,
+}
+
+impl Perch {
+    pub fn new(node: &Node2D, capacity: usize) -> Self {
+        let mut perch = Self {
+            capacity,
+            slots: Vec::new(),
+            up: Vector2::new(0., -1.),
+        };
+        perch.follow(node);
+        perch
+    }
+
+    fn follow(&mut self, node: &Node2D) {
+        let origin = node.global_position();
+        let rotation = node.global_rotation() as f32;
+        let along = rotated(Vector2::new(PERCH_SPACING, 0.), rotation);
+        let first = origin - along * self.capacity.saturating_sub(1) as f32 / 2.;
+
+        self.slots = (0..self.capacity)
+            .map(|i| first + along * i as f32)
+            .collect();
+        self.up = rotated(Vector2::new(0., -1.), rotation);
+    }
+}
+
+// The node a `Perch` follows
+pub struct PerchNode(pub Ref<Node2D>);
+
+impl PerchNode {
+    pub unsafe fn node(&self) -> Option<TRef<Node2D>> {
+        live_node(&self.0)
+    }
+
+    pub unsafe fn is_alive(&self) -> bool {
+        self.node().is_some()
+    }
+}
+
+/// A boid heading for a perch slot, or sitting on it once `sitting`.
+/// `timer` counts down to giving up on the way there, or to taking off.
+#[derive(Debug, Clone, Copy)]
+pub struct Perched {
+    pub perch: Entity,
+    pub slot: usize,
+    pub sitting: bool,
+    pub timer: f32,
+}
+
+// Seconds until a boid that took off can land again
+#[derive(Debug, Clone, Copy)]
+pub struct PerchCooldown(pub f32);
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+// Perches move with their nodes, and are dropped once the node is freed
+pub fn sync_perches() -> impl Runnable {
+    SystemBuilder::new("sync perches")
+        .with_query(<(Entity, &PerchNode, &mut Perch)>::query())
+        .build(|cmd, world, _, query| {
+            for (&entity, node, perch) in query.iter_mut(world) {
+                match unsafe { node.node() } {
+                    Some(node) => perch.follow(&node),
+                    None => cmd.remove(entity),
+                }
+            }
+        })
+}
+
+// Between the forces and the move, so it has the last word on where perching
+// boids go. Anything but a calm mood scares them off.
+pub fn perch() -> impl ParallelRunnable {
+    SystemBuilder::new("perch")
+        .read_resource::<Delta>()
+        .read_resource::<BoundaryMode>()
+        .read_resource::<Viewport>()
+        .with_query(<(Entity, &Perch)>::query())
+        .with_query(
+            <(
+                Entity,
+                &mut Pos,
+                &mut Velocity,
+                &mut Acceleration,
+                Option<&Mood>,
+                Option<&mut Perched>,
+                Option<&mut PerchCooldown>,
+            )>::query()
+            .filter(component::<Boid>()),
+        )
+        .build(|cmd, world, resources, queries| {
+            let (delta, boundary, viewport) = resources;
+            let (perches, boids) = queries;
+            let mut rng = thread_rng();
+
+            let perches = perches
+                .iter(world)
+                .map(|(&entity, perch)| (entity, (perch.slots.clone(), perch.up)))
+                .collect::<HashMap<_, _>>();
+            let mut taken = boids
+                .iter_mut(world)
+                .filter_map(|(.., perched, _)| perched.map(|perched| (perched.perch, perched.slot)))
+                .collect::<HashSet<_>>();
+            let landing_chance = (LANDING_RATE * delta.0).min(1.) as f64;
+
+            for (&entity, pos, vel, acc, mood, perched, cooldown) in boids.iter_mut(world) {
+                let calm = mood
+                    .map(|mood| mood.state == MoodState::Calm)
+                    .unwrap_or(true);
+                if let Some(cooldown) = cooldown {
+                    cooldown.0 -= delta.0;
+                    if cooldown.0 <= 0. {
+                        cmd.remove_component::<PerchCooldown>(entity);
+                    }
+                    continue;
+                }
+
+                let perched = match perched {
+                    Some(perched) => perched,
+                    None => {
+                        if calm && !perches.is_empty() && rng.gen_bool(landing_chance) {
+                            let free = free_slot(**boundary, viewport, pos.0, &perches, &taken);
+                            if let Some((perch, slot)) = free {
+                                taken.insert((perch, slot));
+                                cmd.add_component(
+                                    entity,
+                                    Perched {
+                                        perch,
+                                        slot,
+                                        sitting: false,
+                                        timer: APPROACH_TIMEOUT,
+                                    },
+                                );
+                            }
+                        }
+                        continue;
+                    }
+                };
+
+                let perch = perches.get(&perched.perch);
+                let spot = perch
+                    .and_then(|(slots, _)| slots.get(perched.slot))
+                    .copied();
+                perched.timer -= delta.0;
+                let spot = match spot {
+                    Some(spot) if calm && !(perched.sitting && perched.timer <= 0.) => spot,
+                    _ => {
+                        let up = perch.map(|(_, up)| *up).unwrap_or(Vector2::new(0., -1.));
+                        let spread = rng.gen_range(-0.6, 0.6);
+                        if perched.sitting {
+                            vel.0 = rotated(up, spread) * TAKEOFF_SPEED;
+                        }
+                        take_off(cmd, entity, &mut rng);
+                        continue;
+                    }
+                };
+
+                if !perched.sitting {
+                    let to_spot = boundary.delta(viewport, pos.0, spot);
+                    let distance = to_spot.length();
+                    if distance > PERCH_SNAP {
+                        if perched.timer <= 0. {
+                            take_off(cmd, entity, &mut rng);
+                        } else {
+                            let speed = APPROACH_SPEED * (distance / PERCH_SLOWING).min(1.);
+                            acc.0 = to_spot / distance * speed - vel.0;
+                        }
+                        continue;
+                    }
+                    perched.sitting = true;
+                    perched.timer = rng.gen_range(IDLE_RANGE.0, IDLE_RANGE.1);
+                }
+
+                pos.0 = spot;
+                vel.0 = Vector2::ZERO;
+                acc.0 = Vector2::ZERO;
+            }
+        })
+}
+
+// The nearest slot nobody else has within `PERCH_RADIUS`
+fn free_slot(
+    boundary: BoundaryMode,
+    viewport: &Viewport,
+    pos: Vector2,
+    perches: &HashMap<Entity, (Vec<Vector2>, Vector2)>,
+    taken: &HashSet<(Entity, usize)>,
+) -> Option<(Entity, usize)> {
+    let mut nearest = None;
+    let mut nearest_distance = PERCH_RADIUS * PERCH_RADIUS;
+    for (perch, (slots, _)) in perches {
+        for (slot, spot) in slots.iter().enumerate() {
+            if taken.contains(&(*perch, slot)) {
+                continue;
+            }
+            let distance = boundary.delta(viewport, pos, *spot).length_squared();
+            if distance <= nearest_distance {
+                nearest = Some((*perch, slot));
+                nearest_distance = distance;
+            }
+        }
+    }
+    nearest
+}
+
+fn take_off(cmd: &mut CommandBuffer, entity: Entity, rng: &mut impl Rng) {
+    cmd.remove_component::<Perched>(entity);
+    cmd.add_component(
+        entity,
+        PerchCooldown(rng.gen_range(COOLDOWN_RANGE.0, COOLDOWN_RANGE.1)),
+    );
+}
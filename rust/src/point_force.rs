@@ -0,0 +1,239 @@
+use gdnative::prelude::*;
+use legion::systems::{ParallelRunnable, Runnable};
+use legion::*;
+
+use crate::boids::{live_node, Forces, Pos, MAX_SPEED};
+use crate::gameworld::{BoundaryMode, Viewport};
+
+const DEFAULT_STRENGTH: f32 = 1.;
+// Distance inside which a source pulls (or pushes) at full strength
+const DEFAULT_FALLOFF: f32 = 100.;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+/// A point that attracts boids (positive strength) or repels them (negative),
+/// in units of the max speed. Past `falloff` it weakens with the inverse
+/// square of the distance.
+#[derive(Debug, Clone, Copy)]
+pub struct PointForce {
+    pub position: Vector2,
+    pub strength: f32,
+    pub falloff: f32,
+}
+
+impl PointForce {
+    fn push(&self, to_source: Vector2) -> Vector2 {
+        let distance = to_source.length();
+        if distance == 0. {
+            return Vector2::ZERO;
+        }
+
+        let falloff = (self.falloff / distance).powi(2).min(1.);
+        to_source / distance * MAX_SPEED * self.strength * falloff
+    }
+}
+
+// The `Attractor` or `Repeller` node a `PointForce` follows
+pub struct ForceNode(pub Ref<Node2D>);
+
+impl ForceNode {
+    pub unsafe fn node(&self) -> Option<TRef<Node2D>> {
+        live_node(&self.0)
+    }
+
+    pub unsafe fn is_alive(&self) -> bool {
+        self.node().is_some()
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn sync_point_forces() -> impl Runnable {
+    SystemBuilder::new("sync point forces")
+        .with_query(<(&ForceNode, &mut PointForce)>::query())
+        .build(|_, world, _, query| {
+            for (node, force) in query.iter_mut(world) {
+                if let Some(node) = unsafe { node.node() } {
+                    force.position = node.global_position();
+                }
+            }
+        })
+}
+
+pub fn point_forces() -> impl ParallelRunnable {
+    SystemBuilder::new("point forces")
+        .read_resource::<BoundaryMode>()
+        .read_resource::<Viewport>()
+        .with_query(<&PointForce>::query())
+        .with_query(<(&Pos, &mut Forces)>::query())
+        .build(|_, world, resources, queries| {
+            let (boundary, viewport) = resources;
+            let (sources, boids) = queries;
+
+            let sources = sources.iter(world).copied().collect::<Vec<_>>();
+            if sources.is_empty() {
+                return;
+            }
+
+            for (pos, force) in boids.iter_mut(world) {
+                force.point = sources
+                    .iter()
+                    .map(|source| source.push(boundary.delta(viewport, pos.0, source.position)))
+                    .fold(Vector2::ZERO, |acc, push| acc + push);
+            }
+        })
+}
+
+// -----------------------------------------------------------------------------
+//     - Godot nodes -
+// -----------------------------------------------------------------------------
+
+// Both node types hand themselves to the parent GameWorld, which keeps a
+// `PointForce` entity for each until they leave the tree
+unsafe fn register(owner: TRef<Node2D>, strength: f32, falloff: f32) {
+    let parent = match owner.get_parent() {
+        Some(parent) => parent.assume_safe(),
+        None => return,
+    };
+
+    if !parent.has_method("add_point_force") {
+        godot_error!("point force must be a child of a GameWorld");
+        return;
+    }
+
+    parent.call(
+        "add_point_force",
+        &[
+            owner.to_variant(),
+            Variant::new(strength as f64),
+            Variant::new(falloff as f64),
+        ],
+    );
+}
+
+unsafe fn unregister(owner: TRef<Node2D>) {
+    if let Some(parent) = owner.get_parent() {
+        let parent = parent.assume_safe();
+        if parent.has_method("remove_point_force") {
+            parent.call("remove_point_force", &[owner.to_variant()]);
+        }
+    }
+}
+
+/// Pulls boids towards itself. `strength` is in units of the max speed.
+#[derive(NativeClass)]
+#[inherit(Node2D)]
+#[register_with(Self::register_properties)]
+pub struct Attractor {
+    strength: f32,
+    falloff: f32,
+}
+
+#[methods]
+impl Attractor {
+    pub fn new(_owner: &Node2D) -> Self {
+        Self {
+            strength: DEFAULT_STRENGTH,
+            falloff: DEFAULT_FALLOFF,
+        }
+    }
+
+    fn register_properties(builder: &ClassBuilder<Self>) {
+        builder
+            .property("strength")
+            .with_default(DEFAULT_STRENGTH)
+            .with_getter(|this: &Self, _| this.strength)
+            .with_setter(|this: &mut Self, owner: TRef<Node2D>, strength: f32| {
+                this.strength = strength;
+                this.update(owner);
+            })
+            .done();
+
+        builder
+            .property("falloff")
+            .with_default(DEFAULT_FALLOFF)
+            .with_getter(|this: &Self, _| this.falloff)
+            .with_setter(|this: &mut Self, owner: TRef<Node2D>, falloff: f32| {
+                this.falloff = falloff.max(0.);
+                this.update(owner);
+            })
+            .done();
+    }
+
+    fn update(&self, owner: TRef<Node2D>) {
+        if owner.is_inside_tree() {
+            unsafe { register(owner, self.strength, self.falloff) };
+        }
+    }
+
+    #[method]
+    pub fn _ready(&mut self, #[base] owner: TRef<Node2D>) {
+        unsafe { register(owner, self.strength, self.falloff) };
+    }
+
+    #[method]
+    pub fn _exit_tree(&mut self, #[base] owner: TRef<Node2D>) {
+        unsafe { unregister(owner) };
+    }
+}
+
+/// Pushes boids away from itself, the same as an `Attractor` with a negative
+/// strength.
+#[derive(NativeClass)]
+#[inherit(Node2D)]
+#[register_with(Self::register_properties)]
+pub struct Repeller {
+    strength: f32,
+    falloff: f32,
+}
+
+#[methods]
+impl Repeller {
+    pub fn new(_owner: &Node2D) -> Self {
+        Self {
+            strength: DEFAULT_STRENGTH,
+            falloff: DEFAULT_FALLOFF,
+        }
+    }
+
+    fn register_properties(builder: &ClassBuilder<Self>) {
+        builder
+            .property("strength")
+            .with_default(DEFAULT_STRENGTH)
+            .with_getter(|this: &Self, _| this.strength)
+            .with_setter(|this: &mut Self, owner: TRef<Node2D>, strength: f32| {
+                this.strength = strength;
+                this.update(owner);
+            })
+            .done();
+
+        builder
+            .property("falloff")
+            .with_default(DEFAULT_FALLOFF)
+            .with_getter(|this: &Self, _| this.falloff)
+            .with_setter(|this: &mut Self, owner: TRef<Node2D>, falloff: f32| {
+                this.falloff = falloff.max(0.);
+                this.update(owner);
+            })
+            .done();
+    }
+
+    fn update(&self, owner: TRef<Node2D>) {
+        if owner.is_inside_tree() {
+            unsafe { register(owner, -self.strength, self.falloff) };
+        }
+    }
+
+    #[method]
+    pub fn _ready(&mut self, #[base] owner: TRef<Node2D>) {
+        unsafe { register(owner, -self.strength, self.falloff) };
+    }
+
+    #[method]
+    pub fn _exit_tree(&mut self, #[base] owner: TRef<Node2D>) {
+        unsafe { unregister(owner) };
+    }
+}
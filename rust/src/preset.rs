@@ -0,0 +1,59 @@
+use std::collections::BTreeMap;
+
+use crate::config::Config;
+use crate::error::{BoidsError, Result};
+use crate::files;
+
+// Shipped with the project. res:// is read-only in exported games, so saved
+// presets go to user:// and take precedence over the shipped ones.
+pub const PRESETS_PATH: &str = "res://presets.json";
+pub const USER_PRESETS_PATH: &str = "user://presets.json";
+
+// -----------------------------------------------------------------------------
+//     - Presets -
+// -----------------------------------------------------------------------------
+
+/// Named configurations, stored as a JSON object of name to `Config`
+pub type Presets = BTreeMap<String, Config>;
+
+fn read(path: &str) -> Result<Presets> {
+    if !files::exists(path) {
+        return Ok(Presets::new());
+    }
+
+    let json = files::read_string(path)?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+pub fn load(name: &str) -> Result<Config> {
+    let mut user = read(USER_PRESETS_PATH)?;
+    if let Some(config) = user.remove(name) {
+        return Ok(config);
+    }
+
+    read(PRESETS_PATH)?
+        .remove(name)
+        .ok_or_else(|| BoidsError::Missing(name.to_string()))
+}
+
+pub fn save(name: &str, config: Config) -> Result<()> {
+    let mut presets = read(USER_PRESETS_PATH)?;
+    presets.insert(name.to_string(), config);
+
+    let json = serde_json::to_string_pretty(&presets)?;
+    files::write_string(USER_PRESETS_PATH, &json)
+}
+
+// Shipped and saved presets, without duplicates
+pub fn names() -> Result<Vec<String>> {
+    let mut names = read(PRESETS_PATH)?
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    for (name, _) in read(USER_PRESETS_PATH)? {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
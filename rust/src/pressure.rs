@@ -0,0 +1,93 @@
+use gdnative::core_types::Color;
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::boids::{Boid, Pos, Radius};
+use crate::debug::Selected;
+use crate::gameworld::PerceptionRadii;
+use crate::node_commands::{NodeCommand, NodeCommands};
+use crate::spatial::{FlockIndex, NeighbourRule};
+
+// Closer than this and a neighbour counts as touching
+const TOUCH_DISTANCE: f32 = 1.;
+
+// Pressure at which the tint is fully red
+const TINT_MAX_PRESSURE: f32 = 0.2;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+/// Sum of the inverse (edge to edge) distances to every neighbour within the
+/// separation radius. High values mean a boid is being crushed.
+pub struct Pressure(pub f32);
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CrowdPressure {
+    pub mean: f32,
+    pub max: f32,
+}
+
+pub struct ShowPressure(pub bool);
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn pressure() -> impl ParallelRunnable {
+    SystemBuilder::new("pressure")
+        .read_resource::<FlockIndex>()
+        .read_resource::<PerceptionRadii>()
+        .write_resource::<CrowdPressure>()
+        .with_query(<(Entity, &Pos, &Radius, &mut Pressure)>::query())
+        .build(|_, world, resources, query| {
+            let (index, radii, crowd) = resources;
+            let mut total = 0.;
+            let mut max = 0f32;
+            let mut count = 0;
+
+            for (&entity, pos, radius, pressure) in query.iter_mut(world) {
+                let own = index.index_of(entity);
+                let reach = radii.separation + radius.0 + index.max_radius();
+                pressure.0 = index
+                    .neighbours(pos.0, reach, index.rule_nearest(NeighbourRule::Separation))
+                    .into_iter()
+                    // Skip the boid itself, but not a neighbour right on top of it
+                    .filter(|other| own != Some(*other))
+                    .map(|other| index.gap(pos.0, radius.0, other))
+                    .filter(|gap| *gap < radii.separation)
+                    .map(|gap| 1. / gap.max(TOUCH_DISTANCE))
+                    .sum();
+
+                total += pressure.0;
+                max = max.max(pressure.0);
+                count += 1;
+            }
+
+            crowd.mean = if count > 0 { total / count as f32 } else { 0. };
+            crowd.max = max;
+        })
+}
+
+pub fn pressure_tint() -> impl ParallelRunnable {
+    SystemBuilder::new("pressure tint")
+        .read_resource::<ShowPressure>()
+        .write_resource::<NodeCommands>()
+        .with_query(
+            <(Entity, &Pressure)>::query().filter(component::<Boid>() & !component::<Selected>()),
+        )
+        .build(|_, world, resources, query| {
+            let (show, commands) = resources;
+            if !show.0 {
+                return;
+            }
+
+            for (&entity, pressure) in query.iter(world) {
+                let strain = (pressure.0 / TINT_MAX_PRESSURE).min(1.);
+                let color = Color::from_rgb(1., 1. - strain, 1. - strain);
+                commands.push(entity, NodeCommand::SetModulate(color));
+            }
+        })
+}
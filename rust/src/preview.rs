@@ -0,0 +1,159 @@
+use gdnative::api::Engine;
+use gdnative::prelude::*;
+
+use crate::gameworld::{AlignmentMul, CohesionMul, NeighbourSearch, SeparationMul};
+use crate::headless::HeadlessWorld;
+
+const PREVIEW_BOID_COUNT: i64 = 40;
+const PREVIEW_WIDTH: f32 = 640.;
+const PREVIEW_HEIGHT: f32 = 360.;
+const BOID_LENGTH: f32 = 12.;
+
+// -----------------------------------------------------------------------------
+//     - Editor preview -
+// -----------------------------------------------------------------------------
+
+/// A tool node that runs a small headless flock inside the editor, so the
+/// multipliers can be tuned in the inspector without launching the game.
+/// Boids are drawn rather than instanced so nothing ends up in the scene.
+#[derive(NativeClass)]
+#[inherit(Node2D)]
+#[register_with(Self::register_properties)]
+pub struct BoidPreview {
+    flock: Option<HeadlessWorld>,
+    boid_count: i64,
+    size: Vector2,
+    cohesion: f32,
+    separation: f32,
+    alignment: f32,
+    running: bool,
+}
+
+#[methods]
+impl BoidPreview {
+    pub fn new(_owner: &Node2D) -> Self {
+        Self {
+            flock: None,
+            boid_count: PREVIEW_BOID_COUNT,
+            size: Vector2::new(PREVIEW_WIDTH, PREVIEW_HEIGHT),
+            cohesion: 1.,
+            separation: 1.,
+            alignment: 1.,
+            running: true,
+        }
+    }
+
+    fn register_properties(builder: &ClassBuilder<Self>) {
+        // Changing the count or the size starts a new flock
+        builder
+            .property("boid_count")
+            .with_default(PREVIEW_BOID_COUNT)
+            .with_getter(|this: &Self, _| this.boid_count)
+            .with_setter(|this: &mut Self, _, count: i64| {
+                this.boid_count = count.max(0);
+                this.flock = None;
+            })
+            .done();
+
+        builder
+            .property("size")
+            .with_default(Vector2::new(PREVIEW_WIDTH, PREVIEW_HEIGHT))
+            .with_getter(|this: &Self, _| this.size)
+            .with_setter(|this: &mut Self, _, size: Vector2| {
+                this.size = Vector2::new(size.x.max(1.), size.y.max(1.));
+                this.flock = None;
+            })
+            .done();
+
+        builder
+            .property("cohesion_mul")
+            .with_default(1.)
+            .with_getter(|this: &Self, _| this.cohesion)
+            .with_setter(|this: &mut Self, _, mul: f32| this.cohesion = mul)
+            .done();
+
+        builder
+            .property("separation_mul")
+            .with_default(1.)
+            .with_getter(|this: &Self, _| this.separation)
+            .with_setter(|this: &mut Self, _, mul: f32| this.separation = mul)
+            .done();
+
+        builder
+            .property("alignment_mul")
+            .with_default(1.)
+            .with_getter(|this: &Self, _| this.alignment)
+            .with_setter(|this: &mut Self, _, mul: f32| this.alignment = mul)
+            .done();
+
+        builder
+            .property("running")
+            .with_default(true)
+            .with_getter(|this: &Self, _| this.running)
+            .with_setter(|this: &mut Self, _, running: bool| this.running = running)
+            .done();
+    }
+
+    #[method]
+    pub fn _process(&mut self, #[base] owner: &Node2D, delta: f64) {
+        // The real flock is the GameWorld's job once the game runs
+        if !self.running || !Engine::godot_singleton().is_editor_hint() {
+            return;
+        }
+
+        let (count, size) = (self.boid_count as usize, self.size);
+        let flock = self.flock.get_or_insert_with(|| {
+            HeadlessWorld::with_size(count, NeighbourSearch::SpatialIndex, size)
+        });
+
+        let resources = flock.resources_mut();
+        resources
+            .get_mut::<CohesionMul>()
+            .map(|mut mul| mul.0 = self.cohesion);
+        resources
+            .get_mut::<SeparationMul>()
+            .map(|mut mul| mul.0 = self.separation);
+        resources
+            .get_mut::<AlignmentMul>()
+            .map(|mut mul| mul.0 = self.alignment);
+
+        flock.tick(delta as f32);
+        owner.update();
+    }
+
+    #[method]
+    pub fn _draw(&mut self, #[base] owner: &Node2D) {
+        let flock = match &self.flock {
+            Some(flock) => flock,
+            None => return,
+        };
+
+        let outline = Color::from_rgba(1., 1., 1., 0.3);
+        let corners = [
+            Vector2::ZERO,
+            Vector2::new(self.size.x, 0.),
+            self.size,
+            Vector2::new(0., self.size.y),
+        ];
+
+        for i in 0..corners.len() {
+            let (from, to) = (corners[i], corners[(i + 1) % corners.len()]);
+            owner.draw_line(from, to, outline, 1., false);
+        }
+
+        for (pos, vel) in flock.boids() {
+            let heading = if vel.length_squared() > 0. {
+                vel.normalized()
+            } else {
+                Vector2::new(1., 0.)
+            };
+            owner.draw_line(
+                pos,
+                pos + heading * BOID_LENGTH,
+                Color::from_rgb(1., 1., 1.),
+                2.,
+                true,
+            );
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+
+use gdnative::core_types::Vector2;
+use legion::*;
+
+use crate::boids::MAX_SPEED;
+
+// A target seemingly moving faster than this jumped (a click or a teleport),
+// its track starts over rather than sending the predators off after it
+const MAX_TARGET_SPEED: f32 = MAX_SPEED * 4.;
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy)]
+pub struct TargetTrack {
+    pub position: Vector2,
+    pub rotation: f32,
+    pub velocity: Vector2,
+}
+
+/// Where every target was last physics tick, so its velocity can be
+/// estimated. Read from the nodes by the `GameWorld` before each tick, the
+/// systems only ever see the targets through here.
+#[derive(Default)]
+pub struct TargetTracks(pub HashMap<Entity, TargetTrack>);
+
+impl TargetTracks {
+    /// `delta` is the length of the physics tick unscaled by `TimeScale`,
+    /// targets move in real time whatever the flock does
+    pub fn update(&mut self, targets: impl Iterator<Item = (Entity, Vector2, f32)>, delta: f32) {
+        let mut current = HashMap::new();
+
+        for (entity, position, rotation) in targets {
+            let velocity = match self.0.get(&entity) {
+                Some(track) if delta <= 0. => track.velocity,
+                Some(track) => {
+                    let velocity = (position - track.position) / delta;
+                    if velocity.length() > MAX_TARGET_SPEED {
+                        Vector2::ZERO
+                    } else {
+                        velocity
+                    }
+                }
+                None => Vector2::ZERO,
+            };
+
+            current.insert(
+                entity,
+                TargetTrack {
+                    position,
+                    rotation,
+                    velocity,
+                },
+            );
+        }
+
+        // Drops targets that were removed
+        self.0 = current;
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = Vector2> + '_ {
+        self.0.values().map(|track| track.position)
+    }
+}
+
+// Where a target `delta` away, moving at `velocity`, will be by the time a
+// boid at full speed gets there. Never looks more than `horizon` seconds ahead.
+pub fn intercept(delta: Vector2, velocity: Vector2, horizon: f32) -> Vector2 {
+    let time = (delta.length() / MAX_SPEED).min(horizon);
+    delta + velocity * time
+}
@@ -0,0 +1,112 @@
+// How quickly the measured step time follows the latest frame
+const SMOOTHING: f32 = 0.1;
+// Seconds over budget before dropping a tier, and seconds comfortably under
+// it (below `RESTORE_SHARE` of the budget) before going back up
+const DEGRADE_AFTER: f32 = 0.5;
+const RESTORE_AFTER: f32 = 3.;
+const RESTORE_SHARE: f32 = 0.7;
+
+/// What a quality tier does to the neighbour search, on top of the
+/// configured settings
+#[derive(Debug, Clone, Copy)]
+pub struct QualityTier {
+    // Times the `NeighbourStaleness`
+    pub staleness: usize,
+    // Times the distance neighbours are searched in
+    pub reach: f32,
+    // Most neighbours kept per boid, nearest first
+    pub max_neighbours: Option<usize>,
+}
+
+const TIERS: [QualityTier; 4] = [
+    QualityTier {
+        staleness: 1,
+        reach: 1.,
+        max_neighbours: None,
+    },
+    QualityTier {
+        staleness: 2,
+        reach: 1.,
+        max_neighbours: Some(20),
+    },
+    QualityTier {
+        staleness: 3,
+        reach: 0.85,
+        max_neighbours: Some(12),
+    },
+    QualityTier {
+        staleness: 4,
+        reach: 0.7,
+        max_neighbours: Some(7),
+    },
+];
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Watches how long the simulation takes each physics frame and lowers the
+/// quality a tier at a time while it's over `budget` milliseconds, raising
+/// it again once there is room. A budget of zero keeps full quality.
+#[derive(Debug, Default)]
+pub struct QualityGovernor {
+    pub budget: f32,
+    // 0 is full quality
+    pub tier: usize,
+    // Smoothed milliseconds per physics frame
+    pub frame_time: f32,
+    over: f32,
+    under: f32,
+    // Set when the tier changes, drained by the `GameWorld` into a
+    // `quality_changed` signal
+    pub changed: Option<usize>,
+}
+
+impl QualityGovernor {
+    pub fn current(&self) -> QualityTier {
+        TIERS[self.tier.min(TIERS.len() - 1)]
+    }
+
+    pub fn set_budget(&mut self, budget: f32) {
+        self.budget = budget.max(0.);
+        self.over = 0.;
+        self.under = 0.;
+        if self.budget == 0. {
+            self.set_tier(0);
+        }
+    }
+
+    /// `elapsed` milliseconds spent on a physics frame `delta` seconds long
+    pub fn record(&mut self, elapsed: f32, delta: f32) {
+        self.frame_time += (elapsed - self.frame_time) * SMOOTHING;
+        if self.budget <= 0. {
+            return;
+        }
+
+        if self.frame_time > self.budget {
+            self.over += delta;
+            self.under = 0.;
+        } else if self.frame_time < self.budget * RESTORE_SHARE {
+            self.under += delta;
+            self.over = 0.;
+        } else {
+            self.over = 0.;
+            self.under = 0.;
+        }
+
+        if self.over >= DEGRADE_AFTER && self.tier + 1 < TIERS.len() {
+            self.set_tier(self.tier + 1);
+        } else if self.under >= RESTORE_AFTER && self.tier > 0 {
+            self.set_tier(self.tier - 1);
+        }
+    }
+
+    fn set_tier(&mut self, tier: usize) {
+        self.over = 0.;
+        self.under = 0.;
+        if tier != self.tier {
+            self.tier = tier;
+            self.changed = Some(tier);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::{HashMap, VecDeque};
+
+use gdnative::core_types::Vector2;
+use legion::systems::ParallelRunnable;
+use legion::*;
+use serde::{Deserialize, Serialize};
+
+use crate::boids::{is_finite, Boid, BoidId, Pos, Velocity};
+use crate::node_commands::{NodeCommand, NodeCommands};
+
+// Ten minutes at 60 ticks per second, older frames are dropped
+const MAX_FRAMES: usize = 36_000;
+
+// -----------------------------------------------------------------------------
+//     - Trajectories -
+// -----------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Sample {
+    pub id: u64,
+    pub pos: Vector2,
+    pub rotation: f32,
+}
+
+/// Every boid's position and heading, one entry per physics tick
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Trajectory {
+    pub frames: VecDeque<Vec<Sample>>,
+}
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+#[derive(Default)]
+pub struct TrajectoryRecorder(pub Option<Trajectory>);
+
+pub struct ReplayPlayback {
+    pub trajectory: Trajectory,
+    pub frame: usize,
+}
+
+/// While this holds a playback the physics schedule is paused and the
+/// sprites are driven by the recording instead.
+#[derive(Default)]
+pub struct Replay(pub Option<ReplayPlayback>);
+
+impl Replay {
+    pub fn is_playing(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn record_trajectory() -> impl ParallelRunnable {
+    SystemBuilder::new("record trajectory")
+        .write_resource::<TrajectoryRecorder>()
+        .with_query(<(&BoidId, &Pos, &Velocity)>::query())
+        .build(|_, world, recorder, query| {
+            let trajectory = match recorder.0.as_mut() {
+                Some(trajectory) => trajectory,
+                None => return,
+            };
+
+            // NaN can't be written out as JSON
+            let frame = query
+                .iter(world)
+                .filter(|(_, pos, vel)| is_finite(pos.0) && is_finite(vel.0))
+                .map(|(id, pos, vel)| Sample {
+                    id: id.0,
+                    pos: pos.0,
+                    rotation: vel.0.y.atan2(vel.0.x),
+                })
+                .collect();
+
+            if trajectory.frames.len() == MAX_FRAMES {
+                trajectory.frames.pop_front();
+            }
+            trajectory.frames.push_back(frame);
+        })
+}
+
+// Boids that aren't in the current frame are hidden, recorded boids that
+// no longer exist are skipped
+pub fn replay() -> impl ParallelRunnable {
+    SystemBuilder::new("replay")
+        .write_resource::<Replay>()
+        .write_resource::<NodeCommands>()
+        .with_query(<(Entity, &BoidId)>::query().filter(component::<Boid>()))
+        .build(|_, world, resources, query| {
+            let (replay, commands) = resources;
+            let playback = match replay.0.as_mut() {
+                Some(playback) => playback,
+                None => return,
+            };
+
+            let frame = match playback.trajectory.frames.get(playback.frame) {
+                Some(frame) => frame
+                    .iter()
+                    .map(|sample| (sample.id, sample))
+                    .collect::<HashMap<_, _>>(),
+                None => {
+                    // Done, the next physics tick puts the boids back
+                    for (&entity, _) in query.iter(world) {
+                        commands.push(entity, NodeCommand::SetVisible(true));
+                    }
+                    replay.0 = None;
+                    return;
+                }
+            };
+
+            for (&entity, id) in query.iter(world) {
+                match frame.get(&id.0) {
+                    Some(sample) => {
+                        commands.push(entity, NodeCommand::SetVisible(true));
+                        commands.push(entity, NodeCommand::SetPosition(sample.pos));
+                        commands.push(entity, NodeCommand::SetRotation(sample.rotation));
+                    }
+                    None => commands.push(entity, NodeCommand::SetVisible(false)),
+                }
+            }
+
+            playback.frame += 1;
+        })
+}
@@ -0,0 +1,150 @@
+use gdnative::core_types::{Color, Vector2};
+use legion::systems::ParallelRunnable;
+use legion::*;
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::boids::{Boid, Forces, Velocity, MAX_SPEED};
+use crate::debug::Selected;
+use crate::gameworld::Delta;
+use crate::node_commands::{NodeCommand, NodeCommands};
+use crate::traits::Traits;
+
+// How fast the wander heading drifts, in radians per second
+const WANDER_JITTER: f32 = 6.;
+// Most the wander heading strays from the direction of travel
+const WANDER_ANGLE: f32 = std::f32::consts::FRAC_PI_2;
+
+const SCOUT_COHESION: f32 = 0.4;
+const STRAGGLER_SPEED: f32 = 0.7;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Follower,
+    // Roams ahead of the flock
+    Scout,
+    // Can't keep up
+    Straggler,
+}
+
+impl Role {
+    /// The role's take on traits sampled from the trait ranges
+    pub fn apply(self, traits: Traits) -> Traits {
+        match self {
+            Role::Follower => traits,
+            Role::Scout => Traits {
+                cohesion: traits.cohesion * SCOUT_COHESION,
+                ..traits
+            },
+            Role::Straggler => Traits {
+                max_speed: traits.max_speed * STRAGGLER_SPEED,
+                ..traits
+            },
+        }
+    }
+
+    // Wander force in units of the max speed
+    fn wander(self) -> f32 {
+        match self {
+            Role::Scout => 0.6,
+            Role::Follower | Role::Straggler => 0.,
+        }
+    }
+
+    fn tint(self) -> Color {
+        match self {
+            Role::Follower => Color::from_rgb(1., 1., 1.),
+            Role::Scout => Color::from_rgb(0.6, 1., 0.6),
+            Role::Straggler => Color::from_rgb(0.7, 0.7, 0.7),
+        }
+    }
+}
+
+// Offset of the wander heading from the direction of travel
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Wander(pub f32);
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Share of newly spawned boids given each role, the rest are followers
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RoleRatios {
+    pub scout: f32,
+    pub straggler: f32,
+}
+
+impl Default for RoleRatios {
+    fn default() -> Self {
+        Self {
+            scout: 0.1,
+            straggler: 0.1,
+        }
+    }
+}
+
+impl RoleRatios {
+    pub fn sample(&self, rng: &mut impl Rng) -> Role {
+        let roll = rng.gen::<f32>();
+        if roll < self.scout {
+            Role::Scout
+        } else if roll < self.scout + self.straggler {
+            Role::Straggler
+        } else {
+            Role::Follower
+        }
+    }
+}
+
+pub struct ShowRoles(pub bool);
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn wander() -> impl ParallelRunnable {
+    SystemBuilder::new("wander")
+        .read_resource::<Delta>()
+        .with_query(<(&Role, &Velocity, &mut Wander, &mut Forces)>::query())
+        .build(|_, world, delta, query| {
+            let mut rng = thread_rng();
+            let jitter = WANDER_JITTER * delta.0;
+
+            for (role, vel, wander, force) in query.iter_mut(world) {
+                let strength = role.wander();
+                if strength == 0. || jitter <= 0. {
+                    continue;
+                }
+
+                wander.0 = (wander.0 + rng.gen_range(-jitter, jitter))
+                    .max(-WANDER_ANGLE)
+                    .min(WANDER_ANGLE);
+                let heading = vel.0.y.atan2(vel.0.x) + wander.0;
+                force.wander = Vector2::new(heading.cos(), heading.sin()) * MAX_SPEED * strength;
+            }
+        })
+}
+
+pub fn role_tint() -> impl ParallelRunnable {
+    SystemBuilder::new("role tint")
+        .read_resource::<ShowRoles>()
+        .write_resource::<NodeCommands>()
+        .with_query(
+            <(Entity, &Role)>::query().filter(component::<Boid>() & !component::<Selected>()),
+        )
+        .build(|_, world, resources, query| {
+            let (show, commands) = resources;
+            if !show.0 {
+                return;
+            }
+
+            for (&entity, role) in query.iter(world) {
+                commands.push(entity, NodeCommand::SetModulate(role.tint()));
+            }
+        })
+}
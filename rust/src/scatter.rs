@@ -0,0 +1,81 @@
+use gdnative::core_types::Vector2;
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::boids::{Forces, Pos, MAX_SPEED};
+use crate::gameworld::Delta;
+
+// Boids further than this from the origin don't feel a scatter
+pub const SCATTER_RADIUS: f32 = 400.;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+/// A radial push away from `origin` that fades out over `duration` seconds.
+/// Lives on its own entity, which is deleted once it has run out.
+#[derive(Debug, Clone, Copy)]
+pub struct Scatter {
+    pub origin: Vector2,
+    pub strength: f32,
+    pub duration: f32,
+    pub elapsed: f32,
+}
+
+impl Scatter {
+    pub fn new(origin: Vector2, strength: f32, duration: f32) -> Self {
+        Self {
+            origin,
+            strength,
+            duration,
+            elapsed: 0.,
+        }
+    }
+
+    // Push felt by a boid at `pos`, strongest at the origin and at the start
+    fn push(&self, pos: Vector2) -> Vector2 {
+        let away = pos - self.origin;
+        let distance = away.length();
+        if distance == 0. || distance >= SCATTER_RADIUS || self.duration <= 0. {
+            return Vector2::ZERO;
+        }
+
+        let fade = 1. - self.elapsed / self.duration;
+        let falloff = 1. - distance / SCATTER_RADIUS;
+        away / distance * MAX_SPEED * self.strength * fade.max(0.) * falloff
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn scatter() -> impl ParallelRunnable {
+    SystemBuilder::new("scatter")
+        .read_resource::<Delta>()
+        .with_query(<(Entity, &mut Scatter)>::query())
+        .with_query(<(&Pos, &mut Forces)>::query())
+        .build(|cmd, world, delta, queries| {
+            let (events, boids) = queries;
+
+            let mut active = Vec::new();
+            for (&entity, event) in events.iter_mut(world) {
+                if event.elapsed >= event.duration {
+                    cmd.remove(entity);
+                    continue;
+                }
+
+                active.push(*event);
+                event.elapsed += delta.0;
+            }
+
+            if active.is_empty() {
+                return;
+            }
+
+            for (pos, force) in boids.iter_mut(world) {
+                force.scatter = active
+                    .iter()
+                    .fold(Vector2::ZERO, |acc, event| acc + event.push(pos.0));
+            }
+        })
+}
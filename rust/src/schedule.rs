@@ -0,0 +1,204 @@
+use gdnative::log::godot_error;
+use legion::*;
+use serde::Deserialize;
+use serde_json::{Map, Number, Value};
+
+use crate::config::Config;
+use crate::error::{BoidsError, Result};
+use crate::gameworld::Delta;
+
+// -----------------------------------------------------------------------------
+//     - Schedule -
+// -----------------------------------------------------------------------------
+
+/// What `set_schedule` takes: keyframes at seconds from the start, each a
+/// preset name or a config of its own
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleSpec {
+    pub keyframes: Vec<KeyframeSpec>,
+    // Start over after the last keyframe, or after `length` seconds
+    #[serde(default, rename = "loop")]
+    pub looping: bool,
+    pub length: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyframeSpec {
+    pub time: f32,
+    pub preset: Option<String>,
+    pub config: Option<Config>,
+}
+
+struct Keyframe {
+    time: f32,
+    // The config as a JSON object, so any field can be blended
+    config: Map<String, Value>,
+}
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Blends the config from one keyframe to the next as the simulation clock
+/// runs. Numbers are interpolated, everything else switches halfway. Before
+/// the first keyframe and after the last (unless looping) it holds still.
+#[derive(Default)]
+pub struct BehaviorSchedule {
+    keyframes: Vec<Keyframe>,
+    looping: bool,
+    length: f32,
+    pub time: f32,
+}
+
+impl BehaviorSchedule {
+    /// Takes (time, config) keyframes, with presets already looked up
+    pub fn new(keyframes: Vec<(f32, Config)>, looping: bool, length: Option<f32>) -> Result<Self> {
+        let mut keyframes = keyframes
+            .into_iter()
+            .map(|(time, config)| {
+                let config = match serde_json::to_value(&config)? {
+                    Value::Object(config) => config,
+                    _ => Map::new(),
+                };
+                Ok(Keyframe {
+                    time: time.max(0.),
+                    config,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        keyframes.sort_by(|a, b| {
+            a.time
+                .partial_cmp(&b.time)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let last = keyframes.last().map(|keyframe| keyframe.time).unwrap_or(0.);
+        let length = length.unwrap_or(last).max(last);
+        if looping && length <= 0. {
+            return Err(BoidsError::InvalidArgument(
+                "a looping schedule needs a length".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            keyframes,
+            looping,
+            length,
+            time: 0.,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    // The blended config at the current time
+    fn config(&self) -> Result<Config> {
+        let first = &self.keyframes[0];
+        let last = &self.keyframes[self.keyframes.len() - 1];
+        let time = self.time;
+
+        let (from, to, t) = match self.keyframes.iter().position(|k| k.time > time) {
+            Some(0) if self.looping => {
+                // Wrapping around from the last keyframe to the first
+                let span = first.time + self.length - last.time;
+                let since = time + self.length - last.time;
+                (last, first, fraction(since, span))
+            }
+            Some(0) => (first, first, 0.),
+            Some(next) => {
+                let (from, to) = (&self.keyframes[next - 1], &self.keyframes[next]);
+                (from, to, fraction(time - from.time, to.time - from.time))
+            }
+            None if self.looping => {
+                let span = first.time + self.length - last.time;
+                (last, first, fraction(time - last.time, span))
+            }
+            None => (last, last, 0.),
+        };
+
+        let blended = blend_objects(&from.config, &to.config, t);
+        Ok(serde_json::from_value(Value::Object(blended))?)
+    }
+}
+
+fn fraction(elapsed: f32, span: f32) -> f32 {
+    if span > 0. {
+        (elapsed / span).max(0.).min(1.)
+    } else {
+        1.
+    }
+}
+
+// Missing and null values hold whatever the other side has
+fn blend_objects(from: &Map<String, Value>, to: &Map<String, Value>, t: f32) -> Map<String, Value> {
+    let mut blended = Map::new();
+    for key in from.keys().chain(to.keys()) {
+        if blended.contains_key(key) {
+            continue;
+        }
+        let value = match (from.get(key), to.get(key)) {
+            (Some(a), Some(b)) if !a.is_null() && !b.is_null() => blend(a, b, t),
+            (Some(a), _) if !a.is_null() => a.clone(),
+            (_, Some(b)) => b.clone(),
+            _ => Value::Null,
+        };
+        blended.insert(key.clone(), value);
+    }
+    blended
+}
+
+fn blend(from: &Value, to: &Value, t: f32) -> Value {
+    match (from, to) {
+        (Value::Object(a), Value::Object(b)) => Value::Object(blend_objects(a, b, t)),
+        (Value::Number(a), Value::Number(b)) => {
+            let (x, y) = (a.as_f64().unwrap_or(0.), b.as_f64().unwrap_or(0.));
+            let value = x + (y - x) * t as f64;
+            // Counts have to stay whole numbers
+            if a.is_u64() && b.is_u64() {
+                Value::Number(Number::from(value.round() as u64))
+            } else if a.is_i64() && b.is_i64() {
+                Value::Number(Number::from(value.round() as i64))
+            } else {
+                Number::from_f64(value)
+                    .map(Value::Number)
+                    .unwrap_or_else(|| from.clone())
+            }
+        }
+        _ if t < 0.5 => from.clone(),
+        _ => to.clone(),
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+// Writes to the config resources like `apply_config` does, so this runs as a
+// thread local function with all of them. A config that can't be applied
+// stops the schedule rather than failing every tick.
+pub fn run_schedule(_: &mut World, resources: &mut Resources) {
+    let config = {
+        let delta = resources.get::<Delta>().map(|delta| delta.0).unwrap_or(0.);
+        let mut schedule = match resources.get_mut::<BehaviorSchedule>() {
+            Some(schedule) => schedule,
+            None => return,
+        };
+        if schedule.is_empty() {
+            return;
+        }
+
+        schedule.time += delta;
+        if schedule.looping {
+            schedule.time %= schedule.length;
+        }
+        schedule.config()
+    };
+
+    if let Err(e) = config.and_then(|config| config.apply(resources)) {
+        godot_error!("schedule: {}", e);
+        resources.insert(BehaviorSchedule::default());
+    }
+}
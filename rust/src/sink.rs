@@ -0,0 +1,152 @@
+use std::collections::{HashMap, HashSet};
+
+use gdnative::prelude::*;
+use legion::systems::{ParallelRunnable, Runnable};
+use legion::*;
+
+use crate::area::collision_shapes;
+use crate::boids::{live_node, Boid, Pos};
+use crate::ecology::PopulationChanges;
+use crate::zone::ZoneShape;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+/// Despawns every boid inside its shapes, the `CollisionShape2D` children of
+/// a `BoidSink` node
+pub struct Sink {
+    pub shapes: Vec<ZoneShape>,
+    // Boids despawned since the sink was added
+    pub total: usize,
+}
+
+// The `BoidSink` node a `Sink` follows
+pub struct SinkNode(pub Ref<Node2D>);
+
+impl SinkNode {
+    pub unsafe fn node(&self) -> Option<TRef<Node2D>> {
+        live_node(&self.0)
+    }
+
+    pub unsafe fn is_alive(&self) -> bool {
+        self.node().is_some()
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Boids each sink despawned since the last frame. Drained by the
+/// `GameWorld`, which has the sink nodes emit `boids_absorbed`.
+#[derive(Debug, Default)]
+pub struct SinksDrained(pub HashMap<Entity, usize>);
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+pub fn sync_sinks() -> impl Runnable {
+    SystemBuilder::new("sync sinks")
+        .with_query(<(Entity, &SinkNode, &mut Sink)>::query())
+        .build(|cmd, world, _, query| {
+            for (&entity, node, sink) in query.iter_mut(world) {
+                match unsafe { node.node() } {
+                    Some(node) => sink.shapes = unsafe { collision_shapes(&node) },
+                    None => cmd.remove(entity),
+                }
+            }
+        })
+}
+
+// Queues the boids inside as deaths for the `GameWorld` to free
+pub fn drain_sinks() -> impl ParallelRunnable {
+    SystemBuilder::new("drain sinks")
+        .write_resource::<PopulationChanges>()
+        .write_resource::<SinksDrained>()
+        .with_query(<(Entity, &mut Sink)>::query())
+        .with_query(<(Entity, &Pos)>::query().filter(component::<Boid>()))
+        .build(|_, world, resources, queries| {
+            let (changes, drained) = resources;
+            let (sinks, boids) = queries;
+
+            let boids = boids
+                .iter(world)
+                .map(|(&entity, pos)| (entity, pos.0))
+                .collect::<Vec<_>>();
+
+            // A boid in two sinks only counts for the first
+            let mut dying = changes.deaths.iter().copied().collect::<HashSet<_>>();
+            for (&sink_entity, sink) in sinks.iter_mut(world) {
+                let mut count = 0;
+                for (entity, pos) in &boids {
+                    if !dying.contains(entity) && sink.shapes.iter().any(|s| s.contains(*pos)) {
+                        dying.insert(*entity);
+                        changes.deaths.push(*entity);
+                        count += 1;
+                    }
+                }
+
+                if count > 0 {
+                    sink.total += count;
+                    *drained.0.entry(sink_entity).or_insert(0) += count;
+                }
+            }
+        })
+}
+
+// -----------------------------------------------------------------------------
+//     - Godot nodes -
+// -----------------------------------------------------------------------------
+
+/// Despawns boids of the parent `GameWorld` that enter its `CollisionShape2D`
+/// children. `boids_absorbed` has how many went this frame and since the
+/// sink was added, for measuring throughput.
+#[derive(NativeClass)]
+#[inherit(Node2D)]
+#[register_with(Self::register_signals)]
+pub struct BoidSink;
+
+#[methods]
+impl BoidSink {
+    pub fn new(_owner: &Node2D) -> Self {
+        Self
+    }
+
+    fn register_signals(builder: &ClassBuilder<Self>) {
+        builder
+            .signal("boids_absorbed")
+            .with_param("count", VariantType::I64)
+            .with_param("total", VariantType::I64)
+            .done();
+    }
+
+    #[method]
+    pub fn _ready(&mut self, #[base] owner: TRef<Node2D>) {
+        unsafe {
+            let parent = match owner.get_parent() {
+                Some(parent) => parent.assume_safe(),
+                None => return,
+            };
+
+            if !parent.has_method("add_sink") {
+                godot_error!("boid sink must be a child of a GameWorld");
+                return;
+            }
+            parent.call("add_sink", &[owner.to_variant()]);
+        }
+    }
+
+    #[method]
+    pub fn _exit_tree(&mut self, #[base] owner: TRef<Node2D>) {
+        unsafe {
+            if let Some(parent) = owner.get_parent() {
+                let parent = parent.assume_safe();
+                if parent.has_method("remove_sink") {
+                    parent.call("remove_sink", &[owner.to_variant()]);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use gdnative::core_types::Vector2;
+use legion::*;
+use serde::{Deserialize, Serialize};
+
+use crate::boids::{Boid, BoidId, Pos, Velocity};
+use crate::config::Config;
+use crate::energy::Energy;
+use crate::error::Result;
+use crate::gameworld::NextBoidId;
+use crate::roles::Role;
+use crate::species::Species;
+use crate::traits::Traits;
+
+// -----------------------------------------------------------------------------
+//     - Snapshots -
+// -----------------------------------------------------------------------------
+
+/// One boid as `serialize_to_variant` found it. `node` is the instance id of
+/// its node, which is reused on restore if it's still there.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BoidSnapshot {
+    pub id: u64,
+    pub node: i64,
+    pub pos: Vector2,
+    pub vel: Vector2,
+    pub species: u32,
+    pub traits: Option<Traits>,
+    pub role: Option<Role>,
+    pub energy: Option<f32>,
+}
+
+/// Everything needed to pick the simulation up again after the library is
+/// reloaded: the tunables and the boids. Markers, point forces, areas,
+/// emitters, sinks, perches and linked worlds are dropped, the nodes that
+/// registered them have to do so again after a restore.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FlockSnapshot {
+    pub config: Config,
+    pub next_id: u64,
+    pub boids: Vec<BoidSnapshot>,
+}
+
+impl FlockSnapshot {
+    pub fn capture(world: &World, resources: &Resources) -> Self {
+        let mut query = <(
+            &Boid,
+            &BoidId,
+            &Pos,
+            &Velocity,
+            Option<&Species>,
+            Option<&Traits>,
+            Option<&Role>,
+            Option<&Energy>,
+        )>::query();
+
+        let boids = query
+            .iter(world)
+            .filter_map(|(boid, id, pos, vel, species, traits, role, energy)| {
+                let node = unsafe { boid.node() }?;
+                Some(BoidSnapshot {
+                    id: id.0,
+                    node: node.get_instance_id(),
+                    pos: pos.0,
+                    vel: vel.0,
+                    species: species.map(|species| species.0).unwrap_or(0),
+                    traits: traits.copied(),
+                    role: role.copied(),
+                    energy: energy.map(|energy| energy.level),
+                })
+            })
+            .collect();
+
+        Self {
+            config: Config::capture(resources),
+            next_id: resources
+                .get::<NextBoidId>()
+                .map(|next| next.0)
+                .unwrap_or(0),
+            boids,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
@@ -0,0 +1,453 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::hash::BuildHasherDefault;
+
+use gdnative::core_types::Vector2;
+use legion::Entity;
+use serde::{Deserialize, Serialize};
+use twox_hash::XxHash64;
+
+use crate::error::{BoidsError, Result};
+use crate::gameworld::{NeighbourSearch, Viewport};
+
+type Cells = HashMap<(i32, i32), Vec<usize>, BuildHasherDefault<XxHash64>>;
+
+// -----------------------------------------------------------------------------
+//     - Spatial grid -
+// -----------------------------------------------------------------------------
+pub struct SpatialGrid {
+    cell_size: f32,
+    cells: Cells,
+}
+
+impl SpatialGrid {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: Cells::default(),
+        }
+    }
+
+    fn cell(&self, pos: Vector2) -> (i32, i32) {
+        (
+            (pos.x / self.cell_size).floor() as i32,
+            (pos.y / self.cell_size).floor() as i32,
+        )
+    }
+
+    pub fn rebuild(&mut self, positions: &[Vector2]) {
+        // Keep the allocations around, the flock tends to revisit the same cells
+        self.cells.values_mut().for_each(Vec::clear);
+
+        for (index, pos) in positions.iter().enumerate() {
+            let cell = self.cell(*pos);
+            self.cells.entry(cell).or_insert_with(Vec::new).push(index);
+        }
+    }
+
+    pub fn insert(&mut self, index: usize, pos: Vector2) {
+        let cell = self.cell(pos);
+        self.cells.entry(cell).or_insert_with(Vec::new).push(index);
+    }
+
+    /// Every index stored in a cell touched by the square around `pos`.
+    /// Callers still have to do the actual distance check.
+    pub fn candidates(&self, pos: Vector2, radius: f32) -> impl Iterator<Item = usize> + '_ {
+        let extent = Vector2::new(radius, radius);
+        let (min_x, min_y) = self.cell(pos - extent);
+        let (max_x, max_y) = self.cell(pos + extent);
+
+        (min_x..=max_x)
+            .flat_map(move |x| (min_y..=max_y).map(move |y| (x, y)))
+            .filter_map(move |cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+    }
+
+    /// `candidates` for a world that wraps around at the edges of `viewport`,
+    /// each index at most once
+    pub fn wrapped_candidates(&self, pos: Vector2, radius: f32, viewport: &Viewport) -> Vec<usize> {
+        let mut candidates = viewport
+            .wrap_offsets(pos, radius)
+            .into_iter()
+            .flat_map(|offset| self.candidates(pos + offset, radius))
+            .collect::<Vec<_>>();
+        candidates.sort_unstable();
+        candidates.dedup();
+        candidates
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Nearest counts -
+// -----------------------------------------------------------------------------
+
+/// The flocking rules that can each see a different number of neighbours
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NeighbourRule {
+    Cohesion,
+    Separation,
+    Alignment,
+}
+
+impl NeighbourRule {
+    pub fn parse(rule: &str) -> Result<Self> {
+        match rule {
+            "cohesion" => Ok(NeighbourRule::Cohesion),
+            "separation" => Ok(NeighbourRule::Separation),
+            "alignment" => Ok(NeighbourRule::Alignment),
+            _ => Err(BoidsError::InvalidArgument(format!(
+                "unknown neighbour rule \"{}\"",
+                rule
+            ))),
+        }
+    }
+}
+
+/// How many of the closest boids each rule considers in topological mode.
+/// Rules left at `None` use the shared `NearestCount`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NearestCounts {
+    pub cohesion: Option<usize>,
+    pub separation: Option<usize>,
+    pub alignment: Option<usize>,
+}
+
+impl NearestCounts {
+    pub fn get(&self, rule: NeighbourRule) -> Option<usize> {
+        match rule {
+            NeighbourRule::Cohesion => self.cohesion,
+            NeighbourRule::Separation => self.separation,
+            NeighbourRule::Alignment => self.alignment,
+        }
+    }
+
+    pub fn get_mut(&mut self, rule: NeighbourRule) -> &mut Option<usize> {
+        match rule {
+            NeighbourRule::Cohesion => &mut self.cohesion,
+            NeighbourRule::Separation => &mut self.separation,
+            NeighbourRule::Alignment => &mut self.alignment,
+        }
+    }
+
+    // Every rule without a count of its own gets `shared`
+    pub fn or(self, shared: usize) -> Self {
+        Self {
+            cohesion: self.cohesion.or(Some(shared)),
+            separation: self.separation.or(Some(shared)),
+            alignment: self.alignment.or(Some(shared)),
+        }
+    }
+
+    pub fn sanitized(self) -> Self {
+        Self {
+            cohesion: self.cohesion.map(|count| count.max(1)),
+            separation: self.separation.map(|count| count.max(1)),
+            alignment: self.alignment.map(|count| count.max(1)),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Flock index -
+// -----------------------------------------------------------------------------
+
+/// Snapshot of every boid's position and velocity, taken once per tick so the
+/// steering systems don't each have to collect their own.
+pub struct FlockIndex {
+    pub entities: Vec<Entity>,
+    pub positions: Vec<Vector2>,
+    pub velocities: Vec<Vector2>,
+    pub radii: Vec<f32>,
+    lookup: HashMap<Entity, usize, BuildHasherDefault<XxHash64>>,
+    max_radius: f32,
+    grid: Option<SpatialGrid>,
+    cell_size: f32,
+    nearest: Option<usize>,
+    rule_nearest: NearestCounts,
+    wrap: Option<Viewport>,
+}
+
+impl FlockIndex {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            entities: Vec::new(),
+            positions: Vec::new(),
+            velocities: Vec::new(),
+            radii: Vec::new(),
+            lookup: HashMap::default(),
+            max_radius: 0.,
+            grid: None,
+            cell_size,
+            nearest: None,
+            rule_nearest: NearestCounts::default(),
+            wrap: None,
+        }
+    }
+
+    /// How many of the closest boids `neighbours` keeps (topological mode),
+    /// `None` keeps everything inside the radius (metric mode). `shared` is
+    /// for everything but the flocking rules, which each have their own.
+    pub fn set_nearest(&mut self, shared: Option<usize>, rules: NearestCounts) {
+        self.nearest = shared;
+        self.rule_nearest = rules;
+    }
+
+    pub fn shared_nearest(&self) -> Option<usize> {
+        self.nearest
+    }
+
+    pub fn rule_nearest(&self, rule: NeighbourRule) -> Option<usize> {
+        self.rule_nearest.get(rule)
+    }
+
+    /// Measure distances through the edges of `viewport`, `None` for an open
+    /// world
+    pub fn set_wrap(&mut self, wrap: Option<Viewport>) {
+        self.wrap = wrap;
+    }
+
+    /// Shortest vector from `pos` to the boid at `index`
+    pub fn delta(&self, pos: Vector2, index: usize) -> Vector2 {
+        match &self.wrap {
+            Some(viewport) => viewport.wrapped_delta(pos, self.positions[index]),
+            None => self.positions[index] - pos,
+        }
+    }
+
+    pub fn rebuild(
+        &mut self,
+        search: NeighbourSearch,
+        boids: impl Iterator<Item = (Entity, Vector2, Vector2, f32)>,
+    ) {
+        self.entities.clear();
+        self.positions.clear();
+        self.velocities.clear();
+        self.radii.clear();
+        self.lookup.clear();
+        self.max_radius = 0.;
+
+        for (entity, pos, vel, radius) in boids {
+            self.lookup.insert(entity, self.entities.len());
+            self.entities.push(entity);
+            self.positions.push(pos);
+            self.velocities.push(vel);
+            self.radii.push(radius);
+            self.max_radius = self.max_radius.max(radius);
+        }
+
+        match search {
+            NeighbourSearch::BruteForce => self.grid = None,
+            NeighbourSearch::SpatialIndex => {
+                let cell_size = self.cell_size;
+                let grid = self.grid.get_or_insert_with(|| SpatialGrid::new(cell_size));
+                grid.rebuild(&self.positions);
+            }
+        }
+    }
+
+    /// Indices of all boids closer than `radius` to `pos`, including a boid
+    /// sitting exactly on `pos`. With a `nearest` count (topological mode) the
+    /// radius is still the perception limit, only the k closest inside it are
+    /// kept.
+    pub fn neighbours(&self, pos: Vector2, radius: f32, nearest: Option<usize>) -> Vec<usize> {
+        self.keep_nearest(pos, self.within(pos, radius), nearest)
+    }
+
+    /// Like `neighbours`, but only considers `candidates`, which can be from
+    /// an older search. Entities that are gone are skipped.
+    pub fn neighbours_among(
+        &self,
+        pos: Vector2,
+        radius: f32,
+        candidates: &[Entity],
+        nearest: Option<usize>,
+    ) -> Vec<usize> {
+        let radius_sq = radius * radius;
+        let in_range = candidates
+            .iter()
+            .filter_map(|entity| self.index_of(*entity))
+            .filter(|index| self.delta(pos, *index).length_squared() < radius_sq)
+            .collect();
+        self.keep_nearest(pos, in_range, nearest)
+    }
+
+    // In topological mode, drops all but the closest of `neighbours`
+    fn keep_nearest(
+        &self,
+        pos: Vector2,
+        mut neighbours: Vec<usize>,
+        nearest: Option<usize>,
+    ) -> Vec<usize> {
+        let distance_sq = |index: &usize| self.delta(pos, *index).length_squared();
+
+        if let Some(nearest) = nearest {
+            // The boid itself is always the closest one, so it doesn't use up a slot
+            let keep = nearest + 1;
+            if neighbours.len() > keep {
+                neighbours.select_nth_unstable_by(keep, |a, b| {
+                    distance_sq(a)
+                        .partial_cmp(&distance_sq(b))
+                        .unwrap_or(Ordering::Equal)
+                });
+                neighbours.truncate(keep);
+            }
+        }
+
+        neighbours
+    }
+
+    /// Like `neighbours`, but always metric
+    pub fn within(&self, pos: Vector2, radius: f32) -> Vec<usize> {
+        let radius_sq = radius * radius;
+        let in_range = |index: &usize| self.delta(pos, *index).length_squared() < radius_sq;
+
+        match (&self.grid, &self.wrap) {
+            (Some(grid), Some(viewport)) => grid
+                .wrapped_candidates(pos, radius, viewport)
+                .into_iter()
+                .filter(in_range)
+                .collect(),
+            (Some(grid), None) => grid.candidates(pos, radius).filter(in_range).collect(),
+            (None, _) => (0..self.positions.len()).filter(in_range).collect(),
+        }
+    }
+
+    /// Distance from the boid at `index` to its closest neighbour, `None` if
+    /// there is nobody within `radius`
+    pub fn nearest_distance(&self, index: usize, radius: f32) -> Option<f32> {
+        let pos = self.positions[index];
+        self.within(pos, radius)
+            .into_iter()
+            .filter(|other| *other != index)
+            .map(|other| self.delta(pos, other).length())
+            .min_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+    }
+
+    /// Groups boids that are connected through chains of neighbours closer
+    /// than `link_radius`, measured through the edges like `delta`. Returns
+    /// the group of every boid, numbered from zero, and the number of groups.
+    pub fn clusters(&self, link_radius: f32) -> (Vec<usize>, usize) {
+        fn root(parents: &mut [usize], mut index: usize) -> usize {
+            while parents[index] != index {
+                parents[index] = parents[parents[index]];
+                index = parents[index];
+            }
+            index
+        }
+
+        let mut parents = (0..self.positions.len()).collect::<Vec<_>>();
+
+        for (index, pos) in self.positions.iter().enumerate() {
+            for other in self.within(*pos, link_radius) {
+                let (a, b) = (root(&mut parents, index), root(&mut parents, other));
+                if a != b {
+                    parents[a.max(b)] = a.min(b);
+                }
+            }
+        }
+
+        // Renumber the roots so groups are 0..count
+        let mut groups = vec![usize::MAX; parents.len()];
+        let mut labels = Vec::with_capacity(parents.len());
+        let mut count = 0;
+        for index in 0..parents.len() {
+            let root = root(&mut parents, index);
+            if groups[root] == usize::MAX {
+                groups[root] = count;
+                count += 1;
+            }
+            labels.push(groups[root]);
+        }
+
+        (labels, count)
+    }
+
+    pub fn index_of(&self, entity: Entity) -> Option<usize> {
+        self.lookup.get(&entity).copied()
+    }
+
+    pub fn max_radius(&self) -> f32 {
+        self.max_radius
+    }
+
+    /// Distance between the edges of a boid at `pos` and the boid at `index`
+    pub fn gap(&self, pos: Vector2, radius: f32, index: usize) -> f32 {
+        self.delta(pos, index).length() - radius - self.radii[index]
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Tests -
+// -----------------------------------------------------------------------------
+
+#[cfg(feature = "godot_test")]
+pub mod tests {
+    use legion::*;
+
+    use super::*;
+    use crate::assert_gd;
+    use crate::boids::Pos;
+    use crate::gameworld::WRAP_MARGIN;
+
+    // An index over boids at `positions`, standing still
+    fn index(positions: &[Vector2], search: NeighbourSearch) -> FlockIndex {
+        let mut world = World::default();
+        let entities = world.extend(positions.iter().map(|pos| (Pos(*pos),)).collect::<Vec<_>>());
+
+        let mut index = FlockIndex::new(50.);
+        let boids = entities
+            .iter()
+            .zip(positions)
+            .map(|(entity, pos)| (*entity, *pos, Vector2::ZERO, 16.));
+        index.rebuild(search, boids);
+        index
+    }
+
+    pub fn delta_wraps_through_edges() -> bool {
+        // Across a 100x100 viewport from each other
+        let mut index = index(
+            &[Vector2::new(-45., 0.), Vector2::new(45., 0.)],
+            NeighbourSearch::BruteForce,
+        );
+        let pos = Vector2::new(-45., 0.);
+        assert_gd!(index.delta(pos, 1) == Vector2::new(90., 0.));
+
+        // The short way round is back through the left edge
+        index.set_wrap(Some(Viewport::from_vec2(Vector2::new(100., 100.))));
+        let across = 100. + WRAP_MARGIN * 2.;
+        assert_gd!((index.delta(pos, 1) - Vector2::new(90. - across, 0.)).length() < 1e-4);
+        assert_gd!(index.delta(Vector2::new(40., 0.), 1) == Vector2::new(5., 0.))
+    }
+
+    pub fn keep_nearest_counts() -> bool {
+        let positions = [
+            Vector2::new(0., 0.),
+            Vector2::new(30., 0.),
+            Vector2::new(0., 10.),
+            Vector2::new(-20., 0.),
+            Vector2::new(45., 0.),
+        ];
+        for search in &[NeighbourSearch::BruteForce, NeighbourSearch::SpatialIndex] {
+            let index = index(&positions, *search);
+            let origin = Vector2::ZERO;
+
+            // Metric mode keeps everything inside the radius
+            let mut all = index.neighbours(origin, 40., None);
+            all.sort();
+            assert_gd!(all == vec![0, 1, 2, 3]);
+
+            // The boid itself doesn't use up one of the two slots
+            let mut nearest = index.neighbours(origin, 40., Some(2));
+            nearest.sort();
+            assert_gd!(nearest == vec![0, 2, 3]);
+
+            // The radius still limits how far it looks
+            let mut nearest = index.neighbours(origin, 15., Some(2));
+            nearest.sort();
+            assert_gd!(nearest == vec![0, 2]);
+        }
+        true
+    }
+}
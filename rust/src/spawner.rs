@@ -1,15 +1,208 @@
-use gdnative::{Sprite, ResourceLoader, GodotObject, PackedScene};
+use gdnative::api::{AnimatedSprite, Node, Node2D, PackedScene, ResourceLoader};
+use gdnative::core_types::{Rect2, Vector2};
+use gdnative::object::memory::ManuallyManaged;
+use gdnative::object::ownership::Unique;
+use gdnative::object::{GodotObject, Ref, SubClass, TRef};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-pub fn spawn_boid() -> Sprite {
-    load_resource("res://Boid.tscn")
+use crate::boids::{rotated, MAX_SPEED};
+use crate::error::{BoidsError, Result};
+use crate::spatial::SpatialGrid;
+
+pub const DEFAULT_BOID_SCENE: &str = "res://Boid.tscn";
+// Random points tried for each boid before taking the one with the most room
+const SPAWN_ATTEMPTS: usize = 30;
+
+// Any scene with a `Node2D` (or derived) root will do
+pub fn spawn_boid(path: &str) -> Result<Ref<Node2D, Unique>> {
+    load_resource(path)
+}
+
+// The first `AnimatedSprite` in the boid scene, either the root or a child of it
+pub unsafe fn find_animation(boid: TRef<Node>) -> Option<TRef<AnimatedSprite>> {
+    if let Some(sprite) = boid.cast::<AnimatedSprite>() {
+        return Some(sprite);
+    }
+
+    (0..boid.get_child_count())
+        .filter_map(|i| boid.get_child(i))
+        .find_map(|child| child.assume_safe().cast::<AnimatedSprite>())
 }
 
-fn load_resource<T: GodotObject>(path: &str) -> T {
-    let mut loader = ResourceLoader::godot_singleton();
-    loader.load(path.into(), "PackedScene".into(), false)
+// A freshly instanced scene isn't in the tree yet, so nothing else holds it
+fn load_resource<T>(path: &str) -> Result<Ref<T, Unique>>
+where
+    T: GodotObject<Memory = ManuallyManaged> + SubClass<Node>,
+{
+    let loader = ResourceLoader::godot_singleton();
+    loader
+        .load(path, "PackedScene", false)
         .and_then(|res| res.cast::<PackedScene>())
-        .and_then(|scn| scn.instance(0) )
-        .and_then(|nde| unsafe { nde.cast::<T>() })
-        .unwrap()
+        .and_then(|scene| unsafe { scene.assume_safe().instance(0) })
+        .and_then(|node| unsafe { node.assume_unique().cast::<T>() })
+        .ok_or_else(|| BoidsError::ResourceLoad(path.to_string()))
+}
+
+// -----------------------------------------------------------------------------
+//     - Spawn velocity -
+// -----------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Distribution {
+    Uniform,
+    // Peaking in the middle of the range with the ends two standard
+    // deviations out, clamped to the range
+    Gaussian,
+}
+
+impl Distribution {
+    fn sample(self, min: f32, max: f32, rng: &mut impl Rng) -> f32 {
+        if max <= min {
+            return min;
+        }
+        match self {
+            Distribution::Uniform => rng.gen_range(min, max),
+            Distribution::Gaussian => {
+                let mean = (min + max) / 2.;
+                let std_dev = (max - min) / 4.;
+                (mean + standard_normal(rng) * std_dev).max(min).min(max)
+            }
+        }
+    }
+}
+
+// Box-Muller
+fn standard_normal(rng: &mut impl Rng) -> f32 {
+    let u = rng.gen_range(std::f32::EPSILON, 1.);
+    let angle = rng.gen_range(0., std::f32::consts::PI * 2.);
+    (-2. * u.ln()).sqrt() * angle.cos()
+}
+
+/// How fast and which way boids spawned by `resize_flock` and the
+/// formations start off. Headings are in degrees, 0 to the right, and
+/// `heading_spread` is the width of the range around `heading`, 360 for any
+/// way at all. The default is full speed in any direction.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpawnVelocity {
+    pub min_speed: f32,
+    pub max_speed: f32,
+    pub speed_distribution: Distribution,
+    pub heading: f32,
+    pub heading_spread: f32,
+    pub heading_distribution: Distribution,
+}
+
+impl Default for SpawnVelocity {
+    fn default() -> Self {
+        Self {
+            min_speed: MAX_SPEED,
+            max_speed: MAX_SPEED,
+            speed_distribution: Distribution::Uniform,
+            heading: 0.,
+            heading_spread: 360.,
+            heading_distribution: Distribution::Uniform,
+        }
+    }
 }
 
+impl SpawnVelocity {
+    pub fn sanitized(self) -> Self {
+        let min_speed = self.min_speed.max(0.);
+        Self {
+            min_speed,
+            max_speed: self.max_speed.max(min_speed),
+            heading_spread: self.heading_spread.max(0.).min(360.),
+            ..self
+        }
+    }
+
+    pub fn sample(&self, rng: &mut impl Rng) -> Vector2 {
+        let speed = self.speed_distribution.sample(self.min_speed, self.max_speed, rng);
+        let half = self.heading_spread / 2.;
+        let heading = self.heading + self.heading_distribution.sample(-half, half, rng);
+        rotated(Vector2::new(speed, 0.), heading.to_radians())
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Formations -
+// -----------------------------------------------------------------------------
+
+// `count` random points in `rect`, each at least `spacing` from the others and
+// from `existing` when there is room for it
+pub fn spaced_points(
+    rect: Rect2,
+    count: usize,
+    spacing: f32,
+    existing: &[Vector2],
+    rng: &mut impl Rng,
+) -> Vec<Vector2> {
+    if spacing <= 0. {
+        return (0..count).map(|_| random_point(rect, rng)).collect();
+    }
+
+    let mut placed = existing.to_vec();
+    let mut grid = SpatialGrid::new(spacing);
+    grid.rebuild(&placed);
+
+    for _ in 0..count {
+        let mut best = (Vector2::ZERO, -1.);
+        for _ in 0..SPAWN_ATTEMPTS {
+            let candidate = random_point(rect, rng);
+            let room = grid
+                .candidates(candidate, spacing)
+                .map(|other| (placed[other] - candidate).length())
+                .fold(spacing, f32::min);
+            if room > best.1 {
+                best = (candidate, room);
+            }
+            if room >= spacing {
+                break;
+            }
+        }
+
+        grid.insert(placed.len(), best.0);
+        placed.push(best.0);
+    }
+    placed.split_off(existing.len())
+}
+
+fn random_point(rect: Rect2, rng: &mut impl Rng) -> Vector2 {
+    Vector2::new(
+        rng.gen_range(rect.position.x, rect.end().x),
+        rng.gen_range(rect.position.y, rect.end().y),
+    )
+}
+
+pub fn circle(count: usize, radius: f32, center: Vector2) -> Vec<Vector2> {
+    let step = std::f32::consts::PI * 2. / count.max(1) as f32;
+    (0..count)
+        .map(|i| {
+            let angle = step * i as f32;
+            center + Vector2::new(angle.cos(), angle.sin()) * radius
+        })
+        .collect()
+}
+
+pub fn grid(rows: usize, cols: usize, spacing: f32, center: Vector2) -> Vec<Vector2> {
+    let size = Vector2::new(cols.saturating_sub(1) as f32, rows.saturating_sub(1) as f32) * spacing;
+    let top_left = center - size / 2.;
+    (0..rows)
+        .flat_map(|row| (0..cols).map(move |col| (row, col)))
+        .map(|(row, col)| top_left + Vector2::new(col as f32, row as f32) * spacing)
+        .collect()
+}
+
+// Evenly spaced, including both end points
+pub fn line(count: usize, from: Vector2, to: Vector2) -> Vec<Vector2> {
+    match count {
+        0 => Vec::new(),
+        1 => vec![from],
+        _ => (0..count)
+            .map(|i| from.linear_interpolate(to, i as f32 / (count - 1) as f32))
+            .collect(),
+    }
+}
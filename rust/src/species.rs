@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use gdnative::core_types::Vector2;
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::boids::{safe_normalize, Forces, Pos, MAX_SPEED};
+use crate::error::{BoidsError, Result};
+use crate::gameworld::{BoundaryMode, Viewport};
+use crate::spatial::SpatialGrid;
+
+// How far boids see the species they chase or flee
+const CHASE_RADIUS: f32 = 300.;
+const FLEE_RADIUS: f32 = 200.;
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+// Spawned boids are species 0
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Species(pub u32);
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relation {
+    Neutral,
+    Chase,
+    Flee,
+}
+
+impl Relation {
+    pub fn parse(relation: &str) -> Result<Self> {
+        match relation {
+            "neutral" => Ok(Relation::Neutral),
+            "chase" => Ok(Relation::Chase),
+            "flee" => Ok(Relation::Flee),
+            _ => Err(BoidsError::InvalidArgument(format!(
+                "unknown relation \"{}\"",
+                relation
+            ))),
+        }
+    }
+}
+
+/// How each species reacts to each other species, anything not in here is
+/// neutral. Relations are one way, big fish chasing small fish doesn't make
+/// small fish flee until that is set as well.
+#[derive(Default)]
+pub struct SpeciesRelations(pub HashMap<(Species, Species), Relation>);
+
+impl SpeciesRelations {
+    pub fn get(&self, from: Species, to: Species) -> Relation {
+        self.0
+            .get(&(from, to))
+            .copied()
+            .unwrap_or(Relation::Neutral)
+    }
+
+    pub fn set(&mut self, from: Species, to: Species, relation: Relation) {
+        match relation {
+            Relation::Neutral => self.0.remove(&(from, to)),
+            _ => self.0.insert((from, to), relation),
+        };
+    }
+}
+
+/// What boids of a species are spawned as. Without a `scene` they use the
+/// `BoidScene`, and `scale` multiplies the scale the scene has.
+#[derive(Debug, Clone)]
+pub struct SpeciesLook {
+    pub scene: Option<String>,
+    pub scale: f32,
+}
+
+impl Default for SpeciesLook {
+    fn default() -> Self {
+        Self {
+            scene: None,
+            scale: 1.,
+        }
+    }
+}
+
+// Set with `set_species_scene` and `set_species_scale`
+#[derive(Debug, Default)]
+pub struct SpeciesLooks(pub HashMap<Species, SpeciesLook>);
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+// Steer towards the closest boid of a species we chase and away from the
+// closest one we flee
+pub fn food_chain() -> impl ParallelRunnable {
+    SystemBuilder::new("food chain")
+        .read_resource::<SpeciesRelations>()
+        .read_resource::<BoundaryMode>()
+        .read_resource::<Viewport>()
+        .with_query(<(&Pos, &Species)>::query())
+        .with_query(<(&Pos, &Species, &mut Forces)>::query())
+        .build(|_, world, resources, queries| {
+            let (relations, boundary, viewport) = resources;
+            if relations.0.is_empty() {
+                return;
+            }
+
+            let (others, boids) = queries;
+            let (positions, species): (Vec<_>, Vec<_>) = others
+                .iter(world)
+                .map(|(pos, species)| (pos.0, *species))
+                .unzip();
+
+            let mut grid = SpatialGrid::new(CHASE_RADIUS.max(FLEE_RADIUS));
+            grid.rebuild(&positions);
+
+            let closest = |pos: Vector2, radius: f32, wanted: &dyn Fn(Species) -> bool| {
+                let candidates = match **boundary {
+                    BoundaryMode::Wrap => grid.wrapped_candidates(pos, radius, viewport),
+                    BoundaryMode::Open | BoundaryMode::Walls => {
+                        grid.candidates(pos, radius).collect()
+                    }
+                };
+                candidates
+                    .into_iter()
+                    .filter(|other| wanted(species[*other]))
+                    .map(|other| boundary.delta(viewport, pos, positions[other]))
+                    .filter(|to| to.length_squared() > 0. && to.length_squared() < radius * radius)
+                    .min_by(|a, b| {
+                        a.length_squared()
+                            .partial_cmp(&b.length_squared())
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    })
+            };
+
+            for (pos, own, force) in boids.iter_mut(world) {
+                let chases = |other: Species| relations.get(*own, other) == Relation::Chase;
+                let flees = |other: Species| relations.get(*own, other) == Relation::Flee;
+
+                force.food_chain = Vector2::ZERO;
+                if let Some(to_prey) = closest(pos.0, CHASE_RADIUS, &chases) {
+                    force.food_chain += to_prey.clamped(MAX_SPEED);
+                }
+
+                if let Some(to_predator) = closest(pos.0, FLEE_RADIUS, &flees) {
+                    let closeness = 1. - to_predator.length() / FLEE_RADIUS;
+                    force.food_chain -= safe_normalize(to_predator) * MAX_SPEED * closeness;
+                }
+            }
+        })
+}
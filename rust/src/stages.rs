@@ -0,0 +1,151 @@
+use legion::systems::{Builder, ParallelRunnable, Runnable};
+use legion::*;
+
+use crate::error::{BoidsError, Result};
+
+// -----------------------------------------------------------------------------
+//     - Stages -
+// -----------------------------------------------------------------------------
+
+/// The steps of a tick, in the order they run. The first three make up the
+/// physics schedule, `Presentation` runs once per rendered frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    // Following nodes, resetting the forces and finding the neighbours
+    Perception,
+    // Every force and the measurements taken along the way
+    Steering,
+    // Moving the boids and whatever reacts to where they ended up
+    Integration,
+    // Sprites, tints and node commands
+    Presentation,
+}
+
+const STAGES: [Stage; 4] = [
+    Stage::Perception,
+    Stage::Steering,
+    Stage::Integration,
+    Stage::Presentation,
+];
+
+impl Stage {
+    pub fn parse(stage: &str) -> Result<Self> {
+        STAGES
+            .iter()
+            .copied()
+            .find(|known| known.name() == stage)
+            .ok_or_else(|| BoidsError::InvalidArgument(format!("unknown stage \"{}\"", stage)))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Perception => "perception",
+            Stage::Steering => "steering",
+            Stage::Integration => "integration",
+            Stage::Presentation => "presentation",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+// Adds one stage's worth of systems to a schedule, boxed so parallel systems,
+// thread local ones and plain functions can share a stage
+type StageSystem = Box<dyn FnOnce(&mut Builder)>;
+
+// -----------------------------------------------------------------------------
+//     - Schedule -
+// -----------------------------------------------------------------------------
+
+/// Systems sorted into stages. Each stage keeps the order its systems were
+/// added in, so a system added to a stage runs after everything already in
+/// it and before the next stage. Parallel systems only run alongside their
+/// neighbours in the same stage when their components and resources don't
+/// overlap, and each stage's commands are flushed before the next begins.
+#[derive(Default)]
+pub struct StagedSchedule {
+    stages: [Vec<StageSystem>; 4],
+}
+
+/// The physics schedule runs every simulation step, the presentation one
+/// once per frame
+pub struct BuiltStages {
+    pub physics: Schedule,
+    pub presentation: Schedule,
+}
+
+impl StagedSchedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_system<S: ParallelRunnable + 'static>(self, stage: Stage, system: S) -> Self {
+        self.add_with(stage, move |builder| {
+            builder.add_system(system);
+        })
+    }
+
+    // For systems that touch Godot, which has to happen on the main thread
+    pub fn add_thread_local<S: Runnable + 'static>(self, stage: Stage, system: S) -> Self {
+        self.add_with(stage, move |builder| {
+            builder.add_thread_local(system);
+        })
+    }
+
+    // For systems that need the whole world and resources
+    pub fn add_fn(self, stage: Stage, system: fn(&mut World, &mut Resources)) -> Self {
+        self.add_with(stage, move |builder| {
+            builder.add_thread_local_fn(system);
+        })
+    }
+
+    // For anything else handed the stage's builder, like systems added from
+    // other crates
+    pub fn add_with(mut self, stage: Stage, add: impl FnOnce(&mut Builder) + 'static) -> Self {
+        self.stages[stage.index()].push(Box::new(add));
+        self
+    }
+
+    pub fn build(self) -> BuiltStages {
+        let [perception, steering, integration, presentation] = self.stages;
+        BuiltStages {
+            physics: build(vec![perception, steering, integration]),
+            presentation: build(vec![presentation]),
+        }
+    }
+}
+
+fn build(stages: Vec<Vec<StageSystem>>) -> Schedule {
+    let mut builder = Schedule::builder();
+    for stage in stages {
+        for add in stage {
+            add(&mut builder);
+        }
+        builder.flush();
+    }
+    builder.build()
+}
+
+// -----------------------------------------------------------------------------
+//     - Tests -
+// -----------------------------------------------------------------------------
+
+#[cfg(feature = "godot_test")]
+pub mod tests {
+    use super::*;
+    use crate::assert_gd;
+
+    pub fn stage_names_parse() -> bool {
+        for stage in &STAGES {
+            assert_gd!(Stage::parse(stage.name()).ok() == Some(*stage));
+        }
+
+        // Names are lowercase and exact
+        assert_gd!(Stage::parse("Steering").is_err());
+        assert_gd!(Stage::parse(" steering").is_err());
+        assert_gd!(Stage::parse("").is_err());
+        true
+    }
+}
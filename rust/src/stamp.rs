@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use gdnative::api::Node2D;
+use gdnative::core_types::Vector2;
+use gdnative::object::Ref;
+use legion::systems::ParallelRunnable;
+use legion::*;
+use serde::{Deserialize, Serialize};
+
+use crate::boids::{Boid, BoidId, Pos};
+use crate::gameworld::{BoundaryMode, Viewport};
+use crate::log::Verbosity;
+use crate::node_commands::{NodeCommand, NodeCommands};
+
+// -----------------------------------------------------------------------------
+//     - Motion stamps -
+// -----------------------------------------------------------------------------
+
+/// A short recording of the flock, one entry per physics tick holding every
+/// boid's position relative to the centroid of that tick. Each boid keeps
+/// its own track, `None` while it wasn't around.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MotionStamp {
+    pub frames: Vec<Vec<Option<Vector2>>>,
+}
+
+impl MotionStamp {
+    pub fn boid_count(&self) -> usize {
+        self.frames.iter().map(Vec::len).max().unwrap_or(0)
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+#[derive(Default)]
+pub struct MotionStamps(pub HashMap<String, Arc<MotionStamp>>);
+
+pub struct StampRecording {
+    pub name: String,
+    pub frames_left: usize,
+    pub frames: Vec<Vec<Option<Vector2>>>,
+    // The track each boid is recorded into
+    pub tracks: HashMap<BoidId, usize>,
+}
+
+#[derive(Default)]
+pub struct StampRecorder(pub Option<StampRecording>);
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+/// A stamp being played back with its own sprites. The sprites are freed
+/// once the last frame has been shown and the entity is deleted the tick
+/// after, so `apply_node_commands` still finds them.
+pub struct StampPlayback {
+    pub stamp: Arc<MotionStamp>,
+    pub frame: usize,
+    pub origin: Vector2,
+    pub scale: f32,
+    pub sprites: Vec<Ref<Node2D>>,
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn record_stamp() -> impl ParallelRunnable {
+    SystemBuilder::new("record stamp")
+        .read_resource::<Verbosity>()
+        .read_resource::<BoundaryMode>()
+        .read_resource::<Viewport>()
+        .write_resource::<StampRecorder>()
+        .write_resource::<MotionStamps>()
+        .with_query(<(&Pos, &BoidId)>::query().filter(component::<Boid>()))
+        .build(|_, world, resources, query| {
+            let (verbosity, boundary, viewport, recorder, stamps) = resources;
+            let recording = match recorder.0.as_mut() {
+                Some(recording) => recording,
+                None => return,
+            };
+
+            let boids = query
+                .iter(world)
+                .map(|(pos, id)| (*id, pos.0))
+                .collect::<Vec<_>>();
+
+            // Measured from one of the boids so a flock across an edge in wrap
+            // mode stays in one piece
+            let reference = boids.first().map(|(_, pos)| *pos).unwrap_or(Vector2::ZERO);
+            let offsets = boids
+                .into_iter()
+                .map(|(id, pos)| (id, boundary.delta(viewport, reference, pos)))
+                .collect::<Vec<_>>();
+            let centroid = offsets
+                .iter()
+                .fold(Vector2::ZERO, |acc, (_, offset)| acc + *offset)
+                / offsets.len().max(1) as f32;
+
+            let mut frame = vec![None; recording.tracks.len()];
+            for (id, offset) in offsets {
+                let next = recording.tracks.len();
+                let track = *recording.tracks.entry(id).or_insert(next);
+                if track >= frame.len() {
+                    frame.resize(track + 1, None);
+                }
+                frame[track] = Some(offset - centroid);
+            }
+            recording.frames.push(frame);
+            recording.frames_left = recording.frames_left.saturating_sub(1);
+
+            if recording.frames_left == 0 {
+                if let Some(recording) = recorder.0.take() {
+                    let stamp = MotionStamp {
+                        frames: recording.frames,
+                    };
+                    log_info!(
+                        **verbosity,
+                        "recorded motion stamp \"{}\" ({} frames)",
+                        recording.name,
+                        stamp.frames.len()
+                    );
+                    stamps.0.insert(recording.name, Arc::new(stamp));
+                }
+            }
+        })
+}
+
+pub fn play_stamps() -> impl ParallelRunnable {
+    SystemBuilder::new("play stamps")
+        .write_resource::<NodeCommands>()
+        .with_query(<(Entity, &mut StampPlayback)>::query())
+        .build(|cmd, world, commands, query| {
+            for (&entity, playback) in query.iter_mut(world) {
+                let stamp = Arc::clone(&playback.stamp);
+                let (origin, scale, index) = (playback.origin, playback.scale, playback.frame);
+                playback.frame += 1;
+
+                let frame = match stamp.frames.get(index) {
+                    Some(frame) => frame,
+                    None if index == stamp.frames.len() => {
+                        for i in 0..playback.sprites.len() {
+                            commands.push_stamp_sprite(entity, i, NodeCommand::QueueFree);
+                        }
+                        continue;
+                    }
+                    None => {
+                        cmd.remove(entity);
+                        continue;
+                    }
+                };
+
+                let next = stamp.frames.get(index + 1);
+
+                for i in 0..playback.sprites.len() {
+                    let offset = match frame.get(i).copied().flatten() {
+                        Some(offset) => offset,
+                        None => {
+                            commands.push_stamp_sprite(entity, i, NodeCommand::SetVisible(false));
+                            continue;
+                        }
+                    };
+
+                    commands.push_stamp_sprite(entity, i, NodeCommand::SetVisible(true));
+                    let position = origin + offset * scale;
+                    commands.push_stamp_sprite(entity, i, NodeCommand::SetPosition(position));
+
+                    // Face the direction of travel
+                    if let Some(next) = next.and_then(|next| next.get(i).copied().flatten()) {
+                        let heading = next - offset;
+                        if heading.length_squared() > 0. {
+                            let rotation = heading.y.atan2(heading.x);
+                            commands.push_stamp_sprite(
+                                entity,
+                                i,
+                                NodeCommand::SetRotation(rotation),
+                            );
+                        }
+                    }
+                }
+            }
+        })
+}
@@ -0,0 +1,379 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use gdnative::core_types::Vector2;
+use legion::*;
+
+use crate::boids::{
+    find_neighbours, is_finite, AlignmentBehavior, BoidId, CohesionBehavior, Forces, Neighbours,
+    Pos, Radius, SeparationBehavior, Velocity, MAX_SPEED, MOUSE_RADIUS,
+};
+use crate::error::{BoidsError, Result};
+use crate::exclusion::ExclusionBehavior;
+use crate::formation::FormationBehavior;
+use crate::gameworld::{BoundaryMode, MouseForce, SteeringInterval, Viewport};
+use crate::group::GroupBehavior;
+use crate::home::HomeBehavior;
+use crate::linked::LinkedFleeBehavior;
+use crate::migration::MigrationBehavior;
+use crate::noise::{jitter, PerceptionRng};
+use crate::spatial::FlockIndex;
+use crate::traits::Traits;
+
+pub mod math;
+
+// -----------------------------------------------------------------------------
+//     - Math adapters -
+// -----------------------------------------------------------------------------
+
+// Between Godot's vectors and the plain ones of `math`
+pub fn to_math(v: Vector2) -> math::Vec2 {
+    [v.x, v.y]
+}
+
+pub fn from_math(v: math::Vec2) -> Vector2 {
+    Vector2::new(v[0], v[1])
+}
+
+// -----------------------------------------------------------------------------
+//     - Behaviours -
+// -----------------------------------------------------------------------------
+
+/// The boid a behaviour is steering
+pub struct SteeringBoid {
+    pub entity: Entity,
+    pub pos: Vector2,
+    pub vel: Vector2,
+    pub radius: f32,
+    pub traits: Traits,
+}
+
+/// The boids a behaviour can see, as indices into the flock index
+pub struct Neighbourhood<'a> {
+    pub index: &'a FlockIndex,
+    pub others: &'a [usize],
+    cached: Option<&'a Neighbours>,
+    rng: RefCell<Option<&'a mut PerceptionRng>>,
+}
+
+impl Neighbourhood<'_> {
+    // For behaviours searching at more than one radius, or with a nearest
+    // count of their own. From the boid's cached neighbours when it has them.
+    pub fn find(&self, pos: Vector2, radius: f32, nearest: Option<usize>) -> Vec<usize> {
+        find_neighbours(self.index, self.cached, pos, radius, nearest)
+    }
+
+    // Error in what the boid perceives, see `PerceptionNoise`
+    pub fn jitter(&self, std_dev: f32) -> Vector2 {
+        jitter(self.rng.borrow_mut().as_deref_mut(), std_dev)
+    }
+}
+
+/// Which of the `Forces` a behaviour steers with. The neighbour rules keep
+/// their own, so `apply_forces` can still scale them and they only run when
+/// the boid's `SteeringInterval` is due.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ForceChannel {
+    Behaviors,
+    Cohesion,
+    Separation,
+    Alignment,
+}
+
+impl ForceChannel {
+    fn of(self, force: &mut Forces) -> &mut Vector2 {
+        match self {
+            ForceChannel::Behaviors => &mut force.behaviors,
+            ForceChannel::Cohesion => &mut force.cohesion,
+            ForceChannel::Separation => &mut force.separation,
+            ForceChannel::Alignment => &mut force.alignment,
+        }
+    }
+}
+
+/// A force on each boid, worked out one boid at a time. Behaviours are added
+/// to `SteeringBehaviors` at runtime instead of being systems of their own.
+pub trait SteeringBehavior: Send + Sync {
+    fn name(&self) -> &str;
+
+    // How far out it needs neighbours, scaled by the boid's perception.
+    // Zero skips the search.
+    fn perception(&self) -> f32 {
+        0.
+    }
+
+    fn channel(&self) -> ForceChannel {
+        ForceChannel::Behaviors
+    }
+
+    /// Once per tick before any boid is steered, to copy out the components
+    /// and resources the behaviour needs
+    fn prepare(&mut self, _world: &World, _resources: &Resources) {}
+
+    fn compute(
+        &mut self,
+        boid: &SteeringBoid,
+        neighbours: &Neighbourhood,
+        resources: &Resources,
+    ) -> Vector2;
+}
+
+// Full strength right under the cursor, falling off linearly to nothing at
+// `MOUSE_RADIUS`
+#[derive(Default)]
+pub struct MouseBehavior {
+    mouse: Option<MouseForce>,
+    boundary: Option<BoundaryMode>,
+    viewport: Option<Viewport>,
+}
+
+impl SteeringBehavior for MouseBehavior {
+    fn name(&self) -> &str {
+        "mouse"
+    }
+
+    fn prepare(&mut self, _: &World, resources: &Resources) {
+        self.mouse = resources.get::<MouseForce>().map(|mouse| *mouse);
+        self.boundary = resources.get::<BoundaryMode>().map(|boundary| *boundary);
+        self.viewport = resources.get::<Viewport>().map(|viewport| *viewport);
+    }
+
+    fn compute(&mut self, boid: &SteeringBoid, _: &Neighbourhood, _: &Resources) -> Vector2 {
+        let mouse = match self.mouse {
+            Some(mouse) if mouse.strength != 0. => mouse,
+            _ => return Vector2::ZERO,
+        };
+
+        let to_mouse = match (self.boundary, &self.viewport) {
+            (Some(boundary), Some(viewport)) => boundary.delta(viewport, boid.pos, mouse.position),
+            _ => mouse.position - boid.pos,
+        };
+        let distance = to_mouse.length();
+
+        if distance > 0. && distance < MOUSE_RADIUS {
+            let falloff = 1. - distance / MOUSE_RADIUS;
+            to_mouse / distance * MAX_SPEED * falloff * mouse.strength
+        } else {
+            Vector2::ZERO
+        }
+    }
+}
+
+// Adds the forces from the GDScript callback, see `CustomForces`
+#[derive(Default)]
+pub struct CustomForceBehavior {
+    forces: HashMap<Entity, Vector2>,
+}
+
+impl SteeringBehavior for CustomForceBehavior {
+    fn name(&self) -> &str {
+        "custom"
+    }
+
+    fn prepare(&mut self, _: &World, resources: &Resources) {
+        // Kept for every sub-step until the callback runs again
+        self.forces = resources
+            .get::<CustomForces>()
+            .map(|forces| forces.0.clone())
+            .unwrap_or_default();
+    }
+
+    fn compute(&mut self, boid: &SteeringBoid, _: &Neighbourhood, _: &Resources) -> Vector2 {
+        self.forces
+            .get(&boid.entity)
+            .copied()
+            .unwrap_or(Vector2::ZERO)
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// What the callback set with `set_custom_force_callback` returned for each
+/// boid. Filled in by the `GameWorld` once per physics frame.
+#[derive(Debug, Default)]
+pub struct CustomForces(pub HashMap<Entity, Vector2>);
+
+pub struct RegisteredBehavior {
+    pub behavior: Box<dyn SteeringBehavior>,
+    pub weight: f32,
+    pub enabled: bool,
+}
+
+/// Behaviours run by `run_behaviors`, in order. Their weighted sums end up
+/// in the `Forces` of their `ForceChannel`.
+#[derive(Default)]
+pub struct SteeringBehaviors(pub Vec<RegisteredBehavior>);
+
+impl SteeringBehaviors {
+    /// The built in behaviours, the neighbour rules first
+    pub fn builtin() -> Self {
+        let mut behaviors = Self::default();
+        behaviors.add(Box::new(CohesionBehavior::default()), 1.);
+        behaviors.add(Box::new(SeparationBehavior::default()), 1.);
+        behaviors.add(Box::new(AlignmentBehavior::default()), 1.);
+        behaviors.add(Box::new(MouseBehavior::default()), 1.);
+        behaviors.add(Box::new(CustomForceBehavior::default()), 1.);
+        behaviors.add(Box::new(MigrationBehavior::default()), 1.);
+        behaviors.add(Box::new(FormationBehavior::default()), 1.);
+        behaviors.add(Box::new(LinkedFleeBehavior::default()), 1.);
+        behaviors.add(Box::new(ExclusionBehavior::default()), 1.);
+        behaviors.add(Box::new(HomeBehavior::default()), 1.);
+        behaviors.add(Box::new(GroupBehavior::default()), 1.);
+        behaviors
+    }
+
+    // Replaces a behaviour with the same name, keeping its place
+    pub fn add(&mut self, behavior: Box<dyn SteeringBehavior>, weight: f32) {
+        let registered = RegisteredBehavior {
+            behavior,
+            weight,
+            enabled: true,
+        };
+
+        let name = registered.behavior.name().to_string();
+        match self.position(&name) {
+            Some(position) => self.0[position] = registered,
+            None => self.0.push(registered),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<()> {
+        let position = self.find(name)?;
+        self.0.remove(position);
+        Ok(())
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Result<&mut RegisteredBehavior> {
+        let position = self.find(name)?;
+        Ok(&mut self.0[position])
+    }
+
+    // Moves a behaviour to `position`, or to the end if that is past it
+    pub fn reorder(&mut self, name: &str, position: usize) -> Result<()> {
+        let current = self.find(name)?;
+        let behavior = self.0.remove(current);
+        let position = position.min(self.0.len());
+        self.0.insert(position, behavior);
+        Ok(())
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.0
+            .iter()
+            .map(|registered| registered.behavior.name().to_string())
+            .collect()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.0
+            .iter()
+            .position(|registered| registered.behavior.name() == name)
+    }
+
+    fn find(&self, name: &str) -> Result<usize> {
+        self.position(name)
+            .ok_or_else(|| BoidsError::Missing(format!("steering behavior \"{}\"", name)))
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+
+// Behaviours can read any resource, so this runs as a thread local function
+// with all of them rather than as a system
+pub fn run_behaviors(world: &mut World, resources: &mut Resources) {
+    let mut behaviors = match resources.remove::<SteeringBehaviors>() {
+        Some(behaviors) => behaviors,
+        None => return,
+    };
+
+    steer(world, resources, &mut behaviors);
+    resources.insert(behaviors);
+}
+
+fn steer(world: &mut World, resources: &Resources, behaviors: &mut SteeringBehaviors) {
+    if !behaviors.0.iter().any(|registered| registered.enabled) {
+        return;
+    }
+
+    let index = match resources.get::<FlockIndex>() {
+        Some(index) => index,
+        None => return,
+    };
+    let steering = resources
+        .get::<SteeringInterval>()
+        .map(|steering| *steering);
+
+    for registered in behaviors
+        .0
+        .iter_mut()
+        .filter(|registered| registered.enabled)
+    {
+        registered.behavior.prepare(world, resources);
+    }
+
+    let mut query = <(
+        Entity,
+        &Pos,
+        &Velocity,
+        &Radius,
+        Option<&Traits>,
+        Option<&BoidId>,
+        Option<&Neighbours>,
+        Option<&mut PerceptionRng>,
+        &mut Forces,
+    )>::query();
+
+    for (&entity, pos, vel, radius, traits, id, cached, mut rng, force) in query.iter_mut(world) {
+        let boid = SteeringBoid {
+            entity,
+            pos: pos.0,
+            vel: vel.0,
+            radius: radius.0,
+            traits: traits.copied().unwrap_or_default(),
+        };
+
+        let due = steering.map(|steering| steering.due(id)).unwrap_or(true);
+
+        force.behaviors = Vector2::ZERO;
+        for registered in behaviors
+            .0
+            .iter_mut()
+            .filter(|registered| registered.enabled)
+        {
+            // Between steering passes the rules keep their last result
+            let channel = registered.behavior.channel();
+            if channel != ForceChannel::Behaviors && !due {
+                continue;
+            }
+
+            let perception = registered.behavior.perception() * boid.traits.perception;
+            let others = if perception > 0. {
+                find_neighbours(
+                    &*index,
+                    cached,
+                    boid.pos,
+                    perception,
+                    index.shared_nearest(),
+                )
+            } else {
+                Vec::new()
+            };
+
+            let neighbours = Neighbourhood {
+                index: &*index,
+                others: &others,
+                cached,
+                rng: RefCell::new(rng.as_deref_mut()),
+            };
+            // A bad custom force shouldn't take the boid with it
+            let steer = registered.behavior.compute(&boid, &neighbours, resources);
+            if is_finite(steer) {
+                *channel.of(force) += steer * registered.weight;
+            }
+        }
+    }
+}
@@ -0,0 +1,252 @@
+// The flocking rules on plain arrays, with no Godot or ECS types, so they
+// can be checked on their own. Vectors that aren't finite are ignored
+// wherever they come in, and nothing that isn't finite comes out.
+
+pub type Vec2 = [f32; 2];
+
+pub const ZERO: Vec2 = [0., 0.];
+
+// -----------------------------------------------------------------------------
+//     - Vectors -
+// -----------------------------------------------------------------------------
+
+pub fn add(a: Vec2, b: Vec2) -> Vec2 {
+    [a[0] + b[0], a[1] + b[1]]
+}
+
+pub fn sub(a: Vec2, b: Vec2) -> Vec2 {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+pub fn scale(v: Vec2, factor: f32) -> Vec2 {
+    [v[0] * factor, v[1] * factor]
+}
+
+// Without squaring, which would overflow for big vectors
+pub fn length(v: Vec2) -> f32 {
+    v[0].hypot(v[1])
+}
+
+pub fn is_finite(v: Vec2) -> bool {
+    v[0].is_finite() && v[1].is_finite()
+}
+
+// Zero for the zero vector rather than NaN
+pub fn normalize(v: Vec2) -> Vec2 {
+    let len = length(v);
+    if len > 0. && len.is_finite() {
+        scale(v, 1. / len)
+    } else {
+        ZERO
+    }
+}
+
+pub fn clamp_length(v: Vec2, max: f32) -> Vec2 {
+    let len = length(v);
+    if len > max && len > 0. {
+        scale(v, max / len)
+    } else {
+        v
+    }
+}
+
+// Of the finite vectors, `None` when there aren't any. Each is divided
+// before summing so big ones don't overflow.
+pub fn mean(vectors: &[Vec2]) -> Option<Vec2> {
+    let count = vectors.iter().filter(|v| is_finite(**v)).count();
+    if count == 0 {
+        return None;
+    }
+
+    let share = 1. / count as f32;
+    let mean = vectors
+        .iter()
+        .filter(|v| is_finite(**v))
+        .fold(ZERO, |mean, v| add(mean, scale(*v, share)));
+    if is_finite(mean) {
+        Some(mean)
+    } else {
+        None
+    }
+}
+
+/// Like `mean`, each vector counting for its weight. Vectors with weights
+/// that aren't positive or finite are skipped.
+pub fn weighted_mean(vectors: &[Vec2], weights: &[f32]) -> Option<Vec2> {
+    let counted = |(v, w): &(&Vec2, &f32)| is_finite(**v) && w.is_finite() && **w > 0.;
+    let total = vectors
+        .iter()
+        .zip(weights)
+        .filter(counted)
+        .map(|(_, w)| *w)
+        .sum::<f32>();
+    if !(total > 0.) || !total.is_finite() {
+        return None;
+    }
+
+    let mean = vectors
+        .iter()
+        .zip(weights)
+        .filter(counted)
+        .fold(ZERO, |mean, (v, w)| add(mean, scale(*v, w / total)));
+    if is_finite(mean) {
+        Some(mean)
+    } else {
+        None
+    }
+}
+
+/// From 0 below `from` to 1 above `to`, smoothly in between
+pub fn smoothstep(from: f32, to: f32, x: f32) -> f32 {
+    if to <= from {
+        return if x >= from { 1. } else { 0. };
+    }
+    let t = ((x - from) / (to - from)).max(0.).min(1.);
+    t * t * (3. - 2. * t)
+}
+
+/// How much a neighbour `distance` away belongs to the band from `inner` to
+/// `outer`, 1 inside it and 0 outside, blending over `falloff` around each
+/// edge. An `inner` of zero or less has no inner edge.
+pub fn band(distance: f32, inner: f32, outer: f32, falloff: f32) -> f32 {
+    let half = falloff.max(0.) / 2.;
+    let rise = if inner > 0. {
+        smoothstep(inner - half, inner + half, distance)
+    } else {
+        1.
+    };
+    rise * (1. - smoothstep(outer - half, outer + half, distance))
+}
+
+// -----------------------------------------------------------------------------
+//     - Rules -
+// -----------------------------------------------------------------------------
+
+/// Steers towards the centroid of the neighbours at full speed, rather than
+/// with a force that grows with the distance to it. `offsets` go from the
+/// boid to each neighbour. `None` when there's nowhere to steer, with no
+/// neighbours or the boid already on the centroid.
+pub fn cohesion(offsets: &[Vec2], vel: Vec2, max_speed: f32, max_force: f32) -> Option<Vec2> {
+    mean(offsets).and_then(|to_centroid| seek(to_centroid, vel, max_speed, max_force))
+}
+
+/// The steering part of `cohesion`, for when the centroid is already known
+pub fn seek(to_target: Vec2, vel: Vec2, max_speed: f32, max_force: f32) -> Option<Vec2> {
+    if !is_finite(to_target) || length(to_target) == 0. {
+        return None;
+    }
+    let vel = if is_finite(vel) { vel } else { ZERO };
+    let desired = scale(normalize(to_target), max_speed);
+    let force = clamp_length(sub(desired, vel), max_force);
+    if is_finite(force) {
+        Some(force)
+    } else {
+        None
+    }
+}
+
+/// Away from the mean offset to the neighbours, zero without any
+pub fn separation(offsets: &[Vec2]) -> Vec2 {
+    mean(offsets)
+        .map(|offset| scale(offset, -1.))
+        .unwrap_or(ZERO)
+}
+
+/// The mean velocity of the neighbours
+pub fn alignment(velocities: &[Vec2]) -> Vec2 {
+    mean(velocities).unwrap_or(ZERO)
+}
+
+// -----------------------------------------------------------------------------
+//     - Tests -
+// -----------------------------------------------------------------------------
+
+#[cfg(feature = "godot_test")]
+pub mod tests {
+    use super::*;
+    use crate::assert_gd;
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        length(sub(a, b)) < 1e-4
+    }
+
+    pub fn no_neighbours() -> bool {
+        assert_gd!(cohesion(&[], [1., 0.], 10., 5.).is_none());
+        assert_gd!(separation(&[]) == ZERO);
+        assert_gd!(alignment(&[]) == ZERO);
+        assert_gd!(mean(&[]).is_none())
+    }
+
+    pub fn cohesion_seeks_centroid() -> bool {
+        // Centroid straight ahead at (10, 0), standing still
+        let force = cohesion(&[[5., 5.], [15., -5.]], ZERO, 10., 100.);
+        assert_gd!(force.map(|force| close(force, [10., 0.])).unwrap_or(false));
+
+        // Capped at the max force
+        let force = cohesion(&[[10., 0.]], [-10., 0.], 10., 5.);
+        assert_gd!(force.map(|force| close(force, [5., 0.])).unwrap_or(false))
+    }
+
+    pub fn coincident_positions() -> bool {
+        // On the centroid there's nowhere to go
+        assert_gd!(cohesion(&[ZERO, ZERO], [1., 0.], 10., 5.).is_none());
+        assert_gd!(cohesion(&[[1., 0.], [-1., 0.]], [1., 0.], 10., 5.).is_none());
+        assert_gd!(separation(&[ZERO]) == ZERO);
+        assert_gd!(normalize(ZERO) == ZERO)
+    }
+
+    pub fn separation_pushes_away() -> bool {
+        let force = separation(&[[2., 0.], [0., 4.]]);
+        assert_gd!(close(force, [-1., -2.]))
+    }
+
+    pub fn alignment_averages_velocities() -> bool {
+        let force = alignment(&[[2., 0.], [0., 2.], [1., 1.]]);
+        assert_gd!(close(force, [1., 1.]))
+    }
+
+    pub fn weighted_mean_weighs() -> bool {
+        let weights = [3., 1., 0., std::f32::NAN];
+        let mean = weighted_mean(&[[4., 0.], [0., 4.], [100., 100.], [100., 100.]], &weights);
+        assert_gd!(mean.map(|mean| close(mean, [3., 1.])).unwrap_or(false));
+        assert_gd!(weighted_mean(&[[1., 1.]], &[0.]).is_none());
+
+        // Even weights are the plain mean
+        let vectors = [[2., 0.], [0., 2.], [1., 1.]];
+        let mean = weighted_mean(&vectors, &[1., 1., 1.]);
+        assert_gd!(mean.map(|mean| close(mean, [1., 1.])).unwrap_or(false))
+    }
+
+    pub fn bands_blend() -> bool {
+        // Hard edges without falloff
+        assert_gd!(band(50., 40., 100., 0.) == 1.);
+        assert_gd!(band(30., 40., 100., 0.) == 0.);
+        assert_gd!(band(120., 40., 100., 0.) == 0.);
+        assert_gd!(band(0., 0., 40., 0.) == 1.);
+
+        // Halfway on the edges, and neighbouring bands add up to one
+        assert_gd!((band(40., 40., 100., 20.) - 0.5).abs() < 1e-4);
+        assert_gd!((band(100., 40., 100., 20.) - 0.5).abs() < 1e-4);
+        let sum = band(45., 0., 40., 20.) + band(45., 40., 100., 20.);
+        assert_gd!((sum - 1.).abs() < 1e-4)
+    }
+
+    pub fn nan_guards() -> bool {
+        let nan = [std::f32::NAN, 0.];
+        let inf = [std::f32::INFINITY, 1.];
+
+        assert_gd!(close(alignment(&[nan, [2., 0.], inf]), [2., 0.]));
+        assert_gd!(close(separation(&[nan, [2., 0.]]), [-2., 0.]));
+        assert_gd!(mean(&[nan, inf]).is_none());
+        assert_gd!(cohesion(&[nan], ZERO, 10., 5.).is_none());
+        assert_gd!(seek(inf, ZERO, 10., 5.).is_none());
+
+        // A broken velocity is treated as standing still
+        let force = cohesion(&[[10., 0.]], nan, 10., 100.);
+        assert_gd!(force.map(|force| close(force, [10., 0.])).unwrap_or(false));
+
+        // Huge but finite offsets don't overflow
+        let force = cohesion(&[[std::f32::MAX, 0.], [std::f32::MAX, 0.]], ZERO, 10., 100.);
+        assert_gd!(force.map(|force| close(force, [10., 0.])).unwrap_or(false))
+    }
+}
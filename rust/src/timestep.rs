@@ -0,0 +1,196 @@
+use gdnative::core_types::Vector2;
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::boids::Pos;
+
+// Past this many steps in one frame the rest of the time is dropped, so a
+// long hitch can't snowball into ever longer frames
+pub const MAX_SUBSTEPS: usize = 8;
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Runs the simulation in steps of `step` seconds, however long the physics
+/// tick is. A `step` of zero integrates once per tick with its own delta.
+/// With `every_frame` the sprites are also moved in `_process`, so a slow
+/// step still looks smooth at any frame rate.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedTimestep {
+    pub step: f32,
+    pub interpolate: bool,
+    pub every_frame: bool,
+    pub accumulator: f32,
+    // Seconds rendered since the last physics tick
+    since_tick: f32,
+    // How far the rendered positions are between the last two steps
+    pub alpha: f32,
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self {
+            step: 0.,
+            interpolate: true,
+            every_frame: false,
+            accumulator: 0.,
+            since_tick: 0.,
+            alpha: 1.,
+        }
+    }
+}
+
+impl FixedTimestep {
+    /// Returns the number of steps to run for `delta` seconds, and the delta
+    /// of each.
+    pub fn advance(&mut self, delta: f32) -> (usize, f32) {
+        self.since_tick = 0.;
+        if self.step <= 0. {
+            self.accumulator = 0.;
+            self.alpha = 1.;
+            return (1, delta);
+        }
+
+        self.accumulator += delta;
+        let mut steps = (self.accumulator / self.step) as usize;
+        if steps > MAX_SUBSTEPS {
+            steps = MAX_SUBSTEPS;
+            self.accumulator = self.step * steps as f32;
+        }
+        self.accumulator -= self.step * steps as f32;
+
+        self.alpha = if self.interpolate {
+            (self.accumulator / self.step).min(1.)
+        } else {
+            1.
+        };
+        (steps, self.step)
+    }
+
+    /// Moves the rendered positions on by a frame of `delta` seconds, which
+    /// the next physics tick takes over from
+    pub fn advance_frame(&mut self, delta: f32) {
+        if self.step <= 0. || !self.interpolate {
+            return;
+        }
+        self.since_tick += delta;
+        self.alpha = ((self.accumulator + self.since_tick) / self.step).min(1.);
+    }
+
+    /// Where to draw a boid that moved from `previous` to `current` in the
+    /// last step. Jumps (screen wrap, respawns) aren't smoothed over.
+    pub fn render_position(&self, previous: Vector2, current: Vector2, max_jump: f32) -> Vector2 {
+        if self.alpha >= 1. || (current - previous).length() > max_jump {
+            return current;
+        }
+        previous.linear_interpolate(current, self.alpha)
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+// Position at the start of the last step
+#[derive(Debug, Clone, Copy)]
+pub struct PreviousPos(pub Vector2);
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn store_previous_positions() -> impl ParallelRunnable {
+    SystemBuilder::new("store previous positions")
+        .with_query(<(&Pos, &mut PreviousPos)>::query())
+        .build(|_, world, _, query| {
+            for (pos, previous) in query.iter_mut(world) {
+                previous.0 = pos.0;
+            }
+        })
+}
+
+// -----------------------------------------------------------------------------
+//     - Tests -
+// -----------------------------------------------------------------------------
+
+#[cfg(feature = "godot_test")]
+pub mod tests {
+    use super::*;
+    use crate::assert_gd;
+
+    fn fixed(step: f32) -> FixedTimestep {
+        FixedTimestep {
+            step,
+            ..FixedTimestep::default()
+        }
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    pub fn advance_carries_remainder() -> bool {
+        // 2.5 steps run two and carry the half over
+        let mut timestep = fixed(0.25);
+        let (steps, delta) = timestep.advance(0.625);
+        assert_gd!(steps == 2 && close(delta, 0.25));
+        assert_gd!(close(timestep.accumulator, 0.125));
+        assert_gd!(close(timestep.alpha, 0.5));
+
+        // Which makes up a whole step with the next tick
+        let (steps, _) = timestep.advance(0.125);
+        assert_gd!(steps == 1 && close(timestep.accumulator, 0.));
+
+        // A step of zero runs once with the tick's own delta
+        let mut timestep = fixed(0.);
+        let (steps, delta) = timestep.advance(0.25);
+        assert_gd!(steps == 1 && close(delta, 0.25) && timestep.alpha == 1.)
+    }
+
+    pub fn advance_caps_substeps() -> bool {
+        // A long hitch runs the most steps and drops the rest of the time
+        let mut timestep = fixed(0.25);
+        let (steps, _) = timestep.advance(5.);
+        assert_gd!(steps == MAX_SUBSTEPS);
+        assert_gd!(close(timestep.accumulator, 0.));
+
+        // Without interpolation the current positions are drawn
+        let mut timestep = FixedTimestep {
+            interpolate: false,
+            ..fixed(0.25)
+        };
+        timestep.advance(0.625);
+        assert_gd!(timestep.alpha == 1.)
+    }
+
+    pub fn advance_frame_moves_alpha() -> bool {
+        let mut timestep = fixed(0.25);
+        timestep.advance(0.3125);
+        assert_gd!(close(timestep.alpha, 0.25));
+
+        // Frames between ticks move on from the carried time, up to a step
+        timestep.advance_frame(0.0625);
+        assert_gd!(close(timestep.alpha, 0.5));
+        timestep.advance_frame(0.25);
+        assert_gd!(timestep.alpha == 1.);
+
+        // The next tick starts counting the frames again
+        timestep.advance(0.);
+        timestep.advance_frame(0.0625);
+        assert_gd!(close(timestep.alpha, 0.5))
+    }
+
+    pub fn render_position_skips_jumps() -> bool {
+        let mut timestep = fixed(0.25);
+        timestep.advance(0.375);
+        let (previous, current) = (Vector2::new(0., 0.), Vector2::new(10., 0.));
+
+        // Halfway between the last two steps
+        let pos = timestep.render_position(previous, current, 100.);
+        assert_gd!(close(pos.x, 5.) && close(pos.y, 0.));
+
+        // Further than `max_jump` is drawn where it ended up
+        let pos = timestep.render_position(previous, current, 5.);
+        assert_gd!(pos == current)
+    }
+}
@@ -0,0 +1,104 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::boids::MAX_SPEED;
+use crate::error::{BoidsError, Result};
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+/// Per-boid personality, sampled once at spawn. Boids without one behave like
+/// `Traits::default()`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Traits {
+    pub max_speed: f32,
+    // Scales every perception radius
+    pub perception: f32,
+    pub cohesion: f32,
+    pub separation: f32,
+    pub alignment: f32,
+}
+
+impl Default for Traits {
+    fn default() -> Self {
+        Self {
+            max_speed: MAX_SPEED,
+            perception: 1.,
+            cohesion: 1.,
+            separation: 1.,
+            alignment: 1.,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// Uniform distribution between `min` and `max`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TraitRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl TraitRange {
+    pub fn new(min: f32, max: f32) -> Self {
+        Self { min, max }
+    }
+
+    pub fn sample(&self, rng: &mut impl Rng) -> f32 {
+        if self.max <= self.min {
+            self.min
+        } else {
+            rng.gen_range(self.min, self.max)
+        }
+    }
+}
+
+pub struct TraitRanges {
+    pub max_speed: TraitRange,
+    pub perception: TraitRange,
+    pub cohesion: TraitRange,
+    pub separation: TraitRange,
+    pub alignment: TraitRange,
+}
+
+impl Default for TraitRanges {
+    fn default() -> Self {
+        Self {
+            max_speed: TraitRange::new(MAX_SPEED * 0.8, MAX_SPEED * 1.1),
+            perception: TraitRange::new(0.8, 1.2),
+            cohesion: TraitRange::new(0.8, 1.2),
+            separation: TraitRange::new(0.8, 1.2),
+            alignment: TraitRange::new(0.8, 1.2),
+        }
+    }
+}
+
+impl TraitRanges {
+    pub fn sample(&self, rng: &mut impl Rng) -> Traits {
+        Traits {
+            max_speed: self.max_speed.sample(rng),
+            perception: self.perception.sample(rng),
+            cohesion: self.cohesion.sample(rng),
+            separation: self.separation.sample(rng),
+            alignment: self.alignment.sample(rng),
+        }
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Result<&mut TraitRange> {
+        match name {
+            "max_speed" => Ok(&mut self.max_speed),
+            "perception" => Ok(&mut self.perception),
+            "cohesion" => Ok(&mut self.cohesion),
+            "separation" => Ok(&mut self.separation),
+            "alignment" => Ok(&mut self.alignment),
+            _ => Err(BoidsError::InvalidArgument(format!(
+                "unknown trait \"{}\"",
+                name
+            ))),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use gdnative::core_types::Vector2;
+use legion::systems::ParallelRunnable;
+use legion::*;
+
+use crate::boids::{Forces, Pos, Velocity, MAX_SPEED};
+use crate::gameworld::{BoundaryMode, Viewport};
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+
+/// With `BoundaryMode::Walls`, boids closer than `margin` to an edge are
+/// pushed back in with up to `strength` times the max speed
+#[derive(Debug, Clone, Copy)]
+pub struct Walls {
+    pub strength: f32,
+    pub margin: f32,
+}
+
+impl Default for Walls {
+    fn default() -> Self {
+        Self {
+            strength: 1.,
+            margin: 100.,
+        }
+    }
+}
+
+impl Walls {
+    // Push away from the walls on one axis, `from_min` and `from_max` being
+    // the distances to them
+    fn push(&self, from_min: f32, from_max: f32) -> f32 {
+        if self.margin <= 0. {
+            return 0.;
+        }
+        let closeness = |distance: f32| (1. - distance / self.margin).max(0.).min(1.);
+        (closeness(from_min) - closeness(from_max)) * self.strength * MAX_SPEED
+    }
+}
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn avoid_walls() -> impl ParallelRunnable {
+    SystemBuilder::new("avoid walls")
+        .read_resource::<BoundaryMode>()
+        .read_resource::<Viewport>()
+        .read_resource::<Walls>()
+        .with_query(<(&Pos, &mut Forces)>::query())
+        .build(|_, world, resources, query| {
+            let (boundary, viewport, walls) = resources;
+            if **boundary != BoundaryMode::Walls {
+                return;
+            }
+
+            let rect = viewport.0;
+            for (pos, force) in query.iter_mut(world) {
+                force.wall = Vector2::new(
+                    walls.push(pos.0.x - rect.position.x, rect.end().x - pos.0.x),
+                    walls.push(pos.0.y - rect.position.y, rect.end().y - pos.0.y),
+                );
+            }
+        })
+}
+
+// Boids that got through anyway are put back on the wall, sliding along it
+pub fn contain_in_walls() -> impl ParallelRunnable {
+    SystemBuilder::new("contain in walls")
+        .read_resource::<BoundaryMode>()
+        .read_resource::<Viewport>()
+        .with_query(<(&mut Pos, &mut Velocity)>::query())
+        .build(|_, world, resources, query| {
+            let (boundary, viewport) = resources;
+            if **boundary != BoundaryMode::Walls {
+                return;
+            }
+
+            let rect = viewport.0;
+            for (pos, vel) in query.iter_mut(world) {
+                if pos.0.x < rect.position.x || pos.0.x > rect.end().x {
+                    pos.0.x = pos.0.x.max(rect.position.x).min(rect.end().x);
+                    vel.0.x = 0.;
+                }
+
+                if pos.0.y < rect.position.y || pos.0.y > rect.end().y {
+                    pos.0.y = pos.0.y.max(rect.position.y).min(rect.end().y);
+                    vel.0.y = 0.;
+                }
+            }
+        })
+}
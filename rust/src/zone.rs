@@ -0,0 +1,80 @@
+use gdnative::core_types::{Rect2, Vector2};
+use legion::systems::ParallelRunnable;
+use legion::*;
+use serde::{Deserialize, Serialize};
+
+use crate::boids::Pos;
+
+// -----------------------------------------------------------------------------
+//     - Zones -
+// -----------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy)]
+pub enum ZoneShape {
+    Rect(Rect2),
+    Circle { center: Vector2, radius: f32 },
+}
+
+impl ZoneShape {
+    pub fn contains(&self, pos: Vector2) -> bool {
+        match self {
+            ZoneShape::Rect(rect) => rect.contains_point(pos),
+            ZoneShape::Circle { center, radius } => {
+                (pos - *center).length_squared() < radius * radius
+            }
+        }
+    }
+}
+
+/// Replaces the global multipliers inside a zone, missing ones are left alone
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ZoneOverrides {
+    pub cohesion: Option<f32>,
+    pub separation: Option<f32>,
+    pub alignment: Option<f32>,
+}
+
+// -----------------------------------------------------------------------------
+//     - Components -
+// -----------------------------------------------------------------------------
+
+/// Lives on its own entity. Where zones overlap the one with the highest id,
+/// the most recently added, wins.
+pub struct Zone {
+    pub id: u64,
+    pub shape: ZoneShape,
+    pub overrides: ZoneOverrides,
+}
+
+// The overrides of the zone a boid is in, if any
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ActiveZone(pub Option<ZoneOverrides>);
+
+// -----------------------------------------------------------------------------
+//     - Resources -
+// -----------------------------------------------------------------------------
+pub struct NextZoneId(pub u64);
+
+// -----------------------------------------------------------------------------
+//     - Systems -
+// -----------------------------------------------------------------------------
+pub fn resolve_zones() -> impl ParallelRunnable {
+    SystemBuilder::new("resolve zones")
+        .with_query(<&Zone>::query())
+        .with_query(<(&Pos, &mut ActiveZone)>::query())
+        .build(|_, world, _, queries| {
+            let (zones, boids) = queries;
+            let mut zones = zones
+                .iter(world)
+                .map(|zone| (zone.id, zone.shape, zone.overrides))
+                .collect::<Vec<_>>();
+            zones.sort_by_key(|(id, _, _)| std::cmp::Reverse(*id));
+
+            for (pos, active) in boids.iter_mut(world) {
+                active.0 = zones
+                    .iter()
+                    .find(|(_, shape, _)| shape.contains(pos.0))
+                    .map(|(_, _, overrides)| *overrides);
+            }
+        })
+}